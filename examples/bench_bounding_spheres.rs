@@ -0,0 +1,50 @@
+//! Compares radius queries with and without bounding sphere pruning.
+//!
+//! Run with `cargo run --release --example bench_bounding_spheres`
+use keyde::utils::SplitMix64;
+use keyde::KdTreeBuilder;
+
+use std::time::Instant;
+
+fn random_points(rng: &mut SplitMix64, n: usize) -> Vec<[f32; 4]> {
+    (0..n)
+        .map(|_| {
+            let mut point = [0.0; 4];
+            for value in point.iter_mut() {
+                *value = (rng.next_u64() % 1_000_000) as f32 / 1_000_000.0;
+            }
+            point
+        })
+        .collect()
+}
+
+fn main() {
+    let mut rng = SplitMix64::new(7);
+    let points = random_points(&mut rng, 200_000);
+    let queries = random_points(&mut rng, 10_000);
+    let radius = 0.05;
+
+    for bounding_spheres in [false, true] {
+        let start = Instant::now();
+        let tree = KdTreeBuilder::new()
+            .bounding_spheres(bounding_spheres)
+            .build(&points);
+        let build_time = start.elapsed();
+
+        let mut result = vec![];
+        let mut stack = vec![];
+        let mut found = 0;
+
+        let start = Instant::now();
+        for query in &queries {
+            result.clear();
+            tree.point_indices_within_buffers(*query, radius, &mut result, &mut stack);
+            found += result.len();
+        }
+        let query_time = start.elapsed();
+
+        println!(
+            "bounding spheres: {bounding_spheres:5} build: {build_time:?} queries: {query_time:?} (found {found})"
+        );
+    }
+}
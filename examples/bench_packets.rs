@@ -0,0 +1,51 @@
+//! Compares answering spatially coherent radius queries one by one against query packets.
+//!
+//! Run with `cargo run --release --example bench_packets`
+use keyde::utils::SplitMix64;
+use keyde::{reorder_points_morton, KdTree};
+
+use std::time::Instant;
+
+fn random_points(rng: &mut SplitMix64, n: usize) -> Vec<[f32; 3]> {
+    (0..n)
+        .map(|_| {
+            let mut point = [0.0; 3];
+            for value in point.iter_mut() {
+                *value = (rng.next_u64() % 1_000_000) as f32 / 1_000_000.0;
+            }
+            point
+        })
+        .collect()
+}
+
+fn main() {
+    let mut rng = SplitMix64::new(11);
+    let points = random_points(&mut rng, 200_000);
+    let (queries, _) = reorder_points_morton(&random_points(&mut rng, 50_000));
+    let radius = 0.02;
+
+    let tree = KdTree::from_points(&points);
+
+    let start = Instant::now();
+    let mut result = vec![];
+    let mut stack = vec![];
+    let mut found = 0;
+    for query in &queries {
+        result.clear();
+        tree.point_indices_within_buffers(*query, radius, &mut result, &mut stack);
+        found += result.len();
+    }
+    println!("single:  {:?} (found {found})", start.elapsed());
+
+    let start = Instant::now();
+    let mut results: [Vec<usize>; 8] = Default::default();
+    let mut packet_stack = vec![];
+    let mut found = 0;
+    for packet in queries.chunks_exact(8) {
+        results.iter_mut().for_each(Vec::clear);
+        let packet: &[[f32; 3]; 8] = packet.try_into().unwrap();
+        tree.point_indices_within_packet_buffers(packet, radius, &mut results, &mut packet_stack);
+        found += results.iter().map(Vec::len).sum::<usize>();
+    }
+    println!("packets: {:?} (found {found})", start.elapsed());
+}
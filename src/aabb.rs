@@ -0,0 +1,290 @@
+//! Queries with axis-aligned boxes
+use crate::nearest::{pop_nearest_first, Neighbor};
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+use std::collections::BinaryHeap;
+
+/// The squared distance from `point` to the box `min..=max`, zero inside of it
+#[inline(always)]
+pub(crate) fn distance_squared_to_aabb<const D: usize>(
+    point: &[f32; D],
+    min: &[f32; D],
+    max: &[f32; D],
+) -> f32 {
+    let mut distance_squared = 0.0;
+    for axis in 0..D {
+        let gap = (min[axis] - point[axis])
+            .max(point[axis] - max[axis])
+            .max(0.0);
+        distance_squared += gap * gap;
+    }
+    distance_squared
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// The traversal behind `point_indices_in_aabb_buffers`, calling `found` with the index and
+    /// axis values of every point inside `min..=max`
+    pub(crate) fn for_each_point_in_aabb_buffers<F>(
+        &self,
+        points: &[P],
+        min: &[f32; D],
+        max: &[f32; D],
+        mut found: F,
+        stack: &mut Vec<(usize, usize)>,
+    ) where
+        F: FnMut(usize, &[f32; D]),
+    {
+        if self.tree.is_empty() {
+            return;
+        }
+
+        stack.push((0, 0));
+        while let Some((depth, tree_index)) = stack.pop() {
+            let point_index = self.tree[tree_index].index();
+            let point = axis_values(&points[point_index]);
+
+            if (0..D).all(|axis| min[axis] <= point[axis] && point[axis] <= max[axis]) {
+                found(point_index, &point);
+            }
+
+            let axis = self.tree[tree_index].axis();
+            let split = self.tree[tree_index].split;
+
+            /*
+                NOTE: Points equal to the split value can end up on either side
+            */
+            let [left, right] = self.tree[tree_index].children();
+            if max[axis] >= split {
+                if let Some(child) = right {
+                    stack.push((depth + 1, child));
+                }
+            }
+            if min[axis] <= split {
+                if let Some(child) = left {
+                    stack.push((depth + 1, child));
+                }
+            }
+        }
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_in_aabb_buffers(
+        &self,
+        points: &[P],
+        min: P,
+        max: P,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.for_each_point_in_aabb_buffers(
+            points,
+            &axis_values(&min),
+            &axis_values(&max),
+            |point_index, _| result.push(point_index),
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_in_aabb(&self, points: &[P], min: P, max: P) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_in_aabb_buffers(points, min, max, &mut result, &mut stack);
+
+        result
+    }
+
+    /// See `KdTree`
+    #[allow(clippy::too_many_arguments)]
+    pub fn nearest_to_aabb_buffers(
+        &self,
+        points: &[P],
+        min: P,
+        max: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        if k == 0 || self.tree.is_empty() {
+            return;
+        }
+
+        let min = axis_values(&min);
+        let max = axis_values(&max);
+
+        stack.push((0, 0, 0.0));
+        while let Some((depth, tree_index, lower_bound)) = stack.pop() {
+            let current_bound = if heap.len() == k {
+                heap.peek().map_or(f32::INFINITY, |n| n.distance_squared)
+            } else {
+                f32::INFINITY
+            };
+            if lower_bound > current_bound {
+                continue;
+            }
+
+            let point_index = self.tree[tree_index].index();
+            let point = axis_values(&points[point_index]);
+
+            let distance_squared = distance_squared_to_aabb(&point, &min, &max);
+            if distance_squared <= current_bound {
+                heap.push(Neighbor {
+                    distance_squared,
+                    index: point_index,
+                });
+                if heap.len() > k {
+                    heap.pop();
+                }
+            }
+
+            /*
+                NOTE: Each side of the split is at least as far from the box as the gap
+                      between the box and the splitting plane, if the box is on the other side
+            */
+            let axis = self.tree[tree_index].axis();
+            let split = self.tree[tree_index].split;
+            let left_gap = (min[axis] - split).max(0.0);
+            let right_gap = (split - max[axis]).max(0.0);
+
+            let [left, right] = self.tree[tree_index].children();
+            let left = left.map(|child| (depth + 1, child, lower_bound.max(left_gap * left_gap)));
+            let right =
+                right.map(|child| (depth + 1, child, lower_bound.max(right_gap * right_gap)));
+
+            /*
+                NOTE: The far side is pushed first so that the near side is popped first
+            */
+            if left_gap <= right_gap {
+                stack.extend(right);
+                stack.extend(left);
+            } else {
+                stack.extend(left);
+                stack.extend(right);
+            }
+        }
+
+        pop_nearest_first(heap, result);
+    }
+
+    /// See `KdTree`
+    pub fn nearest_to_aabb(&self, points: &[P], min: P, max: P, k: usize) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.tree.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.tree.len()) + 1);
+        let mut stack = vec![];
+
+        self.nearest_to_aabb_buffers(points, min, max, k, &mut result, &mut heap, &mut stack);
+
+        result
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `point_indices_in_aabb`, but you provide your own buffers. Indices are appended
+    /// to `result` which is not cleared. `stack` is assumed to be empty and is left empty.
+    #[inline(always)]
+    pub fn point_indices_in_aabb_buffers(
+        &self,
+        min: P,
+        max: P,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal
+            .point_indices_in_aabb_buffers(self.points, min, max, result, stack)
+    }
+
+    /// Returns the indices of the points inside the axis-aligned box spanning `min..=max`,
+    /// boundary included. Subtrees entirely on one side of the box are skipped by comparing the
+    /// splitting value against the box, so no distance computations are involved.
+    #[inline(always)]
+    pub fn point_indices_in_aabb(&self, min: P, max: P) -> Vec<usize> {
+        self.internal.point_indices_in_aabb(self.points, min, max)
+    }
+
+    /// Same as `nearest_to_aabb`, but you provide your own buffers, see `nearest_n_buffers`
+    #[inline(always)]
+    pub fn nearest_to_aabb_buffers(
+        &self,
+        min: P,
+        max: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        self.internal
+            .nearest_to_aabb_buffers(self.points, min, max, k, result, heap, stack)
+    }
+
+    /// Returns the indices of the `k` points closest to the axis-aligned box spanning
+    /// `min..=max`, nearest first. Points inside the box are at distance zero and come first,
+    /// ordered by index like all points at the same distance.
+    ///
+    /// Subtrees are skipped when the gap between the box and the splitting plane is larger than
+    /// the distance to the current `k`th candidate.
+    #[inline(always)]
+    pub fn nearest_to_aabb(&self, min: P, max: P, k: usize) -> Vec<usize> {
+        self.internal.nearest_to_aabb(self.points, min, max, k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::SplitMix64;
+    use crate::KdTree;
+
+    #[test]
+    fn test_point_indices_in_aabb() {
+        let mut rng = SplitMix64::new(21);
+        let points = (0..3000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        for (min, max) in [
+            ([10.0, 10.0, 10.0], [30.0, 20.0, 90.0]),
+            ([0.0, 0.0, 0.0], [99.0, 99.0, 99.0]),
+            ([50.0, 50.0, 50.0], [50.0, 50.0, 50.0]),
+            ([60.0, 0.0, 0.0], [40.0, 99.0, 99.0]),
+        ] {
+            let expected = (0..points.len())
+                .filter(|i| (0..3).all(|a| min[a] <= points[*i][a] && points[*i][a] <= max[a]))
+                .collect::<Vec<_>>();
+
+            let mut result = tree.point_indices_in_aabb(min, max);
+            result.sort_unstable();
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_nearest_to_aabb() {
+        let mut rng = SplitMix64::new(31);
+        let points = (0..2000)
+            .map(|_| [(rng.next_u64() % 100) as f32, (rng.next_u64() % 100) as f32])
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        for (min, max, k) in [
+            ([10.0, 10.0], [12.0, 30.0], 40),
+            ([-50.0, 40.0], [-20.0, 45.0], 5),
+            ([0.0, 0.0], [99.0, 99.0], 3),
+        ] {
+            let mut expected = (0..points.len()).collect::<Vec<_>>();
+            let distance = |i: usize| super::distance_squared_to_aabb(&points[i], &min, &max);
+            expected.sort_by(|a, b| distance(*a).total_cmp(&distance(*b)).then(a.cmp(b)));
+            expected.truncate(k);
+
+            assert_eq!(tree.nearest_to_aabb(min, max, k), expected);
+        }
+    }
+}
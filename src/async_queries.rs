@@ -0,0 +1,146 @@
+//! Adapter for issuing batches of queries from async code without blocking the executor.
+//! The queries are answered on separate threads and every query gets a `QueryFuture`
+//! that resolves once its result is ready.
+use crate::{KdTreeNoBorrow, Point};
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+#[derive(Debug)]
+struct Slot {
+    result: Option<Vec<usize>>,
+    waker: Option<Waker>,
+}
+
+#[derive(Debug)]
+/// Resolves to the indices found by a query spawned with `spawn_point_indices_within_batch`
+pub struct QueryFuture {
+    slot: Arc<Mutex<Slot>>,
+}
+
+impl QueryFuture {
+    fn new() -> (Self, Arc<Mutex<Slot>>) {
+        let slot = Arc::new(Mutex::new(Slot {
+            result: None,
+            waker: None,
+        }));
+
+        (Self { slot: slot.clone() }, slot)
+    }
+}
+
+impl Future for QueryFuture {
+    type Output = Vec<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = self.slot.lock().expect("keyde query slot poisoned");
+
+        match slot.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                slot.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Answers `point_indices_within` for every query in `queries` on up to `threads` background
+/// threads and returns one future per query, in the same order as `queries`.
+///
+/// The tree and points are shared through `Arc`s so that the work can outlive the caller.
+/// The threads are detached; dropping the futures does not cancel the work.
+pub fn spawn_point_indices_within_batch<const D: usize, P>(
+    tree: Arc<KdTreeNoBorrow<D, P>>,
+    points: Arc<[P]>,
+    queries: Vec<P>,
+    radius: f32,
+    threads: usize,
+) -> Vec<QueryFuture>
+where
+    P: Point<D> + Send + Sync + 'static,
+{
+    let threads = threads.max(1);
+    let chunk_size = queries.len().div_ceil(threads).max(1);
+
+    let mut futures = Vec::with_capacity(queries.len());
+    let mut jobs = Vec::with_capacity(queries.len());
+    for query in queries {
+        let (future, slot) = QueryFuture::new();
+        futures.push(future);
+        jobs.push((query, slot));
+    }
+
+    let mut jobs = jobs.into_iter().peekable();
+    while jobs.peek().is_some() {
+        let chunk = jobs.by_ref().take(chunk_size).collect::<Vec<_>>();
+        let tree = tree.clone();
+        let points = points.clone();
+
+        std::thread::spawn(move || {
+            let mut stack = vec![];
+
+            for (query, slot) in chunk {
+                let mut result = vec![];
+                tree.point_indices_within_buffers(&points, query, radius, &mut result, &mut stack);
+
+                let mut slot = slot.lock().expect("keyde query slot poisoned");
+                slot.result = Some(result);
+                if let Some(waker) = slot.waker.take() {
+                    waker.wake();
+                }
+            }
+        });
+    }
+
+    futures
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::task::Wake;
+
+    struct ThreadWaker(std::thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = std::pin::pin!(future);
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_async_batch() {
+        let points: Arc<[[f32; 2]]> = (0..200).map(|i| [i as f32, 0.0]).collect();
+        let tree = Arc::new(KdTreeNoBorrow::from_points(&points));
+
+        let queries = vec![[10.0, 0.0], [50.0, 0.5], [500.0, 0.0]];
+        let futures = spawn_point_indices_within_batch(tree, points, queries, 1.0, 2);
+
+        let results = futures
+            .into_iter()
+            .map(|future| {
+                let mut result = block_on(future);
+                result.sort();
+                result
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(results, vec![vec![9, 10, 11], vec![50], vec![]]);
+    }
+}
@@ -0,0 +1,126 @@
+//! Radius queries for many query points at once
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_within_batch_buffers(
+        &self,
+        points: &[P],
+        query_points: &[P],
+        radius: f32,
+        offsets: &mut Vec<usize>,
+        indices: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        offsets.clear();
+        indices.clear();
+        offsets.reserve(query_points.len() + 1);
+
+        offsets.push(0);
+        for query_point in query_points {
+            self.point_indices_within_buffers(points, *query_point, radius, indices, stack);
+            offsets.push(indices.len());
+        }
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_batch(
+        &self,
+        points: &[P],
+        query_points: &[P],
+        radius: f32,
+    ) -> Vec<Vec<usize>> {
+        let mut stack = vec![];
+
+        query_points
+            .iter()
+            .map(|query_point| {
+                let mut result = vec![];
+                self.point_indices_within_buffers(
+                    points,
+                    *query_point,
+                    radius,
+                    &mut result,
+                    &mut stack,
+                );
+                result
+            })
+            .collect()
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `point_indices_within_batch`, but all results are written into one flat buffer
+    /// in compressed sparse row layout: the indices found for `query_points[i]` are
+    /// `indices[offsets[i]..offsets[i + 1]]`. `offsets` and `indices` are cleared first and
+    /// `offsets` ends up with `query_points.len() + 1` entries. `stack` is assumed to be empty
+    /// and is left empty.
+    ///
+    /// Reusing the buffers between batches avoids allocating one `Vec` per query.
+    #[inline(always)]
+    pub fn point_indices_within_batch_buffers(
+        &self,
+        query_points: &[P],
+        radius: f32,
+        offsets: &mut Vec<usize>,
+        indices: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_within_batch_buffers(
+            self.points,
+            query_points,
+            radius,
+            offsets,
+            indices,
+            stack,
+        )
+    }
+
+    /// Answers `point_indices_within` for every query point, sharing one stack between the
+    /// queries. `result[i]` belongs to `query_points[i]`.
+    ///
+    /// See `point_indices_within_batch_packets` for a variant that traverses the tree once
+    /// for several nearby queries.
+    #[inline(always)]
+    pub fn point_indices_within_batch(&self, query_points: &[P], radius: f32) -> Vec<Vec<usize>> {
+        self.internal
+            .point_indices_within_batch(self.points, query_points, radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_indices_within_batch() {
+        let points: Vec<[f32; 2]> = (0..1000)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.13).sin() * 10.0, (i * 0.07).cos() * 10.0]
+            })
+            .collect();
+        let queries: Vec<[f32; 2]> = (0..13)
+            .map(|i| [i as f32 - 6.0, (i as f32 * 0.9).sin() * 5.0])
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        let results = tree.point_indices_within_batch(&queries, 2.0);
+        let (mut offsets, mut indices, mut stack) = (vec![7], vec![7], vec![]);
+        tree.point_indices_within_batch_buffers(
+            &queries,
+            2.0,
+            &mut offsets,
+            &mut indices,
+            &mut stack,
+        );
+
+        assert_eq!(results.len(), queries.len());
+        assert_eq!(offsets.len(), queries.len() + 1);
+        for (i, query) in queries.iter().enumerate() {
+            let expected = tree.point_indices_within(*query, 2.0);
+            assert_eq!(results[i], expected);
+            assert_eq!(indices[offsets[i]..offsets[i + 1]], expected);
+        }
+    }
+}
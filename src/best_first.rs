@@ -0,0 +1,476 @@
+//! Best-first k nearest neighbor traversal, visiting nodes in order of their distance
+use crate::nearest::{pop_nearest_first, Neighbor};
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, KdTreeNode, NodeStorage, Point};
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// The order in which k nearest neighbor queries visit the nodes of the tree
+pub enum QueryStrategy {
+    /// Depth-first, always descending into the side of a split containing the query point
+    /// first. Cheap bookkeeping, this is what `nearest_n` uses.
+    #[default]
+    DepthFirst,
+    /// Always continues with the pending node closest to the query point, using a priority
+    /// queue. Visits the fewest nodes since pruning kicks in as early as possible, at the cost
+    /// of maintaining the queue.
+    BestFirst,
+}
+
+/// A pending node of a best-first traversal, see `nearest_n_best_first_buffers`
+#[derive(Debug, Clone, Copy)]
+pub struct NodeCandidate<const D: usize> {
+    lower_bound: f32,
+    depth: usize,
+    tree_index: usize,
+    /// Per axis distance from the query point to the region of the subtree
+    offsets: [f32; D],
+}
+
+/*
+    NOTE: Ordered by reversed distance so that `BinaryHeap` keeps the closest node on top
+*/
+impl<const D: usize> Ord for NodeCandidate<D> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.lower_bound.total_cmp(&self.lower_bound)
+    }
+}
+
+impl<const D: usize> PartialOrd for NodeCandidate<D> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const D: usize> PartialEq for NodeCandidate<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<const D: usize> Eq for NodeCandidate<D> {}
+
+/// A pending entry of `NearestIterator`, either a subtree or a point that was already measured
+#[derive(Debug, Clone, Copy)]
+enum Pending<const D: usize> {
+    Node(NodeCandidate<D>),
+    Point(Neighbor),
+}
+
+impl<const D: usize> Pending<D> {
+    #[inline(always)]
+    fn distance_squared(&self) -> f32 {
+        match self {
+            Pending::Node(candidate) => candidate.lower_bound,
+            Pending::Point(neighbor) => neighbor.distance_squared,
+        }
+    }
+}
+
+/*
+    NOTE: Ordered so that `BinaryHeap` keeps the closest entry on top. At equal distances, nodes
+          come before points so that every point at that distance has been measured before any
+          of them is handed out, and points come out by index like in `nearest_n`.
+*/
+impl<const D: usize> Ord for Pending<D> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .distance_squared()
+            .total_cmp(&self.distance_squared())
+            .then_with(|| match (self, other) {
+                (Pending::Node(_), Pending::Node(_)) => Ordering::Equal,
+                (Pending::Node(_), Pending::Point(_)) => Ordering::Greater,
+                (Pending::Point(_), Pending::Node(_)) => Ordering::Less,
+                (Pending::Point(a), Pending::Point(b)) => b.index.cmp(&a.index),
+            })
+    }
+}
+
+impl<const D: usize> PartialOrd for Pending<D> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<const D: usize> PartialEq for Pending<D> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<const D: usize> Eq for Pending<D> {}
+
+/// Iterator over the points of a KdTree as `(index, distance)`, nearest first, see
+/// `KdTree::iter_nearest` and `KdTree::iter_nearest_within`
+#[derive(Debug, Clone)]
+pub struct NearestIterator<'a, const D: usize, P: Point<D>, N: NodeStorage = Vec<KdTreeNode>> {
+    tree: &'a KdTreeNoBorrow<D, P, N>,
+    points: &'a [P],
+    query_point: P,
+    query_point_axis_values: [f32; D],
+    /// Nothing further away than this is queued
+    radius_squared: f32,
+    queue: BinaryHeap<Pending<D>>,
+}
+
+impl<'a, const D: usize, P: Point<D>, N: NodeStorage> Iterator for NearestIterator<'a, D, P, N> {
+    type Item = (usize, f32);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(pending) = self.queue.pop() {
+            let candidate = match pending {
+                Pending::Point(neighbor) => {
+                    return Some((neighbor.index, neighbor.distance_squared.sqrt()));
+                }
+                Pending::Node(candidate) => candidate,
+            };
+
+            let NodeCandidate {
+                depth,
+                tree_index,
+                offsets,
+                ..
+            } = candidate;
+
+            let point_index = self.tree.tree[tree_index].index();
+            let point = self.points[point_index];
+
+            let distance_squared = self.query_point.distance_squared(point);
+            if distance_squared <= self.radius_squared {
+                self.queue.push(Pending::Point(Neighbor {
+                    distance_squared,
+                    index: point_index,
+                }));
+            }
+
+            let axis = self.tree.tree[tree_index].axis();
+            let axis_d = self.tree.tree[tree_index].split - self.query_point_axis_values[axis];
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            if let Some(child) = self.tree.tree[tree_index].child(first) {
+                self.queue.push(Pending::Node(NodeCandidate {
+                    depth: depth + 1,
+                    tree_index: child,
+                    ..candidate
+                }));
+            }
+
+            if let Some(child) = self.tree.tree[tree_index].child(last) {
+                let mut far_offsets = offsets;
+                far_offsets[axis] = far_offsets[axis].max(axis_d.abs());
+                let lower_bound = far_offsets.iter().map(|offset| offset * offset).sum();
+
+                if lower_bound <= self.radius_squared {
+                    self.queue.push(Pending::Node(NodeCandidate {
+                        lower_bound,
+                        depth: depth + 1,
+                        tree_index: child,
+                        offsets: far_offsets,
+                    }));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn nearest_n_best_first_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        queue: &mut BinaryHeap<NodeCandidate<D>>,
+    ) {
+        if k == 0 || self.tree.is_empty() {
+            return;
+        }
+
+        let query_point_axis_values = axis_values(&query_point);
+
+        queue.push(NodeCandidate {
+            lower_bound: 0.0,
+            depth: 0,
+            tree_index: 0,
+            offsets: [0.0; D],
+        });
+
+        while let Some(candidate) = queue.pop() {
+            let current_bound = if heap.len() == k {
+                heap.peek().map_or(f32::INFINITY, |n| n.distance_squared)
+            } else {
+                f32::INFINITY
+            };
+
+            /*
+                NOTE: Every node left in the queue is at least as far away as this one
+            */
+            if candidate.lower_bound > current_bound {
+                queue.clear();
+                break;
+            }
+
+            let NodeCandidate {
+                depth,
+                tree_index,
+                offsets,
+                ..
+            } = candidate;
+
+            let point_index = self.tree[tree_index].index();
+            let point = points[point_index];
+
+            let distance_squared = query_point.distance_squared(point);
+            if distance_squared <= current_bound {
+                heap.push(Neighbor {
+                    distance_squared,
+                    index: point_index,
+                });
+                if heap.len() > k {
+                    heap.pop();
+                }
+            }
+
+            let axis = self.tree[tree_index].axis();
+            let axis_d = self.tree[tree_index].split - query_point_axis_values[axis];
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            if let Some(child) = self.tree[tree_index].child(first) {
+                queue.push(NodeCandidate {
+                    depth: depth + 1,
+                    tree_index: child,
+                    ..candidate
+                });
+            }
+
+            if let Some(child) = self.tree[tree_index].child(last) {
+                /*
+                    NOTE: The far side is at least as far as the splitting plane along this
+                          axis. The bound is summed from scratch rather than updated
+                          incrementally so that it never rounds above a true distance.
+                */
+                let mut far_offsets = offsets;
+                far_offsets[axis] = far_offsets[axis].max(axis_d.abs());
+                let lower_bound = far_offsets.iter().map(|offset| offset * offset).sum();
+
+                queue.push(NodeCandidate {
+                    lower_bound,
+                    depth: depth + 1,
+                    tree_index: child,
+                    offsets: far_offsets,
+                });
+            }
+        }
+
+        pop_nearest_first(heap, result);
+    }
+
+    /// See `KdTree`
+    pub fn nearest_n_with_strategy(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        strategy: QueryStrategy,
+    ) -> Vec<usize> {
+        match strategy {
+            QueryStrategy::DepthFirst => self.nearest_n(points, query_point, k),
+            QueryStrategy::BestFirst => {
+                let mut result = Vec::with_capacity(k.min(self.tree.len()));
+                let mut heap = BinaryHeap::with_capacity(k.min(self.tree.len()) + 1);
+                let mut queue = BinaryHeap::new();
+
+                self.nearest_n_best_first_buffers(
+                    points,
+                    query_point,
+                    k,
+                    &mut result,
+                    &mut heap,
+                    &mut queue,
+                );
+
+                result
+            }
+        }
+    }
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn iter_nearest<'a>(
+        &'a self,
+        points: &'a [P],
+        query_point: P,
+    ) -> NearestIterator<'a, D, P, N> {
+        self.iter_nearest_within(points, query_point, f32::INFINITY)
+    }
+
+    /// See `KdTree`
+    pub fn iter_nearest_within<'a>(
+        &'a self,
+        points: &'a [P],
+        query_point: P,
+        radius: f32,
+    ) -> NearestIterator<'a, D, P, N> {
+        let mut queue = BinaryHeap::new();
+        if !self.tree.is_empty() {
+            queue.push(Pending::Node(NodeCandidate {
+                lower_bound: 0.0,
+                depth: 0,
+                tree_index: 0,
+                offsets: [0.0; D],
+            }));
+        }
+
+        NearestIterator {
+            tree: self,
+            points,
+            query_point,
+            query_point_axis_values: axis_values(&query_point),
+            radius_squared: radius * radius,
+            queue,
+        }
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Lazily yields every point of the tree as `(index, distance)`, nearest first. Points at
+    /// the same distance are ordered by index, so `iter_nearest(query_point).take(k)` gives the
+    /// same points as `nearest_n(query_point, k)`.
+    ///
+    /// The tree is traversed best-first, see `QueryStrategy::BestFirst`, and only as far as
+    /// needed for the points that are actually asked for. This makes it easy to find the
+    /// nearest points satisfying some condition without knowing how many to ask for up front:
+    ///
+    /// ```
+    /// # use keyde::KdTree;
+    /// let points = [[0.0, 0.0], [1.0, 0.0], [2.0, 0.0], [3.0, 0.0]];
+    /// let tree = KdTree::from_points(&points);
+    ///
+    /// let nearest_odd = tree.iter_nearest([2.9, 0.0]).find(|(index, _)| index % 2 == 1);
+    /// assert_eq!(nearest_odd.map(|(index, _)| index), Some(3));
+    /// ```
+    #[inline(always)]
+    pub fn iter_nearest(&self, query_point: P) -> NearestIterator<'_, D, P> {
+        self.internal.iter_nearest(self.points, query_point)
+    }
+
+    /// Lazily yields the points within `radius` of `query_point` as `(index, distance)`, nearest
+    /// first and ordered by index at equal distances. Finds the same points as
+    /// `point_indices_within`, which hands them out in the order of the traversal instead.
+    ///
+    /// Points are only measured once the traversal gets close to them, so stopping early, for
+    /// instance after the first few hits, skips most of the work of a full radius query. See
+    /// `iter_nearest`.
+    #[inline(always)]
+    pub fn iter_nearest_within(&self, query_point: P, radius: f32) -> NearestIterator<'_, D, P> {
+        self.internal
+            .iter_nearest_within(self.points, query_point, radius)
+    }
+
+    /// Same as `nearest_n_with_strategy` with `QueryStrategy::BestFirst`, but you provide your
+    /// own buffers. Indices are appended to `result` which is not cleared. `heap` and `queue`
+    /// are assumed to be empty and are left empty.
+    #[inline(always)]
+    pub fn nearest_n_best_first_buffers(
+        &self,
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        queue: &mut BinaryHeap<NodeCandidate<D>>,
+    ) {
+        self.internal
+            .nearest_n_best_first_buffers(self.points, query_point, k, result, heap, queue)
+    }
+
+    /// Same as `nearest_n`, visiting the nodes in the order given by `strategy`. Both
+    /// strategies return the exact same result.
+    ///
+    /// With `QueryStrategy::BestFirst`, nodes are visited in order of a lower bound of their
+    /// distance to the query point, which is the distance to the region of space the subtree
+    /// covers. The query stops as soon as the closest pending node is further away than the
+    /// current `k`th candidate.
+    #[inline(always)]
+    pub fn nearest_n_with_strategy(
+        &self,
+        query_point: P,
+        k: usize,
+        strategy: QueryStrategy,
+    ) -> Vec<usize> {
+        self.internal
+            .nearest_n_with_strategy(self.points, query_point, k, strategy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_best_first_matches_depth_first() {
+        let mut rng = SplitMix64::new(13);
+        let points = (0..3000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        for (query, k) in [
+            ([50.0, 50.0, 50.0], 10),
+            ([-20.0, 120.0, 3.0], 1),
+            ([20.5, 80.0, 45.0], 64),
+        ] {
+            assert_eq!(
+                tree.nearest_n_with_strategy(query, k, QueryStrategy::BestFirst),
+                tree.nearest_n(query, k)
+            );
+        }
+    }
+
+    #[test]
+    fn test_iter_nearest() {
+        let mut rng = SplitMix64::new(43);
+        let points = (0..2000)
+            .map(|_| [(rng.next_u64() % 50) as f32, (rng.next_u64() % 50) as f32])
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        for query in [[25.0, 25.0], [-3.0, 60.0], [10.5, 0.25]] {
+            let all = tree.iter_nearest(query).collect::<Vec<_>>();
+            assert_eq!(all.len(), points.len());
+
+            let indices = all.iter().map(|(index, _)| *index).collect::<Vec<_>>();
+            assert_eq!(indices, tree.nearest_n(query, points.len()));
+
+            for (index, distance) in &all {
+                assert_eq!(*distance, query.distance_squared(points[*index]).sqrt());
+            }
+
+            let within = tree.iter_nearest_within(query, 6.0).collect::<Vec<_>>();
+            let expected = all
+                .iter()
+                .copied()
+                .take_while(|(_, distance)| *distance <= 6.0)
+                .collect::<Vec<_>>();
+            assert_eq!(within, expected);
+            assert_eq!(within.len(), tree.point_indices_within(query, 6.0).len());
+        }
+    }
+}
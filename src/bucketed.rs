@@ -0,0 +1,381 @@
+//! A Kd-tree whose leaves hold buckets of points
+use crate::eytzinger::{Candidates, NearestN};
+use crate::nearest::{pop_nearest_first, Neighbor};
+use crate::utils::points_sorter;
+use crate::{Point, SortingStrategy, SplitStrategy};
+
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Internal node within the KdTreeBucketed
+pub enum KdTreeBucketedNode {
+    /// The points with a coordinate along `axis` up to `split` are under `children[0]` and
+    /// those from `split` on under `children[1]`. Points equal to `split` can be on either side.
+    Split {
+        axis: usize,
+        split: f32,
+        children: [usize; 2],
+    },
+    /// Holds the points `point_ids[start..end]`
+    Leaf { start: usize, end: usize },
+}
+
+/// A tree whose nodes link to their children, such as `KdTreeBucketed` and `KdTreeInline`,
+/// traversed by `for_each_within` and `collect_nearest`
+pub(crate) trait LinkedNodes<const D: usize, P: Point<D>> {
+    fn node_count(&self) -> usize;
+
+    /// Calls `point` with the index and the point of every point held by the node at
+    /// `tree_index`, and returns the axis, the split and the children of the node if it has any
+    fn visit(
+        &self,
+        tree_index: usize,
+        point: impl FnMut(usize, P),
+    ) -> Option<(usize, f32, [Option<usize>; 2])>;
+}
+
+impl<const D: usize, P: Point<D>> LinkedNodes<D, P> for (&KdTreeBucketed<D, P>, &[P]) {
+    #[inline(always)]
+    fn node_count(&self) -> usize {
+        self.0.nodes.len()
+    }
+
+    #[inline(always)]
+    fn visit(
+        &self,
+        tree_index: usize,
+        mut point: impl FnMut(usize, P),
+    ) -> Option<(usize, f32, [Option<usize>; 2])> {
+        let (tree, points) = *self;
+        match tree.nodes[tree_index] {
+            KdTreeBucketedNode::Leaf { start, end } => {
+                for point_index in &tree.point_ids[start..end] {
+                    point(*point_index, points[*point_index]);
+                }
+                None
+            }
+            KdTreeBucketedNode::Split {
+                axis,
+                split,
+                children,
+            } => Some((axis, split, children.map(Some))),
+        }
+    }
+}
+
+/// Calls `found` with every point of `tree` whose squared distance to `query_point` is at most
+/// `radius_squared`. `stack` is assumed to be empty and is left empty.
+pub(crate) fn for_each_within<const D: usize, P: Point<D>>(
+    tree: &impl LinkedNodes<D, P>,
+    query_point: P,
+    radius_squared: f32,
+    stack: &mut Vec<usize>,
+    mut found: impl FnMut(usize),
+) {
+    if tree.node_count() == 0 {
+        return;
+    }
+
+    stack.push(0);
+    while let Some(tree_index) = stack.pop() {
+        let split = tree.visit(tree_index, |point_index, point| {
+            if query_point.distance_squared(point) <= radius_squared {
+                found(point_index);
+            }
+        });
+
+        if let Some((axis, split, children)) = split {
+            let axis_d = split - query_point.get_axis(axis);
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            if axis_d * axis_d <= radius_squared {
+                if let Some(child) = children[last] {
+                    stack.push(child);
+                }
+            }
+            if let Some(child) = children[first] {
+                stack.push(child);
+            }
+        }
+    }
+}
+
+/// Offers the points of `tree` to `candidates`, visiting the side of the query point first and
+/// skipping subtrees beyond `Candidates::bound`. `stack` is assumed to be empty and is left
+/// empty.
+pub(crate) fn collect_nearest<const D: usize, P: Point<D>>(
+    tree: &impl LinkedNodes<D, P>,
+    query_point: P,
+    candidates: &mut impl Candidates<f32>,
+    stack: &mut Vec<(usize, f32)>,
+) {
+    if tree.node_count() == 0 {
+        return;
+    }
+
+    stack.push((0, 0.0));
+    while let Some((tree_index, lower_bound)) = stack.pop() {
+        if lower_bound > candidates.bound() {
+            continue;
+        }
+
+        let split = tree.visit(tree_index, |index, point| {
+            let distance_squared = query_point.distance_squared(point);
+            if distance_squared <= candidates.bound() {
+                candidates.offer(Neighbor {
+                    distance_squared,
+                    index,
+                });
+            }
+        });
+
+        if let Some((axis, split, children)) = split {
+            let axis_d = split - query_point.get_axis(axis);
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            /*
+                NOTE: The far side is pushed first so that the near side is popped first
+            */
+            if let Some(child) = children[last] {
+                stack.push((child, lower_bound.max(axis_d * axis_d)));
+            }
+            if let Some(child) = children[first] {
+                stack.push((child, lower_bound));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A Kd-tree that splits until at most `leaf_size` points are left and stores those in a leaf
+/// instead of giving every point a node of its own. Leaves are scanned linearly, which beats
+/// descending the last few levels once a handful of points fit in a cache line or two. Leaf
+/// sizes around 8 to 32 usually query fastest.
+///
+/// Build it with `KdTreeBuilder::leaf_size` and `KdTreeBuilder::build_bucketed` to choose the
+/// sorting and split strategies. The points are passed to every query.
+pub struct KdTreeBucketed<const D: usize, P: Point<D>> {
+    pub nodes: Vec<KdTreeBucketedNode>,
+    /// The indices of the points, every leaf owning a contiguous range
+    pub point_ids: Vec<usize>,
+    pub leaf_size: usize,
+    pub __marker: std::marker::PhantomData<P>,
+}
+
+impl<const D: usize, P: Point<D>> KdTreeBucketed<D, P> {
+    /// Builds a tree of `points` with at most `leaf_size` points per leaf using default settings
+    pub fn from_points(points: &[P], leaf_size: usize) -> Self {
+        Self::from_point_ids_with_points_sorter_and_split(
+            points,
+            (0..points.len()).collect(),
+            leaf_size,
+            points_sorter(&SortingStrategy::default()),
+            &SplitStrategy::default(),
+        )
+    }
+
+    /// Builds a tree of the points in `point_ids`, sorting with `points_sorter` and splitting by
+    /// `split_strategy`
+    pub(crate) fn from_point_ids_with_points_sorter_and_split<F>(
+        points: &[P],
+        mut point_ids: Vec<usize>,
+        leaf_size: usize,
+        mut points_sorter: F,
+        split_strategy: &SplitStrategy,
+    ) -> Self
+    where
+        F: FnMut(&[P], &mut [usize], usize),
+    {
+        assert!(leaf_size > 0, "a leaf must hold at least one point");
+
+        let mut nodes = vec![];
+
+        /*
+            NOTE: Jobs are `(start, end, depth, parent and side)`. The left side is pushed last so
+                  that nodes are laid out depth first, left before right.
+        */
+        let mut jobs = vec![];
+        if !point_ids.is_empty() {
+            jobs.push((0, point_ids.len(), 0, None));
+        }
+
+        while let Some((start, end, depth, parent)) = jobs.pop() {
+            let tree_index = nodes.len();
+            if let Some((parent, side)) = parent {
+                if let KdTreeBucketedNode::Split { children, .. } = &mut nodes[parent] {
+                    children[side] = tree_index;
+                }
+            }
+
+            if end - start <= leaf_size {
+                nodes.push(KdTreeBucketedNode::Leaf { start, end });
+                continue;
+            }
+
+            let axis = split_strategy.split_axis(points, &point_ids[start..end], depth);
+            points_sorter(points, &mut point_ids[start..end], axis);
+
+            let pivot_index = (start + end) / 2;
+            nodes.push(KdTreeBucketedNode::Split {
+                axis,
+                split: points[point_ids[pivot_index]].get_axis(axis),
+                children: [0, 0],
+            });
+
+            jobs.push((pivot_index, end, depth + 1, Some((tree_index, 1))));
+            jobs.push((start, pivot_index, depth + 1, Some((tree_index, 0))));
+        }
+
+        Self {
+            nodes,
+            point_ids,
+            leaf_size,
+            __marker: std::marker::PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.point_ids.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.point_ids.is_empty()
+    }
+
+    /// Same as `point_indices_within`, but you provide your own buffers. Indices are appended to
+    /// `result` which is not cleared. `stack` is assumed to be empty and is left empty.
+    pub fn point_indices_within_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<usize>,
+    ) {
+        for_each_within(
+            &(self, points),
+            query_point,
+            radius * radius,
+            stack,
+            |index| result.push(index),
+        );
+    }
+
+    /// Returns the indices of the points within `radius` of `query_point`
+    pub fn point_indices_within(&self, points: &[P], query_point: P, radius: f32) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_buffers(points, query_point, radius, &mut result, &mut stack);
+
+        result
+    }
+
+    /// Same as `nearest_n`, but you provide your own buffers. Indices are appended to `result`
+    /// which is not cleared. `heap` and `stack` are assumed to be empty and are left empty.
+    pub fn nearest_n_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, f32)>,
+    ) {
+        if k == 0 {
+            return;
+        }
+
+        collect_nearest(
+            &(self, points),
+            query_point,
+            &mut NearestN { heap, k },
+            stack,
+        );
+        pop_nearest_first(heap, result);
+    }
+
+    /// Returns the indices of the `k` points closest to `query_point`, nearest first. Points at
+    /// the same distance are ordered by index.
+    pub fn nearest_n(&self, points: &[P], query_point: P, k: usize) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.len()) + 1);
+        let mut stack = vec![];
+
+        self.nearest_n_buffers(points, query_point, k, &mut result, &mut heap, &mut stack);
+
+        result
+    }
+
+    /// Returns the index of and distance to the point closest to `query_point`
+    pub fn nearest_one(&self, points: &[P], query_point: P) -> Option<(usize, f32)> {
+        let mut nearest = None;
+        collect_nearest(&(self, points), query_point, &mut nearest, &mut vec![]);
+
+        nearest.map(|n: Neighbor| (n.index, n.distance_squared.sqrt()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::KdTreeBuilder;
+
+    #[test]
+    fn test_bucketed() {
+        let points = fixture::points(97);
+
+        let builder = KdTreeBuilder::new()
+            .leaf_size(16)
+            .split_strategy(SplitStrategy::WidestAxis);
+        let trees = [
+            KdTreeBucketed::from_points(&points, 1),
+            KdTreeBucketed::from_points(&points, 8),
+            builder.build_bucketed(&points),
+        ];
+
+        assert_eq!(trees[0].nodes.len(), 2 * points.len() - 1);
+        for tree in &trees {
+            let mut ids = tree.point_ids.clone();
+            ids.sort_unstable();
+            assert_eq!(ids, (0..points.len()).collect::<Vec<_>>());
+            for node in &tree.nodes {
+                if let KdTreeBucketedNode::Leaf { start, end } = node {
+                    assert!(end - start <= tree.leaf_size);
+                }
+            }
+        }
+
+        for tree in trees {
+            fixture::check_queries(
+                &points,
+                |_| true,
+                |query_point, radius| tree.point_indices_within(&points, query_point, radius),
+                |query_point, k| tree.nearest_n(&points, query_point, k),
+                |query_point| tree.nearest_one(&points, query_point),
+            );
+        }
+
+        let few = &points[..10];
+        let tree = KdTreeBucketed::from_points(few, 64);
+        assert_eq!(
+            tree.nodes,
+            vec![KdTreeBucketedNode::Leaf { start: 0, end: 10 }]
+        );
+        assert_eq!(tree.nearest_n(few, [0.0; 3], 20).len(), 10);
+
+        let empty: [[f32; 2]; 0] = [];
+        let tree = KdTreeBucketed::from_points(&empty, 4);
+        assert!(tree
+            .point_indices_within(&empty, [0.0, 0.0], 1.0)
+            .is_empty());
+        assert_eq!(tree.nearest_one(&empty, [0.0, 0.0]), None);
+    }
+}
@@ -0,0 +1,220 @@
+//! Queries with a budget on the number of visited nodes, trading exactness for a bounded cost
+use crate::nearest::{pop_nearest_first, Neighbor};
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+use std::collections::BinaryHeap;
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_within_budgeted_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        max_visits: usize,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) -> bool {
+        self.for_each_point_within_budgeted_buffers(
+            points,
+            query_point,
+            radius,
+            max_visits,
+            |point_index, _| result.push(point_index),
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_budgeted(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        max_visits: usize,
+    ) -> (Vec<usize>, bool) {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        let complete = self.point_indices_within_budgeted_buffers(
+            points,
+            query_point,
+            radius,
+            max_visits,
+            &mut result,
+            &mut stack,
+        );
+
+        (result, complete)
+    }
+
+    /// See `KdTree`
+    #[allow(clippy::too_many_arguments)]
+    pub fn nearest_n_budgeted_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        max_visits: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) -> bool {
+        let complete = self.nearest_n_scaled_by_buffers(
+            points,
+            &axis_values(&query_point),
+            k,
+            f32::INFINITY,
+            1.0,
+            max_visits,
+            |_| true,
+            |point| query_point.distance_squared(*point),
+            heap,
+            stack,
+        );
+
+        pop_nearest_first(heap, result);
+
+        complete
+    }
+
+    /// See `KdTree`
+    pub fn nearest_n_budgeted(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        max_visits: usize,
+    ) -> (Vec<usize>, bool) {
+        let mut result = Vec::with_capacity(k.min(self.tree.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.tree.len()) + 1);
+        let mut stack = vec![];
+
+        let complete = self.nearest_n_budgeted_buffers(
+            points,
+            query_point,
+            k,
+            max_visits,
+            &mut result,
+            &mut heap,
+            &mut stack,
+        );
+
+        (result, complete)
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `point_indices_within_budgeted`, but you provide your own buffers. Indices are
+    /// appended to `result` which is not cleared. `stack` is assumed to be empty and is left
+    /// empty. Returns whether the query finished within the budget.
+    #[inline(always)]
+    pub fn point_indices_within_budgeted_buffers(
+        &self,
+        query_point: P,
+        radius: f32,
+        max_visits: usize,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) -> bool {
+        self.internal.point_indices_within_budgeted_buffers(
+            self.points,
+            query_point,
+            radius,
+            max_visits,
+            result,
+            stack,
+        )
+    }
+
+    /// Same as `point_indices_within`, but the traversal stops after visiting `max_visits`
+    /// nodes. Returns the indices found so far and whether the query finished, in which case
+    /// the result is exact. Otherwise it is a subset of the exact result, biased towards the
+    /// points on the query point's side of each split.
+    ///
+    /// Use this to put an upper bound on the cost of a single query, for instance to stay within
+    /// a frame budget when a pathological query would hit a huge cluster of points.
+    #[inline(always)]
+    pub fn point_indices_within_budgeted(
+        &self,
+        query_point: P,
+        radius: f32,
+        max_visits: usize,
+    ) -> (Vec<usize>, bool) {
+        self.internal
+            .point_indices_within_budgeted(self.points, query_point, radius, max_visits)
+    }
+
+    /// Same as `nearest_n_budgeted`, but you provide your own buffers, see `nearest_n_buffers`.
+    /// Returns whether the query finished within the budget.
+    #[inline(always)]
+    pub fn nearest_n_budgeted_buffers(
+        &self,
+        query_point: P,
+        k: usize,
+        max_visits: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) -> bool {
+        self.internal.nearest_n_budgeted_buffers(
+            self.points,
+            query_point,
+            k,
+            max_visits,
+            result,
+            heap,
+            stack,
+        )
+    }
+
+    /// Same as `nearest_n`, but the traversal stops after visiting `max_visits` nodes. Returns
+    /// the best candidates found so far, nearest first, and whether the query finished, in which
+    /// case the result is exact. The near side of every split is searched first, so the
+    /// candidates of an interrupted query are usually close to the true neighbors.
+    #[inline(always)]
+    pub fn nearest_n_budgeted(
+        &self,
+        query_point: P,
+        k: usize,
+        max_visits: usize,
+    ) -> (Vec<usize>, bool) {
+        self.internal
+            .nearest_n_budgeted(self.points, query_point, k, max_visits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budgeted_queries() {
+        let points: Vec<[f32; 2]> = (0..2000)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.37).sin() * 10.0, (i * 0.11).cos() * 10.0]
+            })
+            .collect();
+        let tree = KdTree::from_points(&points);
+        let query = [1.0, -2.0];
+
+        let (result, complete) = tree.point_indices_within_budgeted(query, 3.0, usize::MAX);
+        assert!(complete);
+        assert_eq!(result, tree.point_indices_within(query, 3.0));
+
+        let (partial, complete) = tree.point_indices_within_budgeted(query, 3.0, 20);
+        assert!(!complete);
+        assert!(partial.len() <= 20);
+        assert!(partial.iter().all(|i| result.contains(i)));
+
+        let (nearest, complete) = tree.nearest_n_budgeted(query, 5, usize::MAX);
+        assert!(complete);
+        assert_eq!(nearest, tree.nearest_n(query, 5));
+
+        let (nearest, complete) = tree.nearest_n_budgeted(query, 5, 10);
+        assert!(!complete);
+        assert_eq!(nearest.len(), 5);
+    }
+}
@@ -0,0 +1,774 @@
+//! A builder collecting all construction options of the trees in one place
+use crate::layout::nodes_per_page;
+use crate::reorder::{reorder_points_morton, Permutation};
+use crate::utils::{points_sorter, quick_sort_seeded};
+use crate::validation::{finite_point_ids, first_non_finite};
+use crate::{
+    CompactKdTree, KdTree, KdTreeBucketed, KdTreeError, KdTreeInline, KdTreeMoving, KdTreeNoBorrow,
+    KdTreeOwned, KdTreeRefit, KdTreeSpheres, NonFinitePolicy, Point, SortingStrategy,
+    SplitStrategy, TreeIndex,
+};
+
+#[derive(Debug, Clone)]
+/// Collects the configuration used to construct a tree. Prefer this over the
+/// `from_points_*_with_*` family of constructors once you need more than one option.
+/// The same options build the other trees made of `KdTreeNode`s too, such as `build_compact`
+/// which also picks the width of the stored indices, `build_inline`, `build_owned`,
+/// `build_refit`, `build_spheres` and `build_moving`, as well as `build_bucketed` whose leaves
+/// hold up to `leaf_size` points.
+///
+/// `KdTreeEytzinger`, `KdTreePrecise`, `KdTreeInteger` and `KdTreeFixed` always lay out a
+/// complete tree cycling through the axes, which none of the options change, so they are only
+/// built with their own `from_points`.
+///
+/// ```
+/// use keyde::{KdTreeBuilder, SortingStrategy};
+///
+/// let points = [[0.0_f32, 1.0], [2.0, 3.0], [4.0, 5.0]];
+/// let tree = KdTreeBuilder::new()
+///     .sorting_strategy(SortingStrategy::HeapSort)
+///     .threads(2)
+///     .build(&points);
+///
+/// assert_eq!(tree.point_indices_within([2.0, 3.0], 0.5), vec![1]);
+/// ```
+pub struct KdTreeBuilder {
+    pub sorting_strategy: SortingStrategy,
+    pub split_strategy: SplitStrategy,
+    pub presort: bool,
+    pub threads: usize,
+    pub seed: Option<u64>,
+    pub bounding_spheres: bool,
+    pub page_size: Option<usize>,
+    pub deterministic: bool,
+    pub non_finite_policy: Option<NonFinitePolicy>,
+    pub leaf_size: usize,
+    #[cfg(feature = "rayon")]
+    pub thread_pool: Option<std::sync::Arc<rayon::ThreadPool>>,
+}
+
+impl Default for KdTreeBuilder {
+    fn default() -> Self {
+        Self {
+            sorting_strategy: SortingStrategy::default(),
+            split_strategy: SplitStrategy::default(),
+            presort: true,
+            threads: 0,
+            seed: None,
+            bounding_spheres: false,
+            page_size: None,
+            deterministic: false,
+            non_finite_policy: None,
+            leaf_size: 1,
+            #[cfg(feature = "rayon")]
+            thread_pool: None,
+        }
+    }
+}
+
+impl KdTreeBuilder {
+    /// A builder using the default settings, same as what `KdTree::from_points` uses
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The sorting strategy used to find the median at each level
+    pub fn sorting_strategy(mut self, sorting_strategy: SortingStrategy) -> Self {
+        self.sorting_strategy = sorting_strategy;
+        self
+    }
+
+    /// How the axis of every split is chosen, see `SplitStrategy`
+    pub fn split_strategy(mut self, split_strategy: SplitStrategy) -> Self {
+        self.split_strategy = split_strategy;
+        self
+    }
+
+    /// Whether to use the pre-sort optimization, which is on by default. Only builds on a
+    /// single thread use it, parallel builds sort the points at every level.
+    pub fn presort(mut self, presort: bool) -> Self {
+        self.presort = presort;
+        self
+    }
+
+    /// The number of threads to build the tree with. `0` and `1` both mean building on the
+    /// calling thread only.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Seeds the random pivot selection of `SortingStrategy::QuickSort`, and has no effect with
+    /// any other sorting strategy. Without a seed the
+    /// last element of each range is used as pivot, which is quadratic on adversarial input.
+    /// Since ties are broken by index, the seed only changes the pivots and never the tree:
+    /// every seed builds the same nodes as any other for the same number of threads.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Builds the subtrees on the workers of `thread_pool` instead of spawning threads. The
+    /// number of subtrees follows the size of the pool and `threads` is ignored.
+    #[cfg(feature = "rayon")]
+    pub fn thread_pool(mut self, thread_pool: std::sync::Arc<rayon::ThreadPool>) -> Self {
+        self.thread_pool = Some(thread_pool);
+        self
+    }
+
+    /// Guarantees that the same points always produce a bit-identical tree, whatever the
+    /// platform, sorting strategy, seed or number of threads, so that queries also return the
+    /// same results in the same order. This is what lockstep simulations need.
+    ///
+    /// Points are always ordered with `f32::total_cmp` and ties broken by index, so every
+    /// sorting strategy produces the same tree. This option additionally builds on the calling
+    /// thread only, since parallel builds lay the nodes out differently.
+    /// Results are only reproducible as long as `Point::distance_squared` is computed the same way
+    /// everywhere, which holds for the implementations provided by keyde apart from SIMD types
+    /// such as glam's `Vec3A`. Compare `KdTreeNoBorrow::fingerprint` across machines to verify.
+    pub fn deterministic(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Orders the nodes so that subtrees are grouped into pages of `page_size` bytes after
+    /// building, see `KdTreeNoBorrow::relayout_paged`. 4096 matches the memory pages of most
+    /// platforms.
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Whether to compute a bounding sphere per subtree, see `KdTreeNoBorrow::compute_bounding_spheres`
+    pub fn bounding_spheres(mut self, bounding_spheres: bool) -> Self {
+        self.bounding_spheres = bounding_spheres;
+        self
+    }
+
+    /// Checks every coordinate before building and handles points with a NaN or infinite
+    /// coordinate according to `policy`, see `KdTree::from_points_validated`. By default the
+    /// points are not checked.
+    ///
+    /// With `NonFinitePolicy::Error`, `try_build` returns the error while `build` and the other
+    /// builds panic. With `NonFinitePolicy::Exclude`, a tree that leaves points out is built
+    /// on the calling thread without the pre-sort optimization.
+    pub fn non_finite_policy(mut self, policy: NonFinitePolicy) -> Self {
+        self.non_finite_policy = Some(policy);
+        self
+    }
+
+    /// The number of points a leaf of `build_bucketed` holds at most, 1 by default. The trees
+    /// of the other builds give every point a node of its own, so they fail with
+    /// `KdTreeError::Unsupported` for any other leaf size.
+    pub fn leaf_size(mut self, leaf_size: usize) -> Self {
+        self.leaf_size = leaf_size;
+        self
+    }
+
+    /// Builds a `KdTree` borrowing `points`
+    pub fn build<'a, const D: usize, P>(&self, points: &'a [P]) -> KdTree<'a, D, P>
+    where
+        P: Point<D> + Sync,
+    {
+        KdTree {
+            internal: self.build_no_borrow(points),
+            points,
+        }
+    }
+
+    /// Same as `build`, but returns `KdTreeError::NonFinite` instead of panicking when
+    /// `non_finite_policy` is `NonFinitePolicy::Error`
+    pub fn try_build<'a, const D: usize, P>(
+        &self,
+        points: &'a [P],
+    ) -> Result<KdTree<'a, D, P>, KdTreeError>
+    where
+        P: Point<D> + Sync,
+    {
+        Ok(KdTree {
+            internal: self.try_build_no_borrow(points)?,
+            points,
+        })
+    }
+
+    /// Builds a `KdTreeNoBorrow` of `points`
+    pub fn build_no_borrow<const D: usize, P>(&self, points: &[P]) -> KdTreeNoBorrow<D, P>
+    where
+        P: Point<D> + Sync,
+    {
+        self.try_build_no_borrow(points)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// See `try_build`
+    pub fn try_build_no_borrow<const D: usize, P>(
+        &self,
+        points: &[P],
+    ) -> Result<KdTreeNoBorrow<D, P>, KdTreeError>
+    where
+        P: Point<D> + Sync,
+    {
+        unsupported(&[("leaf_size", self.leaf_size != 1)])?;
+
+        let point_ids = self.validated_point_ids(points)?;
+
+        let mut tree = match (&self.sorting_strategy, self.seed) {
+            (SortingStrategy::QuickSort, Some(seed)) => self.build_with_points_sorter(
+                points,
+                point_ids,
+                move |points: &[P], indices: &mut [usize], axis: usize| {
+                    quick_sort_seeded(points, indices, axis, seed)
+                },
+            ),
+
+            (strategy, _) => {
+                self.build_with_points_sorter(points, point_ids, points_sorter(strategy))
+            }
+        };
+
+        tree.config.sorting_strategy = self.sorting_strategy.clone();
+        tree.config.seed = self.seed;
+
+        if self.bounding_spheres {
+            tree.compute_bounding_spheres(points);
+        }
+
+        if let Some(page_size) = self.page_size {
+            tree.relayout_paged(nodes_per_page(page_size));
+        }
+
+        Ok(tree)
+    }
+
+    /// Builds a `KdTreeBucketed` of `points` with at most `leaf_size` points per leaf. It is
+    /// built on the calling thread and is deterministic whatever the options, while skipping
+    /// the pre-sort optimization only makes building slower. Panics if the builder sets
+    /// `threads`, `thread_pool`, `bounding_spheres` or `page_size`, see `try_build_bucketed`.
+    pub fn build_bucketed<const D: usize, P>(&self, points: &[P]) -> KdTreeBucketed<D, P>
+    where
+        P: Point<D>,
+    {
+        self.try_build_bucketed(points)
+            .unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Same as `build_bucketed`, but returns `KdTreeError::Unsupported` for the options the
+    /// tree doesn't support and `KdTreeError::NonFinite` when `non_finite_policy` is
+    /// `NonFinitePolicy::Error`, instead of panicking
+    pub fn try_build_bucketed<const D: usize, P>(
+        &self,
+        points: &[P],
+    ) -> Result<KdTreeBucketed<D, P>, KdTreeError>
+    where
+        P: Point<D>,
+    {
+        unsupported(&[
+            ("threads", self.threads > 1),
+            #[cfg(feature = "rayon")]
+            ("thread_pool", self.thread_pool.is_some()),
+            ("bounding_spheres", self.bounding_spheres),
+            ("page_size", self.page_size.is_some()),
+        ])?;
+
+        let point_ids = self
+            .validated_point_ids(points)?
+            .unwrap_or_else(|| (0..points.len()).collect());
+
+        let tree = match (&self.sorting_strategy, self.seed) {
+            (SortingStrategy::QuickSort, Some(seed)) => {
+                KdTreeBucketed::from_point_ids_with_points_sorter_and_split(
+                    points,
+                    point_ids,
+                    self.leaf_size,
+                    |points: &[P], indices: &mut [usize], axis: usize| {
+                        quick_sort_seeded(points, indices, axis, seed)
+                    },
+                    &self.split_strategy,
+                )
+            }
+
+            (strategy, _) => KdTreeBucketed::from_point_ids_with_points_sorter_and_split(
+                points,
+                point_ids,
+                self.leaf_size,
+                points_sorter(strategy),
+                &self.split_strategy,
+            ),
+        };
+
+        Ok(tree)
+    }
+
+    /// Builds a `CompactKdTree` of `points` whose indices are stored as `I`, the smallest type
+    /// that fits the number of points being the most compact. Panics if they don't fit or if
+    /// `build` would.
+    pub fn build_compact<const D: usize, P, I>(&self, points: &[P]) -> CompactKdTree<D, P, I>
+    where
+        P: Point<D> + Sync,
+        I: TreeIndex,
+    {
+        CompactKdTree::from_tree(&self.build_no_borrow(points))
+    }
+
+    /// Builds a `KdTreeOwned` owning `points`. Panics if `build` would.
+    pub fn build_owned<const D: usize, P>(&self, points: Vec<P>) -> KdTreeOwned<D, P>
+    where
+        P: Point<D> + Sync,
+    {
+        let values = vec![(); points.len()];
+        self.build_owned_with_values(points, values)
+    }
+
+    /// Builds a `KdTreeOwned` owning `points` and a value per point
+    pub fn build_owned_with_values<const D: usize, P, V>(
+        &self,
+        points: Vec<P>,
+        values: Vec<V>,
+    ) -> KdTreeOwned<D, P, V>
+    where
+        P: Point<D> + Sync,
+    {
+        assert_eq!(
+            points.len(),
+            values.len(),
+            "one value per point is required"
+        );
+
+        KdTreeOwned {
+            internal: self.build_no_borrow(&points),
+            removed: vec![false; points.len()],
+            removed_count: 0,
+            points,
+            values,
+        }
+    }
+
+    /// Builds a `KdTreeInline` of copies of `points`. Panics if `build` would.
+    pub fn build_inline<const D: usize, P>(&self, points: &[P]) -> KdTreeInline<D, P>
+    where
+        P: Point<D> + Sync,
+    {
+        KdTreeInline::from_tree(&self.build_no_borrow(points), points)
+    }
+
+    /// Builds a `KdTreeRefit` of `points`. Panics if `build` would.
+    pub fn build_refit<const D: usize, P>(&self, points: &[P]) -> KdTreeRefit<D, P>
+    where
+        P: Point<D> + Sync,
+    {
+        KdTreeRefit::from_tree(self.build_no_borrow(points), points)
+    }
+
+    /// Builds a `KdTreeSpheres` of the spheres centered at `points` with `radii`. Panics if
+    /// `build` would.
+    pub fn build_spheres<const D: usize, P>(
+        &self,
+        points: Vec<P>,
+        radii: Vec<f32>,
+    ) -> KdTreeSpheres<D, P>
+    where
+        P: Point<D> + Sync,
+    {
+        KdTreeSpheres::from_tree(self.build_no_borrow(&points), points, radii)
+    }
+
+    /// Builds a `KdTreeMoving` of `points` at time zero moving by `velocities`. Panics if
+    /// `build` would.
+    pub fn build_moving<'a, const D: usize, P>(
+        &self,
+        points: &'a [P],
+        velocities: &'a [[f32; D]],
+    ) -> KdTreeMoving<'a, D, P>
+    where
+        P: Point<D> + Sync,
+    {
+        KdTreeMoving::from_tree(self.build_no_borrow(points), points, velocities)
+    }
+
+    /// Reorders `points` along the Morton curve using `reorder_points_morton` and builds a
+    /// `KdTreeNoBorrow` of the reordered points. Returns the reordered points, the permutation
+    /// mapping their indices back to the indices in `points`, and the tree.
+    ///
+    /// The indices returned by queries on the tree refer to the reordered points.
+    pub fn build_reordered_morton<const D: usize, P>(
+        &self,
+        points: &[P],
+    ) -> (Vec<P>, Permutation, KdTreeNoBorrow<D, P>)
+    where
+        P: Point<D> + Sync,
+    {
+        let (reordered, permutation) = reorder_points_morton(points);
+        let tree = self.build_no_borrow(&reordered);
+
+        (reordered, permutation, tree)
+    }
+
+    /// The points left to build a tree of after applying `non_finite_policy`, or `None` if that
+    /// is all of them
+    fn validated_point_ids<const D: usize, P>(
+        &self,
+        points: &[P],
+    ) -> Result<Option<Vec<usize>>, KdTreeError>
+    where
+        P: Point<D>,
+    {
+        match self.non_finite_policy {
+            None => Ok(None),
+            Some(NonFinitePolicy::Error) => match first_non_finite(points) {
+                Some((index, axis)) => Err(KdTreeError::NonFinite { index, axis }),
+                None => Ok(None),
+            },
+            Some(NonFinitePolicy::Exclude) => {
+                Ok(Some(finite_point_ids(points)).filter(|ids| ids.len() < points.len()))
+            }
+        }
+    }
+
+    /// Builds a tree of the points in `point_ids`, or of all points if there are none
+    fn build_with_points_sorter<const D: usize, P, F>(
+        &self,
+        points: &[P],
+        point_ids: Option<Vec<usize>>,
+        points_sorter: F,
+    ) -> KdTreeNoBorrow<D, P>
+    where
+        P: Point<D> + Sync,
+        F: Fn(&[P], &mut [usize], usize) + Sync,
+    {
+        let split_strategy = &self.split_strategy;
+
+        if let Some(point_ids) = point_ids {
+            return KdTreeNoBorrow::from_point_ids_with_points_sorter_and_split(
+                points,
+                point_ids,
+                points_sorter,
+                split_strategy,
+            );
+        }
+
+        /*
+            NOTE: The sorter of `MedianSelect` doesn't fully sort, which presorting relies on
+        */
+        let presort =
+            self.presort && !matches!(self.sorting_strategy, SortingStrategy::MedianSelect);
+
+        if self.deterministic && presort {
+            return KdTreeNoBorrow::from_points_presort_with_points_sorter_and_split(
+                points,
+                points_sorter,
+                split_strategy,
+            );
+        }
+        if self.deterministic {
+            return KdTreeNoBorrow::from_points_with_points_sorter_and_split(
+                points,
+                points_sorter,
+                split_strategy,
+            );
+        }
+
+        #[cfg(feature = "rayon")]
+        if let Some(thread_pool) = &self.thread_pool {
+            return KdTreeNoBorrow::from_points_thread_pool_with_points_sorter_and_split(
+                points,
+                points_sorter,
+                split_strategy,
+                thread_pool,
+            );
+        }
+
+        if presort && self.threads <= 1 {
+            KdTreeNoBorrow::from_points_presort_with_points_sorter_and_split(
+                points,
+                points_sorter,
+                split_strategy,
+            )
+        } else {
+            KdTreeNoBorrow::from_points_parallel_with_points_sorter_and_split(
+                points,
+                points_sorter,
+                split_strategy,
+                self.threads,
+            )
+        }
+    }
+}
+
+/// Fails with `KdTreeError::Unsupported` naming the first of `options` that is set
+fn unsupported(options: &[(&'static str, bool)]) -> Result<(), KdTreeError> {
+    match options.iter().find(|(_, set)| *set) {
+        Some((option, _)) => Err(KdTreeError::Unsupported { option }),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parallel_build_matches_serial() {
+        let points: Vec<[f32; 3]> = (0..1000)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.37).sin() * 10.0, (i * 0.11).cos() * 5.0, i * 0.01]
+            })
+            .collect();
+
+        let serial = KdTreeBuilder::new().build(&points);
+
+        for threads in [2, 3, 4, 7, 16] {
+            let parallel = KdTreeBuilder::new().threads(threads).build(&points);
+            assert_eq!(parallel.internal.tree.len(), points.len());
+
+            let mut expected = serial.point_indices_within([1.0, 0.0, 5.0], 4.0);
+            let mut result = parallel.point_indices_within([1.0, 0.0, 5.0], 4.0);
+            expected.sort();
+            result.sort();
+            assert_eq!(expected, result);
+        }
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_thread_pool_build_matches_serial() {
+        let points: Vec<[f32; 2]> = (0..1000)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.37).sin() * 10.0, (i * 0.11).cos() * 5.0]
+            })
+            .collect();
+
+        let serial = KdTreeBuilder::new().build(&points);
+
+        for threads in [1, 3, 4] {
+            let thread_pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let pooled = KdTreeBuilder::new()
+                .thread_pool(std::sync::Arc::new(thread_pool))
+                .build(&points);
+            assert_eq!(pooled.internal.tree.len(), points.len());
+
+            let mut expected = serial.point_indices_within([1.0, 0.0], 3.0);
+            let mut result = pooled.point_indices_within([1.0, 0.0], 3.0);
+            expected.sort();
+            result.sort();
+            assert_eq!(expected, result);
+        }
+    }
+
+    #[test]
+    fn test_deterministic_builds_are_identical() {
+        let points: Vec<[f32; 3]> = (0..2000)
+            .map(|i| {
+                [
+                    (i % 7) as f32,
+                    (i % 13) as f32 * 0.5,
+                    ((i * 31) % 101) as f32,
+                ]
+            })
+            .collect();
+
+        let reference = KdTreeBuilder::new().deterministic(true).build(&points);
+        let fingerprint = reference.internal.fingerprint();
+
+        for strategy in [
+            SortingStrategy::StableSort,
+            SortingStrategy::UnstableSort,
+            SortingStrategy::ShellSort,
+            SortingStrategy::HeapSort,
+            SortingStrategy::QuickSort,
+            SortingStrategy::MedianSelect,
+        ] {
+            for (threads, seed) in [(1, 0), (4, 1), (7, 99)] {
+                let tree = KdTreeBuilder::new()
+                    .deterministic(true)
+                    .sorting_strategy(strategy.clone())
+                    .threads(threads)
+                    .seed(seed)
+                    .build(&points);
+
+                assert_eq!(tree.internal.fingerprint(), fingerprint);
+                assert_eq!(
+                    tree.point_indices_within([3.0, 2.0, 50.0], 10.0),
+                    reference.point_indices_within([3.0, 2.0, 50.0], 10.0)
+                );
+            }
+        }
+
+        let parallel = KdTreeBuilder::new().threads(4).build(&points);
+        assert_ne!(parallel.internal.fingerprint(), fingerprint);
+    }
+
+    #[test]
+    fn test_build_reordered_morton() {
+        let points: Vec<[f32; 2]> = (0..100)
+            .map(|i| [(i % 10) as f32, (i / 10) as f32])
+            .collect();
+        let (reordered, permutation, tree) = KdTreeBuilder::new().build_reordered_morton(&points);
+
+        let mut result =
+            tree.point_indices_within_original(&reordered, [4.0, 4.0], 1.0, &permutation);
+        result.sort();
+        assert_eq!(result, vec![34, 43, 44, 45, 54]);
+    }
+
+    #[test]
+    fn test_non_finite_policy() {
+        let points = (0..500)
+            .map(|i| match i {
+                17 => [1.0, f32::NAN],
+                400 => [f32::NEG_INFINITY, 2.0],
+                _ => [(i % 25) as f32, (i / 25) as f32],
+            })
+            .collect::<Vec<[f32; 2]>>();
+
+        assert_eq!(
+            KdTreeBuilder::new()
+                .non_finite_policy(NonFinitePolicy::Error)
+                .try_build(&points)
+                .unwrap_err(),
+            KdTreeError::NonFinite { index: 17, axis: 1 }
+        );
+        assert!(KdTreeBuilder::new()
+            .non_finite_policy(NonFinitePolicy::Error)
+            .try_build(&points[18..400])
+            .is_ok());
+
+        for builder in [
+            KdTreeBuilder::new(),
+            KdTreeBuilder::new().threads(4),
+            KdTreeBuilder::new()
+                .sorting_strategy(SortingStrategy::QuickSort)
+                .seed(3)
+                .split_strategy(SplitStrategy::WidestAxis)
+                .bounding_spheres(true),
+        ] {
+            let tree = builder
+                .non_finite_policy(NonFinitePolicy::Exclude)
+                .build(&points);
+            assert_eq!(tree.internal.tree.len(), 498);
+            assert!(tree.internal.subset);
+
+            let mut result = tree.point_indices_within([0.0, 0.0], 1000.0);
+            result.sort();
+            let expected = (0..500)
+                .filter(|i| *i != 17 && *i != 400)
+                .collect::<Vec<_>>();
+            assert_eq!(result, expected);
+            assert_eq!(tree.nearest_n([1.0, 0.0], 1), vec![1]);
+        }
+    }
+
+    #[test]
+    fn test_seeded_build_is_reproducible() {
+        let points: Vec<[f32; 2]> = (0..300)
+            .map(|i| [(i % 17) as f32, ((i * 7) % 23) as f32])
+            .collect();
+
+        for threads in [1, 4] {
+            let build = |seed| {
+                KdTreeBuilder::new()
+                    .seed(seed)
+                    .threads(threads)
+                    .build_no_borrow(&points)
+            };
+
+            let a = build(42);
+            let b = build(42);
+            assert_eq!(a.tree, b.tree);
+            assert_eq!(a.fingerprint(), b.fingerprint());
+
+            /*
+                NOTE: Ties are broken by index, so the seed only changes which pivots are taken
+                      and never the order the points are sorted into
+            */
+            assert_eq!(build(43).fingerprint(), a.fingerprint());
+        }
+
+        let tree = KdTreeBuilder::new().seed(42).build(&points);
+        let mut result = tree.point_indices_within([8.0, 11.0], 1.0);
+        result.sort();
+        let expected = (0..points.len())
+            .filter(|i| points[*i].distance_squared([8.0, 11.0]) <= 1.0)
+            .collect::<Vec<_>>();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_unsupported_options() {
+        let points = [[0.0_f32, 1.0], [2.0, 3.0], [4.0, 5.0]];
+
+        let builder = KdTreeBuilder::new().leaf_size(8);
+        assert_eq!(
+            builder.try_build(&points).unwrap_err(),
+            KdTreeError::Unsupported {
+                option: "leaf_size"
+            }
+        );
+        assert_eq!(builder.build_bucketed(&points).len(), 3);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            builder.build_compact::<2, _, u16>(&points)
+        }));
+        assert!(result.is_err());
+
+        for (option, builder) in [
+            ("threads", KdTreeBuilder::new().threads(4)),
+            (
+                "bounding_spheres",
+                KdTreeBuilder::new().bounding_spheres(true),
+            ),
+            ("page_size", KdTreeBuilder::new().page_size(4096)),
+        ] {
+            assert_eq!(
+                builder.try_build_bucketed(&points).unwrap_err(),
+                KdTreeError::Unsupported { option }
+            );
+            assert_eq!(builder.build(&points).internal.tree.len(), 3);
+        }
+
+        let builder = KdTreeBuilder::new().presort(false).deterministic(true);
+        assert_eq!(builder.try_build_bucketed(&points).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_build_variants() {
+        let mut rng = crate::utils::SplitMix64::new(113);
+        let points = (0..500)
+            .map(|_| [(rng.next_u64() % 100) as f32, (rng.next_u64() % 100) as f32])
+            .collect::<Vec<_>>();
+        let velocities = vec![[0.0; 2]; points.len()];
+        let radii = vec![0.0; points.len()];
+
+        let builder = KdTreeBuilder::new()
+            .split_strategy(SplitStrategy::WidestAxis)
+            .bounding_spheres(true)
+            .page_size(256);
+        let tree = builder.build(&points);
+
+        let inline = builder.build_inline(&points);
+        let refit = builder.build_refit(&points);
+        let spheres = builder.build_spheres(points.clone(), radii);
+        let moving = builder.build_moving(&points, &velocities);
+        assert_eq!(refit.internal.fingerprint(), tree.internal.fingerprint());
+        assert_eq!(spheres.internal.fingerprint(), tree.internal.fingerprint());
+        assert_eq!(moving.internal.fingerprint(), tree.internal.fingerprint());
+
+        for query_point in [[50.0, 50.0], [3.0, 97.5]] {
+            let mut expected = tree.point_indices_within(query_point, 9.0);
+            expected.sort_unstable();
+
+            for mut result in [
+                inline.point_indices_within(query_point, 9.0),
+                refit.point_indices_within(&points, query_point, 9.0),
+                spheres.spheres_intersecting_sphere(query_point, 9.0),
+                moving.within_at(query_point, 9.0, 1.0),
+            ] {
+                result.sort_unstable();
+                assert_eq!(result, expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! A Kd-tree with small integer indices, for trees where memory matters
+use crate::{KdTreeNoBorrow, KdTreeNode, Point};
+
+/// An unsigned integer type the indices of a `CompactKdTree` are stored as
+pub trait TreeIndex: Copy + Eq + std::fmt::Debug {
+    /// Stands in for a missing child. A tree can hold at most this many points.
+    const NONE: Self;
+
+    /// Panics if `index` does not fit, or is `NONE`, so that no index aliases another or a
+    /// missing child
+    fn from_usize(index: usize) -> Self;
+    fn to_usize(self) -> usize;
+}
+
+macro_rules! impl_tree_index {
+    ($($t:ty),*) => {
+        $(
+            impl TreeIndex for $t {
+                const NONE: Self = <$t>::MAX;
+
+                #[inline(always)]
+                fn from_usize(index: usize) -> Self {
+                    match <$t>::try_from(index) {
+                        Ok(index) if index != Self::NONE => index,
+                        _ => panic!(
+                            "index {} does not fit in a {} tree index",
+                            index,
+                            stringify!($t)
+                        ),
+                    }
+                }
+
+                #[inline(always)]
+                fn to_usize(self) -> usize {
+                    self as usize
+                }
+            }
+        )*
+    };
+}
+
+impl_tree_index!(u8, u16, u32, u64, usize);
+
+/// A Kd-tree storing the indices of its nodes as `I`. With `u32` the nodes take about half the
+/// memory of the nodes of `KdTreeNoBorrow`, and `u16` shrinks them further for trees of at most
+/// `u16::MAX` points, as on embedded targets. Pick the smallest type that fits the number of
+/// points. It is a `KdTreeNoBorrow`, so every query of it is available, and the points are
+/// passed to every query and must not have been modified since building.
+pub type CompactKdTree<const D: usize, P, I = u32> = KdTreeNoBorrow<D, P, Vec<KdTreeNode<I>>>;
+
+impl<const D: usize, P: Point<D>, I: TreeIndex> CompactKdTree<D, P, I> {
+    /// Copies the structure of `tree`. `KdTreeBuilder::build_compact` builds with any settings.
+    ///
+    /// Panics if a node, point index or axis of the tree is `I::NONE` or more.
+    pub fn from_tree(tree: &KdTreeNoBorrow<D, P>) -> Self {
+        /*
+            NOTE: The point indices of a `subset` tree can go past its number of nodes
+        */
+        let largest_index = tree
+            .tree
+            .iter()
+            .map(|node| node.index())
+            .fold(tree.tree.len().max(D).saturating_sub(1), usize::max);
+        assert!(
+            largest_index < I::NONE.to_usize(),
+            "too many points for the index type of the CompactKdTree"
+        );
+
+        let nodes = tree
+            .tree
+            .iter()
+            .map(|node| {
+                KdTreeNode::new(
+                    node.parent(),
+                    node.index(),
+                    node.axis(),
+                    node.split,
+                    node.children(),
+                )
+            })
+            .collect();
+
+        Self {
+            tree: nodes,
+            bounds_min: tree.bounds_min,
+            bounds_max: tree.bounds_max,
+            bounding_spheres: tree.bounding_spheres.clone(),
+            subset: tree.subset,
+            config: tree.config.clone(),
+            __marker: std::marker::PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::NonFinitePolicy;
+
+    fn check_queries<I: TreeIndex>(tree: &CompactKdTree<3, [f32; 3], I>, points: &[[f32; 3]]) {
+        fixture::check_queries(
+            points,
+            |_| true,
+            |query_point, radius| tree.point_indices_within(points, query_point, radius),
+            |query_point, k| tree.nearest_n(points, query_point, k),
+            |query_point| tree.nearest_one(points, query_point),
+        );
+    }
+
+    #[test]
+    fn test_compact() {
+        assert!(2 * std::mem::size_of::<KdTreeNode<u32>>() <= std::mem::size_of::<KdTreeNode>());
+        assert!(std::mem::size_of::<KdTreeNode<u16>>() < std::mem::size_of::<KdTreeNode<u32>>());
+
+        let points = fixture::points(71);
+
+        let tree = KdTreeNoBorrow::from_points(&points);
+        let compact: CompactKdTree<3, [f32; 3]> = CompactKdTree::from_tree(&tree);
+        check_queries(&compact, &points);
+        check_queries(&CompactKdTree::<3, _, u16>::from_tree(&tree), &points);
+        check_queries(&CompactKdTree::<3, _, usize>::from_tree(&tree), &points);
+        assert_eq!(compact.fingerprint(), tree.fingerprint());
+
+        let result = std::panic::catch_unwind(|| {
+            CompactKdTree::<3, _, u8>::from_tree(&tree);
+        });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_index_overflow() {
+        assert_eq!(u8::from_usize(254), 254);
+        assert_eq!(usize::from_usize(7), 7);
+        for index in [255, 256, 1000] {
+            assert!(std::panic::catch_unwind(|| u8::from_usize(index)).is_err());
+        }
+        assert!(std::panic::catch_unwind(|| u16::from_usize(1 << 16)).is_err());
+
+        /*
+            NOTE: 250 nodes fit in a u8, but the point indices of the excluded tree reach 259
+        */
+        let points = (0..260)
+            .map(|i| [if i < 10 { f32::NAN } else { i as f32 }, 0.0, 0.0])
+            .collect::<Vec<_>>();
+        let tree =
+            KdTreeNoBorrow::from_points_validated(&points, NonFinitePolicy::Exclude).unwrap();
+        assert_eq!(tree.tree.len(), 250);
+        let result = std::panic::catch_unwind(|| {
+            CompactKdTree::<3, _, u8>::from_tree(&tree);
+        });
+        assert!(result.is_err());
+
+        let compact = CompactKdTree::<3, _, u16>::from_tree(&tree);
+        assert_eq!(
+            compact.nearest_one(&points, [259.0, 0.0, 0.0]),
+            Some((259, 0.0))
+        );
+    }
+}
@@ -0,0 +1,213 @@
+//! Queries with cones, for field of view checks
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4};
+
+/// The smallest sphere `(center, radius)` containing the part of the cone at `apex` along the
+/// normalized `direction` within `max_range` of the apex
+fn cone_bounding_sphere<const D: usize>(
+    apex: &[f32; D],
+    direction: &[f32; D],
+    half_angle: f32,
+    max_range: f32,
+) -> ([f32; D], f32) {
+    /*
+        NOTE: Narrow cones are bounded by the sphere through the apex and the rim, wider ones by
+              the sphere around the rim and anything wider than a half-space by the sphere
+              around the apex
+    */
+    let (along, radius) = if half_angle <= FRAC_PI_4 {
+        let along = max_range / (2.0 * half_angle.cos());
+        (along, along)
+    } else if half_angle < FRAC_PI_2 {
+        (max_range * half_angle.cos(), max_range * half_angle.sin())
+    } else {
+        (0.0, max_range)
+    };
+
+    let center = std::array::from_fn(|axis| apex[axis] + direction[axis] * along);
+    (center, radius)
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    #[allow(clippy::too_many_arguments)]
+    pub fn point_indices_in_cone_buffers(
+        &self,
+        points: &[P],
+        apex: P,
+        direction: [f32; D],
+        half_angle: f32,
+        max_range: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        let length = direction.iter().map(|d| d * d).sum::<f32>().sqrt();
+        if self.tree.is_empty() || length == 0.0 {
+            return;
+        }
+
+        let apex = axis_values(&apex);
+        let direction = direction.map(|d| d / length);
+        let (center, radius) = cone_bounding_sphere(&apex, &direction, half_angle, max_range);
+        let radius_squared = radius * radius;
+
+        let max_range_squared = max_range * max_range;
+        let cos_half_angle = half_angle.cos();
+
+        stack.push((0, 0));
+        while let Some((depth, tree_index)) = stack.pop() {
+            if self.sphere_out_of_reach(tree_index, &center, radius) {
+                continue;
+            }
+
+            let point_index = self.tree[tree_index].index();
+            let point = axis_values(&points[point_index]);
+
+            let mut distance_squared = 0.0;
+            let mut along = 0.0;
+            for axis in 0..D {
+                let delta = point[axis] - apex[axis];
+                distance_squared += delta * delta;
+                along += delta * direction[axis];
+            }
+
+            if distance_squared <= max_range_squared
+                && along >= distance_squared.sqrt() * cos_half_angle
+            {
+                result.push(point_index);
+            }
+
+            let axis = self.tree[tree_index].axis();
+            let axis_d = self.tree[tree_index].split - center[axis];
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            let children = self.tree[tree_index].children();
+            if axis_d * axis_d <= radius_squared {
+                if let Some(child) = children[last] {
+                    stack.push((depth + 1, child));
+                }
+            }
+            if let Some(child) = children[first] {
+                stack.push((depth + 1, child));
+            }
+        }
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_in_cone(
+        &self,
+        points: &[P],
+        apex: P,
+        direction: [f32; D],
+        half_angle: f32,
+        max_range: f32,
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_in_cone_buffers(
+            points,
+            apex,
+            direction,
+            half_angle,
+            max_range,
+            &mut result,
+            &mut stack,
+        );
+
+        result
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `point_indices_in_cone`, but you provide your own buffers. Indices are appended
+    /// to `result` which is not cleared. `stack` is assumed to be empty and is left empty.
+    #[inline(always)]
+    pub fn point_indices_in_cone_buffers(
+        &self,
+        apex: P,
+        direction: [f32; D],
+        half_angle: f32,
+        max_range: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_in_cone_buffers(
+            self.points,
+            apex,
+            direction,
+            half_angle,
+            max_range,
+            result,
+            stack,
+        )
+    }
+
+    /// Returns the indices of the points within `max_range` of `apex` and at most `half_angle`
+    /// radians away from `direction` as seen from the apex, for instance what an agent looking
+    /// along `direction` can see. `direction` does not need to be normalized, and nothing is
+    /// found if it is zero. The apex itself counts as inside.
+    ///
+    /// Subtrees are pruned with the smallest sphere around the cone, so the points behind the
+    /// apex are never looked at unless the cone is wider than a half-space.
+    #[inline(always)]
+    pub fn point_indices_in_cone(
+        &self,
+        apex: P,
+        direction: [f32; D],
+        half_angle: f32,
+        max_range: f32,
+    ) -> Vec<usize> {
+        self.internal
+            .point_indices_in_cone(self.points, apex, direction, half_angle, max_range)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_point_indices_in_cone() {
+        let mut rng = SplitMix64::new(41);
+        let points = (0..3000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        let apex = [50.0, 40.0, 60.0];
+        for (direction, half_angle, max_range) in [
+            ([1.0_f32, 0.5, 0.0], 0.3_f32, 40.0),
+            ([0.0, 0.0, -2.0], 1.2, 30.0),
+            ([-1.0, 1.0, 1.0], 2.0, 25.0),
+        ] {
+            let length = direction.iter().map(|d: &f32| d * d).sum::<f32>().sqrt();
+            let expected = (0..points.len())
+                .filter(|i| {
+                    let delta: [f32; 3] = std::array::from_fn(|axis| points[*i][axis] - apex[axis]);
+                    let distance = delta.iter().map(|d| d * d).sum::<f32>().sqrt();
+                    let along = (0..3)
+                        .map(|axis| delta[axis] * direction[axis])
+                        .sum::<f32>();
+                    distance <= max_range && along / length >= distance * half_angle.cos()
+                })
+                .collect::<Vec<_>>();
+            assert!(!expected.is_empty());
+
+            let mut result = tree.point_indices_in_cone(apex, direction, half_angle, max_range);
+            result.sort_unstable();
+            assert_eq!(result, expected);
+        }
+    }
+}
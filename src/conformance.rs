@@ -0,0 +1,720 @@
+//! Checks that every way of building a tree answers queries identically. Each configuration
+//! of `KdTreeBuilder` is run through a workload of queries and compared against brute-force
+//! references, so downstream crates can assert in their own tests that whatever configuration
+//! they pick gives the same answers as any other.
+use crate::utils::SplitMix64;
+use crate::{
+    CompactKdTree, IntegerPoint, KdForest, KdTree, KdTreeBucketed, KdTreeBuilder, KdTreeEytzinger,
+    KdTreeFixed, KdTreeInline, KdTreeInteger, KdTreeMoving, KdTreeOwned, KdTreePrecise,
+    KdTreeRefit, KdTreeSpheres, Permutation, Point, SortingStrategy, SplitStrategy, TreeIndex,
+};
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// One way of building a tree that is checked by `check_configuration`
+#[derive(Debug, Clone)]
+pub struct Configuration {
+    pub name: String,
+    pub builder: KdTreeBuilder,
+    /// Build from the points reordered with `KdTreeBuilder::build_reordered_morton`
+    pub reorder_morton: bool,
+    /// Build another tree type instead of a `KdTree`
+    pub flat: Option<FlatLayout>,
+}
+
+/// The tree types other than `KdTree` a configuration can build, which are passed the points
+/// on every query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlatLayout {
+    /// A `KdTreeEytzinger`, ignoring `builder`. Since it has no farthest point queries, only
+    /// the other queries are checked.
+    Eytzinger,
+    /// A `CompactKdTree` of `u8` indices built with `KdTreeBuilder::build_compact`. Workloads
+    /// of more points than fit are skipped.
+    Compact8,
+    /// A `CompactKdTree` of `u16` indices built with `KdTreeBuilder::build_compact`
+    Compact16,
+    /// A `CompactKdTree` of `u32` indices built with `KdTreeBuilder::build_compact`
+    Compact32,
+    /// A `KdTreeInline` copied from the tree `builder` builds. It has no farthest point queries.
+    Inline,
+    /// A `KdTreePrecise`, ignoring `builder`. It has no farthest point queries.
+    Precise,
+    /// A `KdTreeInteger` of the points converted to `[i64; D]`, ignoring `builder`. Workloads
+    /// with coordinates or radii that aren't integers are skipped. It has no farthest point
+    /// queries.
+    Integer,
+    /// A `KdTreeFixed` of `FIXED_CAPACITY` points, ignoring `builder`. Workloads of more points
+    /// are skipped. It only has radius and single nearest point queries.
+    Fixed,
+    /// A `KdTreeBucketed` built with `KdTreeBuilder::build_bucketed`. It has no farthest point
+    /// queries.
+    Bucketed,
+    /// A `KdTreeRefit` built with `KdTreeBuilder::build_refit`. It has no farthest point
+    /// queries.
+    Refit,
+    /// A `KdTreeMoving` built with `KdTreeBuilder::build_moving` where every point moves, and
+    /// queried at time zero. It only has radius and single nearest point queries.
+    Moving,
+    /// A `KdTreeSpheres` of spheres of radius zero built with `KdTreeBuilder::build_spheres`.
+    /// The nearest point queries run on the centers through `KdTreeSpheres::internal`. It has
+    /// no farthest point queries.
+    Spheres,
+    /// A `KdTreeOwned` built with `KdTreeBuilder::build_owned`, from which the points for which
+    /// `removed_in_layout` holds are removed, half of them before a `KdTreeOwned::compact` and
+    /// half after. It has no farthest point queries.
+    Owned,
+    /// A `KdForest` using `builder` of three chunks, of which the middle one is spilled, and
+    /// from which the points for which `removed_in_layout` holds are removed. It has no
+    /// farthest point queries.
+    Forest,
+}
+
+/// Whether the point at `index` is removed from the trees of `FlatLayout::Owned` and
+/// `FlatLayout::Forest`, which must then be left out of their results
+pub fn removed_in_layout(layout: FlatLayout, index: usize) -> bool {
+    matches!(layout, FlatLayout::Owned | FlatLayout::Forest) && (index % 5 == 1 || index % 7 == 3)
+}
+
+/// The capacity of the `KdTreeFixed` of `FlatLayout::Fixed`
+pub const FIXED_CAPACITY: usize = 512;
+
+enum Flat<'a, const D: usize, P: Point<D>> {
+    Eytzinger(KdTreeEytzinger<D, P>),
+    Compact8(CompactKdTree<D, P, u8>),
+    Compact16(CompactKdTree<D, P, u16>),
+    Compact32(CompactKdTree<D, P, u32>),
+    Inline(KdTreeInline<D, P>),
+    Precise(KdTreePrecise<D, P>),
+    Integer(
+        KdTreeInteger<D, IntegerCoordinates<D>>,
+        Vec<IntegerCoordinates<D>>,
+    ),
+    Fixed(Box<KdTreeFixed<D, P, FIXED_CAPACITY>>),
+    Bucketed(KdTreeBucketed<D, P>),
+    Refit(KdTreeRefit<D, P>),
+    Moving(KdTreeMoving<'a, D, P>),
+    Spheres(KdTreeSpheres<D, P>),
+    /// The tree and the index every point had before compacting
+    Owned(KdTreeOwned<D, P>, Vec<usize>),
+    Forest(KdForest<D, P>, RefCell<HashMap<usize, Vec<P>>>),
+}
+
+/// The points of `FlatLayout::Integer`. Unlike `[i64; D]`, it is a point in any dimension.
+#[derive(Debug, Clone, Copy)]
+struct IntegerCoordinates<const D: usize>([i64; D]);
+
+impl<const D: usize> Point<D> for IntegerCoordinates<D> {
+    fn get_axis(&self, d: usize) -> f32 {
+        self.0[d] as f32
+    }
+}
+
+impl<const D: usize> IntegerPoint<D> for IntegerCoordinates<D> {
+    fn get_axis_i64(&self, d: usize) -> i64 {
+        self.0[d]
+    }
+}
+
+fn integer_coordinates<const D: usize, P: Point<D>>(point: &P) -> IntegerCoordinates<D> {
+    IntegerCoordinates(std::array::from_fn(|axis| point.get_axis(axis) as i64))
+}
+
+fn is_integer(value: f32) -> bool {
+    value.fract() == 0.0 && value.abs() < (1 << 24) as f32
+}
+
+impl<const D: usize, P: Point<D> + Sync> Flat<'_, D, P> {
+    fn point_indices_within(&self, points: &[P], query_point: P, radius: f32) -> Vec<usize> {
+        match self {
+            Flat::Eytzinger(tree) => tree.point_indices_within(points, query_point, radius),
+            Flat::Compact8(tree) => tree.point_indices_within(points, query_point, radius),
+            Flat::Compact16(tree) => tree.point_indices_within(points, query_point, radius),
+            Flat::Compact32(tree) => tree.point_indices_within(points, query_point, radius),
+            Flat::Inline(tree) => tree.point_indices_within(query_point, radius),
+            Flat::Precise(tree) => tree.point_indices_within(points, query_point, radius as f64),
+            Flat::Integer(tree, points) => tree.point_indices_within(
+                points,
+                integer_coordinates(&query_point),
+                (radius * radius) as u128,
+            ),
+            Flat::Fixed(tree) => {
+                let mut result = vec![0; tree.len()];
+                let count = tree.point_indices_within(points, query_point, radius, &mut result);
+                result.truncate(count);
+                result
+            }
+            Flat::Bucketed(tree) => tree.point_indices_within(points, query_point, radius),
+            Flat::Refit(tree) => tree.point_indices_within(points, query_point, radius),
+            Flat::Moving(tree) => tree.within_at(query_point, radius, 0.0),
+            Flat::Spheres(tree) => tree.spheres_intersecting_sphere(query_point, radius),
+            Flat::Owned(tree, kept) => tree
+                .point_indices_within(query_point, radius)
+                .into_iter()
+                .map(|i| kept[i])
+                .collect(),
+            Flat::Forest(forest, store) => {
+                forest.point_indices_within_stored(query_point, radius, &mut *store.borrow_mut())
+            }
+        }
+    }
+
+    fn nearest_one(&self, points: &[P], query_point: P) -> Option<(usize, f32)> {
+        match self {
+            Flat::Eytzinger(tree) => tree.nearest_one(points, query_point),
+            Flat::Compact8(tree) => tree.nearest_one(points, query_point),
+            Flat::Compact16(tree) => tree.nearest_one(points, query_point),
+            Flat::Compact32(tree) => tree.nearest_one(points, query_point),
+            Flat::Inline(tree) => tree.nearest_one(query_point),
+            Flat::Precise(tree) => tree
+                .nearest_one(points, query_point)
+                .map(|(index, distance)| (index, distance as f32)),
+            Flat::Integer(tree, points) => tree
+                .nearest_one(points, integer_coordinates(&query_point))
+                .map(|(index, distance_squared)| (index, (distance_squared as f32).sqrt())),
+            Flat::Fixed(tree) => tree.nearest_one(points, query_point),
+            Flat::Bucketed(tree) => tree.nearest_one(points, query_point),
+            Flat::Refit(tree) => tree.nearest_one(points, query_point),
+            Flat::Moving(tree) => tree.nearest_at(query_point, 0.0),
+            Flat::Spheres(tree) => tree.internal.nearest_one(&tree.points, query_point),
+            Flat::Owned(tree, kept) => tree
+                .nearest_one(query_point)
+                .map(|(index, distance)| (kept[index], distance)),
+            Flat::Forest(forest, store) => {
+                forest.nearest_one_stored(query_point, &mut *store.borrow_mut())
+            }
+        }
+    }
+
+    fn nearest_n(&self, points: &[P], query_point: P, k: usize) -> Option<Vec<usize>> {
+        Some(match self {
+            Flat::Eytzinger(tree) => tree.nearest_n(points, query_point, k),
+            Flat::Compact8(tree) => tree.nearest_n(points, query_point, k),
+            Flat::Compact16(tree) => tree.nearest_n(points, query_point, k),
+            Flat::Compact32(tree) => tree.nearest_n(points, query_point, k),
+            Flat::Inline(tree) => tree.nearest_n(query_point, k),
+            Flat::Precise(tree) => tree.nearest_n(points, query_point, k),
+            Flat::Integer(tree, points) => {
+                tree.nearest_n(points, integer_coordinates(&query_point), k)
+            }
+            Flat::Fixed(_) | Flat::Moving(_) => return None,
+            Flat::Bucketed(tree) => tree.nearest_n(points, query_point, k),
+            Flat::Refit(tree) => tree.nearest_n(points, query_point, k),
+            Flat::Spheres(tree) => tree.internal.nearest_n(&tree.points, query_point, k),
+            Flat::Owned(tree, kept) => tree
+                .nearest_n(query_point, k)
+                .into_iter()
+                .map(|i| kept[i])
+                .collect(),
+            Flat::Forest(forest, store) => {
+                forest.nearest_n_stored(query_point, k, &mut *store.borrow_mut())
+            }
+        })
+    }
+
+    fn farthest_n(&self, points: &[P], query_point: P, k: usize) -> Option<Vec<usize>> {
+        match self {
+            Flat::Eytzinger(_)
+            | Flat::Inline(_)
+            | Flat::Precise(_)
+            | Flat::Integer(..)
+            | Flat::Fixed(_)
+            | Flat::Bucketed(_)
+            | Flat::Refit(_)
+            | Flat::Moving(_)
+            | Flat::Spheres(_)
+            | Flat::Owned(..)
+            | Flat::Forest(..) => None,
+            Flat::Compact8(tree) => Some(tree.farthest_n(points, query_point, k)),
+            Flat::Compact16(tree) => Some(tree.farthest_n(points, query_point, k)),
+            Flat::Compact32(tree) => Some(tree.farthest_n(points, query_point, k)),
+        }
+    }
+}
+
+/// Every sorting strategy combined with every construction mode and layout variant, and every
+/// `FlatLayout`
+pub fn configurations() -> Vec<Configuration> {
+    let strategies = [
+        SortingStrategy::StableSort,
+        SortingStrategy::UnstableSort,
+        SortingStrategy::ShellSort,
+        SortingStrategy::HeapSort,
+        SortingStrategy::QuickSort,
+        SortingStrategy::MedianSelect,
+    ];
+
+    let constructions = [
+        ("presort", KdTreeBuilder::new()),
+        ("serial", KdTreeBuilder::new().presort(false)),
+        ("parallel", KdTreeBuilder::new().threads(4)),
+        ("seeded", KdTreeBuilder::new().seed(7)),
+        ("deterministic", KdTreeBuilder::new().deterministic(true)),
+    ];
+
+    type Layout = fn(KdTreeBuilder) -> KdTreeBuilder;
+    let layouts: [(&str, Layout, bool); 5] = [
+        ("default", |builder| builder, false),
+        (
+            "bounding spheres",
+            |builder| builder.bounding_spheres(true),
+            false,
+        ),
+        ("paged", |builder| builder.page_size(256), false),
+        (
+            "widest axis",
+            |builder| builder.split_strategy(SplitStrategy::WidestAxis),
+            false,
+        ),
+        ("morton", |builder| builder, true),
+    ];
+
+    let mut configurations = vec![];
+    for strategy in &strategies {
+        for (construction_name, construction) in &constructions {
+            for (layout_name, layout, reorder_morton) in &layouts {
+                configurations.push(Configuration {
+                    name: format!("{strategy:?}, {construction_name}, {layout_name}"),
+                    builder: layout(construction.clone().sorting_strategy(strategy.clone())),
+                    reorder_morton: *reorder_morton,
+                    flat: None,
+                });
+            }
+        }
+    }
+
+    configurations.push(Configuration {
+        name: "eytzinger".to_string(),
+        builder: KdTreeBuilder::new(),
+        reorder_morton: false,
+        flat: Some(FlatLayout::Eytzinger),
+    });
+
+    configurations.push(Configuration {
+        name: "precise".to_string(),
+        builder: KdTreeBuilder::new(),
+        reorder_morton: false,
+        flat: Some(FlatLayout::Precise),
+    });
+
+    configurations.push(Configuration {
+        name: "integer".to_string(),
+        builder: KdTreeBuilder::new(),
+        reorder_morton: false,
+        flat: Some(FlatLayout::Integer),
+    });
+
+    configurations.push(Configuration {
+        name: "fixed".to_string(),
+        builder: KdTreeBuilder::new(),
+        reorder_morton: false,
+        flat: Some(FlatLayout::Fixed),
+    });
+
+    for (name, builder, layout) in [
+        ("compact", KdTreeBuilder::new(), FlatLayout::Compact32),
+        (
+            "compact, widest axis",
+            KdTreeBuilder::new().split_strategy(SplitStrategy::WidestAxis),
+            FlatLayout::Compact32,
+        ),
+        ("compact u16", KdTreeBuilder::new(), FlatLayout::Compact16),
+        ("compact u8", KdTreeBuilder::new(), FlatLayout::Compact8),
+        ("inline", KdTreeBuilder::new(), FlatLayout::Inline),
+        (
+            "inline, widest axis",
+            KdTreeBuilder::new().split_strategy(SplitStrategy::WidestAxis),
+            FlatLayout::Inline,
+        ),
+        (
+            "bucketed, leaf size 1",
+            KdTreeBuilder::new(),
+            FlatLayout::Bucketed,
+        ),
+        (
+            "bucketed, leaf size 8",
+            KdTreeBuilder::new().leaf_size(8),
+            FlatLayout::Bucketed,
+        ),
+        (
+            "bucketed, leaf size 32, widest axis",
+            KdTreeBuilder::new()
+                .leaf_size(32)
+                .split_strategy(SplitStrategy::WidestAxis),
+            FlatLayout::Bucketed,
+        ),
+        (
+            "bucketed, leaf size 8, median select",
+            KdTreeBuilder::new()
+                .leaf_size(8)
+                .sorting_strategy(SortingStrategy::MedianSelect),
+            FlatLayout::Bucketed,
+        ),
+        (
+            "bucketed, leaf size 8, seeded",
+            KdTreeBuilder::new().leaf_size(8).seed(7),
+            FlatLayout::Bucketed,
+        ),
+        ("refit", KdTreeBuilder::new(), FlatLayout::Refit),
+        (
+            "refit, widest axis, paged",
+            KdTreeBuilder::new()
+                .split_strategy(SplitStrategy::WidestAxis)
+                .page_size(256),
+            FlatLayout::Refit,
+        ),
+        ("moving", KdTreeBuilder::new(), FlatLayout::Moving),
+        (
+            "moving, widest axis",
+            KdTreeBuilder::new().split_strategy(SplitStrategy::WidestAxis),
+            FlatLayout::Moving,
+        ),
+        (
+            "spheres, bounding spheres",
+            KdTreeBuilder::new().bounding_spheres(true),
+            FlatLayout::Spheres,
+        ),
+        ("owned", KdTreeBuilder::new(), FlatLayout::Owned),
+        (
+            "owned, widest axis, bounding spheres, paged",
+            KdTreeBuilder::new()
+                .split_strategy(SplitStrategy::WidestAxis)
+                .bounding_spheres(true)
+                .page_size(256),
+            FlatLayout::Owned,
+        ),
+        (
+            "owned, quick sort, seeded",
+            KdTreeBuilder::new()
+                .sorting_strategy(SortingStrategy::QuickSort)
+                .seed(7),
+            FlatLayout::Owned,
+        ),
+        ("forest", KdTreeBuilder::new(), FlatLayout::Forest),
+        (
+            "forest, median select, widest axis",
+            KdTreeBuilder::new()
+                .sorting_strategy(SortingStrategy::MedianSelect)
+                .split_strategy(SplitStrategy::WidestAxis),
+            FlatLayout::Forest,
+        ),
+    ] {
+        configurations.push(Configuration {
+            name: name.to_string(),
+            builder,
+            reorder_morton: false,
+            flat: Some(layout),
+        });
+    }
+
+    configurations
+}
+
+/// A randomized set of points and radius queries
+#[derive(Debug, Clone)]
+pub struct Workload<const D: usize> {
+    pub points: Vec<[f32; D]>,
+    pub queries: Vec<([f32; D], f32)>,
+    /// The `k` used for k nearest and k farthest queries
+    pub k: usize,
+}
+
+/// Generates a workload from `seed`. Coordinates are small integers, so the points contain
+/// duplicates and many equal distances, which is where configurations tend to disagree.
+pub fn random_workload<const D: usize>(
+    seed: u64,
+    point_count: usize,
+    query_count: usize,
+) -> Workload<D> {
+    let mut rng = SplitMix64::new(seed);
+    let mut coordinate = |range: u64| (rng.next_u64() % range) as f32;
+
+    let points = (0..point_count)
+        .map(|_| std::array::from_fn(|_| coordinate(32)))
+        .collect();
+    let queries = (0..query_count)
+        .map(|_| {
+            let query_point = std::array::from_fn(|_| coordinate(40) - 4.0);
+            (query_point, coordinate(12))
+        })
+        .collect();
+
+    Workload {
+        points,
+        queries,
+        k: (point_count / 10).max(1),
+    }
+}
+
+fn brute_force_sorted_by_distance<const D: usize, P: Point<D>>(
+    points: &[P],
+    query_point: P,
+) -> Vec<(f32, usize)> {
+    let mut all = points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| (query_point.distance_squared(*point), i))
+        .collect::<Vec<_>>();
+    all.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+    all
+}
+
+/// Builds a tree of `points` with `configuration` and compares `point_indices_within`,
+/// `nearest_one`, `nearest_n` and `farthest_n` for every query against brute force. The
+/// error names the configuration, the query and what differed. A configuration whose tree
+/// can't hold `points` passes without checking anything.
+///
+/// Result sets must match exactly. For k nearest and k farthest, the sequence of distances
+/// must match exactly and, unless the points are reordered, so must the indices.
+pub fn check_configuration<const D: usize, P>(
+    configuration: &Configuration,
+    points: &[P],
+    queries: &[(P, f32)],
+    k: usize,
+) -> Result<(), String>
+where
+    P: Point<D> + Sync,
+{
+    let (points_in_tree, permutation, internal) = if configuration.reorder_morton {
+        configuration.builder.build_reordered_morton(points)
+    } else {
+        /*
+            NOTE: The leaf size only applies to the bucketed tree, the reference tree always
+                  has one point per node
+        */
+        let internal = configuration
+            .builder
+            .clone()
+            .leaf_size(1)
+            .build_no_borrow(points);
+        (
+            points.to_vec(),
+            Permutation::identity(points.len()),
+            internal,
+        )
+    };
+    let tree = KdTree {
+        internal,
+        points: &points_in_tree,
+    };
+    let builder = &configuration.builder;
+    let velocities = (0..points.len())
+        .map(|i| std::array::from_fn(|axis| ((i + axis) % 3) as f32 - 1.0))
+        .collect::<Vec<_>>();
+    let flat = match configuration.flat {
+        None => None,
+        Some(FlatLayout::Eytzinger) => Some(Flat::Eytzinger(KdTreeEytzinger::from_points(points))),
+        Some(FlatLayout::Precise) => Some(Flat::Precise(KdTreePrecise::from_points(points))),
+        Some(FlatLayout::Fixed) => match KdTreeFixed::from_points(points) {
+            Some(tree) => Some(Flat::Fixed(Box::new(tree))),
+            None => return Ok(()),
+        },
+        Some(FlatLayout::Integer) => {
+            let integral = |point: &P| (0..D).all(|axis| is_integer(point.get_axis(axis)));
+            if !points.iter().all(integral)
+                || !queries
+                    .iter()
+                    .all(|(query_point, radius)| integral(query_point) && is_integer(*radius))
+            {
+                return Ok(());
+            }
+
+            let points = points.iter().map(integer_coordinates).collect::<Vec<_>>();
+            Some(Flat::Integer(KdTreeInteger::from_points(&points), points))
+        }
+        Some(FlatLayout::Compact8) if points.len() > u8::NONE.to_usize() => return Ok(()),
+        Some(FlatLayout::Compact8) => Some(Flat::Compact8(builder.build_compact(points))),
+        Some(FlatLayout::Compact16) => Some(Flat::Compact16(builder.build_compact(points))),
+        Some(FlatLayout::Compact32) => Some(Flat::Compact32(builder.build_compact(points))),
+        Some(FlatLayout::Inline) => Some(Flat::Inline(KdTreeInline::from_tree(
+            &builder.build_no_borrow(points),
+            points,
+        ))),
+        Some(FlatLayout::Bucketed) => Some(Flat::Bucketed(builder.build_bucketed(points))),
+        Some(FlatLayout::Refit) => Some(Flat::Refit(builder.build_refit(points))),
+        Some(FlatLayout::Moving) => Some(Flat::Moving(builder.build_moving(points, &velocities))),
+        Some(FlatLayout::Spheres) => Some(Flat::Spheres(
+            builder.build_spheres(points.to_vec(), vec![0.0; points.len()]),
+        )),
+        Some(FlatLayout::Owned) => {
+            let removed = |i: usize| removed_in_layout(FlatLayout::Owned, i);
+
+            let mut tree = builder.build_owned(points.to_vec());
+            for i in (0..points.len()).filter(|i| i % 5 == 1) {
+                tree.remove(i);
+            }
+            let kept = tree.compact();
+            for (new_index, old_index) in kept.iter().enumerate() {
+                if removed(*old_index) {
+                    tree.remove(new_index);
+                }
+            }
+
+            Some(Flat::Owned(tree, kept))
+        }
+        Some(FlatLayout::Forest) => {
+            let mut forest = KdForest::with_builder(builder.clone());
+            let mut store = HashMap::new();
+
+            let third = points.len().div_ceil(3);
+            for (chunk, points) in points.chunks(third.max(1)).enumerate() {
+                if chunk == 1 {
+                    forest.push_spilled_chunk(points.to_vec(), &mut store);
+                } else {
+                    forest.push_chunk(points.to_vec());
+                }
+            }
+            for i in (0..points.len()).filter(|i| removed_in_layout(FlatLayout::Forest, *i)) {
+                forest.remove(i);
+            }
+
+            Some(Flat::Forest(forest, RefCell::new(store)))
+        }
+    };
+    let removed = |i: usize| {
+        configuration
+            .flat
+            .is_some_and(|layout| removed_in_layout(layout, i))
+    };
+
+    let fail = |query_point: &P, message: String| {
+        Err(format!(
+            "{}: query {query_point:?}: {message}",
+            configuration.name
+        ))
+    };
+    let distances = |indices: &[usize], query_point: P| {
+        indices
+            .iter()
+            .map(|i| query_point.distance_squared(points[*i]))
+            .collect::<Vec<_>>()
+    };
+
+    for (query_point, radius) in queries {
+        let mut by_distance = brute_force_sorted_by_distance(points, *query_point);
+        by_distance.retain(|(_, i)| !removed(*i));
+
+        let mut within = match &flat {
+            Some(flat) => flat.point_indices_within(points, *query_point, *radius),
+            None => tree.point_indices_within(*query_point, *radius),
+        };
+        permutation.map_to_original(&mut within);
+        within.sort_unstable();
+        let mut expected_within = by_distance
+            .iter()
+            .filter(|(distance_squared, _)| *distance_squared <= radius * radius)
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        expected_within.sort_unstable();
+        if within != expected_within {
+            return fail(
+                query_point,
+                format!("radius {radius}: got {within:?}, expected {expected_within:?}"),
+            );
+        }
+
+        let nearest_one = match &flat {
+            Some(flat) => flat.nearest_one(points, *query_point),
+            None => tree.nearest_one(*query_point),
+        }
+        .map(|(_, distance)| distance * distance);
+        let expected_nearest_one = by_distance
+            .first()
+            .map(|(distance_squared, _)| *distance_squared);
+        let close_enough = match (nearest_one, expected_nearest_one) {
+            (Some(a), Some(b)) => (a - b).abs() <= 1e-4 * b.max(1.0),
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        if !close_enough {
+            return fail(
+                query_point,
+                format!("nearest one: got {nearest_one:?}, expected {expected_nearest_one:?}"),
+            );
+        }
+
+        let mut nearest = match &flat {
+            Some(flat) => match flat.nearest_n(points, *query_point, k) {
+                Some(nearest) => nearest,
+                None => continue,
+            },
+            None => tree.nearest_n(*query_point, k),
+        };
+        permutation.map_to_original(&mut nearest);
+        let expected_nearest = by_distance
+            .iter()
+            .take(k)
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        if distances(&nearest, *query_point) != distances(&expected_nearest, *query_point)
+            || (!configuration.reorder_morton && nearest != expected_nearest)
+        {
+            return fail(
+                query_point,
+                format!("nearest {k}: got {nearest:?}, expected {expected_nearest:?}"),
+            );
+        }
+
+        let mut farthest = match &flat {
+            Some(flat) => match flat.farthest_n(points, *query_point, k) {
+                Some(farthest) => farthest,
+                None => continue,
+            },
+            None => tree.farthest_n(*query_point, k),
+        };
+        permutation.map_to_original(&mut farthest);
+        let expected_farthest = by_distance
+            .iter()
+            .rev()
+            .take(k)
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        if distances(&farthest, *query_point) != distances(&expected_farthest, *query_point)
+            || (!configuration.reorder_morton && farthest != expected_farthest)
+        {
+            return fail(
+                query_point,
+                format!("farthest {k}: got {farthest:?}, expected {expected_farthest:?}"),
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Runs `check_configuration` for every one of `configurations()`
+pub fn check_all_configurations<const D: usize, P>(
+    points: &[P],
+    queries: &[(P, f32)],
+    k: usize,
+) -> Result<(), String>
+where
+    P: Point<D> + Sync,
+{
+    for configuration in configurations() {
+        check_configuration(&configuration, points, queries, k)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_configurations_agree() {
+        for seed in 0..2 {
+            let workload = random_workload::<2>(seed, 200, 20);
+            let result = check_all_configurations(&workload.points, &workload.queries, workload.k);
+            assert_eq!(result, Ok(()));
+
+            let workload = random_workload::<2>(seed, 300, 20);
+            let result = check_all_configurations(&workload.points, &workload.queries, workload.k);
+            assert_eq!(result, Ok(()));
+
+            let workload = random_workload::<3>(seed, 500, 20);
+            let result = check_all_configurations(&workload.points, &workload.queries, workload.k);
+            assert_eq!(result, Ok(()));
+        }
+    }
+}
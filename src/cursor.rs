@@ -0,0 +1,114 @@
+//! Radius queries that hand out their results in chunks
+use crate::utils::axis_values;
+use crate::{IndicesWithinIterator, KdTree, KdTreeNoBorrow, KdTreeNode, NodeStorage, Point};
+
+/// A radius query that can be paused and resumed. Every call to `next_chunk` continues the
+/// traversal where the previous one stopped, so huge result sets can be paginated without
+/// running the query again or holding all of the results at once.
+///
+/// Every point within the radius is returned exactly once over all chunks, in the same order
+/// as `point_indices_within`.
+#[derive(Debug, Clone)]
+pub struct WithinCursor<'a, const D: usize, P: Point<D>, N: NodeStorage = Vec<KdTreeNode>> {
+    pub tree: &'a KdTreeNoBorrow<D, P, N>,
+    pub points: &'a [P],
+    pub query_point: P,
+    pub radius: f32,
+    /// Nodes left to visit as `(depth, tree_index)`
+    pub stack: Vec<(usize, usize)>,
+}
+
+impl<'a, const D: usize, P: Point<D>, N: NodeStorage> WithinCursor<'a, D, P, N> {
+    /// Appends up to `max` more indices to `out` and returns how many were appended. Fewer
+    /// than `max` means the query is exhausted.
+    pub fn next_chunk(&mut self, out: &mut Vec<usize>, max: usize) -> usize {
+        let start = out.len();
+
+        let iter = IndicesWithinIterator {
+            stack: &mut self.stack,
+            tree: self.tree,
+            points: self.points,
+            radius_squared: self.radius * self.radius,
+            radius: self.radius,
+            query_point_axis_values: axis_values(&self.query_point),
+            query_point: self.query_point,
+        };
+        out.extend(iter.take(max));
+
+        out.len() - start
+    }
+
+    /// Whether the traversal has finished. All results may already have been handed out while
+    /// there are still nodes left to visit, in which case the next chunk is empty.
+    pub fn is_done(&self) -> bool {
+        self.stack.is_empty()
+    }
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_within_cursor<'a>(
+        &'a self,
+        points: &'a [P],
+        query_point: P,
+        radius: f32,
+    ) -> WithinCursor<'a, D, P, N> {
+        let stack = if self.tree.is_empty() {
+            vec![]
+        } else {
+            vec![(0, 0)]
+        };
+
+        WithinCursor {
+            tree: self,
+            points,
+            query_point,
+            radius,
+            stack,
+        }
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Starts a radius query whose results are fetched in chunks, see `WithinCursor`
+    pub fn point_indices_within_cursor(
+        &self,
+        query_point: P,
+        radius: f32,
+    ) -> WithinCursor<'_, D, P> {
+        self.internal
+            .point_indices_within_cursor(self.points, query_point, radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::KdTree;
+
+    #[test]
+    fn test_cursor_chunks() {
+        let points: Vec<[f32; 2]> = (0..2000)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.37).sin() * 10.0, (i * 0.11).cos() * 10.0]
+            })
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        let expected = tree.point_indices_within([1.0, -2.0], 4.0);
+        assert!(expected.len() > 100);
+
+        let mut cursor = tree.point_indices_within_cursor([1.0, -2.0], 4.0);
+        let mut all = vec![];
+        loop {
+            let appended = cursor.next_chunk(&mut all, 37);
+            if appended < 37 {
+                break;
+            }
+        }
+
+        assert!(cursor.is_done());
+        assert_eq!(all, expected);
+        assert_eq!(cursor.next_chunk(&mut all, 10), 0);
+    }
+}
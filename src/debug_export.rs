@@ -0,0 +1,134 @@
+//! Machine readable dumps of queries for external visualizers and debugging tools.
+//!
+//! `export_query_debug` produces JSON of the following shape. New keys might be added, but
+//! existing ones keep their meaning as long as `version` stays the same:
+//!
+//! ```json
+//! {
+//!     "version": 1,
+//!     "dimensions": 2,
+//!     "query": { "point": [0.0, 0.0], "radius": 1.0 },
+//!     "visited": [
+//!         {
+//!             "node": 0,
+//!             "point_index": 4,
+//!             "point": [0.0, 1.0],
+//!             "depth": 0,
+//!             "axis": 0,
+//!             "within": true
+//!         }
+//!     ],
+//!     "results": [4]
+//! }
+//! ```
+//!
+//! `visited` lists the nodes in the order they were visited by the radius query.
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+use serde_json::{json, Value};
+
+/// The version of the schema emitted by `export_query_debug`
+pub const QUERY_DEBUG_VERSION: u32 = 1;
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn export_query_debug(&self, points: &[P], query_point: P, radius: f32) -> Value {
+        let radius_squared = radius * radius;
+        let query_point_axis_values = axis_values(&query_point);
+
+        let mut visited = vec![];
+        let mut results = vec![];
+        let mut stack = vec![(0, 0)];
+
+        while let Some((depth, tree_index)) = stack.pop() {
+            if self.sphere_out_of_reach(tree_index, &query_point_axis_values, radius) {
+                continue;
+            }
+
+            let point_index = self.tree[tree_index].index();
+            let point = points[point_index];
+
+            let axis = self.tree[tree_index].axis();
+            let axis_d = self.tree[tree_index].split - query_point_axis_values[axis];
+
+            let within = query_point.distance_squared(point) <= radius_squared;
+            if within {
+                results.push(point_index);
+            }
+
+            visited.push(json!({
+                "node": tree_index,
+                "point_index": point_index,
+                "point": axis_values(&point).to_vec(),
+                "depth": depth,
+                "axis": axis,
+                "within": within,
+            }));
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            if let Some(child) = self.tree[tree_index].child(first) {
+                stack.push((depth + 1, child));
+            }
+            if axis_d * axis_d <= radius_squared {
+                if let Some(child) = self.tree[tree_index].child(last) {
+                    stack.push((depth + 1, child));
+                }
+            }
+        }
+
+        json!({
+            "version": QUERY_DEBUG_VERSION,
+            "dimensions": D,
+            "query": {
+                "point": query_point_axis_values.to_vec(),
+                "radius": radius,
+            },
+            "visited": visited,
+            "results": results,
+        })
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Runs a radius query like `point_indices_within` while recording every visited node and
+    /// returns the query, the visited nodes and the results as JSON. See the module documentation
+    /// for the schema.
+    pub fn export_query_debug(&self, query_point: P, radius: f32) -> Value {
+        self.internal
+            .export_query_debug(self.points, query_point, radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_query_debug() {
+        let points: Vec<[f32; 2]> = (0..50).map(|i| [i as f32, (i % 7) as f32]).collect();
+        let tree = KdTree::from_points(&points);
+
+        let dump = tree.export_query_debug([10.0, 3.0], 2.5);
+
+        assert_eq!(dump["version"], QUERY_DEBUG_VERSION);
+        assert_eq!(dump["query"]["point"], json!([10.0, 3.0]));
+
+        let mut expected = tree.point_indices_within([10.0, 3.0], 2.5);
+        let mut results = dump["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|value| value.as_u64().unwrap() as usize)
+            .collect::<Vec<_>>();
+        expected.sort();
+        results.sort();
+        assert_eq!(expected, results);
+
+        let visited = dump["visited"].as_array().unwrap();
+        assert!(visited.len() >= results.len() && visited.len() < points.len());
+        assert_eq!(visited[0]["node"], 0);
+    }
+}
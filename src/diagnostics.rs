@@ -0,0 +1,227 @@
+//! Reports on the shape of a built tree, for diagnosing poorly balanced or poorly split data
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+#[derive(Debug, Clone, PartialEq)]
+/// How the nodes at one depth of a tree split their points
+pub struct LevelSplitReport<const D: usize> {
+    pub depth: usize,
+    /// Number of nodes at this depth
+    pub node_count: usize,
+    /// Number of nodes at this depth splitting along each axis
+    pub axis_counts: [usize; D],
+    /// Smallest splitting value along each axis, infinite if no node split along it
+    pub split_min: [f32; D],
+    /// Largest splitting value along each axis, negative infinity if no node split along it
+    pub split_max: [f32; D],
+    /// Mean of `|left - right| / (left + right)` over the nodes with children, where `left`
+    /// and `right` are the sizes of the two subtrees. `0.0` is perfectly balanced and `1.0`
+    /// means every point went to the same side.
+    pub mean_skew: f32,
+    /// Largest skew of any node at this depth
+    pub max_skew: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// Summary of the shape of a tree, see `KdTree::stats`
+pub struct TreeStats {
+    pub node_count: usize,
+    /// The number of levels, `0` for an empty tree
+    pub max_depth: usize,
+    /// The mean depth of the nodes, counting the root as depth `0`
+    pub average_depth: f32,
+    /// The number of nodes without children
+    pub leaf_count: usize,
+    /// `max_depth` divided by the depth of a balanced tree with as many nodes, so `1.0` for a
+    /// balanced tree and larger the more lopsided it is. `1.0` for an empty tree.
+    pub balance_factor: f32,
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// The depth of every node, counting the root as depth `0`
+    fn node_depths(&self) -> Vec<usize> {
+        /*
+            NOTE: Children are always placed after their parent in `tree`, so depths can be
+                  computed front to back.
+        */
+        let mut depths = vec![0; self.tree.len()];
+        for tree_index in 0..self.tree.len() {
+            for child in self.tree[tree_index].children().iter().flatten() {
+                depths[*child] = depths[tree_index] + 1;
+            }
+        }
+
+        depths
+    }
+
+    /// See `KdTree`
+    pub fn depth(&self) -> usize {
+        self.node_depths().iter().max().map_or(0, |depth| depth + 1)
+    }
+
+    /// See `KdTree`
+    pub fn stats(&self) -> TreeStats {
+        let node_count = self.tree.len();
+        let depths = self.node_depths();
+        let max_depth = depths.iter().max().map_or(0, |depth| depth + 1);
+
+        let average_depth = if node_count == 0 {
+            0.0
+        } else {
+            depths.iter().sum::<usize>() as f32 / node_count as f32
+        };
+
+        let balanced_depth = (usize::BITS - node_count.leading_zeros()) as usize;
+        let balance_factor = if node_count == 0 {
+            1.0
+        } else {
+            max_depth as f32 / balanced_depth as f32
+        };
+
+        TreeStats {
+            node_count,
+            max_depth,
+            average_depth,
+            leaf_count: self
+                .tree
+                .iter()
+                .filter(|node| node.children() == [None, None])
+                .count(),
+            balance_factor,
+        }
+    }
+
+    /// See `KdTree`
+    pub fn level_split_report(&self) -> Vec<LevelSplitReport<D>> {
+        let node_count = self.tree.len();
+        let depths = self.node_depths();
+
+        /*
+            NOTE: Children are always placed after their parent in `tree`, so subtree sizes can
+                  be computed back to front.
+        */
+        let mut sizes = vec![1_usize; node_count];
+        for tree_index in (0..node_count).rev() {
+            for child in self.tree[tree_index].children().iter().flatten() {
+                sizes[tree_index] += sizes[*child];
+            }
+        }
+
+        let level_count = depths.iter().max().map_or(0, |depth| depth + 1);
+        let mut reports = (0..level_count)
+            .map(|depth| LevelSplitReport {
+                depth,
+                node_count: 0,
+                axis_counts: [0; D],
+                split_min: [f32::INFINITY; D],
+                split_max: [f32::NEG_INFINITY; D],
+                mean_skew: 0.0,
+                max_skew: 0.0,
+            })
+            .collect::<Vec<_>>();
+        let mut skewed_nodes = vec![0; level_count];
+
+        for (tree_index, node) in self.tree.iter().enumerate() {
+            let depth = depths[tree_index];
+            let report = &mut reports[depth];
+
+            let axis = node.axis();
+            let split = node.split;
+            report.node_count += 1;
+            report.axis_counts[axis] += 1;
+            report.split_min[axis] = report.split_min[axis].min(split);
+            report.split_max[axis] = report.split_max[axis].max(split);
+
+            if sizes[tree_index] > 1 {
+                let [left, right] = node.children().map(|child| child.map_or(0, |c| sizes[c]));
+                let skew = left.abs_diff(right) as f32 / (left + right) as f32;
+                report.mean_skew += skew;
+                report.max_skew = report.max_skew.max(skew);
+                skewed_nodes[depth] += 1;
+            }
+        }
+
+        for (report, count) in reports.iter_mut().zip(skewed_nodes) {
+            if count > 0 {
+                report.mean_skew /= count as f32;
+            }
+        }
+
+        reports
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// The number of levels of the tree, `0` if it is empty. A tree built by this crate is
+    /// balanced, so this is the number of bits needed for the number of points.
+    pub fn depth(&self) -> usize {
+        self.internal.depth()
+    }
+
+    /// Returns the number of nodes and leaves and how deep and how balanced the tree is. Trees
+    /// built by this crate are always balanced, so a `balance_factor` above `1.0` points to a
+    /// tree whose nodes were modified or that was built by other means. Use this to decide when
+    /// to call `KdTreeNoBorrow::rebalance`.
+    pub fn stats(&self) -> TreeStats {
+        self.internal.stats()
+    }
+
+    /// Returns one report per depth of the tree, root first, describing along which axes the
+    /// nodes at that depth split, the range of their splitting values and how evenly they divide
+    /// their points. This shows where and why a tree turns out lopsided, for instance when all
+    /// of the variance of the data is along one axis.
+    ///
+    /// Only the nodes are read, so the report of a `KdTreeNoBorrow` doesn't need its points.
+    pub fn level_split_report(&self) -> Vec<LevelSplitReport<D>> {
+        self.internal.level_split_report()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::KdTree;
+
+    #[test]
+    fn test_stats() {
+        /*
+            NOTE: 1023 points fill all 10 levels of a balanced tree exactly
+        */
+        let points = (0..1023).map(|i| [i as f32, 0.0]).collect::<Vec<_>>();
+        let stats = KdTree::from_points(&points).stats();
+        assert_eq!(stats.node_count, 1023);
+        assert_eq!(stats.max_depth, 10);
+        assert_eq!(stats.balance_factor, 1.0);
+        assert_eq!(stats.leaf_count, 512);
+        assert_eq!(stats.average_depth, 8194.0 / 1023.0);
+
+        let stats = KdTree::from_points(&points[..0]).stats();
+        assert_eq!(stats.max_depth, 0);
+        assert_eq!(stats.balance_factor, 1.0);
+    }
+
+    #[test]
+    fn test_level_split_report() {
+        let points: Vec<[f32; 2]> = (0..1000)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.37).sin() * 10.0, (i * 0.11).cos()]
+            })
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        let reports = tree.level_split_report();
+        assert_eq!(reports.iter().map(|r| r.node_count).sum::<usize>(), 1000);
+        assert_eq!(reports.len(), 10);
+        assert_eq!(reports[0].node_count, 1);
+
+        for report in &reports {
+            assert_eq!(report.axis_counts[report.depth % 2], report.node_count);
+            assert!(report.max_skew <= 1.0);
+        }
+
+        /*
+            NOTE: Median splits keep the two sides within one point of each other
+        */
+        assert!(reports[0].max_skew <= 1.0 / 999.0);
+        assert!(reports[1].split_min[1] >= -1.0 && reports[1].split_max[1] <= 1.0);
+    }
+}
@@ -0,0 +1,307 @@
+//! Handling of points with exactly the same coordinates
+use crate::{KdTree, KdTreeError, KdTreeNoBorrow, NodeStorage, Point};
+
+#[derive(Debug, Clone)]
+/// Groups of points with exactly the same coordinates. Every group is represented by its
+/// smallest index, and the other indices of the group are its aliases.
+pub struct DuplicateGroups {
+    /// The representative of every point, which is the point itself for unique points
+    pub representative: Vec<usize>,
+    /// `alias_ranges[representative]` is the range within `aliases` holding the other indices
+    /// of the group. Empty for points without duplicates and for non-representatives.
+    pub alias_ranges: Vec<(usize, usize)>,
+    pub aliases: Vec<usize>,
+}
+
+impl DuplicateGroups {
+    /// Finds all groups of exact duplicates among `points` by sorting them lexicographically
+    pub fn from_points<const D: usize, P>(points: &[P]) -> Self
+    where
+        P: Point<D>,
+    {
+        let compare_coordinates = |a: usize, b: usize| {
+            (0..D)
+                .map(|axis| {
+                    points[a]
+                        .get_axis(axis)
+                        .total_cmp(&points[b].get_axis(axis))
+                })
+                .find(|ordering| ordering.is_ne())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        };
+
+        let mut sorted = (0..points.len()).collect::<Vec<_>>();
+        sorted.sort_unstable_by(|a, b| compare_coordinates(*a, *b).then(a.cmp(b)));
+
+        let mut representative = (0..points.len()).collect::<Vec<_>>();
+        let mut alias_ranges = vec![(0, 0); points.len()];
+        let mut aliases = vec![];
+
+        let mut group_start = 0;
+        while group_start < sorted.len() {
+            let first = sorted[group_start];
+            let mut group_end = group_start + 1;
+            while group_end < sorted.len() && compare_coordinates(first, sorted[group_end]).is_eq()
+            {
+                group_end += 1;
+            }
+
+            let start = aliases.len();
+            for &alias in &sorted[group_start + 1..group_end] {
+                representative[alias] = first;
+                aliases.push(alias);
+            }
+            alias_ranges[first] = (start, aliases.len());
+
+            group_start = group_end;
+        }
+
+        Self {
+            representative,
+            alias_ranges,
+            aliases,
+        }
+    }
+
+    /// Whether `index` is the representative of its group, which is true for all unique points
+    #[inline(always)]
+    pub fn is_representative(&self, index: usize) -> bool {
+        self.representative[index] == index
+    }
+
+    /// The other indices sharing the coordinates of `representative`
+    #[inline(always)]
+    pub fn aliases_of(&self, representative: usize) -> &[usize] {
+        let (start, end) = self.alias_ranges[representative];
+        &self.aliases[start..end]
+    }
+
+    /// The number of distinct positions
+    pub fn unique_count(&self) -> usize {
+        self.representative.len() - self.aliases.len()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// What `from_points_with_duplicate_policy` does with points that have exactly the same
+/// coordinates as another point
+pub enum DuplicatePolicy {
+    /// Put every point in the tree, as `from_points` does
+    #[default]
+    KeepAll,
+    /// Only put the representative of every group in the tree, so queries return every
+    /// position once. The aliases of a result are found with `DuplicateGroups::aliases_of`.
+    Deduplicate,
+    /// Fail with `KdTreeError::Duplicate` naming the first point that duplicates another
+    Error,
+}
+
+impl<const D: usize, P: Point<D>> KdTreeNoBorrow<D, P> {
+    /// See `KdTree`
+    pub fn from_points_with_duplicate_policy(
+        points: &[P],
+        policy: DuplicatePolicy,
+    ) -> Result<(Self, DuplicateGroups), KdTreeError> {
+        let duplicates = DuplicateGroups::from_points(points);
+
+        let tree = match policy {
+            DuplicatePolicy::KeepAll => Self::from_points(points),
+            DuplicatePolicy::Deduplicate => {
+                let representatives = (0..points.len())
+                    .filter(|i| duplicates.is_representative(*i))
+                    .collect();
+                Self::from_point_ids(points, representatives)
+            }
+            DuplicatePolicy::Error => {
+                if let Some(index) = (0..points.len()).find(|i| !duplicates.is_representative(*i)) {
+                    return Err(KdTreeError::Duplicate {
+                        index,
+                        of: duplicates.representative[index],
+                    });
+                }
+                Self::from_points(points)
+            }
+        };
+
+        Ok((tree, duplicates))
+    }
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_within_deduplicated_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        duplicates: &DuplicateGroups,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        let start = result.len();
+        self.point_indices_within_buffers(points, query_point, radius, result, stack);
+
+        let mut kept = start;
+        for i in start..result.len() {
+            if duplicates.is_representative(result[i]) {
+                result[kept] = result[i];
+                kept += 1;
+            }
+        }
+        result.truncate(kept);
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_deduplicated(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        duplicates: &DuplicateGroups,
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_deduplicated_buffers(
+            points,
+            query_point,
+            radius,
+            duplicates,
+            &mut result,
+            &mut stack,
+        );
+
+        result
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `from_points`, but first groups exactly coincident points and handles them
+    /// according to `policy`. Radius queries around many duplicates report the same position
+    /// many times over. The groups are returned
+    /// either way, so the `_deduplicated` queries can be used with `DuplicatePolicy::KeepAll`.
+    pub fn from_points_with_duplicate_policy(
+        points: &'a [P],
+        policy: DuplicatePolicy,
+    ) -> Result<(Self, DuplicateGroups), KdTreeError> {
+        let (internal, duplicates) =
+            KdTreeNoBorrow::from_points_with_duplicate_policy(points, policy)?;
+
+        Ok((Self { internal, points }, duplicates))
+    }
+
+    /// Same as `point_indices_within_buffers`, but every group of exact duplicates is reported
+    /// once through its representative. Use `DuplicateGroups::aliases_of` to get the rest.
+    /// `duplicates` must have been computed from the points of this tree.
+    #[inline(always)]
+    pub fn point_indices_within_deduplicated_buffers(
+        &self,
+        query_point: P,
+        radius: f32,
+        duplicates: &DuplicateGroups,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_within_deduplicated_buffers(
+            self.points,
+            query_point,
+            radius,
+            duplicates,
+            result,
+            stack,
+        )
+    }
+
+    /// See `point_indices_within_deduplicated_buffers`
+    #[inline(always)]
+    pub fn point_indices_within_deduplicated(
+        &self,
+        query_point: P,
+        radius: f32,
+        duplicates: &DuplicateGroups,
+    ) -> Vec<usize> {
+        self.internal.point_indices_within_deduplicated(
+            self.points,
+            query_point,
+            radius,
+            duplicates,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deduplicated() {
+        #[rustfmt::skip]
+        let points: [[f32; 2]; 7] = [
+            [1.0, 1.0],
+            [0.0, 0.0],
+            [1.0, 1.0],
+            [5.0, 5.0],
+            [0.0, 0.0],
+            [1.0, 1.0],
+            [1.0, 0.0],
+        ];
+        let duplicates = DuplicateGroups::from_points(&points);
+        assert_eq!(duplicates.representative, vec![0, 1, 0, 3, 1, 0, 6]);
+        assert_eq!(duplicates.aliases_of(0), &[2, 5]);
+        assert_eq!(duplicates.aliases_of(1), &[4]);
+        assert!(duplicates.aliases_of(3).is_empty());
+        assert_eq!(duplicates.unique_count(), 4);
+
+        let tree = KdTree::from_points(&points);
+        let mut result = tree.point_indices_within_deduplicated([0.0, 0.0], 2.0, &duplicates);
+        result.sort();
+        assert_eq!(result, vec![0, 1, 6]);
+    }
+
+    #[test]
+    fn test_duplicate_policy() {
+        let points = (0..300)
+            .map(|i| [(i % 7) as f32, (i % 5) as f32])
+            .collect::<Vec<[f32; 2]>>();
+
+        let (tree, duplicates) =
+            KdTree::from_points_with_duplicate_policy(&points, DuplicatePolicy::Deduplicate)
+                .unwrap();
+        assert_eq!(tree.internal.tree.len(), 35);
+        assert_eq!(duplicates.unique_count(), 35);
+        let mut result = tree.point_indices_within([3.0, 2.0], 1.0);
+        result.sort();
+        assert_eq!(result, vec![2, 3, 17, 31, 32]);
+        assert_eq!(duplicates.aliases_of(17).len(), 8);
+
+        let mut internal = tree.internal.clone();
+        assert!(!internal.rebalance(&points));
+        internal.rebuild(&points);
+        assert_eq!(internal.tree, tree.internal.tree);
+
+        let twice = [[1.0_f32, 2.0], [1.0, 2.0], [3.0, 4.0]];
+        let (mut internal, _) =
+            KdTreeNoBorrow::from_points_with_duplicate_policy(&twice, DuplicatePolicy::Deduplicate)
+                .unwrap();
+        assert_eq!(internal.tree.len(), 2);
+        internal.rebuild(&twice);
+        assert_eq!(internal.tree.len(), 2);
+        assert_eq!(
+            internal.point_indices_within(&twice, [1.0, 2.0], 0.0),
+            vec![0]
+        );
+
+        let (tree, _) =
+            KdTree::from_points_with_duplicate_policy(&points, DuplicatePolicy::KeepAll).unwrap();
+        assert_eq!(tree.point_indices_within([3.0, 2.0], 0.0).len(), 9);
+
+        assert_eq!(
+            KdTree::from_points_with_duplicate_policy(&points, DuplicatePolicy::Error).unwrap_err(),
+            KdTreeError::Duplicate { index: 35, of: 0 }
+        );
+        assert!(
+            KdTree::from_points_with_duplicate_policy(&points[..35], DuplicatePolicy::Error)
+                .is_ok()
+        );
+    }
+}
@@ -0,0 +1,105 @@
+//! Errors of the fallible constructors
+use crate::{KdTree, KdTreeNoBorrow, Point};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Why a tree could not be built
+pub enum KdTreeError {
+    /// There were no points to build a tree of
+    Empty,
+    /// The point at `index` has a NaN or infinite coordinate along `axis`
+    NonFinite { index: usize, axis: usize },
+    /// The point at `index` has exactly the same coordinates as the point at `of`
+    Duplicate { index: usize, of: usize },
+    /// The builder sets `option`, which the requested tree type doesn't support
+    Unsupported { option: &'static str },
+}
+
+impl std::fmt::Display for KdTreeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KdTreeError::Empty => write!(f, "cannot build a tree without points"),
+            KdTreeError::NonFinite { index, axis } => {
+                write!(
+                    f,
+                    "point {index} has a non-finite coordinate along axis {axis}"
+                )
+            }
+            KdTreeError::Duplicate { index, of } => {
+                write!(f, "point {index} has the same coordinates as point {of}")
+            }
+            KdTreeError::Unsupported { option } => {
+                write!(
+                    f,
+                    "the option `{option}` is not supported by this tree type"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for KdTreeError {}
+
+impl<const D: usize, P: Point<D>> KdTreeNoBorrow<D, P> {
+    /// See `KdTree`
+    pub fn try_from_points(points: &[P]) -> Result<Self, KdTreeError> {
+        if points.is_empty() {
+            return Err(KdTreeError::Empty);
+        }
+
+        Ok(Self::from_points(points))
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `from_points`, but returns `KdTreeError::Empty` instead of building an empty
+    /// tree when there are no points. `from_points` itself accepts any number of points, and
+    /// queries on an empty tree find nothing.
+    pub fn try_from_points(points: &'a [P]) -> Result<Self, KdTreeError> {
+        Ok(Self {
+            internal: KdTreeNoBorrow::try_from_points(points)?,
+            points,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{KdTreeBuilder, SortingStrategy};
+
+    #[test]
+    fn test_empty_and_single_point() {
+        let empty: [[f32; 2]; 0] = [];
+        assert_eq!(
+            KdTree::try_from_points(&empty).unwrap_err(),
+            KdTreeError::Empty
+        );
+
+        let single = [[1.0_f32, 2.0]];
+        let tree = KdTree::try_from_points(&single).unwrap();
+        assert_eq!(tree.nearest_one([0.0, 0.0]), Some((0, 5.0_f32.sqrt())));
+
+        for strategy in [SortingStrategy::QuickSort, SortingStrategy::MedianSelect] {
+            for builder in [
+                KdTreeBuilder::new(),
+                KdTreeBuilder::new().presort(false),
+                KdTreeBuilder::new().threads(4),
+                KdTreeBuilder::new().bounding_spheres(true).page_size(256),
+            ] {
+                let builder = builder.sorting_strategy(strategy.clone());
+
+                let tree = builder.build(&empty);
+                assert!(tree.internal.tree.is_empty());
+                assert!(tree.point_indices_within([0.0, 0.0], 1.0).is_empty());
+                assert!(tree.nearest_n([0.0, 0.0], 3).is_empty());
+                assert_eq!(tree.nearest_one([0.0, 0.0]), None);
+                assert!(tree.farthest_n([0.0, 0.0], 3).is_empty());
+
+                let tree = builder.build(&single);
+                assert_eq!(tree.point_indices_within([1.0, 2.0], 0.0), vec![0]);
+                assert_eq!(tree.nearest_n([0.0, 0.0], 3), vec![0]);
+                assert_eq!(tree.farthest_n([0.0, 0.0], 3), vec![0]);
+            }
+        }
+    }
+}
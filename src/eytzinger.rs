@@ -0,0 +1,547 @@
+//! A Kd-tree laid out implicitly in an array, without child or parent links. The build and
+//! traversals of the layout are shared with `KdTreePrecise`, `KdTreeInteger` and
+//! `KdTreeFixed`, which read coordinates in another `Scalar` or keep their buffers in arrays.
+#[cfg(feature = "std")]
+use crate::nearest::pop_nearest_first;
+use crate::nearest::{Distance, Neighbor};
+use crate::{IntegerPoint, Point};
+
+use core::cmp::Ordering;
+#[cfg(feature = "std")]
+use std::collections::BinaryHeap;
+
+/// The number of nodes in the left subtree of a complete binary tree of `n` nodes, where
+/// every level is full except the last one which is filled from the left
+pub(crate) fn left_subtree_size(n: usize) -> usize {
+    if n <= 1 {
+        return 0;
+    }
+
+    let height = usize::BITS - 1 - n.leading_zeros();
+    let half_last_level = 1 << (height - 1);
+    let last_level = n - (2 * half_last_level - 1);
+
+    half_last_level - 1 + last_level.min(half_last_level)
+}
+
+/// A coordinate type the layout is built and queried in
+pub(crate) trait Scalar: Copy + PartialOrd {
+    type Squared: Distance;
+
+    fn total_cmp(&self, other: &Self) -> Ordering;
+
+    /// The square of `self - other`
+    fn difference_squared(self, other: Self) -> Self::Squared;
+}
+
+impl Scalar for f32 {
+    type Squared = f32;
+
+    #[inline(always)]
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        f32::total_cmp(self, other)
+    }
+
+    #[inline(always)]
+    fn difference_squared(self, other: Self) -> f32 {
+        (self - other) * (self - other)
+    }
+}
+
+impl Scalar for f64 {
+    type Squared = f64;
+
+    #[inline(always)]
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        f64::total_cmp(self, other)
+    }
+
+    #[inline(always)]
+    fn difference_squared(self, other: Self) -> f64 {
+        (self - other) * (self - other)
+    }
+}
+
+impl Scalar for i64 {
+    type Squared = u128;
+
+    #[inline(always)]
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        self.cmp(other)
+    }
+
+    #[inline(always)]
+    fn difference_squared(self, other: Self) -> u128 {
+        let difference = (self as i128 - other as i128).unsigned_abs();
+        difference * difference
+    }
+}
+
+/// How a point is read as coordinates of type `T`
+pub(crate) trait Coordinates<const D: usize, T: Scalar>: Copy {
+    fn coordinate(&self, axis: usize) -> T;
+    fn distance_squared_to(&self, other: Self) -> T::Squared;
+}
+
+impl<const D: usize, P: Point<D>> Coordinates<D, f32> for P {
+    #[inline(always)]
+    fn coordinate(&self, axis: usize) -> f32 {
+        self.get_axis(axis)
+    }
+
+    #[inline(always)]
+    fn distance_squared_to(&self, other: Self) -> f32 {
+        self.distance_squared(other)
+    }
+}
+
+impl<const D: usize, P: Point<D>> Coordinates<D, f64> for P {
+    #[inline(always)]
+    fn coordinate(&self, axis: usize) -> f64 {
+        self.get_axis_f64(axis)
+    }
+
+    #[inline(always)]
+    fn distance_squared_to(&self, other: Self) -> f64 {
+        self.distance_squared_f64(other)
+    }
+}
+
+impl<const D: usize, P: IntegerPoint<D>> Coordinates<D, i64> for P {
+    #[inline(always)]
+    fn coordinate(&self, axis: usize) -> i64 {
+        self.get_axis_i64(axis)
+    }
+
+    #[inline(always)]
+    fn distance_squared_to(&self, other: Self) -> u128 {
+        self.distance_squared_u128(other)
+    }
+}
+
+/// A traversal stack, either a `Vec` or an `ArrayStack` for trees that never allocate
+pub(crate) trait Stack<T> {
+    fn push(&mut self, item: T);
+    fn pop(&mut self) -> Option<T>;
+}
+
+#[cfg(feature = "std")]
+impl<T> Stack<T> for Vec<T> {
+    #[inline(always)]
+    fn push(&mut self, item: T) {
+        Vec::push(self, item);
+    }
+
+    #[inline(always)]
+    fn pop(&mut self) -> Option<T> {
+        Vec::pop(self)
+    }
+}
+
+/// A stack in an array of `N` items. Pushing onto a full stack panics.
+pub(crate) struct ArrayStack<T, const N: usize> {
+    items: [T; N],
+    len: usize,
+}
+
+impl<T: Copy + Default, const N: usize> ArrayStack<T, N> {
+    pub(crate) fn new() -> Self {
+        Self {
+            items: [T::default(); N],
+            len: 0,
+        }
+    }
+}
+
+impl<T: Copy, const N: usize> Stack<T> for ArrayStack<T, N> {
+    #[inline(always)]
+    fn push(&mut self, item: T) {
+        self.items[self.len] = item;
+        self.len += 1;
+    }
+
+    #[inline(always)]
+    fn pop(&mut self) -> Option<T> {
+        self.len = self.len.checked_sub(1)?;
+        Some(self.items[self.len])
+    }
+}
+
+/// Upper bound of the traversal and construction stack sizes. The layout is complete, so a
+/// tree is less than `usize::BITS` levels deep and a stack holds at most two nodes per level.
+pub(crate) const MAX_STACK: usize = 2 * usize::BITS as usize;
+
+/// The nearest points found so far by `collect_nearest`
+pub(crate) trait Candidates<T: Distance> {
+    /// The squared distance a point has to be within to be offered
+    fn bound(&self) -> T;
+    fn offer(&mut self, neighbor: Neighbor<T>);
+}
+
+/// The `k` nearest candidates in a heap, farthest on top
+#[cfg(feature = "std")]
+pub(crate) struct NearestN<'a, T> {
+    pub(crate) heap: &'a mut BinaryHeap<Neighbor<T>>,
+    pub(crate) k: usize,
+}
+
+#[cfg(feature = "std")]
+impl<T: Distance> Candidates<T> for NearestN<'_, T> {
+    #[inline(always)]
+    fn bound(&self) -> T {
+        if self.heap.len() == self.k {
+            self.heap.peek().map_or(T::MAX, |n| n.distance_squared)
+        } else {
+            T::MAX
+        }
+    }
+
+    #[inline(always)]
+    fn offer(&mut self, neighbor: Neighbor<T>) {
+        self.heap.push(neighbor);
+        if self.heap.len() > self.k {
+            self.heap.pop();
+        }
+    }
+}
+
+/// The nearest candidate, ties going to the lower index
+impl<T: Distance> Candidates<T> for Option<Neighbor<T>> {
+    #[inline(always)]
+    fn bound(&self) -> T {
+        self.map_or(T::MAX, |n| n.distance_squared)
+    }
+
+    #[inline(always)]
+    fn offer(&mut self, neighbor: Neighbor<T>) {
+        if self.is_none_or(|best| neighbor < best) {
+            *self = Some(neighbor);
+        }
+    }
+}
+
+/// Fills `indices` with the point ids of the nodes of the tree of `points` in Eytzinger order,
+/// sorting coordinates as `T`. `point_ids` has to hold every point id and is reordered. Each
+/// range is only partitioned around its pivot, since that is all the layout needs.
+pub(crate) fn build<const D: usize, T: Scalar, P: Coordinates<D, T>>(
+    points: &[P],
+    point_ids: &mut [usize],
+    indices: &mut [usize],
+) {
+    let n = point_ids.len();
+
+    /*
+        NOTE: (start, end, depth, tree_index) where start..end is half-open
+    */
+    let mut jobs = ArrayStack::<_, MAX_STACK>::new();
+    if n > 0 {
+        jobs.push((0, n, 0, 0));
+    }
+
+    while let Some((start, end, depth, tree_index)) = jobs.pop() {
+        let left_len = left_subtree_size(end - start);
+        let pivot_index = start + left_len;
+        let axis = depth % D;
+        point_ids[start..end].select_nth_unstable_by(left_len, |a, b| {
+            points[*a]
+                .coordinate(axis)
+                .total_cmp(&points[*b].coordinate(axis))
+        });
+
+        indices[tree_index] = point_ids[pivot_index];
+
+        if start != pivot_index {
+            jobs.push((start, pivot_index, depth + 1, 2 * tree_index + 1));
+        }
+        if pivot_index + 1 != end {
+            jobs.push((pivot_index + 1, end, depth + 1, 2 * tree_index + 2));
+        }
+    }
+}
+
+/// Calls `found` with every point of the tree `indices` whose squared distance to
+/// `query_point` is at most `radius_squared`. `stack` is assumed to be empty and is left empty.
+pub(crate) fn for_each_within<const D: usize, T, P, S>(
+    indices: &[usize],
+    points: &[P],
+    query_point: P,
+    radius_squared: T::Squared,
+    stack: &mut S,
+    mut found: impl FnMut(usize),
+) where
+    T: Scalar,
+    P: Coordinates<D, T>,
+    S: Stack<(usize, usize)>,
+{
+    if indices.is_empty() {
+        return;
+    }
+
+    stack.push((0, 0));
+    while let Some((depth, tree_index)) = stack.pop() {
+        let point_index = indices[tree_index];
+        let point = points[point_index];
+
+        if query_point.distance_squared_to(point) <= radius_squared {
+            found(point_index);
+        }
+
+        let axis = depth % D;
+        let (point_axis, query_axis) = (point.coordinate(axis), query_point.coordinate(axis));
+
+        let first = if point_axis >= query_axis { 0 } else { 1 };
+        let last = (first + 1) % 2;
+
+        let children = [2 * tree_index + 1, 2 * tree_index + 2];
+        if point_axis.difference_squared(query_axis) <= radius_squared
+            && children[last] < indices.len()
+        {
+            stack.push((depth + 1, children[last]));
+        }
+        if children[first] < indices.len() {
+            stack.push((depth + 1, children[first]));
+        }
+    }
+}
+
+/// Offers the points of the tree `indices` to `candidates`, visiting the side of the query
+/// point first and skipping subtrees beyond `Candidates::bound`. `stack` is assumed to be empty
+/// and is left empty.
+pub(crate) fn collect_nearest<const D: usize, T, P, S>(
+    indices: &[usize],
+    points: &[P],
+    query_point: P,
+    candidates: &mut impl Candidates<T::Squared>,
+    stack: &mut S,
+) where
+    T: Scalar,
+    P: Coordinates<D, T>,
+    S: Stack<(usize, usize, T::Squared)>,
+{
+    if indices.is_empty() {
+        return;
+    }
+
+    stack.push((0, 0, T::Squared::ZERO));
+    while let Some((depth, tree_index, lower_bound)) = stack.pop() {
+        let current_bound = candidates.bound();
+        if lower_bound > current_bound {
+            continue;
+        }
+
+        let point_index = indices[tree_index];
+        let point = points[point_index];
+
+        let distance_squared = query_point.distance_squared_to(point);
+        if distance_squared <= current_bound {
+            candidates.offer(Neighbor {
+                distance_squared,
+                index: point_index,
+            });
+        }
+
+        let axis = depth % D;
+        let (point_axis, query_axis) = (point.coordinate(axis), query_point.coordinate(axis));
+
+        let first = if point_axis >= query_axis { 0 } else { 1 };
+        let last = (first + 1) % 2;
+
+        /*
+            NOTE: The far side is pushed first so that the near side is popped first
+        */
+        let children = [2 * tree_index + 1, 2 * tree_index + 2];
+        if children[last] < indices.len() {
+            let axis_distance_squared = point_axis.difference_squared(query_axis);
+            let far_bound = if axis_distance_squared > lower_bound {
+                axis_distance_squared
+            } else {
+                lower_bound
+            };
+            stack.push((depth + 1, children[last], far_bound));
+        }
+        if children[first] < indices.len() {
+            stack.push((depth + 1, children[first], lower_bound));
+        }
+    }
+}
+
+/// Collects the `k` nearest points into `heap` and empties it into `result`, nearest first
+#[cfg(feature = "std")]
+pub(crate) fn nearest_n<const D: usize, T, P>(
+    indices: &[usize],
+    points: &[P],
+    query_point: P,
+    k: usize,
+    result: &mut Vec<usize>,
+    heap: &mut BinaryHeap<Neighbor<T::Squared>>,
+    stack: &mut Vec<(usize, usize, T::Squared)>,
+) where
+    T: Scalar,
+    P: Coordinates<D, T>,
+{
+    if k == 0 {
+        return;
+    }
+
+    collect_nearest(
+        indices,
+        points,
+        query_point,
+        &mut NearestN { heap, k },
+        stack,
+    );
+    pop_nearest_first(heap, result);
+}
+
+#[cfg(feature = "std")]
+#[derive(Debug, Clone)]
+/// A Kd-tree stored as a complete binary tree in Eytzinger order: the root is at 0 and the
+/// children of the node at `i` are at `2 * i + 1` and `2 * i + 2`, which exist if they are
+/// less than the number of points. Every node is only the index of its point, so the nodes
+/// take a small fraction of the memory of `KdTreeNode`s and the top levels share cache
+/// lines. Like `KdTreeNoBorrow`, the points are passed to every query and must not have been
+/// modified since building.
+///
+/// To keep the array complete, the pivot of every subtree is the point that leaves as many
+/// points on the left as a complete tree has, rather than the median, so the tree is not the
+/// same as the one of `KdTreeNoBorrow` although queries return the same points.
+pub struct KdTreeEytzinger<const D: usize, P: Point<D>> {
+    /// The index of the point at every node
+    pub indices: Vec<usize>,
+    pub __marker: std::marker::PhantomData<P>,
+}
+
+#[cfg(feature = "std")]
+impl<const D: usize, P: Point<D>> KdTreeEytzinger<D, P> {
+    /// Builds a tree of `points`
+    pub fn from_points(points: &[P]) -> Self {
+        let mut indices = vec![0; points.len()];
+        let mut point_ids = (0..points.len()).collect::<Vec<_>>();
+        build::<D, f32, P>(points, &mut point_ids, &mut indices);
+
+        Self {
+            indices,
+            __marker: std::marker::PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Same as `point_indices_within`, but you provide your own buffers. Indices are appended to
+    /// `result` which is not cleared. `stack` is assumed to be empty and is left empty.
+    pub fn point_indices_within_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        for_each_within::<D, f32, P, _>(
+            &self.indices,
+            points,
+            query_point,
+            radius * radius,
+            stack,
+            |index| result.push(index),
+        );
+    }
+
+    /// Returns the indices of the points within `radius` of `query_point`
+    pub fn point_indices_within(&self, points: &[P], query_point: P, radius: f32) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_buffers(points, query_point, radius, &mut result, &mut stack);
+
+        result
+    }
+
+    /// Same as `nearest_n`, but you provide your own buffers. Indices are appended to `result`
+    /// which is not cleared. `heap` and `stack` are assumed to be empty and are left empty.
+    pub fn nearest_n_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        nearest_n::<D, f32, P>(&self.indices, points, query_point, k, result, heap, stack);
+    }
+
+    /// Returns the indices of the `k` points closest to `query_point`, nearest first. Points at
+    /// the same distance are ordered by index.
+    pub fn nearest_n(&self, points: &[P], query_point: P, k: usize) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.indices.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.indices.len()) + 1);
+        let mut stack = vec![];
+
+        self.nearest_n_buffers(points, query_point, k, &mut result, &mut heap, &mut stack);
+
+        result
+    }
+
+    /// Returns the index of and distance to the point closest to `query_point`
+    pub fn nearest_one(&self, points: &[P], query_point: P) -> Option<(usize, f32)> {
+        let mut nearest = None;
+        collect_nearest::<D, f32, P, _>(
+            &self.indices,
+            points,
+            query_point,
+            &mut nearest,
+            &mut ArrayStack::<_, MAX_STACK>::new(),
+        );
+
+        nearest.map(|n: Neighbor| (n.index, n.distance_squared.sqrt()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+
+    #[test]
+    fn test_eytzinger() {
+        for n in 0..20 {
+            let points = (0..n)
+                .map(|i| [i as f32, (n - i) as f32])
+                .collect::<Vec<_>>();
+            let tree = KdTreeEytzinger::from_points(&points);
+
+            let mut indices = tree.indices.clone();
+            indices.sort_unstable();
+            assert_eq!(indices, (0..n).collect::<Vec<_>>());
+
+            let mut nearest = tree.nearest_n(&points, [0.0, n as f32], n + 5);
+            assert_eq!(nearest.first(), (n > 0).then_some(&0));
+            nearest.sort_unstable();
+            assert_eq!(nearest, indices);
+            assert_eq!(
+                tree.point_indices_within(&points, [0.0, 0.0], 100.0).len(),
+                n
+            );
+            assert_eq!(tree.nearest_one(&points, [0.0, 0.0]).is_none(), n == 0);
+        }
+
+        let points = fixture::points(59);
+        let tree = KdTreeEytzinger::from_points(&points);
+        fixture::check_queries(
+            &points,
+            |_| true,
+            |query_point, radius| tree.point_indices_within(&points, query_point, radius),
+            |query_point, k| tree.nearest_n(&points, query_point, k),
+            |query_point| tree.nearest_one(&points, query_point),
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! k farthest neighbor queries
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, Neighbor, NodeStorage, Point};
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Squared distance from `query_point_axis_values` to the farthest corner of the box
+/// `min..=max`, which bounds the distance to every point inside it
+#[inline(always)]
+fn farthest_corner_distance_squared<const D: usize>(
+    query_point_axis_values: &[f32; D],
+    min: &[f32; D],
+    max: &[f32; D],
+) -> f32 {
+    let mut distance_squared = 0.0;
+    for axis in 0..D {
+        let to_min = min[axis] - query_point_axis_values[axis];
+        let to_max = max[axis] - query_point_axis_values[axis];
+        distance_squared += (to_min * to_min).max(to_max * to_max);
+    }
+    distance_squared
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    #[allow(clippy::type_complexity)]
+    pub fn farthest_n_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Reverse<Neighbor>>,
+        stack: &mut Vec<(usize, usize, [f32; D], [f32; D])>,
+    ) {
+        if k == 0 || self.tree.is_empty() {
+            return;
+        }
+
+        let query_point_axis_values = axis_values(&query_point);
+
+        stack.push((0, 0, self.bounds_min, self.bounds_max));
+        while let Some((depth, tree_index, min, max)) = stack.pop() {
+            if heap.len() == k {
+                let upper_bound =
+                    farthest_corner_distance_squared(&query_point_axis_values, &min, &max);
+                if heap
+                    .peek()
+                    .is_some_and(|n| upper_bound < n.0.distance_squared)
+                {
+                    continue;
+                }
+            }
+
+            let point_index = self.tree[tree_index].index();
+            let point = points[point_index];
+
+            heap.push(Reverse(Neighbor {
+                distance_squared: query_point.distance_squared(point),
+                index: point_index,
+            }));
+            if heap.len() > k {
+                heap.pop();
+            }
+
+            let axis = self.tree[tree_index].axis();
+            let split = self.tree[tree_index].split;
+
+            let mut left_max = max;
+            left_max[axis] = split;
+            let mut right_min = min;
+            right_min[axis] = split;
+
+            let [left, right] = self.tree[tree_index].children();
+            let left = left.map(|child| (depth + 1, child, min, left_max));
+            let right = right.map(|child| (depth + 1, child, right_min, max));
+
+            /*
+                NOTE: The side away from the query point is popped first since that is where
+                      the farthest points tend to be
+            */
+            if split - query_point_axis_values[axis] >= 0.0 {
+                stack.extend(left);
+                stack.extend(right);
+            } else {
+                stack.extend(right);
+                stack.extend(left);
+            }
+        }
+
+        /*
+            NOTE: The heap pops the closest candidate first
+        */
+        let start = result.len();
+        while let Some(Reverse(neighbor)) = heap.pop() {
+            result.push(neighbor.index);
+        }
+        result[start..].reverse();
+    }
+
+    /// See `KdTree`
+    pub fn farthest_n(&self, points: &[P], query_point: P, k: usize) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.tree.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.tree.len()) + 1);
+        let mut stack = vec![];
+
+        self.farthest_n_buffers(points, query_point, k, &mut result, &mut heap, &mut stack);
+
+        result
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `farthest_n`, but you provide your own buffers. Indices are appended to `result`
+    /// which is not cleared. `heap` and `stack` are assumed to be empty and are left empty.
+    #[inline(always)]
+    #[allow(clippy::type_complexity)]
+    pub fn farthest_n_buffers(
+        &self,
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Reverse<Neighbor>>,
+        stack: &mut Vec<(usize, usize, [f32; D], [f32; D])>,
+    ) {
+        self.internal
+            .farthest_n_buffers(self.points, query_point, k, result, heap, stack)
+    }
+
+    /// Returns the indices of the `k` points farthest from `query_point`, farthest first. Points
+    /// at the same distance are ordered by descending index, so asking for every point gives
+    /// exactly the reverse of `nearest_n`.
+    ///
+    /// The candidates are kept in a min-heap of size `k`. Every subtree is bounded by the box
+    /// its points live in and skipped if even the farthest corner of that box is closer than
+    /// the current `k`th candidate.
+    #[inline(always)]
+    pub fn farthest_n(&self, query_point: P, k: usize) -> Vec<usize> {
+        self.internal.farthest_n(self.points, query_point, k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_farthest_n() {
+        let mut rng = SplitMix64::new(17);
+        let points = (0..1000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        for (query, k) in [
+            ([50.0, 50.0, 50.0], 10),
+            ([0.0, 100.0, 3.0], 1),
+            ([20.0, 80.0, 45.0], 37),
+        ] {
+            let mut expected = (0..points.len()).collect::<Vec<_>>();
+            expected.sort_by(|a, b| {
+                query
+                    .distance_squared(points[*b])
+                    .total_cmp(&query.distance_squared(points[*a]))
+                    .then(b.cmp(a))
+            });
+            expected.truncate(k);
+
+            assert_eq!(tree.farthest_n(query, k), expected);
+        }
+
+        let mut all = tree.farthest_n([1.0, 2.0, 3.0], points.len());
+        all.reverse();
+        assert_eq!(all, tree.nearest_n([1.0, 2.0, 3.0], points.len()));
+        assert!(tree.farthest_n([0.0; 3], 0).is_empty());
+    }
+}
@@ -0,0 +1,240 @@
+//! A Kd-tree with a fixed capacity that never allocates, for embedded targets indexing a
+//! handful of points. Everything, including the traversal stacks, lives in arrays, and it
+//! builds without the `std` feature.
+use crate::eytzinger::{
+    build, collect_nearest, for_each_within, ArrayStack, Candidates, MAX_STACK,
+};
+use crate::nearest::Distance;
+use crate::{Neighbor, Point};
+
+#[derive(Debug, Clone)]
+/// A Kd-tree of at most `N` points that stores its nodes inline and performs no heap allocations,
+/// neither when building nor when querying. Like `KdTreeNoBorrow`, the points are passed to every
+/// query and must not have been modified since building.
+///
+/// It uses the same layout as `KdTreeEytzinger`, which needs no links between the nodes.
+pub struct KdTreeFixed<const D: usize, P: Point<D>, const N: usize> {
+    /// The index of the point at every node, of which the first `len` are used
+    pub indices: [usize; N],
+    pub len: usize,
+    pub __marker: core::marker::PhantomData<P>,
+}
+
+impl<const D: usize, P: Point<D>, const N: usize> KdTreeFixed<D, P, N> {
+    /// Builds a tree of `points`. Returns `None` if there are more than `N` points.
+    pub fn from_points(points: &[P]) -> Option<Self> {
+        if points.len() > N {
+            return None;
+        }
+
+        let mut indices = [0; N];
+        let mut point_ids = [0; N];
+        for (i, id) in point_ids.iter_mut().enumerate() {
+            *id = i;
+        }
+
+        let len = points.len();
+        build::<D, f32, P>(points, &mut point_ids[..len], &mut indices[..len]);
+
+        Some(Self {
+            indices,
+            len,
+            __marker: core::marker::PhantomData,
+        })
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Calls `found` with the index of every point within `radius` of `query_point`
+    pub fn for_each_point_index_within<F>(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        found: F,
+    ) where
+        F: FnMut(usize),
+    {
+        for_each_within::<D, f32, P, _>(
+            &self.indices[..self.len],
+            points,
+            query_point,
+            radius * radius,
+            &mut ArrayStack::<_, MAX_STACK>::new(),
+            found,
+        );
+    }
+
+    /// Writes the indices of points within `radius` of `query_point` into `result` and returns how
+    /// many points were found. If `result` is too short, the extra indices are not written but
+    /// still counted, so a return value larger than `result.len()` means the result was truncated.
+    pub fn point_indices_within(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        result: &mut [usize],
+    ) -> usize {
+        let mut count = 0;
+
+        self.for_each_point_index_within(points, query_point, radius, |index| {
+            if let Some(slot) = result.get_mut(count) {
+                *slot = index;
+            }
+            count += 1;
+        });
+
+        count
+    }
+
+    /// Returns the index of and distance to the point closest to `query_point`
+    pub fn nearest_one(&self, points: &[P], query_point: P) -> Option<(usize, f32)> {
+        let mut nearest = None;
+        collect_nearest::<D, f32, P, _>(
+            &self.indices[..self.len],
+            points,
+            query_point,
+            &mut nearest,
+            &mut ArrayStack::<_, MAX_STACK>::new(),
+        );
+
+        nearest.map(|n: Neighbor| (n.index, sqrt(n.distance_squared)))
+    }
+
+    /// Writes the indices of the `result.len()` points closest to `query_point` into `result`,
+    /// nearest first, and returns how many were written, which is less when the tree holds
+    /// fewer points. Points at the same distance are ordered by index.
+    pub fn nearest_n(&self, points: &[P], query_point: P, result: &mut [usize]) -> usize {
+        let mut nearest = SortedCandidates::<N> {
+            neighbors: [Neighbor {
+                distance_squared: 0.0,
+                index: 0,
+            }; N],
+            len: 0,
+            k: result.len().min(N),
+        };
+        if nearest.k > 0 {
+            collect_nearest::<D, f32, P, _>(
+                &self.indices[..self.len],
+                points,
+                query_point,
+                &mut nearest,
+                &mut ArrayStack::<_, MAX_STACK>::new(),
+            );
+        }
+
+        for (slot, neighbor) in result.iter_mut().zip(&nearest.neighbors[..nearest.len]) {
+            *slot = neighbor.index;
+        }
+
+        nearest.len
+    }
+}
+
+/// The `k` nearest candidates sorted nearest first, in an array since the tree never holds more
+/// than `N` points
+struct SortedCandidates<const N: usize> {
+    neighbors: [Neighbor; N],
+    len: usize,
+    k: usize,
+}
+
+impl<const N: usize> Candidates<f32> for SortedCandidates<N> {
+    #[inline(always)]
+    fn bound(&self) -> f32 {
+        if self.len == self.k {
+            self.neighbors[self.len - 1].distance_squared
+        } else {
+            <f32 as Distance>::MAX
+        }
+    }
+
+    #[inline(always)]
+    fn offer(&mut self, neighbor: Neighbor) {
+        let position = self.neighbors[..self.len].partition_point(|n| *n < neighbor);
+        if position == self.k {
+            return;
+        }
+
+        self.len = (self.len + 1).min(self.k);
+        self.neighbors
+            .copy_within(position..self.len - 1, position + 1);
+        self.neighbors[position] = neighbor;
+    }
+}
+
+/*
+    NOTE: `core` has no square root, so without `std` it is found with Newton's method in double
+          precision, which converges to within an ulp of the result in a few steps
+*/
+#[inline(always)]
+fn sqrt(x: f32) -> f32 {
+    #[cfg(feature = "std")]
+    {
+        x.sqrt()
+    }
+
+    #[cfg(not(feature = "std"))]
+    {
+        if x.is_nan() || x < 0.0 {
+            return f32::NAN;
+        }
+        if x == 0.0 || x.is_infinite() {
+            return x;
+        }
+
+        let x = x as f64;
+        let mut root = f64::from_bits((x.to_bits() >> 1) + (1023 << 51));
+        for _ in 0..6 {
+            root = 0.5 * (root + x / root);
+        }
+        root as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed() {
+        let points: [[f32; 2]; 20] = core::array::from_fn(|i| [(i % 5) as f32, (i / 5) as f32]);
+        let tree = KdTreeFixed::<2, [f32; 2], 32>::from_points(&points).unwrap();
+        assert_eq!(tree.len(), 20);
+
+        let mut result = [0; 8];
+        let count = tree.point_indices_within(&points, [2.0, 1.0], 1.0, &mut result);
+        assert_eq!(count, 5);
+        result[..count].sort();
+        assert_eq!(&result[..count], &[2, 6, 7, 8, 12]);
+
+        let mut small = [0; 2];
+        assert_eq!(
+            tree.point_indices_within(&points, [2.0, 1.0], 1.0, &mut small),
+            5
+        );
+
+        assert_eq!(tree.nearest_one(&points, [3.9, 3.2]), Some((19, 0.2236068)));
+
+        let mut nearest = [0; 6];
+        assert_eq!(tree.nearest_n(&points, [2.0, 1.0], &mut nearest), 6);
+        assert_eq!(nearest, [7, 2, 6, 8, 12, 1]);
+        let mut all = [0; 24];
+        assert_eq!(tree.nearest_n(&points, [2.0, 1.0], &mut all), 20);
+        assert_eq!(&all[..6], &nearest);
+        assert_eq!(tree.nearest_n(&points, [2.0, 1.0], &mut []), 0);
+
+        let empty = KdTreeFixed::<2, [f32; 2], 4>::from_points(&[]).unwrap();
+        assert_eq!(empty.nearest_n(&points, [2.0, 1.0], &mut nearest), 0);
+        assert_eq!(empty.nearest_one(&points, [2.0, 1.0]), None);
+        assert!(KdTreeFixed::<2, [f32; 2], 8>::from_points(&points).is_none());
+    }
+}
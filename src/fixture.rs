@@ -0,0 +1,96 @@
+//! The points and queries shared by the tests of the tree types, checked against brute force
+use crate::utils::SplitMix64;
+use crate::Point;
+
+/// A query point near the middle of the points of `points`, one at an edge between integer
+/// coordinates and one outside of them
+pub(crate) const QUERY_POINTS: [[f32; 3]; 3] =
+    [[50.0, 50.0, 50.0], [0.0, 99.0, 3.5], [120.0, -4.0, 60.0]];
+
+pub(crate) const RADIUS: f32 = 12.0;
+
+/// The `k` of the k nearest queries
+pub(crate) const K: usize = 25;
+
+/// 3000 points generated from `seed` with integer coordinates from 0 to 99, so that many of
+/// them are at the same distance from a query point
+pub(crate) fn points(seed: u64) -> Vec<[f32; 3]> {
+    let mut rng = SplitMix64::new(seed);
+    (0..3000)
+        .map(|_| {
+            [
+                (rng.next_u64() % 100) as f32,
+                (rng.next_u64() % 100) as f32,
+                (rng.next_u64() % 100) as f32,
+            ]
+        })
+        .collect()
+}
+
+/// Checks a radius query of `RADIUS`, a k nearest query of `K` and a nearest query at every
+/// one of `QUERY_POINTS` against brute force over the points of `points` for which `kept`
+/// holds. Radius query results may be in any order, while the nearest points must be ordered
+/// by distance and then by index.
+pub(crate) fn check_queries(
+    points: &[[f32; 3]],
+    kept: impl Fn(usize) -> bool,
+    point_indices_within: impl Fn([f32; 3], f32) -> Vec<usize>,
+    nearest_n: impl Fn([f32; 3], usize) -> Vec<usize>,
+    nearest_one: impl Fn([f32; 3]) -> Option<(usize, f32)>,
+) {
+    check_queries_at(
+        &QUERY_POINTS,
+        points,
+        kept,
+        point_indices_within,
+        nearest_n,
+        nearest_one,
+    );
+}
+
+/// Same as `check_queries` at `query_points` instead, for trees that can't be queried at all
+/// of `QUERY_POINTS`
+pub(crate) fn check_queries_at(
+    query_points: &[[f32; 3]],
+    points: &[[f32; 3]],
+    kept: impl Fn(usize) -> bool,
+    point_indices_within: impl Fn([f32; 3], f32) -> Vec<usize>,
+    nearest_n: impl Fn([f32; 3], usize) -> Vec<usize>,
+    nearest_one: impl Fn([f32; 3]) -> Option<(usize, f32)>,
+) {
+    for query_point in query_points.iter().copied() {
+        let mut by_distance = (0..points.len())
+            .filter(|i| kept(*i))
+            .map(|i| (points[i].distance_squared(query_point), i))
+            .collect::<Vec<_>>();
+        by_distance.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+        let mut result = point_indices_within(query_point, RADIUS);
+        result.sort_unstable();
+        let mut expected = by_distance
+            .iter()
+            .filter(|(distance_squared, _)| *distance_squared <= RADIUS * RADIUS)
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        expected.sort_unstable();
+        assert_eq!(result, expected, "within {RADIUS} of {query_point:?}");
+
+        let expected = by_distance
+            .iter()
+            .take(K)
+            .map(|(_, i)| *i)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            nearest_n(query_point, K),
+            expected,
+            "{K} nearest to {query_point:?}"
+        );
+        assert_eq!(
+            nearest_one(query_point),
+            by_distance
+                .first()
+                .map(|(distance_squared, i)| (*i, distance_squared.sqrt())),
+            "nearest to {query_point:?}"
+        );
+    }
+}
@@ -0,0 +1,579 @@
+//! A forest of trees built chunk by chunk, for points that arrive in batches
+use crate::aabb::distance_squared_to_aabb;
+use crate::utils::{axis_values, compute_bounds};
+use crate::{KdTreeBuilder, KdTreeOwned, NonFinitePolicy, Point};
+
+use std::collections::HashMap;
+
+/// Keeps the points of the chunks a `KdForest` moved out of memory, for instance in files on
+/// disk, and hands them back when a query needs them. Chunks are identified by their position
+/// in the forest.
+pub trait ChunkStore<P> {
+    /// Takes the points of `chunk`
+    fn store(&mut self, chunk: usize, points: Vec<P>);
+
+    /// Appends the points stored for `chunk` to `points`, in the order they were stored
+    fn load(&mut self, chunk: usize, points: &mut Vec<P>);
+}
+
+/// Keeps the chunks in memory, which is mostly useful for testing a `ChunkStore` workflow
+impl<P: Copy> ChunkStore<P> for HashMap<usize, Vec<P>> {
+    fn store(&mut self, chunk: usize, points: Vec<P>) {
+        self.insert(chunk, points);
+    }
+
+    fn load(&mut self, chunk: usize, points: &mut Vec<P>) {
+        points.extend_from_slice(&self[&chunk]);
+    }
+}
+
+/// The store of the queries that don't take one, which only work while no chunk they need
+/// has been spilled
+struct NotSpilled;
+
+impl<P> ChunkStore<P> for NotSpilled {
+    fn store(&mut self, _chunk: usize, _points: Vec<P>) {
+        unreachable!("nothing is spilled without a store");
+    }
+
+    fn load(&mut self, chunk: usize, _points: &mut Vec<P>) {
+        panic!("chunk {chunk} of the forest is spilled, pass the store it was spilled to");
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A chunk of a `KdForest`
+pub enum ForestChunk<const D: usize, P: Point<D>> {
+    /// A chunk whose tree is in memory
+    Resident(KdTreeOwned<D, P>),
+    /// A chunk whose points are in a `ChunkStore`. `removed` holds the sorted indices within the
+    /// chunk of the points removed from it.
+    Spilled { len: usize, removed: Vec<usize> },
+}
+
+impl<const D: usize, P: Point<D>> ForestChunk<D, P> {
+    /// The number of points of the chunk, including removed ones
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Resident(tree) => tree.points().len(),
+            Self::Spilled { len, .. } => *len,
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A set of trees, one per chunk of points, that is queried as if it were a single tree. Every
+/// chunk is built as soon as it is pushed, so a reader producing batches of points, such as a
+/// LAS reader, never needs all of them in one slice. The points of a chunk get the indices
+/// following those of the chunk before it, so indices are the same as if all chunks had been
+/// concatenated in order.
+///
+/// For point sets larger than memory, chunks can be moved to a `ChunkStore` with `spill_chunk`
+/// or pushed there directly with `push_spilled_chunk`. Only the bounds and removed points of a
+/// spilled chunk stay in memory. The `_stored` queries load the spilled chunks within their
+/// reach one at a time and scan their points, while the other queries panic if they need one.
+/// `load_chunk` builds the tree of a spilled chunk again for chunks that are queried often.
+///
+/// Queries visit every chunk within reach, so once all points have arrived, `into_tree` builds
+/// a single tree that queries faster. Points removed with `remove` are skipped by every query.
+pub struct KdForest<const D: usize, P: Point<D>> {
+    pub chunks: Vec<ForestChunk<D, P>>,
+    /// The index of the first point of every chunk
+    pub offsets: Vec<usize>,
+    /// The smallest and largest value of every axis among the points of every chunk, which lets
+    /// queries skip the chunks out of their reach
+    pub bounds: Vec<([f32; D], [f32; D])>,
+    pub len: usize,
+    /// The settings the tree of every chunk and of `into_tree` is built with
+    pub builder: KdTreeBuilder,
+}
+
+impl<const D: usize, P: Point<D>> Default for KdForest<D, P> {
+    fn default() -> Self {
+        Self {
+            chunks: vec![],
+            offsets: vec![],
+            bounds: vec![],
+            len: 0,
+            builder: KdTreeBuilder::default(),
+        }
+    }
+}
+
+impl<const D: usize, P: Point<D> + Sync> KdForest<D, P> {
+    /// An empty forest building its trees with default settings
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An empty forest building its trees with the settings of `builder`
+    pub fn with_builder(builder: KdTreeBuilder) -> Self {
+        Self {
+            builder,
+            ..Self::default()
+        }
+    }
+
+    /// Builds a tree of `points` and adds it to the forest. Empty chunks are ignored.
+    pub fn push_chunk(&mut self, points: Vec<P>) {
+        if points.is_empty() {
+            return;
+        }
+
+        self.push_bounds(&points);
+        self.chunks
+            .push(ForestChunk::Resident(self.builder.build_owned(points)));
+    }
+
+    /// Adds `points` to the forest as a spilled chunk, handing them to `store` without building
+    /// a tree of them. Empty chunks are ignored.
+    pub fn push_spilled_chunk<S: ChunkStore<P>>(&mut self, points: Vec<P>, store: &mut S) {
+        if points.is_empty() {
+            return;
+        }
+
+        self.push_bounds(&points);
+        let len = points.len();
+        store.store(self.chunks.len(), points);
+        self.chunks.push(ForestChunk::Spilled {
+            len,
+            removed: vec![],
+        });
+    }
+
+    fn push_bounds(&mut self, points: &[P]) {
+        self.offsets.push(self.len);
+        self.bounds.push(compute_bounds(points));
+        self.len += points.len();
+    }
+
+    /// Moves the points of `chunk` to `store` and drops its tree. Does nothing if the chunk is
+    /// spilled already.
+    pub fn spill_chunk<S: ChunkStore<P>>(&mut self, chunk: usize, store: &mut S) {
+        let ForestChunk::Resident(tree) = &self.chunks[chunk] else {
+            return;
+        };
+
+        let removed = (0..tree.points().len())
+            .filter(|i| tree.is_removed(*i))
+            .collect();
+        let spilled = ForestChunk::Spilled {
+            len: tree.points().len(),
+            removed,
+        };
+
+        if let ForestChunk::Resident(tree) = std::mem::replace(&mut self.chunks[chunk], spilled) {
+            store.store(chunk, tree.into_parts().0);
+        }
+    }
+
+    /// Loads the points of `chunk` from `store` and builds its tree again. Does nothing if the
+    /// chunk is resident.
+    pub fn load_chunk<S: ChunkStore<P>>(&mut self, chunk: usize, store: &mut S) {
+        let ForestChunk::Spilled { removed, .. } = &self.chunks[chunk] else {
+            return;
+        };
+
+        let mut points = vec![];
+        store.load(chunk, &mut points);
+
+        let mut tree = self.builder.build_owned(points);
+        for index in removed {
+            tree.remove(*index);
+        }
+        self.chunks[chunk] = ForestChunk::Resident(tree);
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The chunk holding the point at `index` and the index of the point within it
+    fn locate(&self, index: usize) -> (usize, usize) {
+        let chunk = self.offsets.partition_point(|offset| *offset <= index) - 1;
+        (chunk, index - self.offsets[chunk])
+    }
+
+    /// The point at `index`, counting over all chunks. Panics if its chunk is spilled.
+    pub fn point(&self, index: usize) -> P {
+        let (chunk, local) = self.locate(index);
+        match &self.chunks[chunk] {
+            ForestChunk::Resident(tree) => tree.points()[local],
+            ForestChunk::Spilled { .. } => {
+                panic!("chunk {chunk} of the forest is spilled, load it first")
+            }
+        }
+    }
+
+    /// Marks the point at `index` as removed so that queries skip it, whether its chunk is
+    /// resident or spilled. Returns `false` if it was already removed.
+    pub fn remove(&mut self, index: usize) -> bool {
+        let (chunk, local) = self.locate(index);
+        match &mut self.chunks[chunk] {
+            ForestChunk::Resident(tree) => tree.remove(local),
+            ForestChunk::Spilled { removed, .. } => match removed.binary_search(&local) {
+                Ok(_) => false,
+                Err(position) => {
+                    removed.insert(position, local);
+                    true
+                }
+            },
+        }
+    }
+
+    /// Whether the point at `index` has been removed
+    pub fn is_removed(&self, index: usize) -> bool {
+        let (chunk, local) = self.locate(index);
+        match &self.chunks[chunk] {
+            ForestChunk::Resident(tree) => tree.is_removed(local),
+            ForestChunk::Spilled { removed, .. } => removed.binary_search(&local).is_ok(),
+        }
+    }
+
+    /// Calls `found` with the index within `chunk` and the squared distance to `query_point` of
+    /// every point of the spilled `chunk` that queries can return, loading them into `buffer`
+    fn scan_spilled<S, F>(
+        &self,
+        chunk: usize,
+        query_point: P,
+        store: &mut S,
+        buffer: &mut Vec<P>,
+        mut found: F,
+    ) where
+        S: ChunkStore<P>,
+        F: FnMut(usize, f32),
+    {
+        let ForestChunk::Spilled { removed, .. } = &self.chunks[chunk] else {
+            return;
+        };
+
+        buffer.clear();
+        store.load(chunk, buffer);
+
+        /*
+            NOTE: A tree built with `NonFinitePolicy::Exclude` never returns its non-finite
+                  points, so the scan leaves them out as well
+        */
+        let exclude = self.builder.non_finite_policy == Some(NonFinitePolicy::Exclude);
+
+        let mut removed = removed.iter().peekable();
+        for (index, point) in buffer.iter().enumerate() {
+            if removed.next_if_eq(&&index).is_some() {
+                continue;
+            }
+            if exclude && (0..D).any(|axis| !point.get_axis(axis).is_finite()) {
+                continue;
+            }
+
+            found(index, query_point.distance_squared(*point));
+        }
+    }
+
+    /// Returns the indices of the points within `radius` of `query_point` in every chunk.
+    /// Panics if a spilled chunk is within reach, see `point_indices_within_stored`.
+    pub fn point_indices_within(&self, query_point: P, radius: f32) -> Vec<usize> {
+        self.point_indices_within_stored(query_point, radius, &mut NotSpilled)
+    }
+
+    /// Same as `point_indices_within`, loading the spilled chunks within reach from `store`
+    pub fn point_indices_within_stored<S: ChunkStore<P>>(
+        &self,
+        query_point: P,
+        radius: f32,
+        store: &mut S,
+    ) -> Vec<usize> {
+        let query_point_axis_values = axis_values(&query_point);
+        let radius_squared = radius * radius;
+
+        let mut result = vec![];
+        let mut stack = vec![];
+        let mut buffer = vec![];
+
+        for chunk in 0..self.chunks.len() {
+            let (min, max) = &self.bounds[chunk];
+            if distance_squared_to_aabb(&query_point_axis_values, min, max) > radius_squared {
+                continue;
+            }
+
+            let start = result.len();
+            match &self.chunks[chunk] {
+                ForestChunk::Resident(tree) => {
+                    tree.point_indices_within_buffers(query_point, radius, &mut result, &mut stack)
+                }
+                ForestChunk::Spilled { .. } => self.scan_spilled(
+                    chunk,
+                    query_point,
+                    store,
+                    &mut buffer,
+                    |index, distance_squared| {
+                        if distance_squared <= radius_squared {
+                            result.push(index);
+                        }
+                    },
+                ),
+            }
+
+            let offset = self.offsets[chunk];
+            result[start..].iter_mut().for_each(|i| *i += offset);
+        }
+
+        result
+    }
+
+    /// Returns the indices of the `k` points closest to `query_point` over all chunks, nearest
+    /// first. Points at the same distance are ordered by index. Panics if a spilled chunk is
+    /// within reach, see `nearest_n_stored`.
+    pub fn nearest_n(&self, query_point: P, k: usize) -> Vec<usize> {
+        self.nearest_n_stored(query_point, k, &mut NotSpilled)
+    }
+
+    /// Same as `nearest_n`, loading the spilled chunks within reach from `store`
+    pub fn nearest_n_stored<S: ChunkStore<P>>(
+        &self,
+        query_point: P,
+        k: usize,
+        store: &mut S,
+    ) -> Vec<usize> {
+        self.nearest_n_with_distances(query_point, k, store)
+            .into_iter()
+            .map(|(_, i)| i)
+            .collect()
+    }
+
+    /// The `k` closest points as `(squared distance, index)`, nearest first
+    fn nearest_n_with_distances<S: ChunkStore<P>>(
+        &self,
+        query_point: P,
+        k: usize,
+        store: &mut S,
+    ) -> Vec<(f32, usize)> {
+        let query_point_axis_values = axis_values(&query_point);
+
+        /*
+            NOTE: Chunks are visited closest first, so that the ones further away than the k:th
+                  candidate found so far can be skipped
+        */
+        let mut chunks = (0..self.chunks.len())
+            .map(|chunk| {
+                let (min, max) = &self.bounds[chunk];
+                (
+                    distance_squared_to_aabb(&query_point_axis_values, min, max),
+                    chunk,
+                )
+            })
+            .collect::<Vec<_>>();
+        chunks.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+
+        let mut candidates: Vec<(f32, usize)> = vec![];
+        let mut buffer = vec![];
+
+        for (bound, chunk) in chunks {
+            if k == 0 || candidates.len() == k && bound > candidates[k - 1].0 {
+                break;
+            }
+
+            let offset = self.offsets[chunk];
+            match &self.chunks[chunk] {
+                ForestChunk::Resident(tree) => candidates.extend(
+                    tree.nearest_n(query_point, k)
+                        .into_iter()
+                        .map(|i| (query_point.distance_squared(tree.points()[i]), i + offset)),
+                ),
+                ForestChunk::Spilled { .. } => self.scan_spilled(
+                    chunk,
+                    query_point,
+                    store,
+                    &mut buffer,
+                    |index, distance_squared| candidates.push((distance_squared, index + offset)),
+                ),
+            }
+
+            candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            candidates.truncate(k);
+        }
+
+        candidates
+    }
+
+    /// Returns the index of and distance to the point closest to `query_point` over all chunks.
+    /// Panics if a spilled chunk is within reach, see `nearest_one_stored`.
+    pub fn nearest_one(&self, query_point: P) -> Option<(usize, f32)> {
+        self.nearest_one_stored(query_point, &mut NotSpilled)
+    }
+
+    /// Same as `nearest_one`, loading the spilled chunks within reach from `store`
+    pub fn nearest_one_stored<S: ChunkStore<P>>(
+        &self,
+        query_point: P,
+        store: &mut S,
+    ) -> Option<(usize, f32)> {
+        self.nearest_n_with_distances(query_point, 1, store)
+            .first()
+            .map(|(distance_squared, index)| (*index, distance_squared.sqrt()))
+    }
+
+    /// Concatenates the points of all chunks in order and builds a single tree of them with
+    /// `builder`. The points removed from the forest are removed from the tree, so it returns
+    /// the same indices as the forest. Panics if a chunk is spilled, see `into_tree_stored`.
+    pub fn into_tree(self) -> KdTreeOwned<D, P> {
+        self.into_tree_stored(&mut NotSpilled)
+    }
+
+    /// Same as `into_tree`, loading the points of the spilled chunks from `store`
+    pub fn into_tree_stored<S: ChunkStore<P>>(self, store: &mut S) -> KdTreeOwned<D, P> {
+        let mut points = Vec::with_capacity(self.len);
+        let mut removed = vec![];
+
+        for (chunk, (forest_chunk, offset)) in self.chunks.into_iter().zip(self.offsets).enumerate()
+        {
+            match forest_chunk {
+                ForestChunk::Resident(tree) => {
+                    removed.extend(
+                        (0..tree.points().len())
+                            .filter(|i| tree.is_removed(*i))
+                            .map(|i| i + offset),
+                    );
+                    points.extend(tree.into_parts().0);
+                }
+                ForestChunk::Spilled {
+                    removed: spilled_removed,
+                    ..
+                } => {
+                    removed.extend(spilled_removed.into_iter().map(|i| i + offset));
+                    store.load(chunk, &mut points);
+                }
+            }
+        }
+
+        let mut tree = self.builder.build_owned(points);
+        for index in removed {
+            tree.remove(index);
+        }
+
+        tree
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::utils::SplitMix64;
+    use crate::SplitStrategy;
+
+    #[test]
+    fn test_forest() {
+        let points = fixture::points(83);
+
+        let mut forest = KdForest::new();
+        for chunk in [0..700, 700..700, 700..1900, 1900..3000] {
+            forest.push_chunk(points[chunk].to_vec());
+        }
+        assert_eq!(forest.len(), 3000);
+        assert_eq!(forest.chunks.len(), 3);
+        assert_eq!(forest.point(1899), points[1899]);
+        assert_eq!(forest.point(1900), points[1900]);
+
+        fixture::check_queries(
+            &points,
+            |_| true,
+            |query_point, radius| forest.point_indices_within(query_point, radius),
+            |query_point, k| forest.nearest_n(query_point, k),
+            |query_point| forest.nearest_one(query_point),
+        );
+
+        let tree = forest.into_tree();
+        assert_eq!(tree.points, points);
+
+        let empty = KdForest::<3, [f32; 3]>::new();
+        assert!(empty.is_empty());
+        assert!(empty.point_indices_within([0.0; 3], 10.0).is_empty());
+        assert!(empty.nearest_n([0.0; 3], 3).is_empty());
+        assert_eq!(empty.nearest_one([0.0; 3]), None);
+        assert!(empty.into_tree().is_empty());
+    }
+
+    #[test]
+    fn test_forest_removed_and_spilled() {
+        let mut rng = SplitMix64::new(131);
+        let mut points = fixture::points(113);
+        points[5][1] = f32::NAN;
+        points[2500][0] = f32::INFINITY;
+
+        let builder = KdTreeBuilder::new()
+            .split_strategy(SplitStrategy::WidestAxis)
+            .non_finite_policy(NonFinitePolicy::Exclude);
+        let mut store = HashMap::new();
+        let mut forest = KdForest::with_builder(builder);
+        forest.push_chunk(points[0..700].to_vec());
+        forest.push_spilled_chunk(points[700..1900].to_vec(), &mut store);
+        forest.push_chunk(points[1900..3000].to_vec());
+        forest.spill_chunk(2, &mut store);
+        assert!(matches!(forest.chunks[0], ForestChunk::Resident(_)));
+        assert!(matches!(forest.chunks[2], ForestChunk::Spilled { .. }));
+
+        let mut removed = vec![false; points.len()];
+        for _ in 0..1000 {
+            let index = (rng.next_u64() % points.len() as u64) as usize;
+            assert_eq!(forest.remove(index), !removed[index]);
+            removed[index] = true;
+        }
+        let kept = |i: &usize| !removed[*i] && points[*i].iter().all(|value| value.is_finite());
+
+        let check = |forest: &KdForest<3, [f32; 3]>, store: &mut HashMap<usize, Vec<[f32; 3]>>| {
+            let store = std::cell::RefCell::new(store);
+            fixture::check_queries(
+                &points,
+                |i| kept(&i),
+                |query_point, radius| {
+                    forest.point_indices_within_stored(
+                        query_point,
+                        radius,
+                        &mut **store.borrow_mut(),
+                    )
+                },
+                |query_point, k| forest.nearest_n_stored(query_point, k, &mut **store.borrow_mut()),
+                |query_point| forest.nearest_one_stored(query_point, &mut **store.borrow_mut()),
+            );
+        };
+
+        check(&forest, &mut store);
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            forest.nearest_n([50.0, 50.0, 50.0], 25)
+        }));
+        assert!(result.is_err());
+
+        forest.load_chunk(1, &mut store);
+        forest.spill_chunk(0, &mut store);
+        check(&forest, &mut store);
+        assert!((0..points.len()).all(|i| forest.is_removed(i) == removed[i]));
+
+        let tree = forest.into_tree_stored(&mut store);
+        assert!(tree.internal.subset);
+        assert_eq!(
+            tree.internal.config.split_strategy,
+            SplitStrategy::WidestAxis
+        );
+        assert_eq!(tree.points().len(), points.len());
+        fixture::check_queries(
+            &points,
+            |i| kept(&i),
+            |query_point, radius| tree.point_indices_within(query_point, radius),
+            |query_point, k| tree.nearest_n(query_point, k),
+            |query_point| tree.nearest_one(query_point),
+        );
+        assert!((0..points.len()).all(|i| tree.is_removed(i) == removed[i]));
+    }
+}
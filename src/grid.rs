@@ -0,0 +1,237 @@
+//! Evaluating nearest neighbors over every cell of a regular grid
+use crate::nearest::{distance_squared_to_axis_values, Neighbor};
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+use std::collections::BinaryHeap;
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// Sweeps the cells of the grid in row-major order, calling `cell_done` with the `k` nearest
+    /// neighbors of the center of every cell, nearest first. `k` must be at most the number of
+    /// points and greater than zero.
+    pub(crate) fn sweep_grid<F>(
+        &self,
+        points: &[P],
+        grid_min: [f32; D],
+        grid_max: [f32; D],
+        resolution: [usize; D],
+        k: usize,
+        mut cell_done: F,
+    ) where
+        F: FnMut(&[Neighbor]),
+    {
+        let cell_count = resolution.iter().product::<usize>();
+
+        let mut cell_size = [0.0; D];
+        for axis in 0..D {
+            cell_size[axis] = (grid_max[axis] - grid_min[axis]) / resolution[axis] as f32;
+        }
+
+        let mut heap = BinaryHeap::with_capacity(k + 1);
+        let mut sorted: Vec<Neighbor> = Vec::with_capacity(k);
+        let mut stack = vec![];
+
+        let mut cell = [0; D];
+        for _ in 0..cell_count {
+            let mut center = [0.0; D];
+            for axis in 0..D {
+                center[axis] = grid_min[axis] + (cell[axis] as f32 + 0.5) * cell_size[axis];
+            }
+
+            /*
+                NOTE: The neighbors of the previous cell are `k` distinct points, so the
+                      farthest of them from this cell bounds its `k`th nearest neighbor.
+                      Consecutive cells are mostly adjacent, which makes the bound tight.
+            */
+            let bound_squared = if sorted.is_empty() {
+                f32::INFINITY
+            } else {
+                sorted
+                    .iter()
+                    .map(|neighbor| {
+                        distance_squared_to_axis_values(&points[neighbor.index], &center)
+                    })
+                    .fold(0.0, f32::max)
+            };
+
+            self.nearest_n_axis_values_buffers(
+                points,
+                &center,
+                k,
+                bound_squared,
+                &mut heap,
+                &mut stack,
+            );
+
+            sorted.clear();
+            sorted.extend(heap.drain());
+            sorted.sort_unstable();
+
+            cell_done(&sorted);
+
+            for axis in 0..D {
+                cell[axis] += 1;
+                if cell[axis] < resolution[axis] {
+                    break;
+                }
+                cell[axis] = 0;
+            }
+        }
+    }
+
+    /// See `KdTree`
+    pub fn classify_grid(
+        &self,
+        points: &[P],
+        grid_min: [f32; D],
+        grid_max: [f32; D],
+        resolution: [usize; D],
+        k: usize,
+    ) -> Vec<usize> {
+        let k = k.min(self.tree.len());
+        let cell_count = resolution.iter().product::<usize>();
+
+        let mut result = Vec::with_capacity(cell_count * k);
+        if k == 0 {
+            return result;
+        }
+
+        self.sweep_grid(points, grid_min, grid_max, resolution, k, |neighbors| {
+            result.extend(neighbors.iter().map(|neighbor| neighbor.index));
+        });
+
+        result
+    }
+
+    /// See `KdTree`
+    pub fn distance_transform(
+        &self,
+        points: &[P],
+        grid_min: [f32; D],
+        grid_max: [f32; D],
+        resolution: [usize; D],
+    ) -> Vec<f32> {
+        let cell_count = resolution.iter().product::<usize>();
+        if self.tree.is_empty() {
+            return vec![f32::INFINITY; cell_count];
+        }
+
+        let mut result = Vec::with_capacity(cell_count);
+        self.sweep_grid(points, grid_min, grid_max, resolution, 1, |neighbors| {
+            result.push(neighbors[0].distance_squared.sqrt());
+        });
+
+        result
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Finds the `k` nearest points of the center of every cell of the regular grid spanning
+    /// `grid_min..grid_max` with `resolution` cells along each axis.
+    ///
+    /// The result holds `k.min(points.len())` indices per cell, nearest first, with the cells in
+    /// row-major order where axis 0 varies fastest. For `k == 1` this is a Voronoi labeling of
+    /// the grid.
+    ///
+    /// The cells are swept in order and each query is bounded by the neighbors of the previous
+    /// cell, so far fewer nodes are visited before the first candidates are found than with
+    /// independent queries.
+    pub fn classify_grid(
+        &self,
+        grid_min: [f32; D],
+        grid_max: [f32; D],
+        resolution: [usize; D],
+        k: usize,
+    ) -> Vec<usize> {
+        self.internal
+            .classify_grid(self.points, grid_min, grid_max, resolution, k)
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Computes the distance from the center of every cell of the regular grid spanning
+    /// `grid_min..grid_max` to the nearest point, with the cells in the same order as
+    /// `classify_grid`. Every distance is infinite if there are no points.
+    ///
+    /// This is the unsigned distance field of the points, as used for navigation cost fields or
+    /// when baking signed distance fields. It uses the same coherent sweep as `classify_grid`.
+    pub fn distance_transform(
+        &self,
+        grid_min: [f32; D],
+        grid_max: [f32; D],
+        resolution: [usize; D],
+    ) -> Vec<f32> {
+        self.internal
+            .distance_transform(self.points, grid_min, grid_max, resolution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::utils::SplitMix64;
+    use crate::KdTree;
+
+    #[test]
+    fn test_classify_grid() {
+        let mut rng = SplitMix64::new(7);
+        let points = (0..300)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 1000) as f32 / 100.0,
+                    (rng.next_u64() % 1000) as f32 / 100.0,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        let k = 3;
+        let resolution = [17, 11];
+        let labels = tree.classify_grid([0.0, 0.0], [10.0, 10.0], resolution, k);
+        assert_eq!(labels.len(), 17 * 11 * k);
+
+        for y in 0..resolution[1] {
+            for x in 0..resolution[0] {
+                let center = [
+                    (x as f32 + 0.5) * 10.0 / 17.0,
+                    (y as f32 + 0.5) * 10.0 / 11.0,
+                ];
+                let mut expected = (0..points.len()).collect::<Vec<_>>();
+                expected.sort_by(|a, b| {
+                    let da =
+                        (points[*a][0] - center[0]).powi(2) + (points[*a][1] - center[1]).powi(2);
+                    let db =
+                        (points[*b][0] - center[0]).powi(2) + (points[*b][1] - center[1]).powi(2);
+                    da.total_cmp(&db).then(a.cmp(b))
+                });
+
+                let cell = y * resolution[0] + x;
+                assert_eq!(&labels[cell * k..(cell + 1) * k], &expected[..k]);
+            }
+        }
+
+        assert_eq!(
+            tree.classify_grid([0.0, 0.0], [1.0, 1.0], [2, 2], 500)
+                .len(),
+            4 * 300
+        );
+    }
+
+    #[test]
+    fn test_distance_transform() {
+        let points = [[1.0_f32, 1.0], [3.0, 0.5]];
+        let tree = KdTree::from_points(&points);
+
+        let distances = tree.distance_transform([0.0, 0.0], [4.0, 2.0], [4, 2]);
+        assert_eq!(distances.len(), 8);
+
+        for y in 0..2 {
+            for x in 0..4 {
+                let center = [x as f32 + 0.5, y as f32 + 0.5];
+                let expected = points
+                    .iter()
+                    .map(|p| ((p[0] - center[0]).powi(2) + (p[1] - center[1]).powi(2)).sqrt())
+                    .fold(f32::INFINITY, f32::min);
+                assert_eq!(distances[y * 4 + x], expected);
+            }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! A Kd-tree storing a copy of every point in its node
+use crate::bucketed::{collect_nearest, for_each_within, LinkedNodes};
+use crate::eytzinger::NearestN;
+use crate::nearest::{pop_nearest_first, Neighbor};
+use crate::{KdTreeNoBorrow, Point};
+
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone, Copy)]
+/// Internal node within the KdTreeInline
+pub struct KdTreeInlineNode<const D: usize, P: Point<D>> {
+    /// A copy of the point with index `index`
+    pub point: P,
+    pub index: usize,
+    pub axis: usize,
+    pub children: [Option<usize>; 2],
+}
+
+#[derive(Debug, Clone)]
+/// A Kd-tree that keeps a copy of every point next to the rest of its node, so a traversal
+/// reads one node per visited point instead of jumping between the nodes and the point slice.
+/// This costs the memory of a second copy of the points and pays off for queries that run
+/// every frame on trees larger than the cache.
+///
+/// Queries return the indices the points had when building and don't need the points, which
+/// also means that changing the original points doesn't affect the tree.
+pub struct KdTreeInline<const D: usize, P: Point<D>> {
+    pub nodes: Vec<KdTreeInlineNode<D, P>>,
+}
+
+impl<const D: usize, P: Point<D>> KdTreeInline<D, P> {
+    /// Constructs a new tree of copies of `points` using default settings
+    pub fn from_points(points: &[P]) -> Self {
+        Self::from_tree(&KdTreeNoBorrow::from_points(points), points)
+    }
+
+    /// Copies the structure of `tree`, built from `points`, together with the points. Use this
+    /// to build with `KdTreeBuilder`.
+    pub fn from_tree(tree: &KdTreeNoBorrow<D, P>, points: &[P]) -> Self {
+        let nodes = tree
+            .tree
+            .iter()
+            .map(|node| KdTreeInlineNode {
+                point: points[node.index()],
+                index: node.index(),
+                axis: node.axis(),
+                children: node.children(),
+            })
+            .collect();
+
+        Self { nodes }
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Same as `point_indices_within`, but you provide your own buffers. Indices are appended to
+    /// `result` which is not cleared. `stack` is assumed to be empty and is left empty.
+    pub fn point_indices_within_buffers(
+        &self,
+        query_point: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<usize>,
+    ) {
+        for_each_within(self, query_point, radius * radius, stack, |index| {
+            result.push(index)
+        });
+    }
+
+    /// Returns the indices of the points within `radius` of `query_point`
+    pub fn point_indices_within(&self, query_point: P, radius: f32) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_buffers(query_point, radius, &mut result, &mut stack);
+
+        result
+    }
+
+    /// Same as `nearest_n`, but you provide your own buffers. Indices are appended to `result`
+    /// which is not cleared. `heap` and `stack` are assumed to be empty and are left empty.
+    pub fn nearest_n_buffers(
+        &self,
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, f32)>,
+    ) {
+        if k == 0 {
+            return;
+        }
+
+        collect_nearest(self, query_point, &mut NearestN { heap, k }, stack);
+        pop_nearest_first(heap, result);
+    }
+
+    /// Returns the indices of the `k` points closest to `query_point`, nearest first. Points at
+    /// the same distance are ordered by index.
+    pub fn nearest_n(&self, query_point: P, k: usize) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.nodes.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.nodes.len()) + 1);
+        let mut stack = vec![];
+
+        self.nearest_n_buffers(query_point, k, &mut result, &mut heap, &mut stack);
+
+        result
+    }
+
+    /// Returns the index of and distance to the point closest to `query_point`
+    pub fn nearest_one(&self, query_point: P) -> Option<(usize, f32)> {
+        let mut nearest = None;
+        collect_nearest(self, query_point, &mut nearest, &mut vec![]);
+
+        nearest.map(|n: Neighbor| (n.index, n.distance_squared.sqrt()))
+    }
+}
+
+impl<const D: usize, P: Point<D>> LinkedNodes<D, P> for KdTreeInline<D, P> {
+    #[inline(always)]
+    fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    #[inline(always)]
+    fn visit(
+        &self,
+        tree_index: usize,
+        mut point: impl FnMut(usize, P),
+    ) -> Option<(usize, f32, [Option<usize>; 2])> {
+        let node = &self.nodes[tree_index];
+        point(node.index, node.point);
+
+        Some((node.axis, node.point.get_axis(node.axis), node.children))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::{KdTreeBuilder, SplitStrategy};
+
+    #[test]
+    fn test_inline() {
+        let mut points = fixture::points(67);
+
+        let default = KdTreeInline::from_points(&points);
+        let builder = KdTreeBuilder::new().split_strategy(SplitStrategy::WidestAxis);
+        let widest = builder.build_inline(&points);
+
+        /*
+            NOTE: The trees keep their own copies, so the original points can change
+        */
+        let original = points.clone();
+        points.iter_mut().for_each(|point| *point = [0.0; 3]);
+
+        for tree in [default, widest] {
+            fixture::check_queries(
+                &original,
+                |_| true,
+                |query_point, radius| tree.point_indices_within(query_point, radius),
+                |query_point, k| tree.nearest_n(query_point, k),
+                |query_point| tree.nearest_one(query_point),
+            );
+        }
+
+        let tree = KdTreeInline::<2, [f32; 2]>::from_points(&[]);
+        assert!(tree.is_empty());
+        assert!(tree.point_indices_within([0.0, 0.0], 1.0).is_empty());
+        assert!(tree.nearest_n([0.0, 0.0], 3).is_empty());
+        assert_eq!(tree.nearest_one([0.0, 0.0]), None);
+    }
+}
@@ -0,0 +1,200 @@
+//! A Kd-tree over integer coordinates that compares them exactly
+use crate::eytzinger::{build, collect_nearest, for_each_within, nearest_n, ArrayStack, MAX_STACK};
+use crate::{IntegerPoint, Neighbor};
+
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone)]
+/// A Kd-tree that reads coordinates through `IntegerPoint::get_axis_i64` and compares squared
+/// distances as `u128`, which holds the squared distance between any two `i64` coordinates. The
+/// other trees convert integer coordinates to `f32`, which can't tell
+/// apart integers above `2^24`, so voxel and tile coordinates far from the origin get mixed
+/// up. Here every comparison is exact, and ties are decided by index like everywhere else.
+///
+/// Radii are given squared, so that any boundary, not only integer ones, can be expressed
+/// exactly. It uses the same layout as `KdTreeEytzinger`, and the points are passed to every
+/// query.
+pub struct KdTreeInteger<const D: usize, P: IntegerPoint<D>> {
+    /// The index of the point at every node
+    pub indices: Vec<usize>,
+    pub __marker: std::marker::PhantomData<P>,
+}
+
+impl<const D: usize, P: IntegerPoint<D>> KdTreeInteger<D, P> {
+    /// Builds a tree of `points`, ordering them by their integer coordinates
+    pub fn from_points(points: &[P]) -> Self {
+        let mut indices = vec![0; points.len()];
+        let mut point_ids = (0..points.len()).collect::<Vec<_>>();
+        build::<D, i64, P>(points, &mut point_ids, &mut indices);
+
+        Self {
+            indices,
+            __marker: std::marker::PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Same as `point_indices_within`, but you provide your own buffers. Indices are appended to
+    /// `result` which is not cleared. `stack` is assumed to be empty and is left empty.
+    pub fn point_indices_within_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius_squared: u128,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        for_each_within::<D, i64, P, _>(
+            &self.indices,
+            points,
+            query_point,
+            radius_squared,
+            stack,
+            |index| result.push(index),
+        );
+    }
+
+    /// Returns the indices of the points whose squared distance to `query_point` is at most
+    /// `radius_squared`
+    pub fn point_indices_within(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius_squared: u128,
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_buffers(
+            points,
+            query_point,
+            radius_squared,
+            &mut result,
+            &mut stack,
+        );
+
+        result
+    }
+
+    /// Same as `nearest_n`, but you provide your own buffers. Indices are appended to `result`
+    /// which is not cleared. `heap` and `stack` are assumed to be empty and are left empty.
+    pub fn nearest_n_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor<u128>>,
+        stack: &mut Vec<(usize, usize, u128)>,
+    ) {
+        nearest_n::<D, i64, P>(&self.indices, points, query_point, k, result, heap, stack);
+    }
+
+    /// Returns the indices of the `k` points closest to `query_point`, nearest first. Points at
+    /// the same distance are ordered by index.
+    pub fn nearest_n(&self, points: &[P], query_point: P, k: usize) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.indices.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.indices.len()) + 1);
+        let mut stack = vec![];
+
+        self.nearest_n_buffers(points, query_point, k, &mut result, &mut heap, &mut stack);
+
+        result
+    }
+
+    /// Returns the index of and squared distance to the point closest to `query_point`
+    pub fn nearest_one(&self, points: &[P], query_point: P) -> Option<(usize, u128)> {
+        let mut nearest = None;
+        collect_nearest::<D, i64, P, _>(
+            &self.indices,
+            points,
+            query_point,
+            &mut nearest,
+            &mut ArrayStack::<_, MAX_STACK>::new(),
+        );
+
+        nearest.map(|n: Neighbor<u128>| (n.index, n.distance_squared))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::KdTree;
+
+    #[test]
+    fn test_integer() {
+        /*
+            NOTE: Neighboring voxels beyond 2^24, which all round to the same f32
+        */
+        let base = 1_i64 << 25;
+        let points = (0..100)
+            .map(|i| [base + i % 10, base + i / 10])
+            .collect::<Vec<_>>();
+        let query_point = [base + 5, base + 5];
+
+        let tree = KdTreeInteger::from_points(&points);
+        assert_eq!(tree.point_indices_within(&points, query_point, 0), vec![55]);
+        let mut result = tree.point_indices_within(&points, query_point, 1);
+        result.sort_unstable();
+        assert_eq!(result, vec![45, 54, 55, 56, 65]);
+        assert_eq!(
+            tree.nearest_one(&points, [base + 3, base + 12]),
+            Some((93, 9))
+        );
+        assert!(
+            KdTree::from_points(&points)
+                .point_indices_within(query_point, 0.0)
+                .len()
+                > 1
+        );
+
+        /*
+            NOTE: The squared distances between these overflow an i64
+        */
+        let points = [[i32::MIN, 0], [i32::MAX, 0], [0, 0]];
+        let tree = KdTreeInteger::from_points(&points);
+        assert_eq!(tree.nearest_n(&points, [i32::MIN, 0], 3), vec![0, 2, 1]);
+        assert_eq!(
+            tree.nearest_n(&points, [i32::MAX, i32::MAX], 3),
+            vec![1, 2, 0]
+        );
+        assert_eq!(tree.nearest_one(&points, [i32::MAX, 0]), Some((1, 0)));
+        let mut result = tree.point_indices_within(&points, [i32::MIN, 0], 1 << 62);
+        result.sort_unstable();
+        assert_eq!(result, vec![0, 2]);
+        assert_eq!(
+            [i32::MIN, i32::MIN].distance_squared_u128([i32::MAX, i32::MAX]),
+            2 * ((1_u128 << 32) - 1).pow(2)
+        );
+
+        let points = fixture::points(97);
+        let integer = |point: [f32; 3]| point.map(|value| value as i64);
+        let integer_points = points.iter().copied().map(integer).collect::<Vec<_>>();
+        let tree = KdTreeInteger::from_points(&integer_points);
+        fixture::check_queries_at(
+            &[[50.0, 50.0, 50.0], [0.0, 99.0, 3.0], [120.0, -4.0, 60.0]],
+            &points,
+            |_| true,
+            |query_point, radius| {
+                let radius_squared = (radius * radius) as u128;
+                tree.point_indices_within(&integer_points, integer(query_point), radius_squared)
+            },
+            |query_point, k| tree.nearest_n(&integer_points, integer(query_point), k),
+            |query_point| {
+                tree.nearest_one(&integer_points, integer(query_point))
+                    .map(|(index, distance_squared)| (index, (distance_squared as f32).sqrt()))
+            },
+        );
+    }
+}
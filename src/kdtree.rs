@@ -1,12 +1,75 @@
 //! The implementation of a spacial query structure knonw as a `Kd-tree`
-use crate::{Point, SortingStrategy};
+use crate::utils::{
+    axis_values, compute_bounds, points_sorter, quick_sort_seeded, widest_axis, SplitMix64,
+};
+use crate::{NodeStorage, Point, ScratchVec, SortingStrategy, SplitStrategy, TreeIndex};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Internal node within the KdTree. The indices are stored as `I`, which `CompactKdTree` sets
+/// to a smaller type than `usize`. Read them through the methods, which convert to `usize`.
+pub struct KdTreeNode<I: TreeIndex = usize> {
+    pub parent: I,
+    pub index: I,
+    /// The axis this node splits its subtree along, see `SplitStrategy`
+    pub axis: I,
+    /// The value of the point of this node along `axis`, stored so that traversals can pick a
+    /// side without loading the point
+    pub split: f32,
+    /// `TreeIndex::NONE` where there is no child
+    pub children: [I; 2],
+}
 
-#[derive(Debug, Clone)]
-/// Internal node within the KdTree
-pub struct KdTreeNode {
-    pub parent: usize,
-    pub index: usize,
-    pub children: [Option<usize>; 2],
+impl<I: TreeIndex> KdTreeNode<I> {
+    #[inline(always)]
+    pub fn new(
+        parent: usize,
+        index: usize,
+        axis: usize,
+        split: f32,
+        children: [Option<usize>; 2],
+    ) -> Self {
+        Self {
+            parent: I::from_usize(parent),
+            index: I::from_usize(index),
+            axis: I::from_usize(axis),
+            split,
+            children: children.map(|child| child.map_or(I::NONE, I::from_usize)),
+        }
+    }
+
+    /// The index of the point of this node
+    #[inline(always)]
+    pub fn index(&self) -> usize {
+        self.index.to_usize()
+    }
+
+    /// The node this node is a child of. The root is its own parent.
+    #[inline(always)]
+    pub fn parent(&self) -> usize {
+        self.parent.to_usize()
+    }
+
+    #[inline(always)]
+    pub fn axis(&self) -> usize {
+        self.axis.to_usize()
+    }
+
+    /// The left child for `side == 0` and the right one for `side == 1`
+    #[inline(always)]
+    pub fn child(&self, side: usize) -> Option<usize> {
+        let child = self.children[side];
+        (child != I::NONE).then(|| child.to_usize())
+    }
+
+    #[inline(always)]
+    pub fn children(&self) -> [Option<usize>; 2] {
+        [self.child(0), self.child(1)]
+    }
+
+    #[inline(always)]
+    pub fn set_child(&mut self, side: usize, child: Option<usize>) {
+        self.children[side] = child.map_or(I::NONE, I::from_usize);
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -37,7 +100,10 @@ impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
         }
     }
 
-    /// Same as `from_points_with_strategy` but uses the pre-sort optimization
+    /// Same as `from_points_with_strategy` but uses the pre-sort optimization, which is what
+    /// `from_points` does. The points are sorted once along every axis up front and these
+    /// orders are partitioned around the median at every level, instead of sorting every range
+    /// again. This builds the exact same tree in `O(n log n)` instead of `O(n log^2 n)`.
     #[inline(always)]
     pub fn from_points_presort_with_strategy(points: &'a [P], strategy: &SortingStrategy) -> Self {
         Self {
@@ -46,6 +112,97 @@ impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
         }
     }
 
+    /// Same as `from_points` but chooses the axis of every split with `split_strategy`, for
+    /// instance `SplitStrategy::WidestAxis` for data that is much more spread out along some
+    /// axes than others
+    #[inline(always)]
+    pub fn from_points_with_split_strategy(
+        points: &'a [P],
+        split_strategy: &SplitStrategy,
+    ) -> Self {
+        Self {
+            internal: KdTreeNoBorrow::from_points_with_split_strategy(points, split_strategy),
+            points,
+        }
+    }
+
+    /// Same as `from_points` but calls `progress` with the fraction of the points placed in the
+    /// tree so far, about every percent and with `1.0` once done, so that tools can show a
+    /// progress bar during large builds. This builds without the pre-sort optimization, which
+    /// is slower but produces the same tree.
+    #[inline(always)]
+    pub fn from_points_with_progress<F>(points: &'a [P], progress: F) -> Self
+    where
+        F: FnMut(f32),
+    {
+        Self {
+            internal: KdTreeNoBorrow::from_points_with_progress(points, progress),
+            points,
+        }
+    }
+
+    /// Same as `from_points_with_strategy` but builds independent subtrees on up to `threads`
+    /// threads. With `threads <= 1` this is the same as `from_points_with_strategy`.
+    #[inline(always)]
+    pub fn from_points_parallel_with_strategy(
+        points: &'a [P],
+        strategy: &SortingStrategy,
+        threads: usize,
+    ) -> Self
+    where
+        P: Sync,
+    {
+        Self {
+            internal: KdTreeNoBorrow::from_points_parallel_with_strategy(points, strategy, threads),
+            points,
+        }
+    }
+
+    /// Same as `from_points_with_points_sorter` but builds independent subtrees on up to `threads`
+    /// threads. The resulting tree has the same structure as one built on a single thread.
+    pub fn from_points_parallel_with_points_sorter<F>(
+        points: &'a [P],
+        points_sorter: F,
+        threads: usize,
+    ) -> Self
+    where
+        F: Fn(&[P], &mut [usize], usize) + Sync,
+        P: Sync,
+    {
+        Self {
+            internal: KdTreeNoBorrow::from_points_parallel_with_points_sorter(
+                points,
+                points_sorter,
+                threads,
+            ),
+            points,
+        }
+    }
+
+    /// Same as `from_points_parallel_with_points_sorter` but builds the subtrees on the workers
+    /// of `thread_pool`, one subtree per worker, instead of spawning threads. Only the top few
+    /// levels are built on the calling thread, so the build is confined to threads you control
+    /// rather than the global rayon pool.
+    #[cfg(feature = "rayon")]
+    pub fn from_points_thread_pool_with_points_sorter<F>(
+        points: &'a [P],
+        points_sorter: F,
+        thread_pool: &rayon::ThreadPool,
+    ) -> Self
+    where
+        F: Fn(&[P], &mut [usize], usize) + Sync,
+        P: Sync,
+    {
+        Self {
+            internal: KdTreeNoBorrow::from_points_thread_pool_with_points_sorter(
+                points,
+                points_sorter,
+                thread_pool,
+            ),
+            points,
+        }
+    }
+
     /// Allows you to specify your own point sorter function. See `from_points_with_strategy`
     /// if you instead want to chose from some pre-provided algorithms.
     ///
@@ -77,14 +234,19 @@ impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
     ///
     /// Indices of points will be inserted into `result` which is not cleared by this function.
     /// `stack` is assumed to be empty from the start and will be cleared each time after calling this function.
+    ///
+    /// Both buffers can be any `ScratchVec`, for instance a `Vec` allocated from a frame allocator.
     #[inline(always)]
-    pub fn point_indices_within_buffers(
+    pub fn point_indices_within_buffers<R, S>(
         &self,
         query_point: P,
         radius: f32,
-        result: &mut Vec<usize>,
-        stack: &mut Vec<(usize, usize)>,
-    ) {
+        result: &mut R,
+        stack: &mut S,
+    ) where
+        R: ScratchVec<usize>,
+        S: ScratchVec<(usize, usize)>,
+    {
         self.internal
             .point_indices_within_buffers(self.points, query_point, radius, result, stack)
     }
@@ -93,6 +255,12 @@ impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
     /// the specified radius. Note that the distance is determined using `Point::distance_squared`
     /// which is a euclidian distance by default.
     ///
+    /// A point is returned exactly when `query_point.distance_squared(point) <= radius * radius`.
+    /// Subtrees are pruned using the same squared arithmetic, so points lying on a splitting plane
+    /// or right at the radius are never dropped by rounding in the pruning step.
+    ///
+    /// The order of the indices is unspecified, see `point_indices_within_ordered` for control over it.
+    ///
     /// If you want to allocate your own buffer for multiple consecutive queries, see `point_indices_within_buffers`
     #[inline(always)]
     pub fn point_indices_within(&self, query_point: P, radius: f32) -> Vec<usize> {
@@ -100,6 +268,258 @@ impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
             .point_indices_within(self.points, query_point, radius)
     }
 
+    /// Same as `any_within`, but you provide your own stack. `stack` is assumed to be empty
+    /// and is left empty.
+    #[inline(always)]
+    pub fn any_within_buffers<S>(&self, query_point: P, radius: f32, stack: &mut S) -> bool
+    where
+        S: ScratchVec<(usize, usize)>,
+    {
+        self.internal
+            .any_within_buffers(self.points, query_point, radius, stack)
+    }
+
+    /// Whether any point is within `radius` of `query_point`. The traversal stops at the first
+    /// point found, which makes this much cheaper than `point_indices_within` for collision
+    /// checks that only need a yes or no.
+    #[inline(always)]
+    pub fn any_within(&self, query_point: P, radius: f32) -> bool {
+        self.internal.any_within(self.points, query_point, radius)
+    }
+
+    /// Same as `points_within_with_distances`, but you provide your own buffers. Pairs are
+    /// appended to `result` which is not cleared. `stack` is assumed to be empty and is left empty.
+    #[inline(always)]
+    pub fn points_within_with_distances_buffers(
+        &self,
+        query_point: P,
+        radius: f32,
+        result: &mut Vec<(usize, f32)>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.points_within_with_distances_buffers(
+            self.points,
+            query_point,
+            radius,
+            result,
+            stack,
+        )
+    }
+
+    /// Same as `point_indices_within`, but every index comes with the distance of its point from
+    /// `query_point`. The traversal computes these distances anyway, so this saves computing them
+    /// a second time.
+    #[inline(always)]
+    pub fn points_within_with_distances(&self, query_point: P, radius: f32) -> Vec<(usize, f32)> {
+        self.internal
+            .points_within_with_distances(self.points, query_point, radius)
+    }
+
+    /// Same as `point_indices_within_buffers`, but points close to the boundary of the sphere are
+    /// confirmed using `Point::distance_squared_f64`. Pruning still happens in f32, so this is
+    /// almost as fast as the regular query while giving stable results for points whose distance
+    /// is within f32 rounding of `radius`.
+    #[inline(always)]
+    pub fn point_indices_within_exact_buffers(
+        &self,
+        query_point: P,
+        radius: f64,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_within_exact_buffers(
+            self.points,
+            query_point,
+            radius,
+            result,
+            stack,
+        )
+    }
+
+    /// Same as `point_indices_within`, but with the exact boundary handling of
+    /// `point_indices_within_exact_buffers`
+    #[inline(always)]
+    pub fn point_indices_within_exact(&self, query_point: P, radius: f64) -> Vec<usize> {
+        self.internal
+            .point_indices_within_exact(self.points, query_point, radius)
+    }
+
+    /// Returns at most `max_count` indices of points within the hypersphere, meant as
+    /// representatives of the region rather than all of its points, e.g. for level of detail.
+    ///
+    /// The tree is traversed breadth first, so the medians of large subtrees are picked before
+    /// any of the points below them. This spreads the sample over the whole region instead of
+    /// exhausting the budget in one corner of it. If fewer than `max_count` points are within
+    /// the radius, all of them are returned.
+    ///
+    /// Indices are appended to `result`, which is not cleared by this function. `queue` is cleared
+    /// before returning.
+    #[inline(always)]
+    pub fn sample_point_indices_within_buffers(
+        &self,
+        query_point: P,
+        radius: f32,
+        max_count: usize,
+        result: &mut Vec<usize>,
+        queue: &mut std::collections::VecDeque<(usize, usize)>,
+    ) {
+        self.internal.sample_point_indices_within_buffers(
+            self.points,
+            query_point,
+            radius,
+            max_count,
+            result,
+            queue,
+        )
+    }
+
+    /// See `sample_point_indices_within_buffers`
+    #[inline(always)]
+    pub fn sample_point_indices_within(
+        &self,
+        query_point: P,
+        radius: f32,
+        max_count: usize,
+    ) -> Vec<usize> {
+        self.internal
+            .sample_point_indices_within(self.points, query_point, radius, max_count)
+    }
+
+    /// Same as `nearest_one`, but you provide your own stack. `stack` is assumed to be empty
+    /// and is left empty.
+    #[inline(always)]
+    pub fn nearest_one_buffers<S>(&self, query_point: P, stack: &mut S) -> Option<(usize, f32)>
+    where
+        S: ScratchVec<(usize, usize, f32)>,
+    {
+        self.internal
+            .nearest_one_buffers(self.points, query_point, stack)
+    }
+
+    /// Returns the index of and distance to the point closest to `query_point`, or `None` if the
+    /// tree is empty. The near side of each split is visited first and subtrees that can't hold a
+    /// point closer than the best one found so far are skipped.
+    ///
+    /// If you want to allocate your own stack for multiple consecutive queries, see `nearest_one_buffers`
+    #[inline(always)]
+    pub fn nearest_one(&self, query_point: P) -> Option<(usize, f32)> {
+        self.internal.nearest_one(self.points, query_point)
+    }
+
+    /// Returns the index of and distance to the point closest to `query_point`, skipping every
+    /// point `i` where `disabled[i]` is true. `disabled` must have one entry per point.
+    ///
+    /// This lets algorithms that consume points one at a time, like greedy matching, disable
+    /// points as they go instead of rebuilding the tree after every removal.
+    #[inline(always)]
+    pub fn nearest_one_masked(&self, query_point: P, disabled: &[bool]) -> Option<(usize, f32)> {
+        self.internal
+            .nearest_one_masked(self.points, query_point, disabled)
+    }
+
+    /// Same as `point_indices_within_buffers`, but skips every point `i` where `disabled[i]` is true
+    #[inline(always)]
+    pub fn point_indices_within_masked_buffers(
+        &self,
+        query_point: P,
+        radius: f32,
+        disabled: &[bool],
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_within_masked_buffers(
+            self.points,
+            query_point,
+            radius,
+            disabled,
+            result,
+            stack,
+        )
+    }
+
+    /// Same as `point_indices_within_filtered`, but you provide your own buffers, see
+    /// `point_indices_within_buffers`
+    #[inline(always)]
+    pub fn point_indices_within_filtered_buffers<F, R, S>(
+        &self,
+        query_point: P,
+        radius: f32,
+        filter: F,
+        result: &mut R,
+        stack: &mut S,
+    ) where
+        F: FnMut(usize) -> bool,
+        R: ScratchVec<usize>,
+        S: ScratchVec<(usize, usize)>,
+    {
+        self.internal.point_indices_within_filtered_buffers(
+            self.points,
+            query_point,
+            radius,
+            filter,
+            result,
+            stack,
+        )
+    }
+
+    /// Same as `point_indices_within`, but only returns the points `i` for which `filter(i)`
+    /// returns true. `filter` is called during the traversal for every point within the radius,
+    /// so rejected points never make it into the result, like teammates or points already visited.
+    #[inline(always)]
+    pub fn point_indices_within_filtered<F>(
+        &self,
+        query_point: P,
+        radius: f32,
+        filter: F,
+    ) -> Vec<usize>
+    where
+        F: FnMut(usize) -> bool,
+    {
+        self.internal
+            .point_indices_within_filtered(self.points, query_point, radius, filter)
+    }
+
+    /// Same as `point_indices_within`, but skips every point `i` where `disabled[i]` is true
+    #[inline(always)]
+    pub fn point_indices_within_masked(
+        &self,
+        query_point: P,
+        radius: f32,
+        disabled: &[bool],
+    ) -> Vec<usize> {
+        self.internal
+            .point_indices_within_masked(self.points, query_point, radius, disabled)
+    }
+
+    /// Returns the `(min, max)` values of `axis` among all points in the tree. These are
+    /// computed once at build time.
+    #[inline(always)]
+    pub fn axis_bounds(&self, axis: usize) -> (f32, f32) {
+        self.internal.axis_bounds(axis)
+    }
+
+    /// Returns the corners `(min, max)` of the axis aligned bounding box of all points in the
+    /// tree, computed once at build time. For an empty tree `min` is infinite and `max` is
+    /// negative infinity along every axis.
+    #[inline(always)]
+    pub fn bounds(&self) -> ([f32; D], [f32; D]) {
+        self.internal.bounds()
+    }
+
+    /// Returns the axis values of `query_point` clamped to the bounding box of the points in the
+    /// tree, i.e. the closest position inside the data extent.
+    #[inline(always)]
+    pub fn clamp_to_bounds(&self, query_point: P) -> [f32; D] {
+        self.internal.clamp_to_bounds(query_point)
+    }
+
+    /// Returns the squared distance from `query_point` to the bounding box of the points in the tree.
+    /// If it is larger than `radius * radius`, a radius query can not return anything and can be skipped.
+    #[inline(always)]
+    pub fn distance_squared_to_bounds(&self, query_point: P) -> f32 {
+        self.internal.distance_squared_to_bounds(query_point)
+    }
+
     #[inline(always)]
     pub fn iter_point_indices_within_buffers(
         &self,
@@ -112,335 +532,1891 @@ impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
     }
 }
 
+#[derive(Debug, Clone, Default)]
+/// The settings a `KdTreeNoBorrow` was built with, kept on the tree so that `rebuild` and
+/// `rebalance` build it the same way again
+pub struct BuildConfig {
+    pub sorting_strategy: SortingStrategy,
+    /// See `KdTreeBuilder::seed`
+    pub seed: Option<u64>,
+    pub split_strategy: SplitStrategy,
+    /// Whether the tree has `bounding_spheres`, see `KdTreeNoBorrow::compute_bounding_spheres`
+    pub bounding_spheres: bool,
+    /// The page size of the node order, see `KdTreeNoBorrow::relayout_paged`
+    pub nodes_per_page: Option<usize>,
+}
+
+impl BuildConfig {
+    /// The default settings splitting by `split_strategy`
+    pub(crate) fn with_split(split_strategy: &SplitStrategy) -> Self {
+        Self {
+            split_strategy: split_strategy.clone(),
+            ..Self::default()
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 /// A KdTree of points with dimension D that doesn't use lifetime semantics
-pub struct KdTreeNoBorrow<const D: usize, P: Point<D>> {
-    pub tree: Vec<KdTreeNode>,
+///
+/// The nodes are kept in `N`, a `Vec` unless the tree was built with `from_points_in` to
+/// allocate them from somewhere else.
+pub struct KdTreeNoBorrow<const D: usize, P: Point<D>, N: NodeStorage = Vec<KdTreeNode>> {
+    pub tree: N,
+    /// Smallest value of every axis among the points, computed at build time
+    pub bounds_min: [f32; D],
+    /// Largest value of every axis among the points, computed at build time
+    pub bounds_max: [f32; D],
+    /// Optional `(center, radius)` of a sphere enclosing each subtree, indexed like `tree`.
+    /// Empty unless `compute_bounding_spheres` has been called.
+    pub bounding_spheres: Vec<([f32; D], f32)>,
+    /// Whether the tree holds only some of the points, such as a tree built with
+    /// `NonFinitePolicy::Exclude` or `DuplicatePolicy::Deduplicate`. `rebuild` then keeps the
+    /// points the tree holds instead of taking all of them.
+    pub subset: bool,
+    /// The settings the tree was built with, which `rebuild` uses again
+    pub config: BuildConfig,
     pub __marker: std::marker::PhantomData<P>,
 }
 
 impl<const D: usize, P: Point<D>> KdTreeNoBorrow<D, P> {
     /// See `KdTree`
     pub fn from_points(points: &[P]) -> Self {
-        /*
-            TODO: Switch to using presort by default once it is implemented
-        */
-        Self::from_points_with_strategy(points, &SortingStrategy::default())
+        Self::from_points_presort_with_strategy(points, &SortingStrategy::default())
     }
 
     /// See `KdTree`
     pub fn from_points_with_strategy(points: &[P], strategy: &SortingStrategy) -> Self {
-        let points_sorter = match strategy {
-            SortingStrategy::StableSort => crate::utils::stable_sort,
-            SortingStrategy::UnstableSort => crate::utils::unstable_sort,
-            SortingStrategy::ShellSort => crate::utils::shell_sort,
-            SortingStrategy::QuickSort => crate::utils::quick_sort,
-            SortingStrategy::HeapSort => crate::utils::heap_sort,
-        };
-
-        Self::from_points_with_points_sorter(points, points_sorter)
+        let mut tree = Self::from_points_with_points_sorter(points, points_sorter(strategy));
+        tree.config.sorting_strategy = strategy.clone();
+        tree
     }
 
     /// See `KdTree`
     pub fn from_points_presort_with_strategy(points: &[P], strategy: &SortingStrategy) -> Self {
-        let points_sorter = match strategy {
-            SortingStrategy::StableSort => crate::utils::stable_sort,
-            SortingStrategy::UnstableSort => crate::utils::unstable_sort,
-            SortingStrategy::ShellSort => crate::utils::shell_sort,
-            SortingStrategy::QuickSort => crate::utils::quick_sort,
-            SortingStrategy::HeapSort => crate::utils::heap_sort,
+        /*
+            NOTE: Presorting needs every array fully sorted, which selecting the median doesn't do
+        */
+        let presort_strategy = match strategy {
+            SortingStrategy::MedianSelect => &SortingStrategy::UnstableSort,
+            strategy => strategy,
         };
+        let mut tree =
+            Self::from_points_presort_with_points_sorter(points, points_sorter(presort_strategy));
+        tree.config.sorting_strategy = strategy.clone();
+        tree
+    }
 
-        Self::from_points_presort_with_points_sorter(points, points_sorter)
+    /// See `KdTree`
+    pub fn from_points_with_split_strategy(points: &[P], split_strategy: &SplitStrategy) -> Self {
+        Self::from_points_presort_with_points_sorter_and_split(
+            points,
+            points_sorter(&SortingStrategy::default()),
+            split_strategy,
+        )
     }
 
     /// See `KdTree`
-    pub fn from_points_with_points_sorter<F>(points: &[P], mut points_sorter: F) -> Self
+    pub fn from_points_with_progress<F>(points: &[P], mut progress: F) -> Self
     where
-        F: FnMut(&[P], &mut [usize], usize),
+        F: FnMut(f32),
     {
-        let mut tree = Vec::with_capacity(points.len());
-        let mut point_ids = (0..points.len()).into_iter().collect::<Vec<_>>();
+        let sorter = points_sorter(&SortingStrategy::default());
+        let step = points.len().div_ceil(100).max(1);
+        let mut placed = 0;
+
+        /*
+            NOTE: The sorter runs once for every node that is placed, so counting its calls
+                  counts the finished jobs
+        */
+        let tree = Self::from_points_with_points_sorter(
+            points,
+            |points: &[P], indices: &mut [usize], axis: usize| {
+                sorter(points, indices, axis);
+
+                placed += 1;
+                if placed % step == 0 {
+                    progress(placed as f32 / points.len() as f32);
+                }
+            },
+        );
 
-        #[derive(Debug)]
-        struct Job {
-            start: usize,
-            end: usize,
-            left_right: usize,
-            depth: usize,
-            parent: usize,
+        if placed % step != 0 || points.is_empty() {
+            progress(1.0);
         }
 
-        let root_job = Job {
+        tree
+    }
+
+    /// See `KdTree`
+    pub fn from_points_with_points_sorter<F>(points: &[P], points_sorter: F) -> Self
+    where
+        F: FnMut(&[P], &mut [usize], usize),
+    {
+        Self::from_points_with_points_sorter_and_split(
+            points,
+            points_sorter,
+            &SplitStrategy::default(),
+        )
+    }
+
+    /// Same as `from_points_with_points_sorter`, choosing the axis of every split with
+    /// `split_strategy`
+    pub(crate) fn from_points_with_points_sorter_and_split<F>(
+        points: &[P],
+        mut points_sorter: F,
+        split_strategy: &SplitStrategy,
+    ) -> Self
+    where
+        F: FnMut(&[P], &mut [usize], usize),
+    {
+        let mut tree = Vec::with_capacity(points.len());
+        let mut point_ids = (0..points.len()).collect::<Vec<_>>();
+        let (bounds_min, bounds_max) = compute_bounds(points);
+
+        let root_job = BuildJob {
             start: 0,
-            end: points.len() - 1,
+            end: points.len(),
             left_right: 0,
             depth: 0,
             parent: 0,
         };
 
-        let mut jobs = vec![root_job];
-
-        while let Some(job) = jobs.pop() {
-            let Job {
-                start,
-                end,
-                left_right,
-                depth,
-                parent,
-            } = job;
+        build_subtree(
+            points,
+            &mut point_ids,
+            root_job,
+            &mut points_sorter,
+            split_strategy,
+            &mut tree,
+            usize::MAX,
+            &mut vec![],
+        );
 
-            let axis = depth % D;
-            let pivot_index = (start + end) / 2;
+        Self {
+            tree,
+            bounds_min,
+            bounds_max,
+            bounding_spheres: vec![],
+            subset: false,
+            config: BuildConfig::with_split(split_strategy),
+            __marker: std::marker::PhantomData,
+        }
+    }
 
-            points_sorter(points, &mut point_ids[start..end], axis);
+    /// See `KdTree`
+    pub fn from_points_parallel_with_strategy(
+        points: &[P],
+        strategy: &SortingStrategy,
+        threads: usize,
+    ) -> Self
+    where
+        P: Sync,
+    {
+        let mut tree =
+            Self::from_points_parallel_with_points_sorter(points, points_sorter(strategy), threads);
+        tree.config.sorting_strategy = strategy.clone();
+        tree
+    }
 
-            let tree_index = tree.len();
-            tree.push(KdTreeNode {
-                parent,
-                index: point_ids[pivot_index],
-                children: [None, None],
+    /// See `KdTree`
+    pub fn from_points_parallel_with_points_sorter<F>(
+        points: &[P],
+        points_sorter: F,
+        threads: usize,
+    ) -> Self
+    where
+        F: Fn(&[P], &mut [usize], usize) + Sync,
+        P: Sync,
+    {
+        Self::from_points_parallel_with_points_sorter_and_split(
+            points,
+            points_sorter,
+            &SplitStrategy::default(),
+            threads,
+        )
+    }
+
+    /// Same as `from_points_parallel_with_points_sorter`, choosing the axis of every split
+    /// with `split_strategy`
+    pub(crate) fn from_points_parallel_with_points_sorter_and_split<F>(
+        points: &[P],
+        points_sorter: F,
+        split_strategy: &SplitStrategy,
+        threads: usize,
+    ) -> Self
+    where
+        F: Fn(&[P], &mut [usize], usize) + Sync,
+        P: Sync,
+    {
+        if threads <= 1 {
+            return Self::from_points_with_points_sorter_and_split(
+                points,
+                points_sorter,
+                split_strategy,
+            );
+        }
+
+        Self::from_points_split_with_points_sorter(
+            points,
+            &points_sorter,
+            split_strategy,
+            threads,
+            |work, build| {
+                let mut per_thread = (0..threads).map(|_| vec![]).collect::<Vec<_>>();
+                for (i, item) in work.into_iter().enumerate() {
+                    per_thread[i % threads].push(item);
+                }
+
+                std::thread::scope(|scope| {
+                    let handles = per_thread
+                        .into_iter()
+                        .map(|work| {
+                            scope.spawn(move || {
+                                work.into_iter()
+                                    .map(|(i, job, slice)| (i, build(job, slice)))
+                                    .collect::<Vec<_>>()
+                            })
+                        })
+                        .collect::<Vec<_>>();
+
+                    handles
+                        .into_iter()
+                        .flat_map(|handle| handle.join().expect("keyde build thread panicked"))
+                        .collect::<Vec<_>>()
+                })
+            },
+        )
+    }
+
+    /// See `KdTree`
+    #[cfg(feature = "rayon")]
+    pub fn from_points_thread_pool_with_points_sorter<F>(
+        points: &[P],
+        points_sorter: F,
+        thread_pool: &rayon::ThreadPool,
+    ) -> Self
+    where
+        F: Fn(&[P], &mut [usize], usize) + Sync,
+        P: Sync,
+    {
+        Self::from_points_thread_pool_with_points_sorter_and_split(
+            points,
+            points_sorter,
+            &SplitStrategy::default(),
+            thread_pool,
+        )
+    }
+
+    /// Same as `from_points_thread_pool_with_points_sorter`, choosing the axis of every split
+    /// with `split_strategy`
+    #[cfg(feature = "rayon")]
+    pub(crate) fn from_points_thread_pool_with_points_sorter_and_split<F>(
+        points: &[P],
+        points_sorter: F,
+        split_strategy: &SplitStrategy,
+        thread_pool: &rayon::ThreadPool,
+    ) -> Self
+    where
+        F: Fn(&[P], &mut [usize], usize) + Sync,
+        P: Sync,
+    {
+        use rayon::prelude::*;
+
+        /*
+            NOTE: Splitting at least once keeps the bulk of the work in the pool even when it
+                  only has a single worker.
+        */
+        let threads = thread_pool.current_num_threads().max(2);
+
+        Self::from_points_split_with_points_sorter(
+            points,
+            &points_sorter,
+            split_strategy,
+            threads,
+            |work, build| {
+                thread_pool.install(|| {
+                    work.into_par_iter()
+                        .map(|(i, job, slice)| (i, build(job, slice)))
+                        .collect()
+                })
+            },
+        )
+    }
+
+    /// Builds the top levels of the tree on the calling thread until there are at least
+    /// `threads` independent subtrees left, then hands those to `run` together with a function
+    /// building one of them. `run` decides where the subtrees are built and returns them tagged
+    /// with the index they were given.
+    fn from_points_split_with_points_sorter<F, R>(
+        points: &[P],
+        points_sorter: &F,
+        split_strategy: &SplitStrategy,
+        threads: usize,
+        run: R,
+    ) -> Self
+    where
+        F: Fn(&[P], &mut [usize], usize) + Sync,
+        P: Sync,
+        R: FnOnce(
+            Vec<(usize, &BuildJob, &mut [usize])>,
+            &(dyn Fn(&BuildJob, &mut [usize]) -> Vec<KdTreeNode> + Sync),
+        ) -> Vec<(usize, Vec<KdTreeNode>)>,
+    {
+        let mut tree = Vec::with_capacity(points.len());
+        let mut point_ids = (0..points.len()).collect::<Vec<_>>();
+        let (bounds_min, bounds_max) = compute_bounds(points);
+
+        let split_depth = (usize::BITS - (threads - 1).leading_zeros()) as usize;
+        let mut deferred = vec![];
+
+        let root_job = BuildJob {
+            start: 0,
+            end: points.len(),
+            left_right: 0,
+            depth: 0,
+            parent: 0,
+        };
+
+        build_subtree(
+            points,
+            &mut point_ids,
+            root_job,
+            &mut |points: &[P], ids: &mut [usize], axis: usize| points_sorter(points, ids, axis),
+            split_strategy,
+            &mut tree,
+            split_depth,
+            &mut deferred,
+        );
+
+        deferred.sort_by_key(|job| job.start);
+
+        let mut work = Vec::with_capacity(deferred.len());
+        let mut rest = &mut point_ids[..];
+        let mut consumed = 0;
+        for (i, job) in deferred.iter().enumerate() {
+            let (_, tail) = rest.split_at_mut(job.start - consumed);
+            let (slice, tail) = tail.split_at_mut(job.end - job.start);
+            work.push((i, job, slice));
+            rest = tail;
+            consumed = job.end;
+        }
+
+        let build = |job: &BuildJob, slice: &mut [usize]| {
+            let mut subtree = vec![];
+            let local_job = BuildJob {
+                start: 0,
+                end: slice.len(),
+                left_right: job.left_right,
+                depth: job.depth,
+                parent: 0,
+            };
+
+            build_subtree(
+                points,
+                slice,
+                local_job,
+                &mut |points: &[P], ids: &mut [usize], axis: usize| {
+                    points_sorter(points, ids, axis)
+                },
+                split_strategy,
+                &mut subtree,
+                usize::MAX,
+                &mut vec![],
+            );
+
+            subtree
+        };
+
+        let mut subtrees = run(work, &build);
+        subtrees.sort_by_key(|(i, _)| *i);
+
+        for ((_, subtree), job) in subtrees.into_iter().zip(&deferred) {
+            let offset = tree.len();
+            tree.extend(subtree.into_iter().map(|node| {
+                KdTreeNode::new(
+                    node.parent() + offset,
+                    node.index(),
+                    node.axis(),
+                    node.split,
+                    node.children().map(|child| child.map(|c| c + offset)),
+                )
+            }));
+
+            tree[offset].parent = job.parent;
+            tree[job.parent].set_child(job.left_right, Some(offset));
+        }
+
+        Self {
+            tree,
+            bounds_min,
+            bounds_max,
+            bounding_spheres: vec![],
+            subset: false,
+            config: BuildConfig::with_split(split_strategy),
+            __marker: std::marker::PhantomData,
+        }
+    }
+
+    /// See `KdTree`
+    pub fn from_points_presort_with_points_sorter<F>(points: &[P], points_sorter: F) -> Self
+    where
+        F: FnMut(&[P], &mut [usize], usize),
+    {
+        Self::from_points_presort_with_points_sorter_and_split(
+            points,
+            points_sorter,
+            &SplitStrategy::default(),
+        )
+    }
+
+    /// Same as `from_points_presort_with_points_sorter`, choosing the axis of every split with
+    /// `split_strategy`
+    pub(crate) fn from_points_presort_with_points_sorter_and_split<F>(
+        points: &[P],
+        mut points_sorter: F,
+        split_strategy: &SplitStrategy,
+    ) -> Self
+    where
+        F: FnMut(&[P], &mut [usize], usize),
+    {
+        let mut tree = Vec::with_capacity(points.len());
+        let (bounds_min, bounds_max) = compute_bounds(points);
+
+        /*
+            NOTE: The range of every job holds the same points in each of the arrays, sorted
+                  along the axis of that array. Splitting a range around the median of one
+                  axis stably partitions the arrays of the other axes the same way, so they
+                  stay sorted and nothing has to be sorted again further down.
+        */
+        let n = points.len();
+        let mut sorted_axis_ids = (0..D)
+            .map(|axis| {
+                let mut ids = (0..n).collect::<Vec<_>>();
+                points_sorter(points, &mut ids, axis);
+                ids
+            })
+            .collect::<Vec<_>>();
+
+        let mut goes_left = vec![false; n];
+        let mut scratch = Vec::with_capacity(n);
+
+        let mut jobs = vec![];
+        if n > 0 {
+            jobs.push(BuildJob {
+                start: 0,
+                end: n,
+                left_right: 0,
+                depth: 0,
+                parent: 0,
             });
+        }
+
+        while let Some(job) = jobs.pop() {
+            let BuildJob {
+                start,
+                end,
+                left_right,
+                depth,
+                parent,
+            } = job;
+
+            let axis = match split_strategy {
+                SplitStrategy::RoundRobin => depth % D,
+                /*
+                    NOTE: The ends of the sorted ranges are the extent along every axis
+                */
+                SplitStrategy::WidestAxis => widest_axis::<D>(|axis| {
+                    let ids = &sorted_axis_ids[axis];
+                    points[ids[end - 1]].get_axis(axis) - points[ids[start]].get_axis(axis)
+                }),
+            };
+            let pivot_index = (start + end) / 2;
+            let pivot_id = sorted_axis_ids[axis][pivot_index];
 
+            let tree_index = tree.len();
+            tree.push(KdTreeNode::new(
+                parent,
+                pivot_id,
+                axis,
+                points[pivot_id].get_axis(axis),
+                [None, None],
+            ));
+
+            if end - start > 1 {
+                let (left, right) = sorted_axis_ids[axis][start..end].split_at(pivot_index - start);
+                left.iter().for_each(|id| goes_left[*id] = true);
+                right[1..].iter().for_each(|id| goes_left[*id] = false);
+
+                for other_axis in (0..D).filter(|other_axis| *other_axis != axis) {
+                    let ids = &mut sorted_axis_ids[other_axis][start..end];
+                    scratch.clear();
+                    scratch.extend_from_slice(ids);
+
+                    let mut next_left = 0;
+                    let mut next_right = pivot_index - start + 1;
+                    ids[pivot_index - start] = pivot_id;
+                    for id in scratch.iter().filter(|id| **id != pivot_id) {
+                        if goes_left[*id] {
+                            ids[next_left] = *id;
+                            next_left += 1;
+                        } else {
+                            ids[next_right] = *id;
+                            next_right += 1;
+                        }
+                    }
+                }
+            }
+
+            /*
+                NOTE: Pushed in the same order as `build_subtree` so that both lay out the
+                      nodes the same way
+            */
             let new_depth = depth + 1;
-            let (left_start, left_end) = (start, pivot_index);
-            if left_start != left_end {
-                jobs.push(Job {
-                    start: left_start,
-                    end: left_end,
+            if start != pivot_index {
+                jobs.push(BuildJob {
+                    start,
+                    end: pivot_index,
                     left_right: 0,
                     depth: new_depth,
                     parent: tree_index,
                 });
             }
-
-            let (right_start, right_end) = (pivot_index + 1, end);
-            if right_start != right_end {
-                jobs.push(Job {
-                    start: right_start,
-                    end: right_end,
+            if pivot_index + 1 != end {
+                jobs.push(BuildJob {
+                    start: pivot_index + 1,
+                    end,
                     left_right: 1,
                     depth: new_depth,
                     parent: tree_index,
                 });
             }
 
-            if depth > 0 {
+            if tree_index != 0 {
                 /*
                     NOTE: Root has no parent so this only happens when we are
                           not root
                 */
 
-                tree[parent].children[left_right] = Some(tree_index);
+                tree[parent].set_child(left_right, Some(tree_index));
             }
         }
 
         Self {
             tree,
+            bounds_min,
+            bounds_max,
+            bounding_spheres: vec![],
+            subset: false,
+            config: BuildConfig::with_split(split_strategy),
             __marker: std::marker::PhantomData,
         }
     }
 
-    /// See `KdTree`
-    pub fn from_points_presort_with_points_sorter<F>(points: &[P], mut points_sorter: F) -> Self
+    /// Builds a tree of only the points in `point_ids`, using default settings. Queries return
+    /// the indices into `points` and never the points left out.
+    /// The tree is marked as a `subset`, so rebuilding it keeps the same points.
+    pub(crate) fn from_point_ids(points: &[P], point_ids: Vec<usize>) -> Self {
+        Self::from_point_ids_with_points_sorter_and_split(
+            points,
+            point_ids,
+            points_sorter(&SortingStrategy::default()),
+            &SplitStrategy::default(),
+        )
+    }
+
+    /// Same as `from_point_ids`, sorting with `points_sorter` and splitting by `split_strategy`
+    pub(crate) fn from_point_ids_with_points_sorter_and_split<F>(
+        points: &[P],
+        mut point_ids: Vec<usize>,
+        mut points_sorter: F,
+        split_strategy: &SplitStrategy,
+    ) -> Self
     where
         F: FnMut(&[P], &mut [usize], usize),
     {
-        let mut tree = Vec::with_capacity(points.len());
+        let mut tree = Self {
+            tree: Vec::with_capacity(point_ids.len()),
+            bounds_min: [f32::INFINITY; D],
+            bounds_max: [f32::NEG_INFINITY; D],
+            bounding_spheres: vec![],
+            subset: true,
+            config: BuildConfig::with_split(split_strategy),
+            __marker: std::marker::PhantomData,
+        };
+        tree.build_point_ids(points, &mut point_ids, &mut points_sorter, split_strategy);
 
-        let n = points.len();
-        let mut sorted_axis_ids = (0..D)
+        tree
+    }
+
+    /// Whether the tree no longer fits `points`: it holds a different number of points, it is
+    /// more than twice as deep as a balanced tree of them, see `depth`, or a point has moved out
+    /// of the part of space its node covers, where queries would skip it. That part is bounded
+    /// by the splits above the node, the `bounds` and the bounding spheres of the tree.
+    ///
+    /// A `subset` tree is compared against the number of points it holds.
+    pub fn needs_rebalance(&self, points: &[P]) -> bool {
+        let len = if self.subset {
+            self.tree.len()
+        } else {
+            points.len()
+        };
+        let balanced_depth = (usize::BITS - len.leading_zeros()) as usize;
+        if self.tree.len() != len || self.depth() > 2 * balanced_depth {
+            return true;
+        }
+
+        /*
+            NOTE: The depth is bounded by now, so walking up from every node costs about as much
+                  as building the tree
+        */
+        (0..self.tree.len()).any(|tree_index| self.node_out_of_place(points, tree_index))
+    }
+
+    /// Whether the point of the node at `tree_index` has moved to where queries would skip it.
+    /// Non-finite coordinates are never out of place, they can't be found by queries anyway.
+    fn node_out_of_place(&self, points: &[P], tree_index: usize) -> bool {
+        let Some(point) = points.get(self.tree[tree_index].index()) else {
+            return true;
+        };
+        let point_axis_values = axis_values(point);
+
+        let outside_bounds = (0..D).any(|axis| {
+            point_axis_values[axis] < self.bounds_min[axis]
+                || point_axis_values[axis] > self.bounds_max[axis]
+        });
+        if outside_bounds || self.outside_sphere(tree_index, &point_axis_values) {
+            return true;
+        }
+
+        let mut child = tree_index;
+        while child != 0 {
+            let parent = self.tree[child].parent();
+            let node = &self.tree[parent];
+
+            let value = point_axis_values[node.axis()];
+            let wrong_side = if node.child(0) == Some(child) {
+                value > node.split
+            } else {
+                value < node.split
+            };
+            if wrong_side || self.outside_sphere(parent, &point_axis_values) {
+                return true;
+            }
+
+            child = parent;
+        }
+
+        false
+    }
+
+    /// Whether a point is outside of the bounding sphere of the subtree at `tree_index`. Always
+    /// false when no bounding spheres have been computed.
+    fn outside_sphere(&self, tree_index: usize, point_axis_values: &[f32; D]) -> bool {
+        let Some((center, radius)) = self.bounding_spheres.get(tree_index) else {
+            return false;
+        };
+
+        /*
+            NOTE: Merging spheres rounds, which can leave a point that never moved a few ulps of
+                  the coordinates outside of its sphere
+        */
+        let magnitude = center
+            .iter()
+            .fold(*radius, |magnitude, value| magnitude.max(value.abs()));
+        let distance = (0..D)
             .map(|axis| {
-                let mut ids = (0..n).collect::<Vec<_>>();
-                points_sorter(points, &mut ids, axis);
-                ids
+                let delta = center[axis] - point_axis_values[axis];
+                delta * delta
             })
-            .collect::<Vec<_>>();
+            .sum::<f32>()
+            .sqrt();
 
-        let mut point_id_to_sorted_axis_index = (0..D).map(|axis| {
-            let mut map = vec![0; n];
+        distance > radius + 8.0 * f32::EPSILON * magnitude
+    }
 
-            sorted_axis_ids[axis]
-                .iter()
-                .enumerate()
-                .for_each(|(i, value)| {
-                    map[*value] = i;
-                });
+    /// Rebuilds the tree of `points` with the same settings if `needs_rebalance` says so, and
+    /// returns whether it did. The nodes are rebuilt in place, see `rebuild`.
+    ///
+    /// Only the shape of the tree is checked, so rebuild unconditionally if points may have moved.
+    pub fn rebalance(&mut self, points: &[P]) -> bool {
+        if !self.needs_rebalance(points) {
+            return false;
+        }
 
-            map
-        });
+        self.rebuild(points);
+        true
+    }
+
+    /// Same as `rebuild`, but the point ids are sorted in `point_ids`, which is cleared first.
+    /// Keep it around together with the tree and a rebuild allocates nothing once both have
+    /// grown to the number of points.
+    pub fn rebuild_buffers(&mut self, points: &[P], point_ids: &mut Vec<usize>) {
+        point_ids.clear();
+        if self.subset {
+            point_ids.extend(self.tree.iter().map(|node| node.index()));
+        } else {
+            point_ids.extend(0..points.len());
+        }
+
+        self.rebuild_point_ids(points, point_ids);
+    }
+
+    /// Replaces the tree with one of the points in `point_ids` built with the same settings,
+    /// reusing the allocation of `tree`
+    pub(crate) fn rebuild_point_ids(&mut self, points: &[P], point_ids: &mut [usize]) {
+        let config = std::mem::take(&mut self.config);
+        match (&config.sorting_strategy, config.seed) {
+            (SortingStrategy::QuickSort, Some(seed)) => self.build_point_ids(
+                points,
+                point_ids,
+                &mut |points: &[P], indices: &mut [usize], axis: usize| {
+                    quick_sort_seeded(points, indices, axis, seed)
+                },
+                &config.split_strategy,
+            ),
+
+            (strategy, _) => self.build_point_ids(
+                points,
+                point_ids,
+                &mut points_sorter(strategy),
+                &config.split_strategy,
+            ),
+        }
+
+        if config.bounding_spheres {
+            self.compute_bounding_spheres(points);
+        }
+        if let Some(nodes_per_page) = config.nodes_per_page {
+            self.relayout_paged(nodes_per_page);
+        }
+        self.config = config;
+    }
 
-        #[derive(Debug)]
-        struct Job {
-            start: usize,
-            end: usize,
-            left_right: usize,
-            depth: usize,
-            parent: usize,
+    /// Replaces the nodes and bounds with a tree of the points in `point_ids`, reusing the
+    /// allocation of `tree`
+    fn build_point_ids<F>(
+        &mut self,
+        points: &[P],
+        point_ids: &mut [usize],
+        points_sorter: &mut F,
+        split_strategy: &SplitStrategy,
+    ) where
+        F: FnMut(&[P], &mut [usize], usize),
+    {
+        self.bounds_min = [f32::INFINITY; D];
+        self.bounds_max = [f32::NEG_INFINITY; D];
+        for i in point_ids.iter() {
+            for axis in 0..D {
+                let value = points[*i].get_axis(axis);
+                self.bounds_min[axis] = self.bounds_min[axis].min(value);
+                self.bounds_max[axis] = self.bounds_max[axis].max(value);
+            }
         }
+        self.bounding_spheres.clear();
+        self.tree.clear();
 
-        let root_job = Job {
+        let root_job = BuildJob {
             start: 0,
-            end: points.len() - 1,
+            end: point_ids.len(),
             left_right: 0,
             depth: 0,
             parent: 0,
         };
 
-        let mut jobs = vec![root_job];
+        build_subtree(
+            points,
+            point_ids,
+            root_job,
+            points_sorter,
+            split_strategy,
+            &mut self.tree,
+            usize::MAX,
+            &mut vec![],
+        );
+    }
 
-        while let Some(job) = jobs.pop() {
-            let Job {
-                start,
-                end,
-                left_right,
-                depth,
-                parent,
-            } = job;
+    /// Replaces the tree with one of `points` built with the same settings, see `config`,
+    /// reusing the allocation of `tree` instead of allocating a new one. For trees rebuilt every
+    /// frame, this avoids most of the allocator traffic of `from_points`. The result is the same
+    /// tree, built without the pre-sort optimization since that needs a sorted copy of the ids
+    /// per axis. Bounding spheres and a paged layout are computed again if the tree had them.
+    ///
+    /// A `subset` tree is rebuilt of the points it holds, which keeps the points left out by
+    /// `NonFinitePolicy::Exclude` or `DuplicatePolicy::Deduplicate` out of it.
+    pub fn rebuild(&mut self, points: &[P]) {
+        self.rebuild_buffers(points, &mut vec![]);
+    }
+}
 
-            let axis = depth % D;
-            let pivot_index = (start + end) / 2;
-            let relevant_ids = &sorted_axis_ids[axis][start..end];
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn iter_point_indices_within_buffers<'a>(
+        &'a self,
+        points: &'a [P],
+        query_point: P,
+        radius: f32,
+        stack: &'a mut Vec<(usize, usize)>,
+    ) -> IndicesWithinIterator<'a, D, P, N> {
+        let radius_squared = radius * radius;
 
-            let tree_index = tree.len();
-            tree.push(KdTreeNode {
-                parent,
-                index: relevant_ids[pivot_index],
-                children: [None, None],
-            });
+        let query_point_axis_values = axis_values(&query_point);
+
+        if !self.tree.is_empty() {
+            stack.push((0, 0));
+        }
+
+        IndicesWithinIterator {
+            stack,
+            tree: self,
+            points,
+            radius_squared,
+            radius,
+            query_point_axis_values,
+            query_point,
+        }
+    }
+
+    /// See `KdTree`
+    #[inline(always)]
+    pub fn point_indices_within_buffers<R, S>(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        result: &mut R,
+        stack: &mut S,
+    ) where
+        R: ScratchVec<usize>,
+        S: ScratchVec<(usize, usize)>,
+    {
+        self.for_each_point_within_buffers(
+            points,
+            query_point,
+            radius,
+            |point_index, _| result.push(point_index),
+            stack,
+        )
+    }
+
+    /// The traversal behind `point_indices_within_buffers`, calling `found` with the index and
+    /// *squared* distance of every point within `radius` of `query_point`
+    #[inline(always)]
+    pub fn for_each_point_within_buffers<F, S>(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        found: F,
+        stack: &mut S,
+    ) where
+        F: FnMut(usize, f32),
+        S: ScratchVec<(usize, usize)>,
+    {
+        self.for_each_point_within_budgeted_buffers(
+            points,
+            query_point,
+            radius,
+            usize::MAX,
+            found,
+            stack,
+        );
+    }
+
+    /// Same as `for_each_point_within_buffers`, but gives up after visiting `max_visits` nodes.
+    /// Returns whether the traversal finished. `stack` is left empty either way.
+    #[inline(always)]
+    pub(crate) fn for_each_point_within_budgeted_buffers<F, S>(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        max_visits: usize,
+        mut found: F,
+        stack: &mut S,
+    ) -> bool
+    where
+        F: FnMut(usize, f32),
+        S: ScratchVec<(usize, usize)>,
+    {
+        let radius_squared = radius * radius;
+
+        self.for_each_candidate_within_budgeted_buffers(
+            &axis_values(&query_point),
+            radius,
+            max_visits,
+            |point_index| {
+                let distance_squared = query_point.distance_squared(points[point_index]);
+                if distance_squared <= radius_squared {
+                    found(point_index, distance_squared);
+                }
+            },
+            stack,
+        )
+    }
+
+    /// The traversal shared by the radius queries, calling `candidate` with the index of the
+    /// point of every node whose subtree can hold points within `radius` of the query point.
+    /// Deciding whether a candidate is within the radius is up to `candidate`, which lets the
+    /// queries measure distances their own way. Gives up after visiting `max_visits` nodes and
+    /// returns whether the traversal finished. `stack` is left empty either way.
+    #[inline(always)]
+    pub(crate) fn for_each_candidate_within_budgeted_buffers<F, S>(
+        &self,
+        query_point_axis_values: &[f32; D],
+        radius: f32,
+        max_visits: usize,
+        mut candidate: F,
+        stack: &mut S,
+    ) -> bool
+    where
+        F: FnMut(usize),
+        S: ScratchVec<(usize, usize)>,
+    {
+        if self.tree.is_empty() {
+            return true;
+        }
+
+        let radius_squared = radius * radius;
+
+        let mut visits = 0;
+        stack.push((0, 0));
+        while let Some((depth, tree_index)) = stack.pop() {
+            if self.sphere_out_of_reach(tree_index, query_point_axis_values, radius) {
+                continue;
+            }
+
+            if visits == max_visits {
+                stack.clear();
+                return false;
+            }
+            visits += 1;
+
+            let axis = self.tree[tree_index].axis();
+            let axis_query_point_val = query_point_axis_values[axis];
+            let axis_tree_point_val = self.tree[tree_index].split;
+            let axis_d = axis_tree_point_val - axis_query_point_val;
+
+            let left_first = axis_d >= 0.0;
+            let needs_to_go_both = axis_d * axis_d <= radius_squared;
+
+            candidate(self.tree[tree_index].index());
+
+            let first = if left_first { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            if let Some(child) = self.tree[tree_index].child(first) {
+                stack.push((depth + 1, child));
+            }
+            if needs_to_go_both {
+                if let Some(child) = self.tree[tree_index].child(last) {
+                    stack.push((depth + 1, child));
+                }
+            }
+        }
+
+        true
+    }
+
+    /// See `KdTree`
+    #[inline(always)]
+    pub fn point_indices_within(&self, points: &[P], query_point: P, radius: f32) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_buffers(points, query_point, radius, &mut result, &mut stack);
+
+        result
+    }
+
+    /// A hash of the structure of the tree: which point every node holds, its parent and its
+    /// children. Two trees with the same fingerprint are laid out identically, which makes this
+    /// a cheap way to detect desyncs between lockstep simulations, see
+    /// `KdTreeBuilder::deterministic`.
+    pub fn fingerprint(&self) -> u64 {
+        let mut hash = self.tree.len() as u64;
+        let mut mix = |value: u64| hash = SplitMix64::new(hash ^ value).next_u64();
+
+        for node in self.tree.iter() {
+            mix(node.index() as u64);
+            mix(node.parent() as u64);
+            mix(node.axis() as u64);
+            for child in node.children() {
+                mix(child.map_or(u64::MAX, |child| child as u64));
+            }
+        }
+
+        hash
+    }
+
+    /// See `KdTree`
+    pub fn any_within_buffers<S>(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        stack: &mut S,
+    ) -> bool
+    where
+        S: ScratchVec<(usize, usize)>,
+    {
+        if self.tree.is_empty() {
+            return false;
+        }
+
+        let radius_squared = radius * radius;
+        let query_point_axis_values = axis_values(&query_point);
+
+        stack.push((0, 0));
+        while let Some((depth, tree_index)) = stack.pop() {
+            if self.sphere_out_of_reach(tree_index, &query_point_axis_values, radius) {
+                continue;
+            }
+
+            let point_index = self.tree[tree_index].index();
+            if query_point.distance_squared(points[point_index]) <= radius_squared {
+                stack.clear();
+                return true;
+            }
+
+            let axis = self.tree[tree_index].axis();
+            let axis_d = self.tree[tree_index].split - query_point_axis_values[axis];
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            /*
+                NOTE: The near side is the more likely one to hold a point, so it is popped first
+            */
+            if axis_d * axis_d <= radius_squared {
+                if let Some(child) = self.tree[tree_index].child(last) {
+                    stack.push((depth + 1, child));
+                }
+            }
+            if let Some(child) = self.tree[tree_index].child(first) {
+                stack.push((depth + 1, child));
+            }
+        }
+
+        false
+    }
+
+    /// See `KdTree`
+    pub fn any_within(&self, points: &[P], query_point: P, radius: f32) -> bool {
+        let mut stack = vec![];
+        self.any_within_buffers(points, query_point, radius, &mut stack)
+    }
+
+    /// See `KdTree`
+    pub fn points_within_with_distances_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        result: &mut Vec<(usize, f32)>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.for_each_point_within_buffers(
+            points,
+            query_point,
+            radius,
+            |point_index, distance_squared| result.push((point_index, distance_squared.sqrt())),
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn points_within_with_distances(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+    ) -> Vec<(usize, f32)> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.points_within_with_distances_buffers(
+            points,
+            query_point,
+            radius,
+            &mut result,
+            &mut stack,
+        );
+
+        result
+    }
+
+    /// Same as `point_indices_within_buffers`, but the query point is given as its axis values
+    /// rather than as a `P`, which allows querying positions that can't be represented by a `P`
+    /// such as shifted copies of a point. Distances are euclidean, computed from `Point::get_axis`.
+    pub fn point_indices_within_axis_values_buffers(
+        &self,
+        points: &[P],
+        query_point_axis_values: [f32; D],
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        let radius_squared = radius * radius;
+
+        self.for_each_candidate_within_budgeted_buffers(
+            &query_point_axis_values,
+            radius,
+            usize::MAX,
+            |point_index| {
+                let point = points[point_index];
+                let distance_squared = (0..D)
+                    .map(|axis| {
+                        let delta = point.get_axis(axis) - query_point_axis_values[axis];
+                        delta * delta
+                    })
+                    .sum::<f32>();
+                if distance_squared <= radius_squared {
+                    result.push(point_index);
+                }
+            },
+            stack,
+        );
+    }
+
+    /// See `KdTree`
+    pub fn sample_point_indices_within_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        max_count: usize,
+        result: &mut Vec<usize>,
+        queue: &mut std::collections::VecDeque<(usize, usize)>,
+    ) {
+        if self.tree.is_empty() {
+            return;
+        }
+
+        let radius_squared = radius * radius;
+        let query_point_axis_values = axis_values(&query_point);
+        let mut count = 0;
+
+        queue.push_back((0, 0));
+        while let Some((depth, tree_index)) = queue.pop_front() {
+            if count >= max_count {
+                break;
+            }
+
+            if self.sphere_out_of_reach(tree_index, &query_point_axis_values, radius) {
+                continue;
+            }
+
+            let point_index = self.tree[tree_index].index();
+            let point = points[point_index];
+
+            let axis = self.tree[tree_index].axis();
+            let axis_d = self.tree[tree_index].split - query_point_axis_values[axis];
+
+            if query_point.distance_squared(point) <= radius_squared {
+                result.push(point_index);
+                count += 1;
+            }
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            if let Some(child) = self.tree[tree_index].child(first) {
+                queue.push_back((depth + 1, child));
+            }
+            if axis_d * axis_d <= radius_squared {
+                if let Some(child) = self.tree[tree_index].child(last) {
+                    queue.push_back((depth + 1, child));
+                }
+            }
+        }
+
+        queue.clear();
+    }
+
+    /// See `KdTree`
+    pub fn sample_point_indices_within(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        max_count: usize,
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut queue = std::collections::VecDeque::new();
+
+        self.sample_point_indices_within_buffers(
+            points,
+            query_point,
+            radius,
+            max_count,
+            &mut result,
+            &mut queue,
+        );
+
+        result
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_exact_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f64,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        let query_point_axis_values = axis_values(&query_point);
+
+        /*
+            NOTE: Pruning happens in f32 where every coordinate can be off by about an ulp of the
+                  largest magnitude involved. The pruning radius is widened by a few of those so that
+                  nothing inside the radius in f64 is pruned, and only candidates whose f32 distance
+                  lands in the band between `inner` and `outer` are confirmed in f64.
+        */
+        let magnitude = (0..D)
+            .map(|axis| {
+                self.bounds_min[axis]
+                    .abs()
+                    .max(self.bounds_max[axis].abs())
+                    .max(query_point_axis_values[axis].abs())
+            })
+            .fold(0.0_f32, f32::max);
+
+        let radius_f32 = radius as f32;
+        let slack = 4.0 * f32::EPSILON * (magnitude + radius_f32) * D as f32;
+        let outer = radius_f32 + slack;
+        let inner = (radius_f32 - slack).max(0.0);
+        let (outer_squared, inner_squared) = (outer * outer, inner * inner);
+        let radius_squared = radius * radius;
+
+        self.for_each_candidate_within_budgeted_buffers(
+            &query_point_axis_values,
+            outer,
+            usize::MAX,
+            |point_index| {
+                let point = points[point_index];
+                let distance_squared = query_point.distance_squared(point);
+                if distance_squared < inner_squared
+                    || (distance_squared <= outer_squared
+                        && query_point.distance_squared_f64(point) <= radius_squared)
+                {
+                    result.push(point_index);
+                }
+            },
+            stack,
+        );
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_exact(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f64,
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_exact_buffers(
+            points,
+            query_point,
+            radius,
+            &mut result,
+            &mut stack,
+        );
+
+        result
+    }
+
+    /// Computes `bounding_spheres` for every subtree. Radius queries on a tree with bounding
+    /// spheres skip whole subtrees whose sphere does not intersect the query sphere, on top of
+    /// the usual splitting plane pruning. This is mostly useful in higher dimensions, where the
+    /// splitting planes alone prune poorly.
+    pub fn compute_bounding_spheres(&mut self, points: &[P]) {
+        let mut spheres = vec![([0.0; D], 0.0); self.tree.len()];
+
+        /*
+            NOTE: Children are always placed after their parent in `tree`, so iterating in
+                  reverse visits every child before its parent.
+        */
+        for tree_index in (0..self.tree.len()).rev() {
+            let node = &self.tree[tree_index];
+            let mut sphere = (axis_values(&points[node.index()]), 0.0);
+
+            for child in node.children().iter().flatten() {
+                sphere = merge_spheres(sphere, spheres[*child]);
+            }
+
+            spheres[tree_index] = sphere;
+        }
+
+        self.bounding_spheres = spheres;
+        self.config.bounding_spheres = true;
+    }
+
+    /// Whether the bounding sphere of the subtree at `tree_index` is further than `radius`
+    /// from the query point. Always false when no bounding spheres have been computed.
+    #[inline(always)]
+    pub fn sphere_out_of_reach(
+        &self,
+        tree_index: usize,
+        query_point_axis_values: &[f32; D],
+        radius: f32,
+    ) -> bool {
+        let Some((center, sphere_radius)) = self.bounding_spheres.get(tree_index) else {
+            return false;
+        };
+
+        let distance_squared = (0..D)
+            .map(|axis| {
+                let delta = center[axis] - query_point_axis_values[axis];
+                delta * delta
+            })
+            .sum::<f32>();
+
+        let reach = radius + sphere_radius;
+        distance_squared > reach * reach
+    }
+
+    /// A lower bound of the squared distance from the query point to any point in the subtree at
+    /// `tree_index` given by its bounding sphere. Always zero when no bounding spheres have been computed.
+    #[inline(always)]
+    pub fn sphere_distance_squared_lower_bound(
+        &self,
+        tree_index: usize,
+        query_point_axis_values: &[f32; D],
+    ) -> f32 {
+        let Some((center, sphere_radius)) = self.bounding_spheres.get(tree_index) else {
+            return 0.0;
+        };
+
+        let distance = (0..D)
+            .map(|axis| {
+                let delta = center[axis] - query_point_axis_values[axis];
+                delta * delta
+            })
+            .sum::<f32>()
+            .sqrt();
+
+        /*
+            NOTE: Squaring the square root can round up past the squared distance to a point on
+                  the surface, such as the point of a leaf, which would prune it away when it
+                  ties with the current bound. Shrink the bound by a few ulps to stay below it.
+        */
+        let gap = (distance - sphere_radius).max(0.0);
+        gap * gap * (1.0 - 4.0 * f32::EPSILON)
+    }
+
+    /// Finds the point closest to `query_point` among the points for which `accept` returns true,
+    /// returning its index and *squared* distance. This is the traversal shared by all nearest
+    /// neighbor queries.
+    ///
+    /// `stack` holds `(depth, tree_index, lower bound of the squared distance)` and is cleared
+    /// before returning.
+    pub fn nearest_one_by_buffers<F, S>(
+        &self,
+        points: &[P],
+        query_point: P,
+        mut accept: F,
+        stack: &mut S,
+    ) -> Option<(usize, f32)>
+    where
+        F: FnMut(usize) -> bool,
+        S: ScratchVec<(usize, usize, f32)>,
+    {
+        if self.tree.is_empty() {
+            return None;
+        }
+
+        let query_point_axis_values = axis_values(&query_point);
+        let mut best: Option<(usize, f32)> = None;
+
+        stack.push((0, 0, 0.0));
+        while let Some((depth, tree_index, lower_bound)) = stack.pop() {
+            let best_distance_squared = best.map_or(f32::INFINITY, |(_, d)| d);
+            if lower_bound > best_distance_squared {
+                continue;
+            }
+
+            let sphere_bound =
+                self.sphere_distance_squared_lower_bound(tree_index, &query_point_axis_values);
+            if sphere_bound > best_distance_squared {
+                continue;
+            }
+
+            let point_index = self.tree[tree_index].index();
+            let point = points[point_index];
+
+            let distance_squared = query_point.distance_squared(point);
+            if distance_squared < best_distance_squared && accept(point_index) {
+                best = Some((point_index, distance_squared));
+            }
+
+            let axis = self.tree[tree_index].axis();
+            let axis_d = self.tree[tree_index].split - query_point_axis_values[axis];
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            /*
+                NOTE: The far side is pushed first so that the near side is popped first
+            */
+            if let Some(child) = self.tree[tree_index].child(last) {
+                stack.push((depth + 1, child, lower_bound.max(axis_d * axis_d)));
+            }
+            if let Some(child) = self.tree[tree_index].child(first) {
+                stack.push((depth + 1, child, lower_bound));
+            }
+        }
+
+        best
+    }
+
+    /// See `KdTree`
+    pub fn nearest_one_buffers<S>(
+        &self,
+        points: &[P],
+        query_point: P,
+        stack: &mut S,
+    ) -> Option<(usize, f32)>
+    where
+        S: ScratchVec<(usize, usize, f32)>,
+    {
+        if self.tree.is_empty() {
+            return None;
+        }
+
+        self.nearest_one_by_buffers(points, query_point, |_| true, stack)
+            .map(|(index, distance_squared)| (index, distance_squared.sqrt()))
+    }
+
+    /// See `KdTree`
+    pub fn nearest_one(&self, points: &[P], query_point: P) -> Option<(usize, f32)> {
+        let mut stack = vec![];
+        self.nearest_one_buffers(points, query_point, &mut stack)
+    }
+
+    /// See `KdTree`
+    pub fn nearest_one_masked(
+        &self,
+        points: &[P],
+        query_point: P,
+        disabled: &[bool],
+    ) -> Option<(usize, f32)> {
+        let mut stack = vec![];
+
+        self.nearest_one_by_buffers(points, query_point, |i| !disabled[i], &mut stack)
+            .map(|(index, distance_squared)| (index, distance_squared.sqrt()))
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_masked_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        disabled: &[bool],
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.point_indices_within_filtered_buffers(
+            points,
+            query_point,
+            radius,
+            |i| !disabled[i],
+            result,
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_filtered_buffers<F, R, S>(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        mut filter: F,
+        result: &mut R,
+        stack: &mut S,
+    ) where
+        F: FnMut(usize) -> bool,
+        R: ScratchVec<usize>,
+        S: ScratchVec<(usize, usize)>,
+    {
+        self.for_each_point_within_buffers(
+            points,
+            query_point,
+            radius,
+            |point_index, _| {
+                if filter(point_index) {
+                    result.push(point_index);
+                }
+            },
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_filtered<F>(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        filter: F,
+    ) -> Vec<usize>
+    where
+        F: FnMut(usize) -> bool,
+    {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_filtered_buffers(
+            points,
+            query_point,
+            radius,
+            filter,
+            &mut result,
+            &mut stack,
+        );
+
+        result
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_masked(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        disabled: &[bool],
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_masked_buffers(
+            points,
+            query_point,
+            radius,
+            disabled,
+            &mut result,
+            &mut stack,
+        );
+
+        result
+    }
+
+    /// See `KdTree`
+    #[inline(always)]
+    pub fn axis_bounds(&self, axis: usize) -> (f32, f32) {
+        (self.bounds_min[axis], self.bounds_max[axis])
+    }
+
+    /// See `KdTree`
+    #[inline(always)]
+    pub fn bounds(&self) -> ([f32; D], [f32; D]) {
+        (self.bounds_min, self.bounds_max)
+    }
+
+    /// See `KdTree`
+    pub fn clamp_to_bounds(&self, query_point: P) -> [f32; D] {
+        let mut clamped = axis_values(&query_point);
+        for (axis, value) in clamped.iter_mut().enumerate() {
+            *value = value.max(self.bounds_min[axis]).min(self.bounds_max[axis]);
+        }
+        clamped
+    }
+
+    /// See `KdTree`
+    pub fn distance_squared_to_bounds(&self, query_point: P) -> f32 {
+        let clamped = self.clamp_to_bounds(query_point);
+        (0..D)
+            .map(|axis| {
+                let delta = query_point.get_axis(axis) - clamped[axis];
+                delta * delta
+            })
+            .sum()
+    }
+}
+
+impl<P: Point<2>, N: NodeStorage> KdTreeNoBorrow<2, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_within_wrapped_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        world_size: [f32; 2],
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        /*
+            NOTE: Wrapping the query point into the world first means that the closest image of
+                  every point is at most one world size away, whatever the radius
+        */
+        let mut query_point_axis_values = axis_values(&query_point);
+        for axis in 0..2 {
+            query_point_axis_values[axis] =
+                query_point_axis_values[axis].rem_euclid(world_size[axis]);
+        }
+        let start = result.len();
 
-            let new_depth = depth + 1;
+        let shifts = |axis: usize| {
+            let value = query_point_axis_values[axis];
+            let size = world_size[axis];
 
-            let (left_start, left_end) = (start, pivot_index);
-            if left_start != left_end {
-                jobs.push(Job {
-                    start: left_start,
-                    end: left_end,
-                    left_right: 0,
-                    depth: new_depth,
-                    parent: tree_index,
-                });
+            let mut shifts = [Some(0.0), None, None];
+            if value - radius < 0.0 {
+                shifts[1] = Some(size);
             }
-
-            let (right_start, right_end) = (pivot_index + 1, end);
-            if right_start != right_end {
-                jobs.push(Job {
-                    start: right_start,
-                    end: right_end,
-                    left_right: 1,
-                    depth: new_depth,
-                    parent: tree_index,
-                });
+            if value + radius > size {
+                shifts[2] = Some(-size);
             }
+            shifts
+        };
 
-            if depth > 0 {
-                /*
-                    NOTE: Root has no parent so this only happens when we are
-                          not root
-                */
+        for shift_x in shifts(0).into_iter().flatten() {
+            for shift_y in shifts(1).into_iter().flatten() {
+                let shifted = [
+                    query_point_axis_values[0] + shift_x,
+                    query_point_axis_values[1] + shift_y,
+                ];
 
-                tree[parent].children[left_right] = Some(tree_index);
+                self.point_indices_within_axis_values_buffers(
+                    points, shifted, radius, result, stack,
+                );
             }
         }
 
-        Self {
-            tree,
-            __marker: std::marker::PhantomData,
+        /*
+            NOTE: The shifted queries can only overlap when the radius is at least half of
+                  the world size, but when they do, the same point is found more than once
+        */
+        result[start..].sort_unstable();
+        let mut unique = start;
+        for i in start..result.len() {
+            if i == start || result[i] != result[unique - 1] {
+                result[unique] = result[i];
+                unique += 1;
+            }
         }
+        result.truncate(unique);
     }
 
     /// See `KdTree`
-    pub fn iter_point_indices_within_buffers<'a>(
-        &'a self,
-        points: &'a [P],
+    pub fn point_indices_within_wrapped(
+        &self,
+        points: &[P],
         query_point: P,
         radius: f32,
-        stack: &'a mut Vec<(usize, usize)>,
-    ) -> IndicesWithinIterator<'_, D, P> {
-        let radius_squared = radius * radius;
-
-        let mut query_point_axis_values = [0.0; D];
-        for i in 0..D {
-            query_point_axis_values[i] = query_point.get_axis(i);
-        }
-
-        stack.push((0, 0));
+        world_size: [f32; 2],
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
 
-        IndicesWithinIterator {
-            stack,
-            tree: self,
+        self.point_indices_within_wrapped_buffers(
             points,
-            radius_squared,
-            radius,
-            query_point_axis_values,
             query_point,
-        }
+            radius,
+            world_size,
+            &mut result,
+            &mut stack,
+        );
+
+        result
     }
+}
 
-    /// See `KdTree`
+impl<'a, P: Point<2>> KdTree<'a, 2, P> {
+    /// Radius query on a toroidal 2D world where positions wrap around at `0` and `world_size`
+    /// on both axes, such as wrapping game maps. All points are expected to lie within
+    /// `[0, world_size)`, while the query point is wrapped into it. A point is found when its
+    /// closest wrapped image is within `radius`, so any radius is supported and one reaching
+    /// half of the diagonal of the world finds every point.
+    ///
+    /// Near the edges, up to four shifted queries are issued, or nine once the radius reaches
+    /// half of the world size, and their results are deduplicated so every index appears at most
+    /// once. The found indices are sorted.
+    ///
+    /// Indices are appended to `result`, which is not cleared by this function.
     #[inline(always)]
-    pub fn point_indices_within_buffers(
+    pub fn point_indices_within_wrapped_buffers(
         &self,
-        points: &[P],
         query_point: P,
         radius: f32,
+        world_size: [f32; 2],
         result: &mut Vec<usize>,
         stack: &mut Vec<(usize, usize)>,
     ) {
-        let radius_squared = radius * radius;
+        self.internal.point_indices_within_wrapped_buffers(
+            self.points,
+            query_point,
+            radius,
+            world_size,
+            result,
+            stack,
+        )
+    }
 
-        let mut querty_point_axis_values = [0.0; D];
-        for i in 0..D {
-            querty_point_axis_values[i] = query_point.get_axis(i);
-        }
+    /// See `point_indices_within_wrapped_buffers`
+    #[inline(always)]
+    pub fn point_indices_within_wrapped(
+        &self,
+        query_point: P,
+        radius: f32,
+        world_size: [f32; 2],
+    ) -> Vec<usize> {
+        self.internal
+            .point_indices_within_wrapped(self.points, query_point, radius, world_size)
+    }
+}
 
-        stack.push((0, 0));
-        while let Some((depth, tree_index)) = stack.pop() {
-            let point_index = self.tree[tree_index].index;
+/// The smallest sphere enclosing both spheres `a` and `b`
+fn merge_spheres<const D: usize>(a: ([f32; D], f32), b: ([f32; D], f32)) -> ([f32; D], f32) {
+    let distance = (0..D)
+        .map(|axis| {
+            let delta = b.0[axis] - a.0[axis];
+            delta * delta
+        })
+        .sum::<f32>()
+        .sqrt();
+
+    if distance + b.1 <= a.1 {
+        return a;
+    }
+    if distance + a.1 <= b.1 {
+        return b;
+    }
 
-            let axis = depth % D;
-            let axis_query_point_val = querty_point_axis_values[axis];
-            let axis_tree_point_val = points[point_index].get_axis(axis);
-            let axis_d = axis_tree_point_val - axis_query_point_val;
+    let radius = (distance + a.1 + b.1) * 0.5;
+    let t = (radius - a.1) / distance;
 
-            let left_first = axis_d >= 0.0;
-            let needs_to_go_both = axis_d.abs() <= radius;
+    let mut center = a.0;
+    for (axis, value) in center.iter_mut().enumerate() {
+        *value += (b.0[axis] - a.0[axis]) * t;
+    }
 
-            if query_point.distance_squared(points[point_index]) <= radius_squared {
-                result.push(point_index);
-            }
+    /*
+        NOTE: Grow the radius by a tiny bit so that rounding never leaves a point
+              just outside of its sphere
+    */
+    (center, radius * (1.0 + 4.0 * f32::EPSILON))
+}
 
-            let first = if left_first { 0 } else { 1 };
-            let last = (first + 1) % 2;
+#[derive(Debug, Clone, Copy)]
+/// A half-open range `start..end` of point ids that still has to be built into a subtree
+pub(crate) struct BuildJob {
+    pub start: usize,
+    pub end: usize,
+    pub left_right: usize,
+    pub depth: usize,
+    pub parent: usize,
+}
 
-            if let Some(child) = self.tree[tree_index].children[first] {
-                stack.push((depth + 1, child));
-            }
-            if needs_to_go_both {
-                if let Some(child) = self.tree[tree_index].children[last] {
-                    stack.push((depth + 1, child));
-                }
-            }
-        }
+/// Builds the subtree described by `root_job` by pushing its nodes onto `tree`. Jobs that
+/// reach `stop_depth` are not built but pushed onto `deferred` instead so that they can be
+/// built separately (for instance on another thread) and attached afterwards.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn build_subtree<const D: usize, P, F, T>(
+    points: &[P],
+    point_ids: &mut [usize],
+    root_job: BuildJob,
+    points_sorter: &mut F,
+    split_strategy: &SplitStrategy,
+    tree: &mut T,
+    stop_depth: usize,
+    deferred: &mut Vec<BuildJob>,
+) where
+    P: Point<D>,
+    F: FnMut(&[P], &mut [usize], usize),
+    T: ScratchVec<KdTreeNode> + std::ops::DerefMut<Target = [KdTreeNode]>,
+{
+    if root_job.start == root_job.end {
+        return;
     }
 
-    /// See `KdTree`
-    #[inline(always)]
-    pub fn point_indices_within(&self, points: &[P], query_point: P, radius: f32) -> Vec<usize> {
-        let mut result = vec![];
-        let mut stack = vec![];
+    let root_index = tree.len();
 
-        self.point_indices_within_buffers(points, query_point, radius, &mut result, &mut stack);
+    /*
+        NOTE: Pivots are always the middle of their range, so the tree is balanced and at most
+              two jobs per level are pending at once. This keeps the job stack off the heap.
+    */
+    let mut jobs = [root_job; 2 * usize::BITS as usize];
+    let mut jobs_len = 1;
 
-        result
+    while jobs_len > 0 {
+        jobs_len -= 1;
+        let job = jobs[jobs_len];
+
+        if job.depth >= stop_depth {
+            deferred.push(job);
+            continue;
+        }
+
+        let BuildJob {
+            start,
+            end,
+            left_right,
+            depth,
+            parent,
+        } = job;
+
+        let axis = split_strategy.split_axis(points, &point_ids[start..end], depth);
+        let pivot_index = (start + end) / 2;
+
+        points_sorter(points, &mut point_ids[start..end], axis);
+
+        let tree_index = tree.len();
+        tree.push(KdTreeNode::new(
+            parent,
+            point_ids[pivot_index],
+            axis,
+            points[point_ids[pivot_index]].get_axis(axis),
+            [None, None],
+        ));
+
+        let new_depth = depth + 1;
+        let (left_start, left_end) = (start, pivot_index);
+        if left_start != left_end {
+            jobs[jobs_len] = BuildJob {
+                start: left_start,
+                end: left_end,
+                left_right: 0,
+                depth: new_depth,
+                parent: tree_index,
+            };
+            jobs_len += 1;
+        }
+
+        let (right_start, right_end) = (pivot_index + 1, end);
+        if right_start != right_end {
+            jobs[jobs_len] = BuildJob {
+                start: right_start,
+                end: right_end,
+                left_right: 1,
+                depth: new_depth,
+                parent: tree_index,
+            };
+            jobs_len += 1;
+        }
+
+        if tree_index != root_index {
+            /*
+                NOTE: The root of the subtree has no parent within it so this only
+                      happens when we are not the root
+            */
+
+            tree[parent].set_child(left_right, Some(tree_index));
+        }
     }
 }
 
 /// Iterator over indices of points in a KdTree within a hypersphere of `radius` using the
 /// euclidean distance function `Point::distance_squared`
-pub struct IndicesWithinIterator<'a, const D: usize, P: Point<D>> {
+pub struct IndicesWithinIterator<'a, const D: usize, P: Point<D>, N: NodeStorage = Vec<KdTreeNode>>
+{
     pub stack: &'a mut Vec<(usize, usize)>,
-    pub tree: &'a KdTreeNoBorrow<D, P>,
+    pub tree: &'a KdTreeNoBorrow<D, P, N>,
     pub points: &'a [P],
     pub radius_squared: f32,
     pub radius: f32,
@@ -448,29 +2424,38 @@ pub struct IndicesWithinIterator<'a, const D: usize, P: Point<D>> {
     pub query_point: P,
 }
 
-impl<'a, const D: usize, P: Point<D>> std::iter::Iterator for IndicesWithinIterator<'a, D, P> {
+impl<'a, const D: usize, P: Point<D>, N: NodeStorage> std::iter::Iterator
+    for IndicesWithinIterator<'a, D, P, N>
+{
     type Item = usize;
 
     fn next(&mut self) -> Option<Self::Item> {
         while let Some((depth, tree_index)) = self.stack.pop() {
-            let point_index = self.tree.tree[tree_index].index;
+            if self
+                .tree
+                .sphere_out_of_reach(tree_index, &self.query_point_axis_values, self.radius)
+            {
+                continue;
+            }
 
-            let axis = depth % D;
+            let point_index = self.tree.tree[tree_index].index();
+
+            let axis = self.tree.tree[tree_index].axis();
             let axis_query_point_val = self.query_point_axis_values[axis];
-            let axis_tree_point_val = self.points[point_index].get_axis(axis);
+            let axis_tree_point_val = self.tree.tree[tree_index].split;
             let axis_d = axis_tree_point_val - axis_query_point_val;
 
             let left_first = axis_d >= 0.0;
-            let needs_to_go_both = axis_d.abs() <= self.radius;
+            let needs_to_go_both = axis_d * axis_d <= self.radius_squared;
 
             let first = if left_first { 0 } else { 1 };
             let last = (first + 1) % 2;
 
-            if let Some(child) = self.tree.tree[tree_index].children[first] {
+            if let Some(child) = self.tree.tree[tree_index].child(first) {
                 self.stack.push((depth + 1, child));
             }
             if needs_to_go_both {
-                if let Some(child) = self.tree.tree[tree_index].children[last] {
+                if let Some(child) = self.tree.tree[tree_index].child(last) {
                     self.stack.push((depth + 1, child));
                 }
             }
@@ -576,6 +2561,417 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_bounds() {
+        let points: Vec<[f32; 2]> = (0..10)
+            .map(|i| [i as f32, (i * 7 % 5) as f32 - 2.0])
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        assert_eq!(tree.axis_bounds(0), (0.0, 9.0));
+        assert_eq!(tree.axis_bounds(1), (-2.0, 2.0));
+        assert_eq!(tree.bounds(), ([0.0, -2.0], [9.0, 2.0]));
+        assert_eq!(tree.clamp_to_bounds([12.0, 0.5]), [9.0, 0.5]);
+        assert_eq!(tree.distance_squared_to_bounds([12.0, -6.0]), 9.0 + 16.0);
+        assert_eq!(tree.distance_squared_to_bounds([4.0, 1.0]), 0.0);
+
+        let mut all = tree.point_indices_within([0.0, 0.0], 100.0);
+        all.sort();
+        assert_eq!(all, (0..points.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_exact_boundary() {
+        let points: [[f64; 2]; 3] = [
+            [10_000_000.75, 0.0],
+            [9_999_999.75, 0.0],
+            [10_000_000.0, 0.0],
+        ];
+        let tree = KdTree::from_points(&points);
+
+        let mut exact = tree.point_indices_within_exact([10_000_000.3, 0.0], 0.5);
+        exact.sort();
+        assert_eq!(exact, vec![0, 2]);
+    }
+
+    #[test]
+    fn test_bounding_spheres() {
+        let points: Vec<[f32; 4]> = (0..500)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.3).sin(), (i * 0.7).cos(), (i * 1.3).sin(), i * 0.002]
+            })
+            .collect();
+
+        let mut tree = KdTreeNoBorrow::from_points(&points);
+        let mut expected = tree.point_indices_within(&points, [0.2, 0.1, -0.3, 0.5], 0.6);
+
+        tree.compute_bounding_spheres(&points);
+        for (tree_index, node) in tree.tree.iter().enumerate() {
+            let (center, radius) = tree.bounding_spheres[tree_index];
+            let point = points[node.index()];
+            assert!(center.distance_squared(point) <= radius * radius);
+        }
+
+        /*
+            NOTE: On integer coordinates the squared distance to a leaf equals its bound exactly
+                  unless the bound rounds up
+        */
+        let grid = (0..64)
+            .map(|i| [(i % 8) as f32, (i / 8) as f32, 0.0, 0.0])
+            .collect::<Vec<_>>();
+        let mut grid_tree = KdTreeNoBorrow::from_points(&grid);
+        grid_tree.compute_bounding_spheres(&grid);
+        for query_point in [[9.0, 3.0, 0.0, 0.0], [-4.0, -7.0, 1.0, 0.0]] {
+            for (tree_index, node) in grid_tree.tree.iter().enumerate() {
+                let lower_bound = grid_tree
+                    .sphere_distance_squared_lower_bound(tree_index, &axis_values(&query_point));
+                assert!(lower_bound <= query_point.distance_squared(grid[node.index()]));
+            }
+        }
+
+        let mut result = tree.point_indices_within(&points, [0.2, 0.1, -0.3, 0.5], 0.6);
+        expected.sort();
+        result.sort();
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_wrapped() {
+        #[rustfmt::skip]
+        let points: [[f32; 2]; 6] = [
+            [0.5, 0.5],
+            [9.5, 0.5],
+            [0.5, 9.5],
+            [9.5, 9.5],
+            [5.0, 5.0],
+            [9.0, 5.0],
+        ];
+        let tree = KdTree::from_points(&points);
+
+        let result = tree.point_indices_within_wrapped([0.0, 0.0], 1.0, [10.0, 10.0]);
+        assert_eq!(result, vec![0, 1, 2, 3]);
+
+        let result = tree.point_indices_within_wrapped([0.5, 5.0], 1.6, [10.0, 10.0]);
+        assert_eq!(result, vec![5]);
+
+        let result = tree.point_indices_within_wrapped([5.0, 5.0], 100.0, [10.0, 10.0]);
+        assert_eq!(result, vec![0, 1, 2, 3, 4, 5]);
+
+        let mut rng = SplitMix64::new(109);
+        let world_size = [10.0, 4.0];
+        let points = (0..300)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 1000) as f32 / 100.0,
+                    (rng.next_u64() % 400) as f32 / 100.0,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        for (query_point, radius) in [
+            ([0.3, 3.9], 2.1),
+            ([9.9, 0.1], 4.5),
+            ([5.0, 2.0], 10.0),
+            ([-13.0, 9.5], 3.0),
+            ([21.5, -0.5], 5.5),
+        ] {
+            let expected = (0..points.len())
+                .filter(|i| {
+                    let distance_squared = (0..2)
+                        .map(|axis| {
+                            let delta =
+                                (points[*i][axis] - query_point[axis]).rem_euclid(world_size[axis]);
+                            let delta = delta.min(world_size[axis] - delta);
+                            delta * delta
+                        })
+                        .sum::<f32>();
+                    distance_squared <= radius * radius
+                })
+                .collect::<Vec<_>>();
+
+            let result = tree.point_indices_within_wrapped(query_point, radius, world_size);
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_sample_within() {
+        let points: Vec<[f32; 2]> = (0..10_000)
+            .map(|i| [(i % 100) as f32, (i / 100) as f32])
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        let sample = tree.sample_point_indices_within([50.0, 50.0], 30.0, 64);
+        assert_eq!(sample.len(), 64);
+        for index in &sample {
+            assert!(points[*index].distance_squared([50.0, 50.0]) <= 30.0 * 30.0);
+        }
+
+        let quadrants = sample.iter().fold([0; 4], |mut quadrants, index| {
+            let [x, y] = points[*index];
+            quadrants[(x >= 50.0) as usize + 2 * (y >= 50.0) as usize] += 1;
+            quadrants
+        });
+        assert!(quadrants.iter().all(|count| *count > 0));
+
+        let mut all = tree.sample_point_indices_within([50.0, 50.0], 3.0, 1000);
+        let mut expected = tree.point_indices_within([50.0, 50.0], 3.0);
+        all.sort();
+        expected.sort();
+        assert_eq!(all, expected);
+
+        let empty: [[f32; 2]; 0] = [];
+        let tree = KdTree::from_points(&empty);
+        assert!(tree
+            .sample_point_indices_within([50.0, 50.0], 30.0, 64)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_nearest_one_masked() {
+        let points: Vec<[f32; 3]> = (0..400)
+            .map(|i| {
+                let i = i as f32;
+                [
+                    (i * 0.37).sin() * 5.0,
+                    (i * 0.91).cos() * 5.0,
+                    (i * 0.13).sin(),
+                ]
+            })
+            .collect();
+        let mut tree = KdTreeNoBorrow::from_points(&points);
+        let mut disabled = vec![false; points.len()];
+
+        for with_spheres in [false, true] {
+            if with_spheres {
+                tree.compute_bounding_spheres(&points);
+            }
+            disabled.iter_mut().for_each(|d| *d = false);
+
+            let query = [0.3, -1.2, 0.1];
+            for _ in 0..points.len() {
+                let expected = (0..points.len())
+                    .filter(|i| !disabled[*i])
+                    .min_by(|a, b| {
+                        query
+                            .distance_squared(points[*a])
+                            .total_cmp(&query.distance_squared(points[*b]))
+                    })
+                    .unwrap();
+
+                let (index, distance) = tree.nearest_one_masked(&points, query, &disabled).unwrap();
+                assert_eq!(index, expected);
+                assert_eq!(distance, query.distance_squared(points[expected]).sqrt());
+
+                let mut within = tree.point_indices_within_masked(&points, query, 2.0, &disabled);
+                within.sort_unstable();
+                let expected_within = (0..points.len())
+                    .filter(|i| !disabled[*i] && query.distance_squared(points[*i]) <= 4.0)
+                    .collect::<Vec<_>>();
+                assert_eq!(within, expected_within);
+
+                disabled[index] = true;
+            }
+
+            assert_eq!(tree.nearest_one_masked(&points, query, &disabled), None);
+            assert!(tree
+                .point_indices_within_masked(&points, query, 100.0, &disabled)
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn test_nearest_one() {
+        let points: Vec<[f32; 2]> = (0..500)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.37).sin() * 50.0, (i * 0.11).cos() * 20.0]
+            })
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        for query in [[0.0, 0.0], [49.0, -19.0], [-100.0, 3.0], [12.5, 7.25]] {
+            let expected = (0..points.len())
+                .min_by(|a, b| {
+                    query
+                        .distance_squared(points[*a])
+                        .total_cmp(&query.distance_squared(points[*b]))
+                })
+                .unwrap();
+
+            let (index, distance) = tree.nearest_one(query).unwrap();
+            assert_eq!(
+                query.distance_squared(points[index]),
+                query.distance_squared(points[expected])
+            );
+            assert_eq!(distance, query.distance_squared(points[index]).sqrt());
+        }
+    }
+
+    #[test]
+    fn test_point_indices_within_filtered() {
+        let points: Vec<[f32; 2]> = (0..500)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.37).sin() * 10.0, (i * 0.11).cos() * 10.0]
+            })
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        let mut calls = 0;
+        let result = tree.point_indices_within_filtered([0.0, 0.0], 5.0, |i| {
+            calls += 1;
+            i % 2 == 0
+        });
+
+        let all = tree.point_indices_within([0.0, 0.0], 5.0);
+        assert_eq!(calls, all.len());
+        assert_eq!(
+            result,
+            all.into_iter().filter(|i| i % 2 == 0).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_any_within() {
+        let points: Vec<[f32; 2]> = (0..300)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.37).sin() * 20.0, (i * 0.11).cos() * 20.0]
+            })
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        let mut stack = vec![];
+        for query in [[0.0, 0.0], [19.0, 19.0], [-30.0, 5.0], [3.3, -7.1]] {
+            for radius in [0.01, 0.5, 2.0, 50.0] {
+                let expected = !tree.point_indices_within(query, radius).is_empty();
+                assert_eq!(tree.any_within_buffers(query, radius, &mut stack), expected);
+                assert!(stack.is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_points_within_with_distances() {
+        let points: Vec<[f32; 3]> = (0..800)
+            .map(|i| {
+                let i = i as f32;
+                [
+                    (i * 0.37).sin() * 5.0,
+                    (i * 0.91).cos() * 5.0,
+                    (i * 0.13).sin(),
+                ]
+            })
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        let query = [1.0, 0.5, 0.0];
+        let with_distances = tree.points_within_with_distances(query, 2.0);
+        let indices = tree.point_indices_within(query, 2.0);
+
+        assert_eq!(
+            with_distances.iter().map(|(i, _)| *i).collect::<Vec<_>>(),
+            indices
+        );
+        for (index, distance) in with_distances {
+            assert_eq!(distance, query.distance_squared(points[index]).sqrt());
+        }
+    }
+
+    #[test]
+    fn test_ties_build_identical_trees() {
+        let points: Vec<[f32; 2]> = (0..200).map(|i| [(i % 3) as f32, (i % 5) as f32]).collect();
+
+        let strategies = [
+            SortingStrategy::StableSort,
+            SortingStrategy::UnstableSort,
+            SortingStrategy::ShellSort,
+            SortingStrategy::HeapSort,
+            SortingStrategy::QuickSort,
+            SortingStrategy::MedianSelect,
+        ];
+        let trees = strategies
+            .iter()
+            .flat_map(|strategy| {
+                [
+                    KdTreeNoBorrow::from_points_with_strategy(&points, strategy),
+                    KdTreeNoBorrow::from_points_presort_with_strategy(&points, strategy),
+                ]
+            })
+            .map(|tree| {
+                tree.tree
+                    .iter()
+                    .map(|node| node.index())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        for tree in &trees[1..] {
+            assert_eq!(tree, &trees[0]);
+        }
+
+        let tree = KdTree::from_points(&points);
+        let mut result = tree.point_indices_within([1.0, 2.0], 1.0);
+        result.sort();
+        let expected = (0..points.len())
+            .filter(|i| points[*i].distance_squared([1.0, 2.0]) <= 1.0)
+            .collect::<Vec<_>>();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_widest_axis_split() {
+        let mut rng = SplitMix64::new(61);
+        let points = (0..1000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 10000) as f32,
+                    (rng.next_u64() % 10) as f32,
+                    (rng.next_u64() % 100) as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let serial = KdTreeNoBorrow::from_points_with_points_sorter_and_split(
+            &points,
+            points_sorter(&SortingStrategy::QuickSort),
+            &SplitStrategy::WidestAxis,
+        );
+        let presort =
+            KdTreeNoBorrow::from_points_with_split_strategy(&points, &SplitStrategy::WidestAxis);
+        assert_eq!(serial.fingerprint(), presort.fingerprint());
+
+        /*
+            NOTE: The points are spread 1000 times more along x than along y, so the top levels
+                  all split along x
+        */
+        let mut depth = vec![0; presort.tree.len()];
+        for (tree_index, node) in presort.tree.iter().enumerate() {
+            for child in node.children().iter().flatten() {
+                depth[*child] = depth[tree_index] + 1;
+            }
+            if depth[tree_index] < 3 {
+                assert_eq!(node.axis(), 0);
+            }
+            assert_eq!(node.split, points[node.index()][node.axis()]);
+        }
+        assert!(presort.tree.iter().any(|node| node.axis() == 2));
+
+        let tree = KdTree::from_points_with_split_strategy(&points, &SplitStrategy::WidestAxis);
+        for query_point in [[5000.0, 5.0, 50.0], [120.0, 0.0, 99.0]] {
+            let mut result = tree.point_indices_within(query_point, 200.0);
+            result.sort();
+            let expected = (0..points.len())
+                .filter(|i| points[*i].distance_squared(query_point) <= 200.0 * 200.0)
+                .collect::<Vec<_>>();
+            assert!(!expected.is_empty());
+            assert_eq!(result, expected);
+        }
+    }
+
     #[test]
     fn test_arr_12_non_owning() {
         let points: [[f32; 3]; 12] = [
@@ -600,4 +2996,192 @@ mod tests {
             dbg!(point);
         }
     }
+
+    #[test]
+    fn test_rebalance() {
+        let points = (0..64).map(|i| [i as f32, 0.0]).collect::<Vec<_>>();
+        let balanced = KdTreeNoBorrow::from_points(&points);
+        assert_eq!(balanced.depth(), 7);
+
+        let mut tree = balanced.clone();
+        assert!(!tree.rebalance(&points));
+
+        /*
+            NOTE: A valid but degenerate tree where every node only has a right child
+        */
+        tree.tree = (0..64)
+            .map(|i| {
+                KdTreeNode::new(
+                    i.max(1) - 1,
+                    i,
+                    0,
+                    i as f32,
+                    [None, (i + 1 < 64).then_some(i + 1)],
+                )
+            })
+            .collect();
+        assert_eq!(tree.depth(), 64);
+        assert_eq!(
+            tree.point_indices_within(&points, [10.0, 0.0], 1.5),
+            vec![9, 10, 11]
+        );
+
+        let capacity = tree.tree.capacity();
+        assert!(tree.rebalance(&points));
+        assert_eq!(tree.fingerprint(), balanced.fingerprint());
+        assert_eq!(tree.tree.capacity(), capacity);
+        assert!(!tree.rebalance(&points));
+
+        assert!(tree.rebalance(&points[..10]));
+        assert_eq!(tree.depth(), 4);
+        assert_eq!(tree.bounds_max, [9.0, 0.0]);
+    }
+
+    #[test]
+    fn test_rebalance_moved_points() {
+        let mut rng = SplitMix64::new(103);
+        let mut points = (0..1000)
+            .map(|_| [(rng.next_u64() % 100) as f32, (rng.next_u64() % 100) as f32])
+            .collect::<Vec<_>>();
+
+        for bounding_spheres in [false, true] {
+            let mut tree = crate::KdTreeBuilder::new()
+                .split_strategy(SplitStrategy::WidestAxis)
+                .bounding_spheres(bounding_spheres)
+                .build_no_borrow(&points);
+            assert!(!tree.rebalance(&points));
+
+            /*
+                NOTE: Swapping two points moves both of them across the root split
+            */
+            let (low, high) = (
+                (0..points.len()).min_by(|a, b| points[*a][0].total_cmp(&points[*b][0])),
+                (0..points.len()).max_by(|a, b| points[*a][0].total_cmp(&points[*b][0])),
+            );
+            points.swap(low.unwrap(), high.unwrap());
+            assert!(tree.needs_rebalance(&points));
+
+            let query_point = points[low.unwrap()];
+            let mut expected = (0..points.len())
+                .filter(|i| points[*i].distance_squared(query_point) <= 25.0)
+                .collect::<Vec<_>>();
+            expected.sort();
+
+            assert!(tree.rebalance(&points));
+            assert!(!tree.rebalance(&points));
+            assert_eq!(tree.config.split_strategy, SplitStrategy::WidestAxis);
+            assert_eq!(tree.bounding_spheres.is_empty(), !bounding_spheres);
+
+            let mut result = tree.point_indices_within(&points, query_point, 5.0);
+            result.sort();
+            assert_eq!(result, expected);
+
+            points[3][1] = 150.0;
+            assert!(tree.needs_rebalance(&points));
+            points[3][1] = 99.0;
+            tree.rebuild(&points);
+        }
+
+        let mut points = vec![[0.0, 0.0], [f32::NAN, 1.0], [2.0, 2.0]];
+        let tree = KdTreeNoBorrow::from_points(&points);
+        assert!(!tree.needs_rebalance(&points));
+        points[2] = [-2.0, 2.0];
+        assert!(tree.needs_rebalance(&points));
+    }
+
+    #[test]
+    fn test_build_progress() {
+        let mut rng = SplitMix64::new(79);
+        for n in [0, 1, 57, 1234] {
+            let points = (0..n)
+                .map(|_| [(rng.next_u64() % 100) as f32, (rng.next_u64() % 100) as f32])
+                .collect::<Vec<_>>();
+
+            let mut reports = vec![];
+            let tree = KdTree::from_points_with_progress(&points, |done| reports.push(done));
+
+            assert_eq!(
+                tree.internal.fingerprint(),
+                KdTree::from_points(&points).internal.fingerprint()
+            );
+            assert_eq!(reports.last(), Some(&1.0));
+            assert!(reports.len() <= 101);
+            assert!(reports.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+    }
+
+    #[test]
+    fn test_rebuild_reuses_buffers() {
+        let mut rng = SplitMix64::new(89);
+        let mut points = (0..500)
+            .map(|_| [(rng.next_u64() % 100) as f32, (rng.next_u64() % 100) as f32])
+            .collect::<Vec<_>>();
+
+        let mut tree = KdTreeNoBorrow::from_points(&points);
+        let mut point_ids = vec![];
+        for frame in 0..3 {
+            points.iter_mut().for_each(|point| point[0] += 1.5);
+            points.truncate(500 - frame * 100);
+
+            let tree_pointer = tree.tree.as_ptr();
+            tree.rebuild_buffers(&points, &mut point_ids);
+            assert_eq!(tree.tree.as_ptr(), tree_pointer);
+            assert_eq!(
+                tree.fingerprint(),
+                KdTreeNoBorrow::from_points(&points).fingerprint()
+            );
+            assert_eq!(tree.bounds(), compute_bounds(&points));
+        }
+        assert_eq!(point_ids.capacity(), 500);
+    }
+
+    #[test]
+    fn test_rebuild_keeps_config() {
+        let mut rng = SplitMix64::new(101);
+        let mut points = (0..2000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 1000) as f32,
+                    (rng.next_u64() % 10) as f32,
+                    (rng.next_u64() % 100) as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let builder = crate::KdTreeBuilder::new()
+            .sorting_strategy(SortingStrategy::QuickSort)
+            .seed(7)
+            .split_strategy(SplitStrategy::WidestAxis)
+            .bounding_spheres(true)
+            .page_size(4096);
+        let mut tree = builder.build_no_borrow(&points);
+        assert_eq!(tree.config.split_strategy, SplitStrategy::WidestAxis);
+        assert!(tree.config.bounding_spheres);
+        assert!(tree.config.nodes_per_page.is_some());
+
+        points.iter_mut().for_each(|point| point[1] *= 300.0);
+        tree.rebuild(&points);
+
+        let expected = builder.build_no_borrow(&points);
+        assert_eq!(tree.tree, expected.tree);
+        assert_eq!(tree.bounding_spheres, expected.bounding_spheres);
+        assert_eq!(tree.config.seed, Some(7));
+        assert_eq!(tree.config.nodes_per_page, expected.config.nodes_per_page);
+        assert_ne!(
+            tree.fingerprint(),
+            KdTreeNoBorrow::from_points(&points).fingerprint()
+        );
+
+        let mut tree =
+            KdTreeNoBorrow::from_points_with_split_strategy(&points, &SplitStrategy::WidestAxis);
+        tree.rebuild(&points[..1000]);
+        assert_eq!(
+            tree.fingerprint(),
+            KdTreeNoBorrow::from_points_with_split_strategy(
+                &points[..1000],
+                &SplitStrategy::WidestAxis
+            )
+            .fingerprint()
+        );
+    }
 }
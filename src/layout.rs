@@ -0,0 +1,150 @@
+//! Rearranging the nodes of a tree in memory without changing its structure
+use crate::{KdTreeNoBorrow, KdTreeNode, Point};
+
+use std::collections::VecDeque;
+
+/// The number of nodes that fit in a page of `page_size` bytes, at least one
+pub fn nodes_per_page(page_size: usize) -> usize {
+    (page_size / std::mem::size_of::<KdTreeNode>()).max(1)
+}
+
+impl<const D: usize, P: Point<D>> KdTreeNoBorrow<D, P> {
+    /// Reorders the nodes so that `tree` is made up of pages of `nodes_per_page` nodes where each
+    /// page holds the top of a subtree, filled breadth-first, and the subtrees hanging off the
+    /// bottom of a page start new pages. A traversal then touches few distinct pages, which
+    /// helps TLB and cache behaviour on large trees.
+    ///
+    /// Page `p` is `tree[p * nodes_per_page..(p + 1) * nodes_per_page]` and every page but the
+    /// last is full. When the top of a subtree doesn't fill its page, the rest of the page is
+    /// used for the next pending subtree.
+    ///
+    /// This only changes the order of the nodes. Children are still stored as indices into the
+    /// whole tree rather than as offsets within a page, so a page can't be loaded or used on its
+    /// own.
+    ///
+    /// The root stays at index 0 and children stay after their parents. Bounding spheres are
+    /// reordered along with the nodes, and `rebuild` lays the nodes out the same way again.
+    pub fn relayout_paged(&mut self, nodes_per_page: usize) {
+        assert!(nodes_per_page > 0, "a page must hold at least one node");
+
+        let node_count = self.tree.len();
+        let mut new_to_old = Vec::with_capacity(node_count);
+        let mut page_roots = VecDeque::new();
+        let mut queue = VecDeque::new();
+
+        if node_count > 0 {
+            page_roots.push_back(0);
+        }
+
+        while !page_roots.is_empty() {
+            let mut used = 0;
+
+            while used < nodes_per_page {
+                let Some(root) = page_roots.pop_front() else {
+                    break;
+                };
+
+                queue.push_back(root);
+                while let Some(old_index) = queue.pop_front() {
+                    if used == nodes_per_page {
+                        page_roots.push_back(old_index);
+                        continue;
+                    }
+
+                    new_to_old.push(old_index);
+                    used += 1;
+                    queue.extend(self.tree[old_index].children().iter().flatten());
+                }
+            }
+        }
+
+        let mut old_to_new = vec![0; node_count];
+        for (new_index, old_index) in new_to_old.iter().enumerate() {
+            old_to_new[*old_index] = new_index;
+        }
+
+        let tree = new_to_old
+            .iter()
+            .map(|old_index| {
+                let node = &self.tree[*old_index];
+                KdTreeNode::new(
+                    old_to_new[node.parent()],
+                    node.index(),
+                    node.axis(),
+                    node.split,
+                    node.children().map(|child| child.map(|c| old_to_new[c])),
+                )
+            })
+            .collect();
+        self.tree = tree;
+        self.config.nodes_per_page = Some(nodes_per_page);
+
+        if !self.bounding_spheres.is_empty() {
+            self.bounding_spheres = new_to_old
+                .iter()
+                .map(|old_index| self.bounding_spheres[*old_index])
+                .collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+    use crate::KdTree;
+
+    #[test]
+    fn test_relayout_paged() {
+        let mut rng = SplitMix64::new(17);
+        let points = (0..3000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let reference = KdTree::from_points(&points);
+        let mut paged = KdTree::from_points(&points);
+        paged.internal.compute_bounding_spheres(&points);
+        paged.internal.relayout_paged(64);
+
+        let tree = &paged.internal.tree;
+        assert_eq!(tree.len(), points.len());
+        assert_eq!(tree[0].parent(), 0);
+        for (tree_index, node) in tree.iter().enumerate() {
+            for child in node.children().iter().flatten() {
+                assert!(*child > tree_index);
+                assert_eq!(tree[*child].parent(), tree_index);
+            }
+        }
+
+        /*
+            NOTE: The first page is the top of the tree, so all of it is reachable from the
+                  root without leaving the page.
+        */
+        let mut reachable = vec![0];
+        let mut i = 0;
+        while i < reachable.len() {
+            let children = tree[reachable[i]].children();
+            reachable.extend(children.iter().flatten().filter(|c| **c < 64));
+            i += 1;
+        }
+        assert_eq!(reachable.len(), 64);
+
+        for query in [[10.0, 10.0], [50.0, 75.0], [99.0, 0.0]] {
+            let mut expected = reference.point_indices_within(query, 8.0);
+            let mut result = paged.point_indices_within(query, 8.0);
+            expected.sort_unstable();
+            result.sort_unstable();
+            assert_eq!(expected, result);
+        }
+
+        assert_eq!(
+            nodes_per_page(4096),
+            4096 / std::mem::size_of::<KdTreeNode>()
+        );
+    }
+}
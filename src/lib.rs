@@ -1,10 +1,240 @@
 //! Keyde - Simple and fast spacial queries
+//!
+//! Everything but `KdTreeFixed` and the `Point` trait needs the `std` feature, which is on by
+//! default. Without it the crate is `no_std` and builds for embedded targets.
+#![cfg_attr(not(feature = "std"), no_std)]
 
+#[cfg(feature = "std")]
 pub mod kdtree;
+#[cfg(feature = "std")]
 pub use kdtree::*;
 
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "std")]
+pub use error::KdTreeError;
+
+#[cfg(feature = "std")]
+pub mod validation;
+#[cfg(feature = "std")]
+pub use validation::NonFinitePolicy;
+
+#[cfg(feature = "std")]
+pub mod owned;
+#[cfg(feature = "std")]
+pub use owned::KdTreeOwned;
+
+#[cfg(feature = "std")]
+pub mod forest;
+#[cfg(feature = "std")]
+pub use forest::{ChunkStore, ForestChunk, KdForest};
+
+pub mod fixed;
+pub use fixed::KdTreeFixed;
+
+pub mod eytzinger;
+#[cfg(feature = "std")]
+pub use eytzinger::KdTreeEytzinger;
+
+#[cfg(feature = "std")]
+pub mod precise;
+#[cfg(feature = "std")]
+pub use precise::KdTreePrecise;
+
+#[cfg(feature = "std")]
+pub mod integer;
+#[cfg(feature = "std")]
+pub use integer::KdTreeInteger;
+
+#[cfg(feature = "std")]
+pub mod inline;
+#[cfg(feature = "std")]
+pub use inline::KdTreeInline;
+
+#[cfg(feature = "std")]
+pub mod bucketed;
+#[cfg(feature = "std")]
+pub use bucketed::KdTreeBucketed;
+
+#[cfg(feature = "std")]
+pub mod compact;
+#[cfg(feature = "std")]
+pub use compact::{CompactKdTree, TreeIndex};
+
+#[cfg(feature = "std")]
+pub mod moving;
+#[cfg(feature = "std")]
+pub use moving::KdTreeMoving;
+
+#[cfg(feature = "std")]
+pub mod refit;
+#[cfg(feature = "std")]
+pub use refit::KdTreeRefit;
+
+#[cfg(feature = "std")]
+pub mod spheres;
+#[cfg(feature = "std")]
+pub use spheres::KdTreeSpheres;
+
 pub mod point_implementations;
 pub use point_implementations::*;
 
+pub mod nearest;
+pub use nearest::Neighbor;
+
+#[cfg(feature = "std")]
+pub mod best_first;
+#[cfg(feature = "std")]
+pub use best_first::{NearestIterator, QueryStrategy};
+
+#[cfg(feature = "std")]
+pub mod metric;
+#[cfg(feature = "std")]
+pub use metric::{
+    Chebyshev, Cosine, Euclidean, Mahalanobis, Manhattan, Metric, Minkowski, WeightedEuclidean,
+};
+
+#[cfg(feature = "std")]
+pub mod farthest;
+
+#[cfg(feature = "std")]
+pub mod aabb;
+
+#[cfg(feature = "std")]
+pub mod oriented_box;
+#[cfg(feature = "std")]
+pub use oriented_box::OrientedBox;
+
+#[cfg(feature = "std")]
+pub mod cone;
+
+#[cfg(feature = "std")]
+pub mod polygon;
+
+#[cfg(feature = "std")]
+pub mod swept;
+
+#[cfg(feature = "std")]
+pub mod ray;
+
+#[cfg(feature = "std")]
+pub mod varying_radius;
+
+#[cfg(feature = "std")]
+pub mod budget;
+
+#[cfg(feature = "std")]
+pub mod batch;
+
+#[cfg(feature = "std")]
+pub mod pairs;
+
+#[cfg(feature = "std")]
+pub mod join;
+#[cfg(feature = "std")]
+pub use join::{cross_pairs_within, nearest_in_other};
+
+#[cfg(feature = "std")]
+pub mod packets;
+
+#[cfg(feature = "std")]
+pub mod region;
+#[cfg(feature = "std")]
+pub use region::Region;
+
+#[cfg(feature = "std")]
+pub mod mask;
+#[cfg(feature = "std")]
+pub use mask::{mask_indices, mask_words};
+
+#[cfg(feature = "std")]
+pub mod cursor;
+#[cfg(feature = "std")]
+pub use cursor::WithinCursor;
+
+#[cfg(feature = "std")]
+pub mod ordering;
+#[cfg(feature = "std")]
+pub use ordering::ResultOrder;
+
+#[cfg(feature = "std")]
+pub mod grid;
+
+#[cfg(feature = "std")]
+pub mod sdf;
+
+#[cfg(feature = "std")]
+pub mod voronoi;
+#[cfg(feature = "std")]
+pub use voronoi::assign_nearest_site;
+
+#[cfg(feature = "std")]
+pub mod layout;
+
+#[cfg(feature = "std")]
+pub mod reorder;
+#[cfg(feature = "std")]
+pub use reorder::{reorder_points_morton, Permutation};
+
+#[cfg(feature = "std")]
+pub mod duplicates;
+#[cfg(feature = "std")]
+pub use duplicates::{DuplicateGroups, DuplicatePolicy};
+
+#[cfg(feature = "std")]
+pub mod matching;
+#[cfg(feature = "std")]
+pub use matching::greedy_match;
+
+#[cfg(feature = "std")]
+pub mod snapshot_diff;
+#[cfg(feature = "std")]
+pub use snapshot_diff::{diff, SnapshotDiff};
+
+#[cfg(feature = "std")]
+pub mod statistics;
+#[cfg(feature = "std")]
+pub use statistics::EdgeCorrection;
+
+#[cfg(feature = "std")]
+pub mod diagnostics;
+#[cfg(feature = "std")]
+pub use diagnostics::{LevelSplitReport, TreeStats};
+
+#[cfg(feature = "std")]
+pub mod memory;
+#[cfg(feature = "std")]
+pub use memory::MemoryReport;
+
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub use builder::KdTreeBuilder;
+
+#[cfg(feature = "std")]
 pub mod utils;
-pub use utils::SortingStrategy;
+
+#[cfg(feature = "std")]
+pub mod scratch;
+#[cfg(feature = "std")]
+pub use scratch::{NodeStorage, ScratchVec};
+
+#[cfg(feature = "async")]
+pub mod async_queries;
+
+#[cfg(feature = "rayon")]
+pub mod parallel;
+
+#[cfg(feature = "testing")]
+pub mod testing;
+
+#[cfg(feature = "conformance")]
+pub mod conformance;
+
+#[cfg(feature = "serde_json")]
+pub mod debug_export;
+#[cfg(feature = "std")]
+pub use utils::{SortingStrategy, SplitStrategy};
+
+#[cfg(test)]
+mod fixture;
@@ -0,0 +1,121 @@
+//! Radius query results as bitmasks with one bit per point, which are cheap to combine with
+//! bitwise operations
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point, ScratchVec};
+
+/// The number of `u64` words needed for a mask of `point_count` points
+#[inline(always)]
+pub fn mask_words(point_count: usize) -> usize {
+    point_count.div_ceil(64)
+}
+
+/// Iterates over the indices of the bits set in `mask`, in ascending order
+pub fn mask_indices(mask: &[u64]) -> impl Iterator<Item = usize> + '_ {
+    mask.iter().enumerate().flat_map(|(word_index, word)| {
+        let mut word = *word;
+        std::iter::from_fn(move || {
+            if word == 0 {
+                return None;
+            }
+
+            let bit = word.trailing_zeros() as usize;
+            word &= word - 1;
+            Some(word_index * 64 + bit)
+        })
+    })
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn point_mask_within_buffers<S>(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        mask: &mut [u64],
+        stack: &mut S,
+    ) where
+        S: ScratchVec<(usize, usize)>,
+    {
+        assert!(
+            mask.len() >= mask_words(points.len()),
+            "the mask needs one bit per point"
+        );
+
+        self.for_each_point_within_buffers(
+            points,
+            query_point,
+            radius,
+            |point_index, _| mask[point_index / 64] |= 1 << (point_index % 64),
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn point_mask_within(&self, points: &[P], query_point: P, radius: f32, mask: &mut [u64]) {
+        let mut stack = vec![];
+        self.point_mask_within_buffers(points, query_point, radius, mask, &mut stack)
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `point_mask_within`, but you provide your own stack. `stack` is assumed to be
+    /// empty and is left empty.
+    #[inline(always)]
+    pub fn point_mask_within_buffers<S>(
+        &self,
+        query_point: P,
+        radius: f32,
+        mask: &mut [u64],
+        stack: &mut S,
+    ) where
+        S: ScratchVec<(usize, usize)>,
+    {
+        self.internal
+            .point_mask_within_buffers(self.points, query_point, radius, mask, stack)
+    }
+
+    /// Sets bit `i % 64` of `mask[i / 64]` for every point `i` within `radius` of `query_point`.
+    /// Bits are only ever set, never cleared, so several queries into the same mask give their
+    /// union. Intersections and differences of queries are plain bitwise operations between
+    /// masks, and `mask_indices` turns a mask back into indices.
+    ///
+    /// `mask` must hold at least `mask_words(points.len())` words.
+    #[inline(always)]
+    pub fn point_mask_within(&self, query_point: P, radius: f32, mask: &mut [u64]) {
+        self.internal
+            .point_mask_within(self.points, query_point, radius, mask)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_point_mask_within() {
+        let points: Vec<[f32; 2]> = (0..700)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.37).sin() * 10.0, (i * 0.11).cos() * 10.0]
+            })
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        let mut a = vec![0; mask_words(points.len())];
+        let mut b = vec![0; mask_words(points.len())];
+        tree.point_mask_within([0.0, 0.0], 5.0, &mut a);
+        tree.point_mask_within([3.0, 0.0], 5.0, &mut b);
+
+        let mut expected_a = tree.point_indices_within([0.0, 0.0], 5.0);
+        expected_a.sort_unstable();
+        assert_eq!(mask_indices(&a).collect::<Vec<_>>(), expected_a);
+
+        let both = a.iter().zip(&b).map(|(a, b)| a & b).collect::<Vec<_>>();
+        let expected_both = expected_a
+            .iter()
+            .copied()
+            .filter(|i| points[*i].distance_squared([3.0, 0.0]) <= 25.0)
+            .collect::<Vec<_>>();
+        assert_eq!(mask_indices(&both).collect::<Vec<_>>(), expected_both);
+    }
+}
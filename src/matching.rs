@@ -0,0 +1,165 @@
+//! Matching points of two sets to each other
+use crate::{KdTreeNoBorrow, Point};
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    distance_squared: f32,
+    a: usize,
+    b: usize,
+}
+
+/*
+    NOTE: Reversed so that `BinaryHeap` pops the closest candidate first
+*/
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .distance_squared
+            .total_cmp(&self.distance_squared)
+            .then_with(|| other.a.cmp(&self.a))
+            .then_with(|| other.b.cmp(&self.b))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+/// Greedily pairs points of `a_points` with points of `b_points`: the closest pair of unmatched
+/// points is matched first, then the closest among the remaining ones and so on until no unmatched
+/// pair is within `max_dist`. Returns the `(a_index, b_index)` pairs in the order they were matched.
+///
+/// A tree is built over `b_points` and matched points are disabled in it instead of rebuilding,
+/// so the whole matching costs about one nearest neighbor query per point of `a_points` plus one
+/// more each time a point loses its closest partner to another point.
+pub fn greedy_match<const D: usize, P>(
+    a_points: &[P],
+    b_points: &[P],
+    max_dist: f32,
+) -> Vec<(usize, usize)>
+where
+    P: Point<D>,
+{
+    if a_points.is_empty() || b_points.is_empty() {
+        return vec![];
+    }
+
+    let tree = KdTreeNoBorrow::from_points(b_points);
+    greedy_match_with_tree(a_points, &tree, b_points, max_dist)
+}
+
+/// Same as `greedy_match`, but uses an existing tree of `b_points` instead of building one
+pub fn greedy_match_with_tree<const D: usize, P>(
+    a_points: &[P],
+    b_tree: &KdTreeNoBorrow<D, P>,
+    b_points: &[P],
+    max_dist: f32,
+) -> Vec<(usize, usize)>
+where
+    P: Point<D>,
+{
+    if a_points.is_empty() || b_points.is_empty() {
+        return vec![];
+    }
+
+    let tree = b_tree;
+    let max_distance_squared = max_dist * max_dist;
+
+    let mut b_matched = vec![false; b_points.len()];
+    let mut stack = vec![];
+    let mut heap = BinaryHeap::with_capacity(a_points.len());
+
+    let mut nearest_candidate = |a: usize, b_matched: &[bool]| {
+        tree.nearest_one_by_buffers(b_points, a_points[a], |b| !b_matched[b], &mut stack)
+            .filter(|(_, distance_squared)| *distance_squared <= max_distance_squared)
+            .map(|(b, distance_squared)| Candidate {
+                distance_squared,
+                a,
+                b,
+            })
+    };
+
+    for a in 0..a_points.len() {
+        if let Some(candidate) = nearest_candidate(a, &b_matched) {
+            heap.push(candidate);
+        }
+    }
+
+    let mut matching = vec![];
+    while let Some(Candidate { a, b, .. }) = heap.pop() {
+        if b_matched[b] {
+            /*
+                NOTE: Someone closer took `b` since this candidate was found. The new nearest
+                      point is at least as far away, so it is safe to put `a` back in line
+            */
+            if let Some(candidate) = nearest_candidate(a, &b_matched) {
+                heap.push(candidate);
+            }
+            continue;
+        }
+
+        b_matched[b] = true;
+        matching.push((a, b));
+    }
+
+    matching
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_greedy_match() {
+        let a_points: Vec<[f32; 2]> = (0..60)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.71).sin() * 10.0, (i * 0.37).cos() * 10.0]
+            })
+            .collect();
+        let b_points: Vec<[f32; 2]> = (0..45)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.53).cos() * 10.0, (i * 0.29).sin() * 10.0]
+            })
+            .collect();
+        let max_dist = 3.0;
+
+        let matching = greedy_match(&a_points, &b_points, max_dist);
+
+        let mut expected = vec![];
+        let mut a_matched = vec![false; a_points.len()];
+        let mut b_matched = vec![false; b_points.len()];
+        loop {
+            let mut best: Option<(f32, usize, usize)> = None;
+            for a in (0..a_points.len()).filter(|a| !a_matched[*a]) {
+                for b in (0..b_points.len()).filter(|b| !b_matched[*b]) {
+                    let d = a_points[a].distance_squared(b_points[b]);
+                    if d <= max_dist * max_dist && best.is_none_or(|(best, _, _)| d < best) {
+                        best = Some((d, a, b));
+                    }
+                }
+            }
+
+            let Some((_, a, b)) = best else { break };
+            a_matched[a] = true;
+            b_matched[b] = true;
+            expected.push((a, b));
+        }
+
+        assert_eq!(matching, expected);
+    }
+}
@@ -0,0 +1,292 @@
+//! Reports of how much memory the trees use
+use crate::bucketed::KdTreeBucketedNode;
+use crate::inline::KdTreeInlineNode;
+use crate::moving::MovingBounds;
+use crate::refit::NodeBounds;
+use crate::{
+    IntegerPoint, KdTree, KdTreeBucketed, KdTreeEytzinger, KdTreeInline, KdTreeInteger,
+    KdTreeMoving, KdTreeNoBorrow, KdTreeNode, KdTreeOwned, KdTreePrecise, KdTreeRefit,
+    KdTreeSpheres, Point, TreeIndex,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// Bytes used by a tree, counting the capacity of its buffers rather than their length. The
+/// size of the tree struct itself is not included.
+pub struct MemoryReport {
+    pub nodes: usize,
+    pub bounding_spheres: usize,
+    /// The bounds kept per node by `KdTreeRefit`, `KdTreeMoving` and `KdTreeSpheres`
+    pub node_bounds: usize,
+    /// The points, for trees that own them
+    pub points: usize,
+    /// The values and removal marks of a `KdTreeOwned`, or the radii of a `KdTreeSpheres`
+    pub values: usize,
+    /// The bytes of stack a single radius or nearest query pushes at most on its `stack`
+    /// buffer. The tree doesn't hold this, but a caller keeping buffers around per thread does.
+    pub query_scratch: usize,
+}
+
+impl MemoryReport {
+    /// The bytes held by the tree, which is everything but `query_scratch`
+    pub fn total(&self) -> usize {
+        self.nodes + self.bounding_spheres + self.node_bounds + self.points + self.values
+    }
+}
+
+/*
+    NOTE: Traversals push both children of a node and pop one of them right away, so the stack
+          holds at most one pending entry per level
+*/
+fn query_scratch<T>(depth: usize) -> usize {
+    (depth + 1) * std::mem::size_of::<T>()
+}
+
+/// The depth of a tree of `len` nodes in the layout of `KdTreeEytzinger`
+fn eytzinger_depth(len: usize) -> usize {
+    (usize::BITS - len.leading_zeros()) as usize
+}
+
+impl<const D: usize, P: Point<D>, I: TreeIndex> KdTreeNoBorrow<D, P, Vec<KdTreeNode<I>>> {
+    /// See `KdTree`. This includes the `CompactKdTree` of every index type.
+    pub fn memory_usage(&self) -> MemoryReport {
+        MemoryReport {
+            nodes: self.tree.capacity() * std::mem::size_of::<KdTreeNode<I>>(),
+            bounding_spheres: self.bounding_spheres.capacity()
+                * std::mem::size_of::<([f32; D], f32)>(),
+            node_bounds: 0,
+            points: 0,
+            values: 0,
+            query_scratch: query_scratch::<(usize, usize, f32)>(self.depth()),
+        }
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Returns how many bytes the nodes and bounding spheres of the tree take, and how much
+    /// stack a query needs. The points are borrowed and not counted.
+    pub fn memory_usage(&self) -> MemoryReport {
+        self.internal.memory_usage()
+    }
+}
+
+impl<const D: usize, P: Point<D>, V> KdTreeOwned<D, P, V> {
+    /// See `KdTree`. The points, values and removal marks are included since they are owned.
+    pub fn memory_usage(&self) -> MemoryReport {
+        MemoryReport {
+            points: self.points.capacity() * std::mem::size_of::<P>(),
+            values: self.values.capacity() * std::mem::size_of::<V>()
+                + self.removed.capacity() * std::mem::size_of::<bool>(),
+            ..self.internal.memory_usage()
+        }
+    }
+}
+
+impl<const D: usize, P: Point<D>> KdTreeEytzinger<D, P> {
+    /// See `KdTree`
+    pub fn memory_usage(&self) -> MemoryReport {
+        MemoryReport {
+            nodes: self.indices.capacity() * std::mem::size_of::<usize>(),
+            query_scratch: query_scratch::<(usize, usize, f32)>(eytzinger_depth(self.len())),
+            ..MemoryReport::default()
+        }
+    }
+}
+
+impl<const D: usize, P: Point<D>> KdTreePrecise<D, P> {
+    /// See `KdTree`
+    pub fn memory_usage(&self) -> MemoryReport {
+        MemoryReport {
+            nodes: self.indices.capacity() * std::mem::size_of::<usize>(),
+            query_scratch: query_scratch::<(usize, usize, f64)>(eytzinger_depth(self.len())),
+            ..MemoryReport::default()
+        }
+    }
+}
+
+impl<const D: usize, P: IntegerPoint<D>> KdTreeInteger<D, P> {
+    /// See `KdTree`
+    pub fn memory_usage(&self) -> MemoryReport {
+        MemoryReport {
+            nodes: self.indices.capacity() * std::mem::size_of::<usize>(),
+            query_scratch: query_scratch::<(usize, usize, u128)>(eytzinger_depth(self.len())),
+            ..MemoryReport::default()
+        }
+    }
+}
+
+impl<const D: usize, P: Point<D>> KdTreeInline<D, P> {
+    /// See `KdTree`. The copies of the points are part of the nodes.
+    pub fn memory_usage(&self) -> MemoryReport {
+        let mut depth = 0;
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push((0, 1));
+        }
+        while let Some((node, node_depth)) = stack.pop() {
+            depth = depth.max(node_depth);
+            for child in self.nodes[node].children.iter().flatten() {
+                stack.push((*child, node_depth + 1));
+            }
+        }
+
+        MemoryReport {
+            nodes: self.nodes.capacity() * std::mem::size_of::<KdTreeInlineNode<D, P>>(),
+            query_scratch: query_scratch::<(usize, f32)>(depth),
+            ..MemoryReport::default()
+        }
+    }
+}
+
+impl<const D: usize, P: Point<D>> KdTreeBucketed<D, P> {
+    /// See `KdTree`. The point indices of the leaves are part of the nodes.
+    pub fn memory_usage(&self) -> MemoryReport {
+        let mut depth = 0;
+        let mut stack = vec![];
+        if !self.nodes.is_empty() {
+            stack.push((0, 1));
+        }
+        while let Some((node, node_depth)) = stack.pop() {
+            depth = depth.max(node_depth);
+            if let KdTreeBucketedNode::Split { children, .. } = self.nodes[node] {
+                stack.extend(children.map(|child| (child, node_depth + 1)));
+            }
+        }
+
+        MemoryReport {
+            nodes: self.nodes.capacity() * std::mem::size_of::<KdTreeBucketedNode>()
+                + self.point_ids.capacity() * std::mem::size_of::<usize>(),
+            query_scratch: query_scratch::<(usize, f32)>(depth),
+            ..MemoryReport::default()
+        }
+    }
+}
+
+impl<const D: usize, P: Point<D>> KdTreeRefit<D, P> {
+    /// See `KdTree`
+    pub fn memory_usage(&self) -> MemoryReport {
+        MemoryReport {
+            node_bounds: self.node_bounds.capacity() * std::mem::size_of::<NodeBounds<D>>(),
+            query_scratch: query_scratch::<(usize, f32)>(self.internal.depth()),
+            ..self.internal.memory_usage()
+        }
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTreeMoving<'a, D, P> {
+    /// See `KdTree`. The points and velocities are borrowed and not counted.
+    pub fn memory_usage(&self) -> MemoryReport {
+        MemoryReport {
+            node_bounds: self.node_bounds.capacity() * std::mem::size_of::<MovingBounds<D>>(),
+            query_scratch: query_scratch::<usize>(self.internal.depth()),
+            ..self.internal.memory_usage()
+        }
+    }
+}
+
+impl<const D: usize, P: Point<D>> KdTreeSpheres<D, P> {
+    /// See `KdTree`. The centers and radii are included since they are owned.
+    pub fn memory_usage(&self) -> MemoryReport {
+        MemoryReport {
+            node_bounds: self.subtree_max_radius.capacity() * std::mem::size_of::<f32>(),
+            points: self.points.capacity() * std::mem::size_of::<P>(),
+            values: self.radii.capacity() * std::mem::size_of::<f32>(),
+            query_scratch: query_scratch::<(usize, usize)>(self.internal.depth()),
+            ..self.internal.memory_usage()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CompactKdTree;
+
+    #[test]
+    fn test_memory_usage() {
+        let points = (0..1000)
+            .map(|i| [i as f32, (i % 7) as f32])
+            .collect::<Vec<_>>();
+
+        let mut tree = KdTreeNoBorrow::from_points(&points);
+        let report = tree.memory_usage();
+        assert_eq!(report.nodes, 1000 * std::mem::size_of::<KdTreeNode>());
+        assert_eq!(report.bounding_spheres, 0);
+        assert_eq!(report.total(), report.nodes);
+        assert_eq!(
+            report.query_scratch,
+            11 * std::mem::size_of::<(usize, usize, f32)>()
+        );
+
+        tree.compute_bounding_spheres(&points);
+        assert_eq!(tree.memory_usage().bounding_spheres, 1000 * 12);
+
+        let owned = KdTreeOwned::from_points_and_values(points.clone(), vec![0_u64; 1000]);
+        let report = owned.memory_usage();
+        assert_eq!(report.points, 1000 * 8);
+        assert_eq!(report.values, 1000 * 8 + 1000);
+        assert_eq!(report.total(), report.nodes + 8000 + 9000);
+
+        let compact: CompactKdTree<2, [f32; 2], u16> = CompactKdTree::from_tree(&owned.internal);
+        assert_eq!(
+            compact.memory_usage().nodes,
+            1000 * std::mem::size_of::<KdTreeNode<u16>>()
+        );
+        assert!(compact.memory_usage().nodes * 2 < report.nodes);
+        assert_eq!(
+            KdTreeEytzinger::from_points(&points).memory_usage().nodes,
+            1000 * std::mem::size_of::<usize>()
+        );
+        assert_eq!(
+            KdTreePrecise::from_points(&points)
+                .memory_usage()
+                .query_scratch,
+            11 * std::mem::size_of::<(usize, usize, f64)>()
+        );
+
+        let integer_points = (0..1000).map(|i| [i, i % 7]).collect::<Vec<[i32; 2]>>();
+        assert_eq!(
+            KdTreeInteger::from_points(&integer_points).memory_usage(),
+            MemoryReport {
+                nodes: 1000 * std::mem::size_of::<usize>(),
+                query_scratch: 11 * std::mem::size_of::<(usize, usize, u128)>(),
+                ..MemoryReport::default()
+            }
+        );
+
+        let inline = KdTreeInline::from_points(&points);
+        let report = inline.memory_usage();
+        assert_eq!(
+            report.nodes,
+            1000 * std::mem::size_of::<KdTreeInlineNode<2, [f32; 2]>>()
+        );
+        assert_eq!(
+            report.query_scratch,
+            (tree.depth() + 1) * std::mem::size_of::<(usize, f32)>()
+        );
+
+        let bucketed = KdTreeBucketed::from_points(&points, 8);
+        let report = bucketed.memory_usage();
+        assert_eq!(
+            report.nodes,
+            bucketed.nodes.capacity() * std::mem::size_of::<KdTreeBucketedNode>() + 1000 * 8
+        );
+        assert!(report.query_scratch < tree.memory_usage().query_scratch);
+
+        let refit = KdTreeRefit::from_points(&points);
+        let report = refit.memory_usage();
+        assert_eq!(report.node_bounds, 1000 * 16);
+        assert_eq!(report.total(), report.nodes + report.node_bounds);
+
+        let velocities = vec![[1.0, 0.0]; 1000];
+        let moving = KdTreeMoving::from_points_and_velocities(&points, &velocities);
+        let report = moving.memory_usage();
+        assert_eq!(report.node_bounds, 1000 * 32);
+        assert_eq!(report.points, 0);
+
+        let spheres = KdTreeSpheres::from_spheres(points.clone(), vec![1.0; 1000]);
+        let report = spheres.memory_usage();
+        assert_eq!(report.node_bounds, 1000 * 4);
+        assert_eq!(report.points, 1000 * 8);
+        assert_eq!(report.values, 1000 * 4);
+    }
+}
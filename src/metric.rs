@@ -0,0 +1,845 @@
+//! Queries with other distance functions than the euclidean distance of `Point`
+use crate::nearest::{pop_nearest_first, Neighbor};
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+use std::collections::BinaryHeap;
+
+/// A distance function the tree can be queried with, see `KdTree::point_indices_within_metric`
+/// and `KdTree::nearest_n_metric`.
+///
+/// The tree only knows how far the query point is from a splitting plane along a single axis,
+/// so `axis_distance` has to be a lower bound of `distance` to any point with that difference
+/// along that axis. This holds whenever `distance` never decreases as the difference along any
+/// single axis grows, which is true for all norms.
+pub trait Metric<const D: usize> {
+    /// The distance between `a` and `b`
+    fn distance(&self, a: &[f32; D], b: &[f32; D]) -> f32;
+
+    /// The smallest distance between two points differing by `axis_delta` along `axis`
+    fn axis_distance(&self, axis: usize, axis_delta: f32) -> f32;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// The euclidean distance, the same as `Point::distance_squared` but not squared
+pub struct Euclidean;
+
+impl<const D: usize> Metric<D> for Euclidean {
+    #[inline(always)]
+    fn distance(&self, a: &[f32; D], b: &[f32; D]) -> f32 {
+        (0..D)
+            .map(|axis| {
+                let delta = a[axis] - b[axis];
+                delta * delta
+            })
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    #[inline(always)]
+    fn axis_distance(&self, _axis: usize, axis_delta: f32) -> f32 {
+        axis_delta.abs()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// The Manhattan or taxicab distance, the sum of the differences along every axis
+pub struct Manhattan;
+
+impl<const D: usize> Metric<D> for Manhattan {
+    #[inline(always)]
+    fn distance(&self, a: &[f32; D], b: &[f32; D]) -> f32 {
+        (0..D).map(|axis| (a[axis] - b[axis]).abs()).sum()
+    }
+
+    #[inline(always)]
+    fn axis_distance(&self, _axis: usize, axis_delta: f32) -> f32 {
+        axis_delta.abs()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// The Chebyshev distance, the largest of the differences along every axis. The points within
+/// a radius form an axis-aligned cube.
+pub struct Chebyshev;
+
+impl<const D: usize> Metric<D> for Chebyshev {
+    #[inline(always)]
+    fn distance(&self, a: &[f32; D], b: &[f32; D]) -> f32 {
+        (0..D)
+            .map(|axis| (a[axis] - b[axis]).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[inline(always)]
+    fn axis_distance(&self, _axis: usize, axis_delta: f32) -> f32 {
+        axis_delta.abs()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The Minkowski distance of order `p`, `(sum of |difference|^p)^(1 / p)` over every axis.
+/// `p == 1.0` is `Manhattan`, `p == 2.0` is `Euclidean` and `p == f32::INFINITY` is
+/// `Chebyshev`, which are computed the same way as those.
+pub struct Minkowski {
+    pub p: f32,
+}
+
+impl Minkowski {
+    /// Panics unless `p` is positive
+    pub fn new(p: f32) -> Self {
+        assert!(
+            p > 0.0,
+            "the order of a Minkowski distance must be positive"
+        );
+        Self { p }
+    }
+}
+
+impl<const D: usize> Metric<D> for Minkowski {
+    #[inline(always)]
+    fn distance(&self, a: &[f32; D], b: &[f32; D]) -> f32 {
+        match self.p {
+            1.0 => Manhattan.distance(a, b),
+            2.0 => Euclidean.distance(a, b),
+            f32::INFINITY => Chebyshev.distance(a, b),
+            p => (0..D)
+                .map(|axis| (a[axis] - b[axis]).abs().powf(p))
+                .sum::<f32>()
+                .powf(p.recip()),
+        }
+    }
+
+    /*
+        NOTE: Any single term of the sum is at most the whole sum, also for `p` below one where
+              this is not a norm
+    */
+    #[inline(always)]
+    fn axis_distance(&self, _axis: usize, axis_delta: f32) -> f32 {
+        axis_delta.abs()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The euclidean distance with the difference along every axis multiplied by its weight in
+/// `weights`, so that some axes count more than others. Weights must not be negative.
+///
+/// This is the euclidean distance between the points scaled by `weights`, for instance to let
+/// height matter less than the position on the ground, or to bring features of different
+/// units to a common scale.
+pub struct WeightedEuclidean<const D: usize> {
+    pub weights: [f32; D],
+}
+
+impl<const D: usize> WeightedEuclidean<D> {
+    pub fn new(weights: [f32; D]) -> Self {
+        Self { weights }
+    }
+}
+
+impl<const D: usize> Metric<D> for WeightedEuclidean<D> {
+    #[inline(always)]
+    fn distance(&self, a: &[f32; D], b: &[f32; D]) -> f32 {
+        (0..D)
+            .map(|axis| {
+                let delta = (a[axis] - b[axis]) * self.weights[axis];
+                delta * delta
+            })
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    #[inline(always)]
+    fn axis_distance(&self, axis: usize, axis_delta: f32) -> f32 {
+        (axis_delta * self.weights[axis]).abs()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// The cosine distance, one minus the cosine similarity, between unit vectors such as
+/// normalized embeddings. Both the stored points and the query point must have length one.
+///
+/// For unit vectors, `1 - dot(a, b)` is half the squared euclidean distance between them,
+/// which is how it is computed here. This is exact also when the angle is tiny, and a point
+/// at a difference of `axis_delta` along a single axis is at least `axis_delta^2 / 2` away.
+pub struct Cosine;
+
+impl<const D: usize> Metric<D> for Cosine {
+    #[inline(always)]
+    fn distance(&self, a: &[f32; D], b: &[f32; D]) -> f32 {
+        (0..D)
+            .map(|axis| {
+                let delta = a[axis] - b[axis];
+                delta * delta
+            })
+            .sum::<f32>()
+            * 0.5
+    }
+
+    #[inline(always)]
+    fn axis_distance(&self, _axis: usize, axis_delta: f32) -> f32 {
+        axis_delta * axis_delta * 0.5
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The Mahalanobis distance `sqrt(d^T M d)` of the difference `d` for a symmetric positive
+/// definite matrix `M`, for instance the inverse of the covariance of a measurement to gate
+/// tracks by how likely a detection belongs to them.
+///
+/// Stored as `transform`, the transpose of the Cholesky factor `L` of `M = L L^T`, so that the
+/// distance is the euclidean length of `transform * d`, together with the smallest distance
+/// to a point at a given difference along each axis.
+pub struct Mahalanobis<const D: usize> {
+    /// `L^T`, stored as `transform[row][column]`
+    pub transform: [[f32; D]; D],
+    /// `1 / sqrt((M^-1)[axis][axis])` for every axis, see `Metric::axis_distance`
+    pub axis_scale: [f32; D],
+}
+
+impl<const D: usize> Mahalanobis<D> {
+    /// Factorizes `matrix` with a Cholesky decomposition. Only the lower triangle is read.
+    /// Returns `None` if `matrix` is not positive definite.
+    pub fn from_matrix(matrix: [[f32; D]; D]) -> Option<Self> {
+        let mut lower = [[0.0_f64; D]; D];
+        for row in 0..D {
+            for column in 0..=row {
+                let sum = (0..column)
+                    .map(|i| lower[row][i] * lower[column][i])
+                    .sum::<f64>();
+                let value = matrix[row][column] as f64 - sum;
+
+                if row == column {
+                    if value <= 0.0 || !value.is_finite() {
+                        return None;
+                    }
+                    lower[row][row] = value.sqrt();
+                } else {
+                    lower[row][column] = value / lower[column][column];
+                }
+            }
+        }
+
+        Some(Self::from_lower(&lower))
+    }
+
+    /// Uses `cholesky` as the lower triangular Cholesky factor `L` of the matrix `M = L L^T`.
+    /// Only the lower triangle is read, and the diagonal must be positive.
+    pub fn from_cholesky(cholesky: [[f32; D]; D]) -> Self {
+        let lower = std::array::from_fn(|row| {
+            std::array::from_fn(|column| {
+                if column <= row {
+                    cholesky[row][column] as f64
+                } else {
+                    0.0
+                }
+            })
+        });
+
+        Self::from_lower(&lower)
+    }
+
+    fn from_lower(lower: &[[f64; D]; D]) -> Self {
+        /*
+            NOTE: The smallest `d^T M d` with `d[axis]` fixed is `d[axis]^2 / (M^-1)[axis][axis]`
+                  and `M^-1 = L^-T L^-1`, so the diagonal is the squared length of the columns of
+                  `L^-1`, found by forward substitution
+        */
+        let mut inverse_columns = [[0.0_f64; D]; D];
+        for (column, inverse_column) in inverse_columns.iter_mut().enumerate() {
+            for row in column..D {
+                let identity = if row == column { 1.0 } else { 0.0 };
+                let sum = (column..row)
+                    .map(|i| lower[row][i] * inverse_column[i])
+                    .sum::<f64>();
+                inverse_column[row] = (identity - sum) / lower[row][row];
+            }
+        }
+
+        Self {
+            transform: std::array::from_fn(|row| {
+                std::array::from_fn(|column| lower[column][row] as f32)
+            }),
+            axis_scale: std::array::from_fn(|axis| {
+                let diagonal = inverse_columns[axis].iter().map(|x| x * x).sum::<f64>();
+                diagonal.sqrt().recip() as f32
+            }),
+        }
+    }
+}
+
+impl<const D: usize> Metric<D> for Mahalanobis<D> {
+    #[inline(always)]
+    fn distance(&self, a: &[f32; D], b: &[f32; D]) -> f32 {
+        let delta: [f32; D] = std::array::from_fn(|axis| a[axis] - b[axis]);
+        self.transform
+            .iter()
+            .map(|row| {
+                let component = (0..D).map(|axis| row[axis] * delta[axis]).sum::<f32>();
+                component * component
+            })
+            .sum::<f32>()
+            .sqrt()
+    }
+
+    /*
+        NOTE: Scaled down slightly so that rounding in `distance` never makes it smaller than
+              this bound
+    */
+    #[inline(always)]
+    fn axis_distance(&self, axis: usize, axis_delta: f32) -> f32 {
+        axis_delta.abs() * self.axis_scale[axis] * (1.0 - 1e-5)
+    }
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_within_metric_buffers<M: Metric<D>>(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        metric: &M,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        if self.tree.is_empty() {
+            return;
+        }
+
+        let query_point_axis_values = axis_values(&query_point);
+
+        stack.push((0, 0));
+        while let Some((depth, tree_index)) = stack.pop() {
+            let point_index = self.tree[tree_index].index();
+            let point = axis_values(&points[point_index]);
+
+            if metric.distance(&query_point_axis_values, &point) <= radius {
+                result.push(point_index);
+            }
+
+            let axis = self.tree[tree_index].axis();
+            let axis_d = self.tree[tree_index].split - query_point_axis_values[axis];
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            if metric.axis_distance(axis, axis_d) <= radius {
+                if let Some(child) = self.tree[tree_index].child(last) {
+                    stack.push((depth + 1, child));
+                }
+            }
+            if let Some(child) = self.tree[tree_index].child(first) {
+                stack.push((depth + 1, child));
+            }
+        }
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_metric<M: Metric<D>>(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        metric: &M,
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_metric_buffers(
+            points,
+            query_point,
+            radius,
+            metric,
+            &mut result,
+            &mut stack,
+        );
+
+        result
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_l1_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.point_indices_within_metric_buffers(
+            points,
+            query_point,
+            radius,
+            &Manhattan,
+            result,
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_l1(&self, points: &[P], query_point: P, radius: f32) -> Vec<usize> {
+        self.point_indices_within_metric(points, query_point, radius, &Manhattan)
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_linf_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.point_indices_within_metric_buffers(
+            points,
+            query_point,
+            radius,
+            &Chebyshev,
+            result,
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_linf(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+    ) -> Vec<usize> {
+        self.point_indices_within_metric(points, query_point, radius, &Chebyshev)
+    }
+
+    /// See `KdTree`
+    #[allow(clippy::too_many_arguments)]
+    pub fn nearest_n_metric_buffers<M: Metric<D>>(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        metric: &M,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        if k == 0 || self.tree.is_empty() {
+            return;
+        }
+
+        let query_point_axis_values = axis_values(&query_point);
+
+        /*
+            NOTE: `distance_squared` of the neighbors holds the distance of `metric`, which
+                  orders them just the same
+        */
+        stack.push((0, 0, 0.0));
+        while let Some((depth, tree_index, lower_bound)) = stack.pop() {
+            let current_bound = if heap.len() == k {
+                heap.peek().map_or(f32::INFINITY, |n| n.distance_squared)
+            } else {
+                f32::INFINITY
+            };
+            if lower_bound > current_bound {
+                continue;
+            }
+
+            let point_index = self.tree[tree_index].index();
+            let point = axis_values(&points[point_index]);
+
+            let distance = metric.distance(&query_point_axis_values, &point);
+            if distance <= current_bound {
+                heap.push(Neighbor {
+                    distance_squared: distance,
+                    index: point_index,
+                });
+                if heap.len() > k {
+                    heap.pop();
+                }
+            }
+
+            let axis = self.tree[tree_index].axis();
+            let axis_d = self.tree[tree_index].split - query_point_axis_values[axis];
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            /*
+                NOTE: The far side is pushed first so that the near side is popped first
+            */
+            if let Some(child) = self.tree[tree_index].child(last) {
+                let far_bound = lower_bound.max(metric.axis_distance(axis, axis_d));
+                stack.push((depth + 1, child, far_bound));
+            }
+            if let Some(child) = self.tree[tree_index].child(first) {
+                stack.push((depth + 1, child, lower_bound));
+            }
+        }
+
+        pop_nearest_first(heap, result);
+    }
+
+    /// See `KdTree`
+    pub fn nearest_n_metric<M: Metric<D>>(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        metric: &M,
+    ) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.tree.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.tree.len()) + 1);
+        let mut stack = vec![];
+
+        self.nearest_n_metric_buffers(
+            points,
+            query_point,
+            k,
+            metric,
+            &mut result,
+            &mut heap,
+            &mut stack,
+        );
+
+        result
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `point_indices_within_metric`, but you provide your own buffers. Indices are
+    /// appended to `result` which is not cleared. `stack` is assumed to be empty and is left
+    /// empty.
+    #[inline(always)]
+    pub fn point_indices_within_metric_buffers<M: Metric<D>>(
+        &self,
+        query_point: P,
+        radius: f32,
+        metric: &M,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_within_metric_buffers(
+            self.points,
+            query_point,
+            radius,
+            metric,
+            result,
+            stack,
+        )
+    }
+
+    /// Returns the indices of the points within `radius` of `query_point` as measured by
+    /// `metric`, in unspecified order. A point is returned exactly when
+    /// `metric.distance(query_point, point) <= radius`.
+    ///
+    /// Subtrees are pruned with `Metric::axis_distance` to the splitting planes. The bounding
+    /// spheres of the tree are euclidean and are not used.
+    #[inline(always)]
+    pub fn point_indices_within_metric<M: Metric<D>>(
+        &self,
+        query_point: P,
+        radius: f32,
+        metric: &M,
+    ) -> Vec<usize> {
+        self.internal
+            .point_indices_within_metric(self.points, query_point, radius, metric)
+    }
+
+    /// Same as `point_indices_within_l1`, but you provide your own buffers. Indices are appended
+    /// to `result` which is not cleared. `stack` is assumed to be empty and is left empty.
+    #[inline(always)]
+    pub fn point_indices_within_l1_buffers(
+        &self,
+        query_point: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_within_l1_buffers(
+            self.points,
+            query_point,
+            radius,
+            result,
+            stack,
+        )
+    }
+
+    /// Returns the indices of the points within a Manhattan distance of `radius` from
+    /// `query_point`, that is inside the diamond, or cross-polytope, of that radius around it.
+    /// Same as `point_indices_within_metric` with `Manhattan`.
+    #[inline(always)]
+    pub fn point_indices_within_l1(&self, query_point: P, radius: f32) -> Vec<usize> {
+        self.internal
+            .point_indices_within_l1(self.points, query_point, radius)
+    }
+
+    /// Same as `point_indices_within_linf`, but you provide your own buffers. Indices are
+    /// appended to `result` which is not cleared. `stack` is assumed to be empty and is left
+    /// empty.
+    #[inline(always)]
+    pub fn point_indices_within_linf_buffers(
+        &self,
+        query_point: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_within_linf_buffers(
+            self.points,
+            query_point,
+            radius,
+            result,
+            stack,
+        )
+    }
+
+    /// Returns the indices of the points within a Chebyshev distance of `radius` from
+    /// `query_point`, that is inside the axis-aligned cube with half-size `radius` centered at
+    /// it, boundary included. In a grid, a radius of one cell gives the Moore neighborhood, the
+    /// moves of a king in chess. Same as `point_indices_within_metric` with `Chebyshev`.
+    #[inline(always)]
+    pub fn point_indices_within_linf(&self, query_point: P, radius: f32) -> Vec<usize> {
+        self.internal
+            .point_indices_within_linf(self.points, query_point, radius)
+    }
+
+    /// Same as `nearest_n_metric`, but you provide your own buffers, see `nearest_n_buffers`.
+    /// The `distance_squared` of the neighbors in `heap` holds the distance of `metric`.
+    #[inline(always)]
+    pub fn nearest_n_metric_buffers<M: Metric<D>>(
+        &self,
+        query_point: P,
+        k: usize,
+        metric: &M,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        self.internal.nearest_n_metric_buffers(
+            self.points,
+            query_point,
+            k,
+            metric,
+            result,
+            heap,
+            stack,
+        )
+    }
+
+    /// Same as `nearest_n`, with distances measured by `metric`. Points at the same distance
+    /// are ordered by index.
+    #[inline(always)]
+    pub fn nearest_n_metric<M: Metric<D>>(
+        &self,
+        query_point: P,
+        k: usize,
+        metric: &M,
+    ) -> Vec<usize> {
+        self.internal
+            .nearest_n_metric(self.points, query_point, k, metric)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_cosine() {
+        let mut rng = SplitMix64::new(61);
+        let mut unit_vector = || {
+            let v: [f32; 4] = std::array::from_fn(|_| (rng.next_u64() % 2001) as f32 - 1000.0);
+            let length = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+            v.map(|x| x / length)
+        };
+        let points = (0..2000).map(|_| unit_vector()).collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        let query = unit_vector();
+        let similarity =
+            |point: &[f32; 4]| (0..4).map(|axis| point[axis] * query[axis]).sum::<f32>();
+
+        let nearest = tree.nearest_n_metric(query, 10, &Cosine);
+        let mut by_similarity = (0..points.len()).collect::<Vec<_>>();
+        by_similarity.sort_by(|a, b| similarity(&points[*b]).total_cmp(&similarity(&points[*a])));
+        assert_eq!(nearest[0], by_similarity[0]);
+
+        let mut expected = (0..points.len()).collect::<Vec<_>>();
+        expected.sort_by(|a, b| {
+            Cosine
+                .distance(&query, &points[*a])
+                .total_cmp(&Cosine.distance(&query, &points[*b]))
+                .then(a.cmp(b))
+        });
+        expected.truncate(10);
+        assert_eq!(nearest, expected);
+
+        let mut within = tree.point_indices_within_metric(query, 0.2, &Cosine);
+        within.sort_unstable();
+        let expected = (0..points.len())
+            .filter(|i| Cosine.distance(&query, &points[*i]) <= 0.2)
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+        assert_eq!(within, expected);
+    }
+
+    #[test]
+    fn test_mahalanobis() {
+        let matrix = [[4.0, 1.5, 0.0], [1.5, 2.0, -0.5], [0.0, -0.5, 1.0]];
+        let mahalanobis = Mahalanobis::from_matrix(matrix).unwrap();
+
+        for delta in [[1.0, 0.0, 0.0], [0.5, -2.0, 3.0], [-1.0, 1.0, 1.0]] {
+            let expected = (0..3)
+                .flat_map(|i| (0..3).map(move |j| (i, j)))
+                .map(|(i, j)| delta[i] * matrix[i][j] * delta[j])
+                .sum::<f32>()
+                .sqrt();
+            let distance = mahalanobis.distance(&delta, &[0.0; 3]);
+            assert!((distance - expected).abs() < 1e-4);
+        }
+
+        assert!(Mahalanobis::from_matrix([[1.0, 2.0], [2.0, 1.0]]).is_none());
+
+        let mut rng = SplitMix64::new(67);
+        let points = (0..2000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        let query = [50.0, 30.0, 70.0];
+        let mut within = tree.point_indices_within_metric(query, 15.0, &mahalanobis);
+        within.sort_unstable();
+        let expected = (0..points.len())
+            .filter(|i| mahalanobis.distance(&query, &points[*i]) <= 15.0)
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+        assert_eq!(within, expected);
+
+        let mut expected = (0..points.len()).collect::<Vec<_>>();
+        expected.sort_by(|a, b| {
+            mahalanobis
+                .distance(&query, &points[*a])
+                .total_cmp(&mahalanobis.distance(&query, &points[*b]))
+                .then(a.cmp(b))
+        });
+        expected.truncate(10);
+        assert_eq!(tree.nearest_n_metric(query, 10, &mahalanobis), expected);
+    }
+
+    /// The euclidean distance with the first axis stretched, as a custom metric
+    struct Stretched;
+
+    impl Metric<2> for Stretched {
+        fn distance(&self, a: &[f32; 2], b: &[f32; 2]) -> f32 {
+            let x = (a[0] - b[0]) * 3.0;
+            let y = a[1] - b[1];
+            (x * x + y * y).sqrt()
+        }
+
+        fn axis_distance(&self, axis: usize, axis_delta: f32) -> f32 {
+            if axis == 0 {
+                axis_delta.abs() * 3.0
+            } else {
+                axis_delta.abs()
+            }
+        }
+    }
+
+    #[test]
+    fn test_metric_queries() {
+        let mut rng = SplitMix64::new(59);
+        let points = (0..2000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        let query = [40.0, 60.0];
+
+        let mut within = tree.point_indices_within_metric(query, 9.0, &Euclidean);
+        within.sort_unstable();
+        let mut expected = tree.point_indices_within(query, 9.0);
+        expected.sort_unstable();
+        assert_eq!(within, expected);
+        assert_eq!(
+            tree.nearest_n_metric(query, 12, &Euclidean),
+            tree.nearest_n(query, 12)
+        );
+
+        let mut within = tree.point_indices_within_metric(query, 9.0, &Stretched);
+        within.sort_unstable();
+        let expected = (0..points.len())
+            .filter(|i| Stretched.distance(&query, &points[*i]) <= 9.0)
+            .collect::<Vec<_>>();
+        assert_eq!(within, expected);
+
+        let mut expected = (0..points.len()).collect::<Vec<_>>();
+        expected.sort_by(|a, b| {
+            Stretched
+                .distance(&query, &points[*a])
+                .total_cmp(&Stretched.distance(&query, &points[*b]))
+                .then(a.cmp(b))
+        });
+        expected.truncate(12);
+        assert_eq!(tree.nearest_n_metric(query, 12, &Stretched), expected);
+
+        let mut within = tree.point_indices_within_l1(query, 11.0);
+        within.sort_unstable();
+        let expected = (0..points.len())
+            .filter(|i| (points[*i][0] - query[0]).abs() + (points[*i][1] - query[1]).abs() <= 11.0)
+            .collect::<Vec<_>>();
+        assert_eq!(within, expected);
+
+        for p in [0.5, 1.0, 1.5, 2.0, 3.0, f32::INFINITY] {
+            let minkowski = Minkowski::new(p);
+
+            let mut within = tree.point_indices_within_metric(query, 8.0, &minkowski);
+            within.sort_unstable();
+            let expected = (0..points.len())
+                .filter(|i| minkowski.distance(&query, &points[*i]) <= 8.0)
+                .collect::<Vec<_>>();
+            assert!(!expected.is_empty());
+            assert_eq!(within, expected);
+
+            let nearest = tree.nearest_n_metric(query, 5, &minkowski);
+            let mut expected = (0..points.len()).collect::<Vec<_>>();
+            expected.sort_by(|a, b| {
+                minkowski
+                    .distance(&query, &points[*a])
+                    .total_cmp(&minkowski.distance(&query, &points[*b]))
+                    .then(a.cmp(b))
+            });
+            expected.truncate(5);
+            assert_eq!(nearest, expected);
+        }
+
+        let weighted = WeightedEuclidean::new([0.25, 2.0]);
+        let mut within = tree.point_indices_within_metric(query, 10.0, &weighted);
+        within.sort_unstable();
+        let expected = (0..points.len())
+            .filter(|i| {
+                let x = (points[*i][0] - query[0]) * 0.25;
+                let y = (points[*i][1] - query[1]) * 2.0;
+                (x * x + y * y).sqrt() <= 10.0
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(within, expected);
+
+        let mut within = tree.point_indices_within_linf(query, 7.5);
+        within.sort_unstable();
+        let mut expected = tree.point_indices_in_aabb([32.5, 52.5], [47.5, 67.5]);
+        expected.sort_unstable();
+        assert_eq!(within, expected);
+    }
+}
@@ -0,0 +1,279 @@
+//! Queries over points moving at constant velocities
+use crate::utils::axis_values;
+use crate::{KdTreeNoBorrow, Point};
+
+#[derive(Debug, Clone, Copy)]
+/// Bounds of the positions and velocities of all points in a subtree
+pub struct MovingBounds<const D: usize> {
+    pub position_min: [f32; D],
+    pub position_max: [f32; D],
+    pub velocity_min: [f32; D],
+    pub velocity_max: [f32; D],
+}
+
+impl<const D: usize> MovingBounds<D> {
+    /// The squared distance from `query_point_axis_values` to the box that contains every point
+    /// of the subtree at time `t`
+    #[inline(always)]
+    pub fn distance_squared_at(&self, query_point_axis_values: &[f32; D], t: f32) -> f32 {
+        let mut distance_squared = 0.0;
+
+        for (axis, value) in query_point_axis_values.iter().enumerate() {
+            let (low, high) = if t >= 0.0 {
+                (
+                    self.position_min[axis] + self.velocity_min[axis] * t,
+                    self.position_max[axis] + self.velocity_max[axis] * t,
+                )
+            } else {
+                (
+                    self.position_min[axis] + self.velocity_max[axis] * t,
+                    self.position_max[axis] + self.velocity_min[axis] * t,
+                )
+            };
+
+            let delta = (low - value).max(value - high).max(0.0);
+            distance_squared += delta * delta;
+        }
+
+        distance_squared
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A KdTree of points that move with constant velocities. The tree is built once from the
+/// positions at time zero and queried at any time `t` without rebuilding: every subtree keeps
+/// the bounds of its positions and velocities, from which a box containing the subtree at time
+/// `t` follows. Pruning gets less effective the further `t` is from zero, so rebuild every now
+/// and then when querying far ahead.
+pub struct KdTreeMoving<'a, const D: usize, P: Point<D>> {
+    pub internal: KdTreeNoBorrow<D, P>,
+    pub points: &'a [P],
+    pub velocities: &'a [[f32; D]],
+    /// Indexed like `internal.tree`
+    pub node_bounds: Vec<MovingBounds<D>>,
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTreeMoving<'a, D, P> {
+    /// Builds a tree of `points` at time zero, where point `i` moves by `velocities[i]` per unit
+    /// of time
+    pub fn from_points_and_velocities(points: &'a [P], velocities: &'a [[f32; D]]) -> Self {
+        Self::from_tree(KdTreeNoBorrow::from_points(points), points, velocities)
+    }
+
+    /// Wraps `internal`, which was built from `points` at time zero. Use this to build with
+    /// `KdTreeBuilder`.
+    pub fn from_tree(
+        internal: KdTreeNoBorrow<D, P>,
+        points: &'a [P],
+        velocities: &'a [[f32; D]],
+    ) -> Self {
+        assert_eq!(
+            points.len(),
+            velocities.len(),
+            "one velocity per point is required"
+        );
+
+        let mut node_bounds = Vec::with_capacity(internal.tree.len());
+        for node in &internal.tree {
+            let position = axis_values(&points[node.index()]);
+            node_bounds.push(MovingBounds {
+                position_min: position,
+                position_max: position,
+                velocity_min: velocities[node.index()],
+                velocity_max: velocities[node.index()],
+            });
+        }
+
+        /*
+            NOTE: Children are always placed after their parent in `tree`, so iterating in
+                  reverse visits every child before its parent.
+        */
+        for tree_index in (0..internal.tree.len()).rev() {
+            for child in internal.tree[tree_index].children().iter().flatten() {
+                let child_bounds = node_bounds[*child];
+                let bounds = &mut node_bounds[tree_index];
+                for axis in 0..D {
+                    bounds.position_min[axis] =
+                        bounds.position_min[axis].min(child_bounds.position_min[axis]);
+                    bounds.position_max[axis] =
+                        bounds.position_max[axis].max(child_bounds.position_max[axis]);
+                    bounds.velocity_min[axis] =
+                        bounds.velocity_min[axis].min(child_bounds.velocity_min[axis]);
+                    bounds.velocity_max[axis] =
+                        bounds.velocity_max[axis].max(child_bounds.velocity_max[axis]);
+                }
+            }
+        }
+
+        Self {
+            internal,
+            points,
+            velocities,
+            node_bounds,
+        }
+    }
+
+    /// The position of point `index` at time `t`
+    #[inline(always)]
+    pub fn position_at(&self, index: usize, t: f32) -> [f32; D] {
+        let mut position = axis_values(&self.points[index]);
+        for (axis, value) in position.iter_mut().enumerate() {
+            *value += self.velocities[index][axis] * t;
+        }
+        position
+    }
+
+    #[inline(always)]
+    fn distance_squared_at(&self, index: usize, query_point_axis_values: &[f32; D], t: f32) -> f32 {
+        let position = self.position_at(index, t);
+        (0..D)
+            .map(|axis| {
+                let delta = position[axis] - query_point_axis_values[axis];
+                delta * delta
+            })
+            .sum()
+    }
+
+    /// Same as `within_at`, but you provide your own buffers. Indices are appended to `result`
+    /// which is not cleared. `stack` is assumed to be empty and is left empty.
+    pub fn within_at_buffers(
+        &self,
+        query_point: P,
+        radius: f32,
+        t: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<usize>,
+    ) {
+        if self.internal.tree.is_empty() {
+            return;
+        }
+
+        let radius_squared = radius * radius;
+        let query_point_axis_values = axis_values(&query_point);
+
+        stack.push(0);
+        while let Some(tree_index) = stack.pop() {
+            if self.node_bounds[tree_index].distance_squared_at(&query_point_axis_values, t)
+                > radius_squared
+            {
+                continue;
+            }
+
+            let node = &self.internal.tree[tree_index];
+            if self.distance_squared_at(node.index(), &query_point_axis_values, t) <= radius_squared
+            {
+                result.push(node.index());
+            }
+
+            stack.extend(node.children().iter().flatten());
+        }
+    }
+
+    /// Returns the indices of the points that are within `radius` of `query_point` at time `t`
+    pub fn within_at(&self, query_point: P, radius: f32, t: f32) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.within_at_buffers(query_point, radius, t, &mut result, &mut stack);
+
+        result
+    }
+
+    /// Returns the index of and distance to the point closest to `query_point` at time `t`, or
+    /// `None` if the tree is empty
+    pub fn nearest_at(&self, query_point: P, t: f32) -> Option<(usize, f32)> {
+        if self.internal.tree.is_empty() {
+            return None;
+        }
+
+        let query_point_axis_values = axis_values(&query_point);
+        let mut best = (usize::MAX, f32::INFINITY);
+
+        let mut stack = vec![(0, 0.0)];
+        while let Some((tree_index, lower_bound)) = stack.pop() {
+            if lower_bound > best.1 {
+                continue;
+            }
+
+            let node = &self.internal.tree[tree_index];
+            let distance_squared =
+                self.distance_squared_at(node.index(), &query_point_axis_values, t);
+            if distance_squared < best.1 {
+                best = (node.index(), distance_squared);
+            }
+
+            let mut children = node.children().map(|child| {
+                child.map(|child| {
+                    let bound =
+                        self.node_bounds[child].distance_squared_at(&query_point_axis_values, t);
+                    (child, bound)
+                })
+            });
+
+            /*
+                NOTE: The closer child is pushed last so that it is popped first
+            */
+            if let [Some(a), Some(b)] = children {
+                if a.1 < b.1 {
+                    children = [Some(b), Some(a)];
+                }
+            }
+            stack.extend(children.into_iter().flatten());
+        }
+
+        Some((best.0, best.1.sqrt()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_moving_queries() {
+        let mut rng = SplitMix64::new(13);
+        let mut random = |scale: f32| (rng.next_u64() % 2001) as f32 / 1000.0 * scale - scale;
+
+        let points = (0..1500)
+            .map(|_| [random(50.0), random(50.0)])
+            .collect::<Vec<_>>();
+        let velocities = (0..1500)
+            .map(|_| [random(5.0), random(5.0)])
+            .collect::<Vec<_>>();
+        let tree = KdTreeMoving::from_points_and_velocities(&points, &velocities);
+
+        let at = |i: usize, t: f32| {
+            [
+                points[i][0] + velocities[i][0] * t,
+                points[i][1] + velocities[i][1] * t,
+            ]
+        };
+        let distance_squared =
+            |a: [f32; 2], b: [f32; 2]| (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2);
+
+        for t in [0.0, 0.5, 3.0, -2.0] {
+            for query in [[0.0, 0.0], [30.0, -20.0], [-45.0, 45.0]] {
+                let expected = (0..points.len())
+                    .filter(|i| distance_squared(at(*i, t), query) <= 10.0 * 10.0)
+                    .collect::<Vec<_>>();
+                let mut result = tree.within_at(query, 10.0, t);
+                result.sort_unstable();
+                assert_eq!(result, expected);
+
+                let nearest = (0..points.len())
+                    .min_by(|a, b| {
+                        distance_squared(at(*a, t), query)
+                            .total_cmp(&distance_squared(at(*b, t), query))
+                    })
+                    .unwrap();
+                let (index, distance) = tree.nearest_at(query, t).unwrap();
+                assert_eq!(
+                    distance_squared(at(index, t), query),
+                    distance_squared(at(nearest, t), query)
+                );
+                assert_eq!(distance, distance_squared(at(index, t), query).sqrt());
+            }
+        }
+    }
+}
@@ -0,0 +1,708 @@
+//! k nearest neighbor queries
+#[cfg(feature = "std")]
+use crate::utils::axis_values;
+#[cfg(feature = "std")]
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+use core::cmp::Ordering;
+#[cfg(feature = "std")]
+use std::collections::BinaryHeap;
+
+/// A type squared distances are measured in. `f32` for most trees, `f64` for `KdTreePrecise`
+/// and `u128` for `KdTreeInteger`.
+pub trait Distance: Copy + PartialOrd + core::fmt::Debug {
+    const ZERO: Self;
+    /// Farther than any point
+    const MAX: Self;
+
+    fn total_cmp(&self, other: &Self) -> Ordering;
+}
+
+impl Distance for f32 {
+    const ZERO: Self = 0.0;
+    const MAX: Self = f32::INFINITY;
+
+    #[inline(always)]
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        f32::total_cmp(self, other)
+    }
+}
+
+impl Distance for f64 {
+    const ZERO: Self = 0.0;
+    const MAX: Self = f64::INFINITY;
+
+    #[inline(always)]
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        f64::total_cmp(self, other)
+    }
+}
+
+impl Distance for u128 {
+    const ZERO: Self = 0;
+    const MAX: Self = u128::MAX;
+
+    #[inline(always)]
+    fn total_cmp(&self, other: &Self) -> Ordering {
+        self.cmp(other)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// A candidate of a k nearest neighbor query
+pub struct Neighbor<T = f32> {
+    pub distance_squared: T,
+    pub index: usize,
+}
+
+/*
+    NOTE: Ordered by distance so that `BinaryHeap` keeps the farthest of the current
+          candidates on top, where it can be replaced by a closer one.
+*/
+impl<T: Distance> Ord for Neighbor<T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance_squared
+            .total_cmp(&other.distance_squared)
+            .then_with(|| self.index.cmp(&other.index))
+    }
+}
+
+impl<T: Distance> PartialOrd for Neighbor<T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Distance> PartialEq for Neighbor<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T: Distance> Eq for Neighbor<T> {}
+
+#[cfg(feature = "std")]
+#[inline(always)]
+pub(crate) fn distance_squared_to_axis_values<const D: usize, P: Point<D>>(
+    point: &P,
+    axis_values: &[f32; D],
+) -> f32 {
+    (0..D)
+        .map(|axis| {
+            let delta = point.get_axis(axis) - axis_values[axis];
+            delta * delta
+        })
+        .sum()
+}
+
+/// Empties `heap` into `result`, nearest first
+#[cfg(feature = "std")]
+pub(crate) fn pop_nearest_first<T: Distance>(
+    heap: &mut BinaryHeap<Neighbor<T>>,
+    result: &mut Vec<usize>,
+) {
+    /*
+        NOTE: The heap pops the farthest neighbor first
+    */
+    let start = result.len();
+    while let Some(neighbor) = heap.pop() {
+        result.push(neighbor.index);
+    }
+    result[start..].reverse();
+}
+
+#[cfg(feature = "std")]
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// Collects the `k` points closest to `query_point_axis_values` into `heap` using
+    /// `distance_squared` to measure points. This is the traversal shared by all k nearest
+    /// neighbor queries.
+    ///
+    /// `heap` must be empty and holds the neighbors afterwards, farthest on top. `bound_squared`
+    /// must be at least the squared distance to the `k`th closest point and prunes the traversal
+    /// until `k` candidates have been found, pass `f32::INFINITY` if no bound is known. `stack`
+    /// holds `(depth, tree_index, lower bound of the squared distance)` and is cleared before
+    /// returning.
+    #[allow(clippy::too_many_arguments)]
+    pub fn nearest_n_by_buffers<F>(
+        &self,
+        points: &[P],
+        query_point_axis_values: &[f32; D],
+        k: usize,
+        bound_squared: f32,
+        distance_squared: F,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) where
+        F: FnMut(&P) -> f32,
+    {
+        self.nearest_n_scaled_by_buffers(
+            points,
+            query_point_axis_values,
+            k,
+            bound_squared,
+            1.0,
+            usize::MAX,
+            |_| true,
+            distance_squared,
+            heap,
+            stack,
+        );
+    }
+
+    /// Same as `nearest_n_by_buffers`, but lower bounds of subtrees are multiplied by
+    /// `lower_bound_scale` before comparing them to the current `k`th candidate. A scale above
+    /// one prunes more aggressively at the cost of exactness.
+    ///
+    /// The traversal gives up after visiting `max_visits` nodes, leaving the best candidates
+    /// found so far in `heap`. Returns whether it finished. Only points for which `accept`
+    /// returns true become candidates.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn nearest_n_scaled_by_buffers<A, F>(
+        &self,
+        points: &[P],
+        query_point_axis_values: &[f32; D],
+        k: usize,
+        bound_squared: f32,
+        lower_bound_scale: f32,
+        max_visits: usize,
+        mut accept: A,
+        mut distance_squared: F,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) -> bool
+    where
+        A: FnMut(usize) -> bool,
+        F: FnMut(&P) -> f32,
+    {
+        if k == 0 || self.tree.is_empty() {
+            return true;
+        }
+
+        let mut visits = 0;
+        stack.push((0, 0, 0.0));
+        while let Some((depth, tree_index, lower_bound)) = stack.pop() {
+            let current_bound = if heap.len() == k {
+                heap.peek().map_or(bound_squared, |n| n.distance_squared)
+            } else {
+                bound_squared
+            };
+            if lower_bound * lower_bound_scale > current_bound
+                || self.sphere_distance_squared_lower_bound(tree_index, query_point_axis_values)
+                    * lower_bound_scale
+                    > current_bound
+            {
+                continue;
+            }
+
+            if visits == max_visits {
+                stack.clear();
+                return false;
+            }
+            visits += 1;
+
+            let point_index = self.tree[tree_index].index();
+            if accept(point_index) {
+                let point_distance_squared = distance_squared(&points[point_index]);
+                if point_distance_squared <= current_bound {
+                    heap.push(Neighbor {
+                        distance_squared: point_distance_squared,
+                        index: point_index,
+                    });
+                    if heap.len() > k {
+                        heap.pop();
+                    }
+                }
+            }
+
+            let axis = self.tree[tree_index].axis();
+            let axis_d = self.tree[tree_index].split - query_point_axis_values[axis];
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            /*
+                NOTE: The far side is pushed first so that the near side is popped first
+            */
+            if let Some(child) = self.tree[tree_index].child(last) {
+                stack.push((depth + 1, child, lower_bound.max(axis_d * axis_d)));
+            }
+            if let Some(child) = self.tree[tree_index].child(first) {
+                stack.push((depth + 1, child, lower_bound));
+            }
+        }
+
+        true
+    }
+
+    /// Same as `nearest_n_by_buffers` with euclidean distances computed from `Point::get_axis`
+    pub(crate) fn nearest_n_axis_values_buffers(
+        &self,
+        points: &[P],
+        query_point_axis_values: &[f32; D],
+        k: usize,
+        bound_squared: f32,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        self.nearest_n_by_buffers(
+            points,
+            query_point_axis_values,
+            k,
+            bound_squared,
+            |point| distance_squared_to_axis_values(point, query_point_axis_values),
+            heap,
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn nearest_n_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        self.nearest_n_by_buffers(
+            points,
+            &axis_values(&query_point),
+            k,
+            f32::INFINITY,
+            |point| query_point.distance_squared(*point),
+            heap,
+            stack,
+        );
+
+        pop_nearest_first(heap, result);
+    }
+
+    /// Same as `nearest_n_buffers`, but only points for which `filter` returns true are
+    /// considered. Rejected points are still descended through.
+    #[allow(clippy::too_many_arguments)]
+    pub fn nearest_n_filtered_buffers<F>(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        filter: F,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) where
+        F: FnMut(usize) -> bool,
+    {
+        self.nearest_n_scaled_by_buffers(
+            points,
+            &axis_values(&query_point),
+            k,
+            f32::INFINITY,
+            1.0,
+            usize::MAX,
+            filter,
+            |point| query_point.distance_squared(*point),
+            heap,
+            stack,
+        );
+
+        pop_nearest_first(heap, result);
+    }
+
+    /// See `KdTree`
+    pub fn nearest_n(&self, points: &[P], query_point: P, k: usize) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.tree.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.tree.len()) + 1);
+        let mut stack = vec![];
+
+        self.nearest_n_buffers(points, query_point, k, &mut result, &mut heap, &mut stack);
+
+        result
+    }
+
+    /// See `KdTree`
+    pub fn kth_nearest_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) -> Option<(usize, f32)> {
+        self.nearest_n_by_buffers(
+            points,
+            &axis_values(&query_point),
+            k,
+            f32::INFINITY,
+            |point| query_point.distance_squared(*point),
+            heap,
+            stack,
+        );
+
+        /*
+            NOTE: The farthest of the `k` candidates is on top of the heap
+        */
+        let kth = heap
+            .peek()
+            .filter(|_| heap.len() == k)
+            .map(|neighbor| (neighbor.index, neighbor.distance_squared.sqrt()));
+        heap.clear();
+
+        kth
+    }
+
+    /// See `KdTree`
+    pub fn kth_nearest(&self, points: &[P], query_point: P, k: usize) -> Option<(usize, f32)> {
+        let mut heap = BinaryHeap::with_capacity(k.min(self.tree.len()) + 1);
+        let mut stack = vec![];
+
+        self.kth_nearest_buffers(points, query_point, k, &mut heap, &mut stack)
+    }
+
+    /// See `KdTree`
+    #[allow(clippy::too_many_arguments)]
+    pub fn nearest_n_excluding_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        exclude: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        if k == 0 {
+            return;
+        }
+
+        /*
+            NOTE: The `k + 1` nearest points contain the `k` nearest ones other than `exclude`,
+                  in the same order, whether `exclude` is among them or not
+        */
+        let start = result.len();
+        self.nearest_n_buffers(
+            points,
+            query_point,
+            k.saturating_add(1),
+            result,
+            heap,
+            stack,
+        );
+
+        match result[start..].iter().position(|index| *index == exclude) {
+            Some(position) => {
+                result.remove(start + position);
+            }
+            None => result.truncate(start + k),
+        }
+    }
+
+    /// See `KdTree`
+    pub fn nearest_n_excluding(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        exclude: usize,
+    ) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.tree.len()) + 1);
+        let mut heap = BinaryHeap::with_capacity(k.min(self.tree.len()) + 2);
+        let mut stack = vec![];
+
+        self.nearest_n_excluding_buffers(
+            points,
+            query_point,
+            k,
+            exclude,
+            &mut result,
+            &mut heap,
+            &mut stack,
+        );
+
+        result
+    }
+
+    /// See `KdTree`
+    #[allow(clippy::too_many_arguments)]
+    pub fn nearest_n_approx_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        epsilon: f32,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        let scale = 1.0 + epsilon.max(0.0);
+
+        self.nearest_n_scaled_by_buffers(
+            points,
+            &axis_values(&query_point),
+            k,
+            f32::INFINITY,
+            scale * scale,
+            usize::MAX,
+            |_| true,
+            |point| query_point.distance_squared(*point),
+            heap,
+            stack,
+        );
+
+        pop_nearest_first(heap, result);
+    }
+
+    /// See `KdTree`
+    pub fn nearest_n_approx(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        epsilon: f32,
+    ) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.tree.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.tree.len()) + 1);
+        let mut stack = vec![];
+
+        self.nearest_n_approx_buffers(
+            points,
+            query_point,
+            k,
+            epsilon,
+            &mut result,
+            &mut heap,
+            &mut stack,
+        );
+
+        result
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `nearest_n`, but you provide your own buffers. Indices are appended to `result`
+    /// which is not cleared. `heap` and `stack` are assumed to be empty and are left empty.
+    #[inline(always)]
+    pub fn nearest_n_buffers(
+        &self,
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        self.internal
+            .nearest_n_buffers(self.points, query_point, k, result, heap, stack)
+    }
+
+    /// Returns the indices of the `k` points closest to `query_point`, nearest first. Points at
+    /// the same distance are ordered by index. Fewer than `k` indices are returned if the tree
+    /// holds fewer points.
+    ///
+    /// The candidates are kept in a max-heap of size `k` and subtrees that can't contain a point
+    /// closer than the current `k`th candidate are skipped.
+    ///
+    /// If you want to allocate your own buffers for multiple consecutive queries, see `nearest_n_buffers`
+    #[inline(always)]
+    pub fn nearest_n(&self, query_point: P, k: usize) -> Vec<usize> {
+        self.internal.nearest_n(self.points, query_point, k)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `kth_nearest`, but you provide your own buffers. `heap` and `stack` are assumed
+    /// to be empty and are left empty.
+    #[inline(always)]
+    pub fn kth_nearest_buffers(
+        &self,
+        query_point: P,
+        k: usize,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) -> Option<(usize, f32)> {
+        self.internal
+            .kth_nearest_buffers(self.points, query_point, k, heap, stack)
+    }
+
+    /// Returns the index of the `k`th closest point to `query_point` and its distance, where
+    /// `k == 1` is the closest point. This is the last point of `nearest_n(query_point, k)`,
+    /// found without collecting the others into a list. `None` if `k` is zero or the tree
+    /// holds fewer than `k` points.
+    ///
+    /// The distance to the `k`th neighbor is a common local density estimate, for instance to
+    /// pick the radius of DBSCAN.
+    #[inline(always)]
+    pub fn kth_nearest(&self, query_point: P, k: usize) -> Option<(usize, f32)> {
+        self.internal.kth_nearest(self.points, query_point, k)
+    }
+
+    /// Same as `nearest_n_excluding`, but you provide your own buffers, see `nearest_n_buffers`
+    #[inline(always)]
+    pub fn nearest_n_excluding_buffers(
+        &self,
+        query_point: P,
+        k: usize,
+        exclude: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        self.internal.nearest_n_excluding_buffers(
+            self.points,
+            query_point,
+            k,
+            exclude,
+            result,
+            heap,
+            stack,
+        )
+    }
+
+    /// Same as `nearest_n`, but the point at index `exclude` is never part of the result. Use
+    /// this when querying with one of the points of the tree, for instance to build a k nearest
+    /// neighbor graph, where the query point would otherwise always be its own nearest neighbor.
+    /// Other points at the same position as `exclude` are still returned.
+    #[inline(always)]
+    pub fn nearest_n_excluding(&self, query_point: P, k: usize, exclude: usize) -> Vec<usize> {
+        self.internal
+            .nearest_n_excluding(self.points, query_point, k, exclude)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `nearest_n_approx`, but you provide your own buffers, see `nearest_n_buffers`
+    #[inline(always)]
+    pub fn nearest_n_approx_buffers(
+        &self,
+        query_point: P,
+        k: usize,
+        epsilon: f32,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        self.internal.nearest_n_approx_buffers(
+            self.points,
+            query_point,
+            k,
+            epsilon,
+            result,
+            heap,
+            stack,
+        )
+    }
+
+    /// Approximate version of `nearest_n`. Subtrees are skipped unless they could hold a point
+    /// closer than the current `k`th candidate divided by `1 + epsilon`, so the `i`th returned
+    /// point is at most `1 + epsilon` times further away than the true `i`th nearest point.
+    ///
+    /// With `epsilon == 0.0` this is the same as `nearest_n`. Larger values visit fewer nodes,
+    /// which pays off on large point clouds where an almost-nearest answer is good enough.
+    #[inline(always)]
+    pub fn nearest_n_approx(&self, query_point: P, k: usize, epsilon: f32) -> Vec<usize> {
+        self.internal
+            .nearest_n_approx(self.points, query_point, k, epsilon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_nearest_n() {
+        let mut rng = SplitMix64::new(5);
+        let points = (0..1000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        for (query, k) in [
+            ([50.0, 50.0, 50.0], 10),
+            ([0.0, 100.0, 3.0], 1),
+            ([20.0, 80.0, 45.0], 37),
+        ] {
+            let mut expected = (0..points.len()).collect::<Vec<_>>();
+            expected.sort_by(|a, b| {
+                query
+                    .distance_squared(points[*a])
+                    .total_cmp(&query.distance_squared(points[*b]))
+                    .then(a.cmp(b))
+            });
+            expected.truncate(k);
+
+            assert_eq!(tree.nearest_n(query, k), expected);
+        }
+
+        assert!(tree.nearest_n([0.0; 3], 0).is_empty());
+        assert_eq!(
+            tree.nearest_n_approx([1.0, 2.0, 3.0], 7, 0.0),
+            tree.nearest_n([1.0, 2.0, 3.0], 7)
+        );
+        assert_eq!(tree.nearest_n([0.0; 3], 5000).len(), points.len());
+
+        for k in [1, 5, 40] {
+            let nearest = tree.nearest_n([30.0, 60.0, 90.0], k);
+            let kth = nearest[k - 1];
+            assert_eq!(
+                tree.kth_nearest([30.0, 60.0, 90.0], k),
+                Some((kth, [30.0, 60.0, 90.0].distance_squared(points[kth]).sqrt()))
+            );
+        }
+        assert_eq!(tree.kth_nearest([0.0; 3], 0), None);
+        assert_eq!(tree.kth_nearest([0.0; 3], points.len() + 1), None);
+
+        for exclude in [0, 17, 999] {
+            let mut expected = tree.nearest_n(points[exclude], 9);
+            assert_eq!(expected.remove(0), exclude);
+            assert_eq!(
+                tree.nearest_n_excluding(points[exclude], 8, exclude),
+                expected
+            );
+        }
+        assert_eq!(
+            tree.nearest_n_excluding([50.0, 50.0, 50.0], 6, usize::MAX),
+            tree.nearest_n([50.0, 50.0, 50.0], 6)
+        );
+        assert_eq!(
+            tree.nearest_n_excluding([0.0; 3], 5000, 3).len(),
+            points.len() - 1
+        );
+    }
+
+    #[test]
+    fn test_nearest_n_approx() {
+        let mut rng = SplitMix64::new(9);
+        let points = (0..2000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        let epsilon = 0.5;
+        for query in [[50.0, 50.0], [3.0, 97.0], [71.5, 12.25]] {
+            let exact = tree.nearest_n(query, 16);
+            let approx = tree.nearest_n_approx(query, 16, epsilon);
+            assert_eq!(approx.len(), exact.len());
+
+            for (a, e) in approx.iter().zip(&exact) {
+                let approx_distance = query.distance_squared(points[*a]).sqrt();
+                let exact_distance = query.distance_squared(points[*e]).sqrt();
+                assert!(approx_distance <= exact_distance * (1.0 + epsilon) + 1e-4);
+            }
+        }
+    }
+}
@@ -0,0 +1,228 @@
+//! Controlling the order in which the results of radius queries are returned
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// The order of the indices returned by `point_indices_within_ordered`
+pub enum ResultOrder {
+    /// Whatever order the traversal happens to produce. This is the order of
+    /// `point_indices_within` and may change between versions, but costs nothing extra.
+    #[default]
+    Traversal,
+    /// Left-to-right pre-order of the tree. Points that share a subtree, and thereby a region
+    /// of space, are next to each other in the result, and the order of any two points is the
+    /// same for every query that returns both.
+    Tree,
+    /// Nearest first. Points at the same distance are ordered by index.
+    Distance,
+    /// Ascending index
+    Index,
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_within_ordered_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        order: ResultOrder,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        let start = result.len();
+
+        match order {
+            ResultOrder::Traversal => {
+                self.point_indices_within_buffers(points, query_point, radius, result, stack)
+            }
+            ResultOrder::Tree => {
+                self.point_indices_within_tree_order_buffers(
+                    points,
+                    query_point,
+                    radius,
+                    result,
+                    stack,
+                );
+            }
+            ResultOrder::Distance => {
+                self.point_indices_within_buffers(points, query_point, radius, result, stack);
+                result[start..].sort_by(|a, b| {
+                    query_point
+                        .distance_squared(points[*a])
+                        .total_cmp(&query_point.distance_squared(points[*b]))
+                        .then(a.cmp(b))
+                });
+            }
+            ResultOrder::Index => {
+                self.point_indices_within_buffers(points, query_point, radius, result, stack);
+                result[start..].sort_unstable();
+            }
+        }
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_ordered(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        order: ResultOrder,
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_ordered_buffers(
+            points,
+            query_point,
+            radius,
+            order,
+            &mut result,
+            &mut stack,
+        );
+
+        result
+    }
+
+    fn point_indices_within_tree_order_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        if self.tree.is_empty() {
+            return;
+        }
+
+        let radius_squared = radius * radius;
+        let query_point_axis_values = axis_values(&query_point);
+
+        stack.push((0, 0));
+        while let Some((depth, tree_index)) = stack.pop() {
+            if self.sphere_out_of_reach(tree_index, &query_point_axis_values, radius) {
+                continue;
+            }
+
+            let point_index = self.tree[tree_index].index();
+            let point = points[point_index];
+
+            if query_point.distance_squared(point) <= radius_squared {
+                result.push(point_index);
+            }
+
+            let axis = self.tree[tree_index].axis();
+            let axis_d = self.tree[tree_index].split - query_point_axis_values[axis];
+            let reaches_both = axis_d * axis_d <= radius_squared;
+
+            /*
+                NOTE: The right child is pushed first so that the left one is popped first,
+                      independently of which side the query point is on.
+            */
+            let [left, right] = self.tree[tree_index].children();
+            if reaches_both || axis_d < 0.0 {
+                if let Some(child) = right {
+                    stack.push((depth + 1, child));
+                }
+            }
+            if reaches_both || axis_d >= 0.0 {
+                if let Some(child) = left {
+                    stack.push((depth + 1, child));
+                }
+            }
+        }
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `point_indices_within_ordered`, but you provide your own buffers. Indices are
+    /// appended to `result` and only the appended part is ordered.
+    pub fn point_indices_within_ordered_buffers(
+        &self,
+        query_point: P,
+        radius: f32,
+        order: ResultOrder,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_within_ordered_buffers(
+            self.points,
+            query_point,
+            radius,
+            order,
+            result,
+            stack,
+        )
+    }
+
+    /// Same as `point_indices_within`, but the indices are returned in the given `order`.
+    ///
+    /// `ResultOrder::Tree` groups spatially close points together without sorting, which suits
+    /// processing the results in SIMD batches. `ResultOrder::Distance` and `ResultOrder::Index`
+    /// sort the results after the query.
+    pub fn point_indices_within_ordered(
+        &self,
+        query_point: P,
+        radius: f32,
+        order: ResultOrder,
+    ) -> Vec<usize> {
+        self.internal
+            .point_indices_within_ordered(self.points, query_point, radius, order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_point_indices_within_ordered() {
+        let mut rng = SplitMix64::new(11);
+        let points = (0..500)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 1000) as f32 / 100.0,
+                    (rng.next_u64() % 1000) as f32 / 100.0,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        let query = [4.0, 6.0];
+        let mut expected = tree.point_indices_within(query, 2.0);
+        expected.sort_unstable();
+
+        let by_index = tree.point_indices_within_ordered(query, 2.0, ResultOrder::Index);
+        assert_eq!(by_index, expected);
+
+        let by_distance = tree.point_indices_within_ordered(query, 2.0, ResultOrder::Distance);
+        let mut sorted = by_distance.clone();
+        sorted.sort_unstable();
+        assert_eq!(sorted, expected);
+        assert!(by_distance.windows(2).all(|w| {
+            query.distance_squared(points[w[0]]) <= query.distance_squared(points[w[1]])
+        }));
+
+        let mut position = vec![0; points.len()];
+        let mut stack = vec![0];
+        let mut next_position = 0;
+        while let Some(tree_index) = stack.pop() {
+            let node = &tree.internal.tree[tree_index];
+            position[node.index()] = next_position;
+            next_position += 1;
+            stack.extend(node.children().iter().rev().flatten());
+        }
+        for query in [[4.0, 6.0], [0.0, 0.0], [9.5, 2.0]] {
+            let by_tree = tree.point_indices_within_ordered(query, 2.0, ResultOrder::Tree);
+            assert!(by_tree.windows(2).all(|w| position[w[0]] < position[w[1]]));
+
+            let mut sorted = by_tree.clone();
+            sorted.sort_unstable();
+            let mut expected = tree.point_indices_within(query, 2.0);
+            expected.sort_unstable();
+            assert_eq!(sorted, expected);
+        }
+    }
+}
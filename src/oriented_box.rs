@@ -0,0 +1,156 @@
+//! Queries with rotated boxes
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A box rotated away from the axes, such as a rotated selection box in an editor
+pub struct OrientedBox<const D: usize> {
+    pub center: [f32; D],
+    /// Half of the size of the box along each of its own axes
+    pub half_extents: [f32; D],
+    /// Maps box-local coordinates to world coordinates, `world = center + rotation * local`,
+    /// stored by rows as `rotation[row][column]`. The columns are the axes of the box and must
+    /// be orthonormal.
+    pub rotation: [[f32; D]; D],
+}
+
+impl<const D: usize> OrientedBox<D> {
+    pub fn new<P: Point<D>>(center: P, half_extents: [f32; D], rotation: [[f32; D]; D]) -> Self {
+        Self {
+            center: axis_values(&center),
+            half_extents,
+            rotation,
+        }
+    }
+
+    /// Whether `point` is inside the box, boundary included
+    pub fn contains<P: Point<D>>(&self, point: &P) -> bool {
+        self.contains_axis_values(&axis_values(point))
+    }
+
+    pub(crate) fn contains_axis_values(&self, point: &[f32; D]) -> bool {
+        (0..D).all(|box_axis| {
+            let local = (0..D)
+                .map(|axis| self.rotation[axis][box_axis] * (point[axis] - self.center[axis]))
+                .sum::<f32>();
+            local.abs() <= self.half_extents[box_axis]
+        })
+    }
+
+    /// The smallest axis-aligned box `(min, max)` containing the oriented box
+    pub fn aabb(&self) -> ([f32; D], [f32; D]) {
+        let mut min = self.center;
+        let mut max = self.center;
+        for axis in 0..D {
+            let extent = (0..D)
+                .map(|box_axis| self.rotation[axis][box_axis].abs() * self.half_extents[box_axis])
+                .sum::<f32>();
+            min[axis] -= extent;
+            max[axis] += extent;
+        }
+        (min, max)
+    }
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_in_oriented_box_buffers(
+        &self,
+        points: &[P],
+        oriented_box: &OrientedBox<D>,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        let (min, max) = oriented_box.aabb();
+
+        self.for_each_point_in_aabb_buffers(
+            points,
+            &min,
+            &max,
+            |point_index, point| {
+                if oriented_box.contains_axis_values(point) {
+                    result.push(point_index);
+                }
+            },
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_in_oriented_box(
+        &self,
+        points: &[P],
+        oriented_box: &OrientedBox<D>,
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_in_oriented_box_buffers(points, oriented_box, &mut result, &mut stack);
+
+        result
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `point_indices_in_oriented_box`, but you provide your own buffers. Indices are
+    /// appended to `result` which is not cleared. `stack` is assumed to be empty and is left
+    /// empty.
+    #[inline(always)]
+    pub fn point_indices_in_oriented_box_buffers(
+        &self,
+        oriented_box: &OrientedBox<D>,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_in_oriented_box_buffers(
+            self.points,
+            oriented_box,
+            result,
+            stack,
+        )
+    }
+
+    /// Returns the indices of the points inside `oriented_box`, boundary included. The tree is
+    /// pruned with the axis-aligned bounding box of the oriented box, see `OrientedBox::aabb`,
+    /// and the points inside of that are tested against the oriented box itself.
+    #[inline(always)]
+    pub fn point_indices_in_oriented_box(&self, oriented_box: &OrientedBox<D>) -> Vec<usize> {
+        self.internal
+            .point_indices_in_oriented_box(self.points, oriented_box)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_point_indices_in_oriented_box() {
+        let mut rng = SplitMix64::new(37);
+        let points = (0..3000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        let (sin, cos) = 0.5_f32.sin_cos();
+        let oriented_box = OrientedBox::new([50.0, 40.0], [20.0, 5.0], [[cos, -sin], [sin, cos]]);
+
+        let expected = (0..points.len())
+            .filter(|i| {
+                let [x, y] = [points[*i][0] - 50.0, points[*i][1] - 40.0];
+                (cos * x + sin * y).abs() <= 20.0 && (-sin * x + cos * y).abs() <= 5.0
+            })
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+
+        let mut result = tree.point_indices_in_oriented_box(&oriented_box);
+        result.sort_unstable();
+        assert_eq!(result, expected);
+    }
+}
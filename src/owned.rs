@@ -0,0 +1,459 @@
+//! A Kd-tree owning its points, with an optional value attached to every point
+use crate::{KdTreeNoBorrow, Neighbor, Point};
+
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone)]
+/// A Kd-tree that owns its points together with a value `V` per point, such as an entity id
+/// or any other metadata. Since the points can't be modified through the tree, there is no
+/// lifetime to keep track of, while the values can be freely mutated.
+///
+/// Points can be removed with `remove`, which only marks them so that the queries of this type
+/// skip them. `compact` drops the removed points for good.
+///
+/// Queries not forwarded by this type can be run on `internal` with `points()`, and return
+/// removed points unless they are masked with `removed()`.
+///
+/// The points and removal marks are private so that they can't get out of sync with the tree.
+pub struct KdTreeOwned<const D: usize, P: Point<D>, V = ()> {
+    pub internal: KdTreeNoBorrow<D, P>,
+    pub(crate) points: Vec<P>,
+    pub values: Vec<V>,
+    /// Whether every point has been removed, indexed like `points`
+    pub(crate) removed: Vec<bool>,
+    pub(crate) removed_count: usize,
+}
+
+impl<const D: usize, P: Point<D>> KdTreeOwned<D, P, ()> {
+    /// Constructs a new tree owning `points` using default settings
+    pub fn from_points(points: Vec<P>) -> Self {
+        let values = vec![(); points.len()];
+        Self::from_points_and_values(points, values)
+    }
+}
+
+impl<const D: usize, P: Point<D>, V> KdTreeOwned<D, P, V> {
+    /// Constructs a new tree owning `points` and `values` using default settings, where
+    /// `values[i]` belongs to `points[i]`
+    pub fn from_points_and_values(points: Vec<P>, values: Vec<V>) -> Self {
+        assert_eq!(
+            points.len(),
+            values.len(),
+            "one value per point is required"
+        );
+
+        Self {
+            internal: KdTreeNoBorrow::from_points(&points),
+            removed: vec![false; points.len()],
+            removed_count: 0,
+            points,
+            values,
+        }
+    }
+
+    /// Returns the points and values, dropping the tree. Removed points are included unless
+    /// `compact` was called first.
+    pub fn into_parts(self) -> (Vec<P>, Vec<V>) {
+        (self.points, self.values)
+    }
+
+    /// The points, including removed ones, indexed like the results of queries
+    #[inline(always)]
+    pub fn points(&self) -> &[P] {
+        &self.points
+    }
+
+    /// Whether every point has been removed, indexed like `points()`. Pass it as the mask of
+    /// the masked queries of `internal`.
+    #[inline(always)]
+    pub fn removed(&self) -> &[bool] {
+        &self.removed
+    }
+
+    /// The number of points that have not been removed
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.points.len() - self.removed_count
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Marks the point at `index` as removed so that queries skip it, without touching the tree.
+    /// Returns `false` if it was already removed. The indices of the other points stay valid
+    /// until `compact` is called.
+    pub fn remove(&mut self, index: usize) -> bool {
+        if std::mem::replace(&mut self.removed[index], true) {
+            return false;
+        }
+
+        self.removed_count += 1;
+        true
+    }
+
+    #[inline(always)]
+    pub fn is_removed(&self, index: usize) -> bool {
+        self.removed[index]
+    }
+
+    /// The fraction of the points in the tree that have been removed
+    #[inline(always)]
+    pub fn removed_ratio(&self) -> f32 {
+        if self.points.is_empty() {
+            0.0
+        } else {
+            self.removed_count as f32 / self.points.len() as f32
+        }
+    }
+
+    /// Drops the removed points and their values and rebuilds the tree of the remaining ones in
+    /// place, with the settings the tree was built with. The remaining points keep their order
+    /// but move to lower indices, so the returned `Vec` holds at every new index the index the
+    /// point had before. Points a `subset` tree left out stay out of it.
+    pub fn compact(&mut self) -> Vec<usize> {
+        let kept = (0..self.points.len())
+            .filter(|i| !self.removed[*i])
+            .collect::<Vec<_>>();
+
+        /*
+            NOTE: The ids are gathered from the old tree before the points move, and then
+                  renumbered to where their points end up
+        */
+        let mut point_ids = if self.internal.subset {
+            let mut new_index = vec![usize::MAX; self.points.len()];
+            for (new, old) in kept.iter().enumerate() {
+                new_index[*old] = new;
+            }
+
+            self.internal
+                .tree
+                .iter()
+                .map(|node| node.index())
+                .filter(|i| !self.removed[*i])
+                .map(|i| new_index[i])
+                .collect::<Vec<_>>()
+        } else {
+            (0..kept.len()).collect()
+        };
+
+        let mut index = 0;
+        self.points.retain(|_| {
+            index += 1;
+            !self.removed[index - 1]
+        });
+        let mut index = 0;
+        self.values.retain(|_| {
+            index += 1;
+            !self.removed[index - 1]
+        });
+
+        self.removed.clear();
+        self.removed.resize(self.points.len(), false);
+        self.removed_count = 0;
+        self.internal
+            .rebuild_point_ids(&self.points, &mut point_ids);
+
+        kept
+    }
+
+    /// Calls `compact` if more than `max_removed_ratio` of the points have been removed, and
+    /// returns what it returned. With a ratio like `0.25` the cost of rebuilding is spread over
+    /// many removals while queries never visit many removed points.
+    pub fn compact_if_above(&mut self, max_removed_ratio: f32) -> Option<Vec<usize>> {
+        if self.removed_ratio() > max_removed_ratio {
+            Some(self.compact())
+        } else {
+            None
+        }
+    }
+
+    #[inline(always)]
+    pub fn value(&self, index: usize) -> &V {
+        &self.values[index]
+    }
+
+    #[inline(always)]
+    pub fn value_mut(&mut self, index: usize) -> &mut V {
+        &mut self.values[index]
+    }
+
+    /// See `KdTree`
+    #[inline(always)]
+    pub fn point_indices_within_buffers(
+        &self,
+        query_point: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_within_filtered_buffers(
+            &self.points,
+            query_point,
+            radius,
+            |i| !self.removed[i],
+            result,
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    #[inline(always)]
+    pub fn point_indices_within(&self, query_point: P, radius: f32) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_buffers(query_point, radius, &mut result, &mut stack);
+
+        result
+    }
+
+    /// See `KdTree`
+    #[inline(always)]
+    pub fn iter_point_indices_within_buffers<'a>(
+        &'a self,
+        query_point: P,
+        radius: f32,
+        stack: &'a mut Vec<(usize, usize)>,
+    ) -> impl Iterator<Item = usize> + 'a {
+        self.internal
+            .iter_point_indices_within_buffers(&self.points, query_point, radius, stack)
+            .filter(|i| !self.removed[*i])
+    }
+
+    /// Same as `point_indices_within` but also returns the value of every point found
+    pub fn values_within(&self, query_point: P, radius: f32) -> Vec<(usize, &V)> {
+        let mut stack = vec![];
+
+        self.iter_point_indices_within_buffers(query_point, radius, &mut stack)
+            .map(|index| (index, &self.values[index]))
+            .collect()
+    }
+
+    /// Same as `point_indices_within` but returns the points found together with their values
+    pub fn points_and_values_within(&self, query_point: P, radius: f32) -> Vec<(&P, &V)> {
+        let mut stack = vec![];
+
+        self.iter_point_indices_within_buffers(query_point, radius, &mut stack)
+            .map(|index| (&self.points[index], &self.values[index]))
+            .collect()
+    }
+
+    /// See `KdTree`. Removed points still count until `compact` is called.
+    #[inline(always)]
+    pub fn bounds(&self) -> ([f32; D], [f32; D]) {
+        self.internal.bounds()
+    }
+
+    /// See `KdTree`
+    #[inline(always)]
+    pub fn nearest_one(&self, query_point: P) -> Option<(usize, f32)> {
+        let mut stack = vec![];
+
+        self.internal
+            .nearest_one_by_buffers(&self.points, query_point, |i| !self.removed[i], &mut stack)
+            .map(|(index, distance_squared)| (index, distance_squared.sqrt()))
+    }
+
+    /// See `KdTree`
+    #[inline(always)]
+    pub fn nearest_n_buffers(
+        &self,
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, usize, f32)>,
+    ) {
+        self.internal.nearest_n_filtered_buffers(
+            &self.points,
+            query_point,
+            k,
+            |i| !self.removed[i],
+            result,
+            heap,
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    #[inline(always)]
+    pub fn nearest_n(&self, query_point: P, k: usize) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.len()) + 1);
+        let mut stack = vec![];
+
+        self.nearest_n_buffers(query_point, k, &mut result, &mut heap, &mut stack);
+
+        result
+    }
+
+    /// See `KdTree`
+    #[inline(always)]
+    pub fn nearest_one_masked(&self, query_point: P, disabled: &[bool]) -> Option<(usize, f32)> {
+        let mut stack = vec![];
+
+        self.internal
+            .nearest_one_by_buffers(
+                &self.points,
+                query_point,
+                |i| !self.removed[i] && !disabled[i],
+                &mut stack,
+            )
+            .map(|(index, distance_squared)| (index, distance_squared.sqrt()))
+    }
+}
+
+/// Collects the points into the tree and builds it using default settings, so a tree can be
+/// built at the end of an iterator chain with `collect` or `KdTreeOwned::from_iter`
+impl<const D: usize, P: Point<D>> FromIterator<P> for KdTreeOwned<D, P, ()> {
+    fn from_iter<I: IntoIterator<Item = P>>(iter: I) -> Self {
+        Self::from_points(iter.into_iter().collect())
+    }
+}
+
+/// Same as collecting points, but every item is a point together with its value
+impl<const D: usize, P: Point<D>, V> FromIterator<(P, V)> for KdTreeOwned<D, P, V> {
+    fn from_iter<I: IntoIterator<Item = (P, V)>>(iter: I) -> Self {
+        let (points, values) = iter.into_iter().unzip();
+        Self::from_points_and_values(points, values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+    use crate::{KdTreeBuilder, NonFinitePolicy, SplitStrategy};
+
+    #[test]
+    fn test_owned_values() {
+        let points = vec![[0.0_f32, 0.0], [1.0, 0.0], [5.0, 5.0]];
+        let mut tree = KdTreeOwned::from_points_and_values(points, vec!["a", "b", "c"]);
+
+        let mut found = tree.values_within([0.0, 0.0], 1.5);
+        found.sort();
+        assert_eq!(found, vec![(0, &"a"), (1, &"b")]);
+
+        *tree.value_mut(2) = "d";
+        let found = tree.points_and_values_within([5.0, 5.0], 0.1);
+        assert_eq!(found, vec![(&[5.0, 5.0], &"d")]);
+
+        let plain = KdTreeOwned::from_points(vec![1.0_f32, 2.0, 3.0]);
+        let mut result = plain.point_indices_within(2.0, 1.0);
+        result.sort();
+        assert_eq!(result, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_owned_from_iter() {
+        let tree = KdTreeOwned::from_iter((0..100).map(|i| [i as f32, (i % 10) as f32]));
+        assert_eq!(tree.len(), 100);
+        assert_eq!(tree.nearest_one([41.0, 1.5]), Some((41, 0.5)));
+
+        let tree: KdTreeOwned<2, [f32; 2], usize> = (0..100)
+            .map(|i| ([i as f32, 0.0], i * 2))
+            .filter(|(_, value)| value % 3 == 0)
+            .collect();
+        let mut found = tree.values_within([30.0, 0.0], 3.5);
+        found.sort();
+        assert_eq!(
+            found
+                .into_iter()
+                .map(|(_, value)| *value)
+                .collect::<Vec<_>>(),
+            vec![54, 60, 66]
+        );
+    }
+
+    #[test]
+    fn test_owned_remove() {
+        let points = (0..200)
+            .map(|i| [(i % 20) as f32, (i / 20) as f32])
+            .collect::<Vec<_>>();
+        let mut tree = KdTreeOwned::from_points_and_values(points.clone(), (0..200).collect());
+
+        for index in (0..200).filter(|i| i % 3 == 0) {
+            assert!(tree.remove(index));
+        }
+        assert!(!tree.remove(0));
+        assert_eq!(tree.len(), 133);
+
+        let query_point = [10.0, 5.0];
+        let expected = (0..200)
+            .filter(|i| i % 3 != 0 && points[*i].distance_squared(query_point) <= 4.0)
+            .collect::<Vec<_>>();
+        let mut result = tree.point_indices_within(query_point, 2.0);
+        result.sort_unstable();
+        assert_eq!(result, expected);
+        assert_eq!(tree.values_within(query_point, 2.0).len(), expected.len());
+        assert!(tree.is_removed(111));
+        assert_eq!(tree.nearest_one(points[110]), Some((110, 0.0)));
+        assert_ne!(tree.nearest_one(points[111]).map(|(i, _)| i), Some(111));
+
+        assert_eq!(tree.compact_if_above(0.5), None);
+        let kept = tree.compact_if_above(0.25).unwrap();
+        assert_eq!(tree.len(), 133);
+        assert_eq!(tree.points.len(), 133);
+        assert_eq!(tree.removed_ratio(), 0.0);
+
+        let mut result = tree
+            .point_indices_within(query_point, 2.0)
+            .into_iter()
+            .map(|i| {
+                assert_eq!(*tree.value(i), kept[i]);
+                kept[i]
+            })
+            .collect::<Vec<_>>();
+        result.sort_unstable();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_compact_keeps_subset_and_config() {
+        let mut rng = SplitMix64::new(107);
+        let mut points = (0..200)
+            .map(|_| [(rng.next_u64() % 50) as f32, (rng.next_u64() % 50) as f32])
+            .collect::<Vec<_>>();
+        points[17][0] = f32::NAN;
+        points[150][1] = f32::INFINITY;
+
+        let mut tree = KdTreeBuilder::new()
+            .non_finite_policy(NonFinitePolicy::Exclude)
+            .split_strategy(SplitStrategy::WidestAxis)
+            .bounding_spheres(true)
+            .build_owned(points.clone());
+        assert!(tree.internal.subset);
+        assert_eq!(tree.internal.tree.len(), 198);
+
+        for index in [3, 17, 40, 41, 99, 199] {
+            tree.remove(index);
+        }
+        let kept = tree.compact();
+
+        assert!(tree.internal.subset);
+        assert_eq!(tree.internal.tree.len(), 193);
+        assert_eq!(
+            tree.internal.config.split_strategy,
+            SplitStrategy::WidestAxis
+        );
+        assert_eq!(tree.internal.bounding_spheres.len(), 193);
+        assert!(!tree.internal.needs_rebalance(tree.points()));
+
+        for query_point in tree.points().to_vec() {
+            let mut expected = (0..kept.len())
+                .filter(|i| {
+                    let point = points[kept[*i]];
+                    point.iter().all(|value| value.is_finite())
+                        && point.distance_squared(query_point) <= 36.0
+                })
+                .collect::<Vec<_>>();
+            expected.sort_unstable();
+
+            let mut result = tree.point_indices_within(query_point, 6.0);
+            result.sort_unstable();
+            assert_eq!(result, expected);
+        }
+    }
+}
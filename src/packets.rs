@@ -0,0 +1,199 @@
+//! Traversal of the tree with packets of several query points at once, analogous to ray packets
+//! in ray tracing. Spatially coherent queries visit mostly the same nodes, so sharing one traversal
+//! between them saves most of the per-node overhead, and the per-lane work is laid out so the
+//! compiler can vectorize it.
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+/// The default number of queries per packet used by the batch functions
+pub const DEFAULT_PACKET_SIZE: usize = 8;
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_within_packet_buffers<const L: usize>(
+        &self,
+        points: &[P],
+        query_points: &[P; L],
+        radius: f32,
+        results: &mut [Vec<usize>; L],
+        stack: &mut Vec<(usize, usize, u32)>,
+    ) {
+        assert!(L <= 32, "packets can hold at most 32 queries");
+        if L == 0 || self.tree.is_empty() {
+            return;
+        }
+
+        let radius_squared = radius * radius;
+        let lane_values: [[f32; D]; L] =
+            std::array::from_fn(|lane| axis_values(&query_points[lane]));
+        let all_lanes = if L == 32 { u32::MAX } else { (1 << L) - 1 };
+
+        stack.push((0, 0, all_lanes));
+        while let Some((depth, tree_index, mask)) = stack.pop() {
+            let node = &self.tree[tree_index];
+            let node_values = axis_values(&points[node.index()]);
+            let axis = node.axis();
+            let split = node.split;
+
+            /*
+                NOTE: Computed for every lane regardless of the mask so that these loops
+                      don't branch and can be vectorized
+            */
+            let mut distances_squared = [0.0_f32; L];
+            let mut axis_ds = [0.0_f32; L];
+            for lane in 0..L {
+                for (axis, value) in node_values.iter().enumerate() {
+                    let delta = value - lane_values[lane][axis];
+                    distances_squared[lane] += delta * delta;
+                }
+                axis_ds[lane] = split - lane_values[lane][axis];
+            }
+
+            let mut left_mask = 0;
+            let mut right_mask = 0;
+
+            let mut remaining = mask;
+            while remaining != 0 {
+                let lane = remaining.trailing_zeros() as usize;
+                remaining &= remaining - 1;
+
+                if distances_squared[lane] <= radius_squared {
+                    results[lane].push(node.index());
+                }
+
+                let axis_d = axis_ds[lane];
+                let within_plane = axis_d * axis_d <= radius_squared;
+                if axis_d >= 0.0 || within_plane {
+                    left_mask |= 1 << lane;
+                }
+                if axis_d < 0.0 || within_plane {
+                    right_mask |= 1 << lane;
+                }
+            }
+
+            if let (Some(child), true) = (node.child(1), right_mask != 0) {
+                stack.push((depth + 1, child, right_mask));
+            }
+            if let (Some(child), true) = (node.child(0), left_mask != 0) {
+                stack.push((depth + 1, child, left_mask));
+            }
+        }
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_batch_packets(
+        &self,
+        points: &[P],
+        query_points: &[P],
+        radius: f32,
+    ) -> Vec<Vec<usize>> {
+        let mut all_results = Vec::with_capacity(query_points.len());
+        let mut stack = vec![];
+
+        let mut chunks = query_points.chunks_exact(DEFAULT_PACKET_SIZE);
+        for chunk in chunks.by_ref() {
+            let packet: &[P; DEFAULT_PACKET_SIZE] = chunk.try_into().expect("exact chunk");
+            let mut results: [Vec<usize>; DEFAULT_PACKET_SIZE] = Default::default();
+
+            self.point_indices_within_packet_buffers(
+                points,
+                packet,
+                radius,
+                &mut results,
+                &mut stack,
+            );
+            all_results.extend(results);
+        }
+
+        for query_point in chunks.remainder() {
+            let mut result = vec![];
+            let mut single_stack = vec![];
+            self.point_indices_within_buffers(
+                points,
+                *query_point,
+                radius,
+                &mut result,
+                &mut single_stack,
+            );
+            all_results.push(result);
+        }
+
+        all_results
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Answers `point_indices_within` for all `L` query points of a packet in one traversal. Each
+    /// node is visited once for all lanes that still need it, and the indices found for
+    /// `query_points[lane]` are appended to `results[lane]`. `L` can be at most 32. Distances are
+    /// euclidean, computed from `Point::get_axis`.
+    ///
+    /// This pays off when the queries of a packet are close to each other. Unrelated queries
+    /// visit the union of their nodes, which can be slower than querying them one by one.
+    #[inline(always)]
+    pub fn point_indices_within_packet_buffers<const L: usize>(
+        &self,
+        query_points: &[P; L],
+        radius: f32,
+        results: &mut [Vec<usize>; L],
+        stack: &mut Vec<(usize, usize, u32)>,
+    ) {
+        self.internal.point_indices_within_packet_buffers(
+            self.points,
+            query_points,
+            radius,
+            results,
+            stack,
+        )
+    }
+
+    /// Answers `point_indices_within` for every query point, traversing the tree with packets
+    /// of `DEFAULT_PACKET_SIZE` consecutive queries. Sort the queries spatially, for instance with
+    /// `reorder_points_morton`, to get the most out of it.
+    #[inline(always)]
+    pub fn point_indices_within_batch_packets(
+        &self,
+        query_points: &[P],
+        radius: f32,
+    ) -> Vec<Vec<usize>> {
+        self.internal
+            .point_indices_within_batch_packets(self.points, query_points, radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packets_match_single_queries() {
+        let points: Vec<[f32; 3]> = (0..2000)
+            .map(|i| {
+                let i = i as f32;
+                [
+                    (i * 0.13).sin() * 10.0,
+                    (i * 0.07).cos() * 10.0,
+                    (i * 0.29).sin() * 3.0,
+                ]
+            })
+            .collect();
+        let queries: Vec<[f32; 3]> = (0..21)
+            .map(|i| {
+                let i = i as f32;
+                [i * 0.4 - 4.0, (i * 0.5).sin() * 4.0, 0.3]
+            })
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        let results = tree.point_indices_within_batch_packets(&queries, 1.5);
+        assert_eq!(results.len(), queries.len());
+
+        for (query, result) in queries.iter().zip(results) {
+            let mut result = result;
+            let mut expected = tree.point_indices_within(*query, 1.5);
+            result.sort();
+            expected.sort();
+            assert_eq!(result, expected);
+        }
+    }
+}
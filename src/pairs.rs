@@ -0,0 +1,208 @@
+//! Fixed-radius self-join: all pairs of points of a tree within a radius of each other
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+/// Squared distance between the closest points of the boxes `a` and `b`
+#[inline(always)]
+pub(crate) fn aabb_distance_squared<const D: usize>(
+    a: &([f32; D], [f32; D]),
+    b: &([f32; D], [f32; D]),
+) -> f32 {
+    let mut distance_squared = 0.0;
+    for axis in 0..D {
+        let gap = (a.0[axis] - b.1[axis]).max(b.0[axis] - a.1[axis]).max(0.0);
+        distance_squared += gap * gap;
+    }
+    distance_squared
+}
+
+/// Either the single point stored at a node, or the whole subtree below a node
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Item {
+    Point(usize),
+    Subtree(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Job {
+    /// All pairs within the subtree
+    Within(usize),
+    /// All pairs with one point in each item, which never overlap
+    Cross(Item, Item),
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// The axis-aligned bounding box `(min, max)` of every subtree, indexed like `tree`
+    pub(crate) fn subtree_bounds(&self, points: &[P]) -> Vec<([f32; D], [f32; D])> {
+        let mut bounds = vec![([0.0; D], [0.0; D]); self.tree.len()];
+
+        /*
+            NOTE: Children are always placed after their parent in `tree`, so iterating in
+                  reverse visits every child before its parent.
+        */
+        for tree_index in (0..self.tree.len()).rev() {
+            let node = &self.tree[tree_index];
+            let point = axis_values(&points[node.index()]);
+            let (mut min, mut max) = (point, point);
+
+            for child in node.children().iter().flatten() {
+                let (child_min, child_max) = bounds[*child];
+                for axis in 0..D {
+                    min[axis] = min[axis].min(child_min[axis]);
+                    max[axis] = max[axis].max(child_max[axis]);
+                }
+            }
+
+            bounds[tree_index] = (min, max);
+        }
+
+        bounds
+    }
+
+    /// See `KdTree`
+    pub fn for_each_pair_within<F>(&self, points: &[P], radius: f32, mut found: F)
+    where
+        F: FnMut(usize, usize, f32),
+    {
+        if self.tree.is_empty() {
+            return;
+        }
+
+        let radius_squared = radius * radius;
+        let subtree_bounds = self.subtree_bounds(points);
+        let point_bounds = |tree_index: usize| {
+            let point = axis_values(&points[self.tree[tree_index].index()]);
+            (point, point)
+        };
+        let bounds = |item: Item| match item {
+            Item::Point(tree_index) => point_bounds(tree_index),
+            Item::Subtree(tree_index) => subtree_bounds[tree_index],
+        };
+
+        let mut jobs = vec![Job::Within(0)];
+        while let Some(job) = jobs.pop() {
+            match job {
+                Job::Within(tree_index) => {
+                    /*
+                        NOTE: The pairs of a subtree are those between its point and its
+                              children, those within each child and those across the children
+                    */
+                    let children = self.tree[tree_index].children();
+                    for child in children.into_iter().flatten() {
+                        jobs.push(Job::Cross(Item::Point(tree_index), Item::Subtree(child)));
+                        jobs.push(Job::Within(child));
+                    }
+                    if let [Some(left), Some(right)] = children {
+                        jobs.push(Job::Cross(Item::Subtree(left), Item::Subtree(right)));
+                    }
+                }
+
+                Job::Cross(a, b) => {
+                    let (a_bounds, b_bounds) = (bounds(a), bounds(b));
+                    if aabb_distance_squared(&a_bounds, &b_bounds) > radius_squared {
+                        continue;
+                    }
+
+                    /*
+                        NOTE: Split the larger of the two subtrees into its point and its
+                              children
+                    */
+                    let extent = |(min, max): ([f32; D], [f32; D])| {
+                        (0..D).map(|axis| max[axis] - min[axis]).fold(0.0, f32::max)
+                    };
+                    let (split, other) = match (a, b) {
+                        (Item::Point(a), Item::Point(b)) => {
+                            let (i, j) = (self.tree[a].index(), self.tree[b].index());
+                            let distance_squared = points[i].distance_squared(points[j]);
+                            if distance_squared <= radius_squared {
+                                found(i.min(j), i.max(j), distance_squared);
+                            }
+                            continue;
+                        }
+                        (Item::Subtree(a), Item::Point(_)) => (a, b),
+                        (Item::Point(_), Item::Subtree(b)) => (b, a),
+                        (Item::Subtree(a_index), Item::Subtree(b_index)) => {
+                            if extent(a_bounds) >= extent(b_bounds) {
+                                (a_index, b)
+                            } else {
+                                (b_index, a)
+                            }
+                        }
+                    };
+
+                    jobs.push(Job::Cross(Item::Point(split), other));
+                    for child in self.tree[split].children().into_iter().flatten() {
+                        jobs.push(Job::Cross(Item::Subtree(child), other));
+                    }
+                }
+            }
+        }
+    }
+
+    /// See `KdTree`
+    pub fn all_pairs_within(&self, points: &[P], radius: f32) -> Vec<(usize, usize)> {
+        let mut pairs = vec![];
+        self.for_each_pair_within(points, radius, |i, j, _| pairs.push((i, j)));
+        pairs
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Calls `found(i, j, distance_squared)` once for every pair of points within `radius` of
+    /// each other, with `i < j`. See `all_pairs_within`.
+    #[inline(always)]
+    pub fn for_each_pair_within<F>(&self, radius: f32, found: F)
+    where
+        F: FnMut(usize, usize, f32),
+    {
+        self.internal
+            .for_each_pair_within(self.points, radius, found)
+    }
+
+    /// Returns every pair `(i, j)` of points within `radius` of each other, once, with `i < j`.
+    /// The order of the pairs is unspecified.
+    ///
+    /// Instead of one radius query per point, which finds every pair twice, this traverses
+    /// pairs of subtrees at once and skips a pair of subtrees as soon as their bounding boxes
+    /// are further than `radius` apart.
+    #[inline(always)]
+    pub fn all_pairs_within(&self, radius: f32) -> Vec<(usize, usize)> {
+        self.internal.all_pairs_within(self.points, radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_all_pairs_within() {
+        let mut rng = SplitMix64::new(3);
+        let points = (0..800)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 50) as f32,
+                    (rng.next_u64() % 50) as f32,
+                    (rng.next_u64() % 50) as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        for radius in [0.0, 2.0, 7.5] {
+            let mut expected = vec![];
+            for i in 0..points.len() {
+                for j in i + 1..points.len() {
+                    if points[i].distance_squared(points[j]) <= radius * radius {
+                        expected.push((i, j));
+                    }
+                }
+            }
+
+            let mut pairs = tree.all_pairs_within(radius);
+            pairs.sort_unstable();
+            assert_eq!(pairs, expected);
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Batches of queries answered in parallel on the rayon thread pool
+use crate::{KdTree, KdTreeNoBorrow, Point};
+
+use rayon::prelude::*;
+use std::collections::BinaryHeap;
+
+impl<const D: usize, P: Point<D> + Sync> KdTreeNoBorrow<D, P> {
+    /// See `KdTree`
+    pub fn par_point_indices_within_batch(
+        &self,
+        points: &[P],
+        query_points: &[P],
+        radius: f32,
+    ) -> Vec<Vec<usize>> {
+        query_points
+            .par_iter()
+            .map_init(Vec::new, |stack, query_point| {
+                let mut result = vec![];
+                self.point_indices_within_buffers(points, *query_point, radius, &mut result, stack);
+                result
+            })
+            .collect()
+    }
+
+    /// See `KdTree`
+    pub fn par_nearest_n_batch(
+        &self,
+        points: &[P],
+        query_points: &[P],
+        k: usize,
+    ) -> Vec<Vec<usize>> {
+        query_points
+            .par_iter()
+            .map_init(
+                || (BinaryHeap::new(), vec![]),
+                |(heap, stack), query_point| {
+                    let mut result = Vec::with_capacity(k.min(self.tree.len()));
+                    self.nearest_n_buffers(points, *query_point, k, &mut result, heap, stack);
+                    result
+                },
+            )
+            .collect()
+    }
+}
+
+impl<'a, const D: usize, P: Point<D> + Sync> KdTree<'a, D, P> {
+    /// Same as `point_indices_within_batch`, but the queries are split across the threads of
+    /// the current rayon thread pool. Every thread reuses its own stack between queries.
+    /// `result[i]` belongs to `query_points[i]`.
+    #[inline(always)]
+    pub fn par_point_indices_within_batch(
+        &self,
+        query_points: &[P],
+        radius: f32,
+    ) -> Vec<Vec<usize>> {
+        self.internal
+            .par_point_indices_within_batch(self.points, query_points, radius)
+    }
+
+    /// Answers `nearest_n` for every query point, split across the threads of the current rayon
+    /// thread pool. Every thread reuses its own heap and stack between queries. `result[i]`
+    /// belongs to `query_points[i]`.
+    #[inline(always)]
+    pub fn par_nearest_n_batch(&self, query_points: &[P], k: usize) -> Vec<Vec<usize>> {
+        self.internal
+            .par_nearest_n_batch(self.points, query_points, k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parallel_batches_match_single_queries() {
+        let points: Vec<[f32; 3]> = (0..2000)
+            .map(|i| {
+                let i = i as f32;
+                [
+                    (i * 0.13).sin() * 10.0,
+                    (i * 0.07).cos() * 10.0,
+                    (i * 0.29).sin(),
+                ]
+            })
+            .collect();
+        let queries: Vec<[f32; 3]> = (0..100)
+            .map(|i| [i as f32 * 0.2 - 10.0, (i as f32).sin() * 5.0, 0.0])
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        let within = tree.par_point_indices_within_batch(&queries, 1.5);
+        let nearest = tree.par_nearest_n_batch(&queries, 8);
+
+        for (i, query) in queries.iter().enumerate() {
+            assert_eq!(within[i], tree.point_indices_within(*query, 1.5));
+            assert_eq!(nearest[i], tree.nearest_n(*query, 8));
+        }
+    }
+}
@@ -2,9 +2,20 @@
 //! Also check the features tab to see if we provide specific implementations for your favorite linear algebra crate.
 //! Currently, we have special implementations for:
 //!     - `glam`
-pub trait Point<const D: usize>: Copy + std::fmt::Debug {
+//!     - `nalgebra`
+//!     - `cgmath`
+//!     - `mint`
+//!     - `vek`
+pub trait Point<const D: usize>: Copy + core::fmt::Debug {
     fn get_axis(&self, d: usize) -> f32;
 
+    /// The value of axis `d` in double precision. Defaults to widening `get_axis`, but types
+    /// storing more precise coordinates should return them here.
+    #[inline(always)]
+    fn get_axis_f64(&self, d: usize) -> f64 {
+        self.get_axis(d) as f64
+    }
+
     #[inline(always)]
     fn distance_squared(self, b: Self) -> f32 {
         (0..D)
@@ -12,7 +23,38 @@ pub trait Point<const D: usize>: Copy + std::fmt::Debug {
                 let delta = self.get_axis(d) - b.get_axis(d);
                 delta * delta
             })
-            .sum::<f32>() as f32
+            .sum::<f32>()
+    }
+
+    /// Same as `distance_squared` but computed in double precision using `get_axis_f64`
+    #[inline(always)]
+    fn distance_squared_f64(self, b: Self) -> f64 {
+        (0..D)
+            .map(|d| {
+                let delta = self.get_axis_f64(d) - b.get_axis_f64(d);
+                delta * delta
+            })
+            .sum::<f64>()
+    }
+}
+
+/// A point with integer coordinates, for `KdTreeInteger` which compares them without rounding.
+/// Coordinates are widened to `i64` by `as`, so `u64` and `usize` values above `i64::MAX`
+/// wrap around.
+pub trait IntegerPoint<const D: usize>: Point<D> {
+    fn get_axis_i64(&self, d: usize) -> i64;
+
+    /// The exact squared distance. The square of any difference of `i64`s fits in a `u128`, and
+    /// the sum only saturates at `u128::MAX` when several axes differ by more than `2^63`.
+    #[inline(always)]
+    fn distance_squared_u128(self, b: Self) -> u128 {
+        (0..D)
+            .map(|d| {
+                let delta =
+                    (self.get_axis_i64(d) as i128 - b.get_axis_i64(d) as i128).unsigned_abs();
+                delta * delta
+            })
+            .fold(0, u128::saturating_add)
     }
 }
 
@@ -23,23 +65,41 @@ macro_rules! impl_point_value {
             fn get_axis(&self, _d: usize) -> f32 {
                 *self as _
             }
+
+            #[inline(always)]
+            fn get_axis_f64(&self, _d: usize) -> f64 {
+                *self as _
+            }
         }
     };
 }
 impl_point_value!(f32);
 impl_point_value!(f64);
-impl_point_value!(u8);
-impl_point_value!(u16);
-impl_point_value!(u32);
-impl_point_value!(u64);
 impl_point_value!(u128);
-impl_point_value!(usize);
-impl_point_value!(i8);
-impl_point_value!(i16);
-impl_point_value!(i32);
-impl_point_value!(i64);
 impl_point_value!(i128);
-impl_point_value!(isize);
+
+macro_rules! impl_point_value_integer {
+    ($t: ty) => {
+        impl_point_value!($t);
+
+        impl IntegerPoint<1> for $t {
+            #[inline(always)]
+            fn get_axis_i64(&self, _d: usize) -> i64 {
+                *self as _
+            }
+        }
+    };
+}
+impl_point_value_integer!(u8);
+impl_point_value_integer!(u16);
+impl_point_value_integer!(u32);
+impl_point_value_integer!(u64);
+impl_point_value_integer!(usize);
+impl_point_value_integer!(i8);
+impl_point_value_integer!(i16);
+impl_point_value_integer!(i32);
+impl_point_value_integer!(i64);
+impl_point_value_integer!(isize);
 
 macro_rules! impl_point_array {
     ($t: ty, $n: literal) => {
@@ -48,6 +108,11 @@ macro_rules! impl_point_array {
             fn get_axis(&self, d: usize) -> f32 {
                 self[d] as _
             }
+
+            #[inline(always)]
+            fn get_axis_f64(&self, d: usize) -> f64 {
+                self[d] as _
+            }
         }
     };
 }
@@ -60,6 +125,28 @@ impl_point_array!(f64, 2);
 impl_point_array!(f64, 3);
 impl_point_array!(f64, 4);
 
+macro_rules! impl_point_array_integer {
+    ($($t: ty),*) => {
+        $(
+            impl_point_array!($t, 1);
+            impl_point_array!($t, 2);
+            impl_point_array!($t, 3);
+            impl_point_array!($t, 4);
+
+            impl<const D: usize> IntegerPoint<D> for [$t; D]
+            where
+                [$t; D]: Point<D>,
+            {
+                #[inline(always)]
+                fn get_axis_i64(&self, d: usize) -> i64 {
+                    self[d] as _
+                }
+            }
+        )*
+    };
+}
+impl_point_array_integer!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
 macro_rules! impl_point_tuple_2 {
     ($t: ty) => {
         impl Point<2> for ($t, $t) {
@@ -71,6 +158,15 @@ macro_rules! impl_point_tuple_2 {
                     _ => unreachable!(),
                 }
             }
+
+            #[inline(always)]
+            fn get_axis_f64(&self, d: usize) -> f64 {
+                match d {
+                    0 => self.0 as _,
+                    1 => self.1 as _,
+                    _ => unreachable!(),
+                }
+            }
         }
     };
 }
@@ -89,6 +185,16 @@ macro_rules! impl_point_tuple_3 {
                     _ => unreachable!(),
                 }
             }
+
+            #[inline(always)]
+            fn get_axis_f64(&self, d: usize) -> f64 {
+                match d {
+                    0 => self.0 as _,
+                    1 => self.1 as _,
+                    2 => self.2 as _,
+                    _ => unreachable!(),
+                }
+            }
         }
     };
 }
@@ -108,14 +214,23 @@ macro_rules! impl_point_tuple_4 {
                     _ => unreachable!(),
                 }
             }
+
+            #[inline(always)]
+            fn get_axis_f64(&self, d: usize) -> f64 {
+                match d {
+                    0 => self.0 as _,
+                    1 => self.1 as _,
+                    2 => self.2 as _,
+                    3 => self.3 as _,
+                    _ => unreachable!(),
+                }
+            }
         }
     };
 }
 impl_point_tuple_4!(f32);
 impl_point_tuple_4!(f64);
 
-#[cfg(feature = "glam")]
-pub use glam_implementations::*;
 #[cfg(feature = "glam")]
 pub mod glam_implementations {
     use super::*;
@@ -189,7 +304,6 @@ pub mod glam_implementations {
 
     #[cfg(test)]
     mod glam_tests {
-        use super::*;
         use crate::KdTree;
 
         use glam::{vec2, vec3a};
@@ -237,3 +351,378 @@ pub mod glam_implementations {
         }
     }
 }
+
+#[cfg(feature = "nalgebra")]
+pub mod nalgebra_implementations {
+    use super::*;
+
+    macro_rules! impl_point_nalgebra_f32 {
+        ($t: ty, $n: literal) => {
+            impl Point<$n> for $t {
+                #[inline(always)]
+                fn distance_squared(self, b: Self) -> f32 {
+                    (self - b).norm_squared()
+                }
+
+                #[inline(always)]
+                fn get_axis(&self, d: usize) -> f32 {
+                    self[d]
+                }
+            }
+        };
+    }
+    impl_point_nalgebra_f32!(nalgebra::Vector2<f32>, 2);
+    impl_point_nalgebra_f32!(nalgebra::Vector3<f32>, 3);
+    impl_point_nalgebra_f32!(nalgebra::Vector4<f32>, 4);
+    impl_point_nalgebra_f32!(nalgebra::Point2<f32>, 2);
+    impl_point_nalgebra_f32!(nalgebra::Point3<f32>, 3);
+    impl_point_nalgebra_f32!(nalgebra::Point4<f32>, 4);
+
+    /*
+        NOTE: The f64 types keep their precision in `get_axis_f64`, which `KdTreePrecise` uses
+    */
+    macro_rules! impl_point_nalgebra_f64 {
+        ($t: ty, $n: literal) => {
+            impl Point<$n> for $t {
+                #[inline(always)]
+                fn get_axis(&self, d: usize) -> f32 {
+                    self[d] as _
+                }
+
+                #[inline(always)]
+                fn get_axis_f64(&self, d: usize) -> f64 {
+                    self[d]
+                }
+
+                #[inline(always)]
+                fn distance_squared_f64(self, b: Self) -> f64 {
+                    (self - b).norm_squared()
+                }
+            }
+        };
+    }
+    impl_point_nalgebra_f64!(nalgebra::Vector2<f64>, 2);
+    impl_point_nalgebra_f64!(nalgebra::Vector3<f64>, 3);
+    impl_point_nalgebra_f64!(nalgebra::Vector4<f64>, 4);
+    impl_point_nalgebra_f64!(nalgebra::Point2<f64>, 2);
+    impl_point_nalgebra_f64!(nalgebra::Point3<f64>, 3);
+    impl_point_nalgebra_f64!(nalgebra::Point4<f64>, 4);
+
+    #[cfg(test)]
+    mod nalgebra_tests {
+        use crate::{KdTree, KdTreePrecise, Point};
+
+        use nalgebra::{point, vector};
+
+        #[test]
+        fn test_nalgebra() {
+            let points = (0..9)
+                .map(|i| point![i as f32, 0.0, (i % 3) as f32])
+                .collect::<Vec<_>>();
+            let tree = KdTree::from_points(&points);
+
+            let mut result = tree.point_indices_within(point![0.0, 0.0, 0.0], 2.0);
+            result.sort_unstable();
+            assert_eq!(result, vec![0, 1]);
+            assert_eq!(
+                points[2].distance_squared(points[4]),
+                [2.0_f32, 0.0, 2.0].distance_squared([4.0, 0.0, 1.0])
+            );
+
+            let points = (0..9)
+                .map(|i| vector![1e7 + i as f64 * 0.01, 2.0])
+                .collect::<Vec<_>>();
+            let tree = KdTreePrecise::from_points(&points);
+            assert_eq!(
+                tree.point_indices_within(&points, vector![1e7 + 0.04, 2.0], 0.005),
+                vec![4]
+            );
+        }
+    }
+}
+
+#[cfg(feature = "cgmath")]
+pub mod cgmath_implementations {
+    use super::*;
+
+    use cgmath::MetricSpace;
+
+    macro_rules! impl_point_cgmath_2 {
+        ($t: ty) => {
+            impl Point<2> for $t {
+                #[inline(always)]
+                fn distance_squared(self, b: Self) -> f32 {
+                    self.distance2(b)
+                }
+
+                #[inline(always)]
+                fn get_axis(&self, d: usize) -> f32 {
+                    match d {
+                        0 => self.x,
+                        1 => self.y,
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        };
+    }
+    impl_point_cgmath_2!(cgmath::Point2<f32>);
+    impl_point_cgmath_2!(cgmath::Vector2<f32>);
+
+    macro_rules! impl_point_cgmath_3 {
+        ($t: ty) => {
+            impl Point<3> for $t {
+                #[inline(always)]
+                fn distance_squared(self, b: Self) -> f32 {
+                    self.distance2(b)
+                }
+
+                #[inline(always)]
+                fn get_axis(&self, d: usize) -> f32 {
+                    match d {
+                        0 => self.x,
+                        1 => self.y,
+                        2 => self.z,
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        };
+    }
+    impl_point_cgmath_3!(cgmath::Point3<f32>);
+    impl_point_cgmath_3!(cgmath::Vector3<f32>);
+
+    macro_rules! impl_point_cgmath_4 {
+        ($t: ty) => {
+            impl Point<4> for $t {
+                #[inline(always)]
+                fn distance_squared(self, b: Self) -> f32 {
+                    self.distance2(b)
+                }
+
+                #[inline(always)]
+                fn get_axis(&self, d: usize) -> f32 {
+                    match d {
+                        0 => self.x,
+                        1 => self.y,
+                        2 => self.z,
+                        3 => self.w,
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        };
+    }
+    impl_point_cgmath_4!(cgmath::Vector4<f32>);
+
+    #[cfg(test)]
+    mod cgmath_tests {
+        use crate::{KdTree, Point};
+
+        use cgmath::{point3, vec2, vec4};
+
+        #[test]
+        fn test_cgmath() {
+            let points = (0..9)
+                .map(|i| point3(i as f32, 0.0, (i % 3) as f32))
+                .collect::<Vec<_>>();
+            let tree = KdTree::from_points(&points);
+
+            let mut result = tree.point_indices_within(point3(0.0, 0.0, 0.0), 2.0);
+            result.sort_unstable();
+            assert_eq!(result, vec![0, 1]);
+
+            let points = (0..9).map(|i| vec2(i as f32, 1.0)).collect::<Vec<_>>();
+            let tree = KdTree::from_points(&points);
+            assert_eq!(tree.nearest_n(vec2(6.2, 0.0), 2), vec![6, 7]);
+
+            assert_eq!(
+                vec4(1.0_f32, 2.0, 3.0, 4.0).distance_squared(vec4(0.0, 0.0, 0.0, 0.0)),
+                30.0
+            );
+        }
+    }
+}
+
+#[cfg(feature = "mint")]
+pub mod mint_implementations {
+    use super::*;
+
+    macro_rules! impl_point_mint_2 {
+        ($t: ty) => {
+            impl Point<2> for $t {
+                #[inline(always)]
+                fn get_axis(&self, d: usize) -> f32 {
+                    match d {
+                        0 => self.x,
+                        1 => self.y,
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        };
+    }
+    impl_point_mint_2!(mint::Point2<f32>);
+    impl_point_mint_2!(mint::Vector2<f32>);
+
+    macro_rules! impl_point_mint_3 {
+        ($t: ty) => {
+            impl Point<3> for $t {
+                #[inline(always)]
+                fn get_axis(&self, d: usize) -> f32 {
+                    match d {
+                        0 => self.x,
+                        1 => self.y,
+                        2 => self.z,
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        };
+    }
+    impl_point_mint_3!(mint::Point3<f32>);
+    impl_point_mint_3!(mint::Vector3<f32>);
+
+    macro_rules! impl_point_mint_4 {
+        ($t: ty) => {
+            impl Point<4> for $t {
+                #[inline(always)]
+                fn get_axis(&self, d: usize) -> f32 {
+                    match d {
+                        0 => self.x,
+                        1 => self.y,
+                        2 => self.z,
+                        3 => self.w,
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        };
+    }
+    impl_point_mint_4!(mint::Vector4<f32>);
+
+    #[cfg(test)]
+    mod mint_tests {
+        use crate::KdTree;
+
+        #[test]
+        fn test_mint() {
+            let points = (0..9)
+                .map(|i| mint::Point3::from([i as f32, 0.0, (i % 3) as f32]))
+                .collect::<Vec<_>>();
+            let tree = KdTree::from_points(&points);
+
+            let mut result = tree.point_indices_within(mint::Point3::from([0.0, 0.0, 0.0]), 2.0);
+            result.sort_unstable();
+            assert_eq!(result, vec![0, 1]);
+
+            let points = (0..9)
+                .map(|i| mint::Vector2::from([i as f32, 1.0]))
+                .collect::<Vec<_>>();
+            let tree = KdTree::from_points(&points);
+            assert_eq!(
+                tree.nearest_n(mint::Vector2::from([6.2, 0.0]), 2),
+                vec![6, 7]
+            );
+        }
+    }
+}
+
+#[cfg(feature = "vek")]
+pub mod vek_implementations {
+    use super::*;
+
+    macro_rules! impl_point_vek_2 {
+        ($t: ty) => {
+            impl Point<2> for $t {
+                #[inline(always)]
+                fn distance_squared(self, b: Self) -> f32 {
+                    self.distance_squared(b)
+                }
+
+                #[inline(always)]
+                fn get_axis(&self, d: usize) -> f32 {
+                    match d {
+                        0 => self.x,
+                        1 => self.y,
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        };
+    }
+    impl_point_vek_2!(vek::Vec2<f32>);
+
+    macro_rules! impl_point_vek_3 {
+        ($t: ty) => {
+            impl Point<3> for $t {
+                #[inline(always)]
+                fn distance_squared(self, b: Self) -> f32 {
+                    self.distance_squared(b)
+                }
+
+                #[inline(always)]
+                fn get_axis(&self, d: usize) -> f32 {
+                    match d {
+                        0 => self.x,
+                        1 => self.y,
+                        2 => self.z,
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        };
+    }
+    impl_point_vek_3!(vek::Vec3<f32>);
+
+    macro_rules! impl_point_vek_4 {
+        ($t: ty) => {
+            impl Point<4> for $t {
+                #[inline(always)]
+                fn distance_squared(self, b: Self) -> f32 {
+                    self.distance_squared(b)
+                }
+
+                #[inline(always)]
+                fn get_axis(&self, d: usize) -> f32 {
+                    match d {
+                        0 => self.x,
+                        1 => self.y,
+                        2 => self.z,
+                        3 => self.w,
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        };
+    }
+    impl_point_vek_4!(vek::Vec4<f32>);
+
+    #[cfg(test)]
+    mod vek_tests {
+        use crate::{KdTree, Point};
+
+        use vek::{Vec2, Vec3, Vec4};
+
+        #[test]
+        fn test_vek() {
+            let points = (0..9)
+                .map(|i| Vec3::new(i as f32, 0.0, (i % 3) as f32))
+                .collect::<Vec<_>>();
+            let tree = KdTree::from_points(&points);
+
+            let mut result = tree.point_indices_within(Vec3::zero(), 2.0);
+            result.sort_unstable();
+            assert_eq!(result, vec![0, 1]);
+
+            let points = (0..9).map(|i| Vec2::new(i as f32, 1.0)).collect::<Vec<_>>();
+            let tree = KdTree::from_points(&points);
+            assert_eq!(tree.nearest_n(Vec2::new(6.2, 0.0), 2), vec![6, 7]);
+
+            assert_eq!(
+                Point::distance_squared(Vec4::new(1.0_f32, 2.0, 3.0, 4.0), Vec4::zero()),
+                30.0
+            );
+        }
+    }
+}
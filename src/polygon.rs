@@ -0,0 +1,155 @@
+//! Queries with polygons in the plane
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+/// Whether `point` is inside `polygon` by the even-odd rule, counting the edges crossed by a
+/// ray from the point along the x axis
+fn polygon_contains<P: Point<2>>(polygon: &[P], point: &[f32; 2]) -> bool {
+    let [x, y] = *point;
+
+    let mut inside = false;
+    let mut previous = match polygon.last() {
+        Some(last) => [last.get_axis(0), last.get_axis(1)],
+        None => return false,
+    };
+    for vertex in polygon {
+        let current = [vertex.get_axis(0), vertex.get_axis(1)];
+
+        /*
+            NOTE: Edges are half-open along y so that a ray through a vertex crosses exactly
+                  one of the two edges meeting there
+        */
+        if (current[1] > y) != (previous[1] > y) {
+            let t = (y - current[1]) / (previous[1] - current[1]);
+            let crossing_x = current[0] + t * (previous[0] - current[0]);
+            if x < crossing_x {
+                inside = !inside;
+            }
+        }
+
+        previous = current;
+    }
+
+    inside
+}
+
+impl<P: Point<2>, N: NodeStorage> KdTreeNoBorrow<2, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_in_polygon_buffers(
+        &self,
+        points: &[P],
+        polygon: &[P],
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        if polygon.is_empty() {
+            return;
+        }
+
+        let mut min = [f32::INFINITY; 2];
+        let mut max = [f32::NEG_INFINITY; 2];
+        for vertex in polygon {
+            for axis in 0..2 {
+                min[axis] = min[axis].min(vertex.get_axis(axis));
+                max[axis] = max[axis].max(vertex.get_axis(axis));
+            }
+        }
+
+        self.for_each_point_in_aabb_buffers(
+            points,
+            &min,
+            &max,
+            |point_index, point| {
+                if polygon_contains(polygon, point) {
+                    result.push(point_index);
+                }
+            },
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_in_polygon(&self, points: &[P], polygon: &[P]) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_in_polygon_buffers(points, polygon, &mut result, &mut stack);
+
+        result
+    }
+}
+
+impl<'a, P: Point<2>> KdTree<'a, 2, P> {
+    /// Same as `point_indices_in_polygon`, but you provide your own buffers. Indices are
+    /// appended to `result` which is not cleared. `stack` is assumed to be empty and is left
+    /// empty.
+    #[inline(always)]
+    pub fn point_indices_in_polygon_buffers(
+        &self,
+        polygon: &[P],
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal
+            .point_indices_in_polygon_buffers(self.points, polygon, result, stack)
+    }
+
+    /// Returns the indices of the points inside `polygon`, given by its vertices in order and
+    /// implicitly closed, for instance to select the points inside an administrative boundary.
+    /// The polygon doesn't need to be convex and may intersect itself, in which case the
+    /// even-odd rule decides what is inside. Points exactly on an edge may or may not be
+    /// included.
+    ///
+    /// The tree is pruned with the bounding box of the polygon and the points inside of that
+    /// are tested against every edge.
+    #[inline(always)]
+    pub fn point_indices_in_polygon(&self, polygon: &[P]) -> Vec<usize> {
+        self.internal.point_indices_in_polygon(self.points, polygon)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_point_indices_in_polygon() {
+        let mut rng = SplitMix64::new(47);
+        let points = (0..3000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 1000) as f32 / 10.0 + 0.05,
+                    (rng.next_u64() % 1000) as f32 / 10.0 + 0.05,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        /*
+            NOTE: An L shape, the union of two rectangles
+        */
+        let polygon = [
+            [10.0, 10.0],
+            [80.0, 10.0],
+            [80.0, 30.0],
+            [30.0, 30.0],
+            [30.0, 90.0],
+            [10.0, 90.0],
+        ];
+        let expected = (0..points.len())
+            .filter(|i| {
+                let [x, y] = points[*i];
+                let bottom = (10.0..80.0).contains(&x) && (10.0..30.0).contains(&y);
+                let left = (10.0..30.0).contains(&x) && (10.0..90.0).contains(&y);
+                bottom || left
+            })
+            .collect::<Vec<_>>();
+        assert!(!expected.is_empty());
+
+        let mut result = tree.point_indices_in_polygon(&polygon);
+        result.sort_unstable();
+        assert_eq!(result, expected);
+
+        assert!(tree.point_indices_in_polygon(&[]).is_empty());
+    }
+}
@@ -0,0 +1,171 @@
+//! A Kd-tree that sorts, prunes and measures distances in double precision
+use crate::eytzinger::{build, collect_nearest, for_each_within, nearest_n, ArrayStack, MAX_STACK};
+use crate::{Neighbor, Point};
+
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone)]
+/// A Kd-tree that only ever reads coordinates through `Point::get_axis_f64` and
+/// `Point::distance_squared_f64`. The other trees sort, prune and compare in `f32`, so `f64`
+/// points that are closer together than an `f32` ulp of their magnitude, such as GIS
+/// coordinates in meters, end up in the wrong order and distances to them are rounded. This
+/// tree orders and measures them in double precision, at the cost of slower arithmetic.
+///
+/// It uses the same layout as `KdTreeEytzinger`, and the points are passed to every query.
+pub struct KdTreePrecise<const D: usize, P: Point<D>> {
+    /// The index of the point at every node
+    pub indices: Vec<usize>,
+    pub __marker: std::marker::PhantomData<P>,
+}
+
+impl<const D: usize, P: Point<D>> KdTreePrecise<D, P> {
+    /// Builds a tree of `points`, ordering them by their `f64` coordinates
+    pub fn from_points(points: &[P]) -> Self {
+        let mut indices = vec![0; points.len()];
+        let mut point_ids = (0..points.len()).collect::<Vec<_>>();
+        build::<D, f64, P>(points, &mut point_ids, &mut indices);
+
+        Self {
+            indices,
+            __marker: std::marker::PhantomData,
+        }
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Same as `point_indices_within`, but you provide your own buffers. Indices are appended to
+    /// `result` which is not cleared. `stack` is assumed to be empty and is left empty.
+    pub fn point_indices_within_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f64,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        for_each_within::<D, f64, P, _>(
+            &self.indices,
+            points,
+            query_point,
+            radius * radius,
+            stack,
+            |index| result.push(index),
+        );
+    }
+
+    /// Returns the indices of the points within `radius` of `query_point`. A point is returned
+    /// exactly when `query_point.distance_squared_f64(point) <= radius * radius`.
+    pub fn point_indices_within(&self, points: &[P], query_point: P, radius: f64) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_buffers(points, query_point, radius, &mut result, &mut stack);
+
+        result
+    }
+
+    /// Same as `nearest_n`, but you provide your own buffers. Indices are appended to `result`
+    /// which is not cleared. `heap` and `stack` are assumed to be empty and are left empty.
+    pub fn nearest_n_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor<f64>>,
+        stack: &mut Vec<(usize, usize, f64)>,
+    ) {
+        nearest_n::<D, f64, P>(&self.indices, points, query_point, k, result, heap, stack);
+    }
+
+    /// Returns the indices of the `k` points closest to `query_point`, nearest first. Points at
+    /// the same distance are ordered by index.
+    pub fn nearest_n(&self, points: &[P], query_point: P, k: usize) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.indices.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.indices.len()) + 1);
+        let mut stack = vec![];
+
+        self.nearest_n_buffers(points, query_point, k, &mut result, &mut heap, &mut stack);
+
+        result
+    }
+
+    /// Returns the index of and distance to the point closest to `query_point`
+    pub fn nearest_one(&self, points: &[P], query_point: P) -> Option<(usize, f64)> {
+        let mut nearest = None;
+        collect_nearest::<D, f64, P, _>(
+            &self.indices,
+            points,
+            query_point,
+            &mut nearest,
+            &mut ArrayStack::<_, MAX_STACK>::new(),
+        );
+
+        nearest.map(|n: Neighbor<f64>| (n.index, n.distance_squared.sqrt()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::KdTree;
+
+    #[test]
+    fn test_precise() {
+        /*
+            NOTE: Centimeters apart around a million meters, where an f32 ulp is 6.25cm
+        */
+        let points = (0..100)
+            .map(|i| {
+                [
+                    1_000_000.0 + (i % 10) as f64 * 0.01,
+                    2_000_000.0 + (i / 10) as f64 * 0.01,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let query_point = [1_000_000.05, 2_000_000.05];
+
+        let tree = KdTreePrecise::from_points(&points);
+        assert_eq!(
+            tree.point_indices_within(&points, query_point, 0.001),
+            vec![55]
+        );
+        assert_eq!(
+            tree.nearest_one(&points, query_point).map(|n| n.0),
+            Some(55)
+        );
+        assert!(
+            KdTree::from_points(&points)
+                .point_indices_within(query_point, 0.001)
+                .len()
+                > 1
+        );
+
+        let points = fixture::points(89);
+        let tree = KdTreePrecise::from_points(&points);
+        fixture::check_queries(
+            &points,
+            |_| true,
+            |query_point, radius| tree.point_indices_within(&points, query_point, radius as f64),
+            |query_point, k| tree.nearest_n(&points, query_point, k),
+            |query_point| {
+                tree.nearest_one(&points, query_point)
+                    .map(|(index, distance)| (index, distance as f32))
+            },
+        );
+
+        let tree = KdTreePrecise::<2, [f64; 2]>::from_points(&[]);
+        assert!(tree.is_empty());
+        assert!(tree.nearest_n(&[], [0.0, 0.0], 3).is_empty());
+        assert_eq!(tree.nearest_one(&[], [0.0, 0.0]), None);
+    }
+}
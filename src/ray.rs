@@ -0,0 +1,351 @@
+//! Queries with rays and line segments, for picking, hitscan and capsule tests
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+/// The squared distance from `point` to the part of the line `origin + t * direction` with
+/// `t` in `0..=t_end`
+#[inline(always)]
+pub(crate) fn distance_squared_to_ray<const D: usize>(
+    point: &[f32; D],
+    origin: &[f32; D],
+    direction: &[f32; D],
+    t_end: f32,
+) -> f32 {
+    let mut along = 0.0;
+    let mut length_squared = 0.0;
+    for axis in 0..D {
+        along += (point[axis] - origin[axis]) * direction[axis];
+        length_squared += direction[axis] * direction[axis];
+    }
+
+    let t = if length_squared > 0.0 {
+        (along / length_squared).clamp(0.0, t_end)
+    } else {
+        0.0
+    };
+
+    (0..D)
+        .map(|axis| {
+            let delta = point[axis] - (origin[axis] + direction[axis] * t);
+            delta * delta
+        })
+        .sum()
+}
+
+/// Clips the ray parameter interval `t_min..=t_max` to where `origin + t * direction` is on the
+/// `keep_below` side of `plane` along one axis. Returns `None` if nothing is left.
+#[inline(always)]
+fn clip_to_plane(
+    (t_min, t_max): (f32, f32),
+    origin: f32,
+    direction: f32,
+    plane: f32,
+    keep_below: bool,
+) -> Option<(f32, f32)> {
+    if direction == 0.0 {
+        let inside = if keep_below {
+            origin <= plane
+        } else {
+            origin >= plane
+        };
+        return inside.then_some((t_min, t_max));
+    }
+
+    let t = (plane - origin) / direction;
+    let (t_min, t_max) = if keep_below == (direction > 0.0) {
+        (t_min, t_max.min(t))
+    } else {
+        (t_min.max(t), t_max)
+    };
+
+    (t_min <= t_max).then_some((t_min, t_max))
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// Collects the points within `radius` of `origin + t * direction` for any `t` in
+    /// `0..=t_end`, which is a ray when `t_end` is infinite and a segment otherwise
+    #[allow(clippy::too_many_arguments)]
+    fn point_indices_within_of_ray_segment_buffers(
+        &self,
+        points: &[P],
+        origin: &[f32; D],
+        direction: &[f32; D],
+        t_end: f32,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize, f32, f32)>,
+    ) {
+        if self.tree.is_empty() {
+            return;
+        }
+
+        let radius_squared = radius * radius;
+
+        stack.push((0, 0, 0.0, t_end));
+        while let Some((depth, tree_index, t_min, t_max)) = stack.pop() {
+            let point_index = self.tree[tree_index].index();
+            let point = axis_values(&points[point_index]);
+
+            if distance_squared_to_ray(&point, origin, direction, t_end) <= radius_squared {
+                result.push(point_index);
+            }
+
+            /*
+                NOTE: `t_min..=t_max` is the part of the ray within `radius` of the region of
+                      the subtree along every axis split so far. Each side of the split keeps
+                      the part of it within `radius` of that side.
+            */
+            let axis = self.tree[tree_index].axis();
+            let split = self.tree[tree_index].split;
+
+            let [left, right] = self.tree[tree_index].children();
+            if let Some(child) = right {
+                let clipped = clip_to_plane(
+                    (t_min, t_max),
+                    origin[axis],
+                    direction[axis],
+                    split - radius,
+                    false,
+                );
+                if let Some((t_min, t_max)) = clipped {
+                    stack.push((depth + 1, child, t_min, t_max));
+                }
+            }
+            if let Some(child) = left {
+                let clipped = clip_to_plane(
+                    (t_min, t_max),
+                    origin[axis],
+                    direction[axis],
+                    split + radius,
+                    true,
+                );
+                if let Some((t_min, t_max)) = clipped {
+                    stack.push((depth + 1, child, t_min, t_max));
+                }
+            }
+        }
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_of_ray_buffers(
+        &self,
+        points: &[P],
+        origin: P,
+        direction: [f32; D],
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize, f32, f32)>,
+    ) {
+        self.point_indices_within_of_ray_segment_buffers(
+            points,
+            &axis_values(&origin),
+            &direction,
+            f32::INFINITY,
+            radius,
+            result,
+            stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_of_ray(
+        &self,
+        points: &[P],
+        origin: P,
+        direction: [f32; D],
+        radius: f32,
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_of_ray_buffers(
+            points,
+            origin,
+            direction,
+            radius,
+            &mut result,
+            &mut stack,
+        );
+
+        result
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_of_segment_buffers(
+        &self,
+        points: &[P],
+        a: P,
+        b: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize, f32, f32)>,
+    ) {
+        let a = axis_values(&a);
+        let b = axis_values(&b);
+        let direction = std::array::from_fn(|axis| b[axis] - a[axis]);
+
+        self.point_indices_within_of_ray_segment_buffers(
+            points, &a, &direction, 1.0, radius, result, stack,
+        )
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_of_segment(
+        &self,
+        points: &[P],
+        a: P,
+        b: P,
+        radius: f32,
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_of_segment_buffers(points, a, b, radius, &mut result, &mut stack);
+
+        result
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `point_indices_within_of_ray`, but you provide your own buffers. Indices are
+    /// appended to `result` which is not cleared. `stack` is assumed to be empty and is left
+    /// empty.
+    #[inline(always)]
+    pub fn point_indices_within_of_ray_buffers(
+        &self,
+        origin: P,
+        direction: [f32; D],
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize, f32, f32)>,
+    ) {
+        self.internal.point_indices_within_of_ray_buffers(
+            self.points,
+            origin,
+            direction,
+            radius,
+            result,
+            stack,
+        )
+    }
+
+    /// Returns the indices of the points within `radius` of the ray starting at `origin` and
+    /// going along `direction` forever, for instance for mouse picking or hitscan weapons.
+    /// `direction` does not need to be normalized. For a ray of finite length, see
+    /// `point_indices_within_of_segment`.
+    ///
+    /// The ray is clipped against the splitting planes as it descends the tree, like the slab
+    /// test of a ray against a box, and subtrees the clipped ray can't come within `radius` of
+    /// are skipped.
+    #[inline(always)]
+    pub fn point_indices_within_of_ray(
+        &self,
+        origin: P,
+        direction: [f32; D],
+        radius: f32,
+    ) -> Vec<usize> {
+        self.internal
+            .point_indices_within_of_ray(self.points, origin, direction, radius)
+    }
+
+    /// Same as `point_indices_within_of_segment`, but you provide your own buffers. Indices are
+    /// appended to `result` which is not cleared. `stack` is assumed to be empty and is left
+    /// empty.
+    #[inline(always)]
+    pub fn point_indices_within_of_segment_buffers(
+        &self,
+        a: P,
+        b: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize, f32, f32)>,
+    ) {
+        self.internal.point_indices_within_of_segment_buffers(
+            self.points,
+            a,
+            b,
+            radius,
+            result,
+            stack,
+        )
+    }
+
+    /// Returns the indices of the points within `radius` of the segment from `a` to `b`, i.e.
+    /// inside the capsule around it, for instance for road snapping or the hit test of a sword
+    /// swing.
+    ///
+    /// This finds the same points as `point_indices_hit_by_swept_sphere`, but the segment is
+    /// clipped against the splitting planes like `point_indices_within_of_ray`, which prunes
+    /// much better for long diagonal segments than the segment's bounding box does.
+    #[inline(always)]
+    pub fn point_indices_within_of_segment(&self, a: P, b: P, radius: f32) -> Vec<usize> {
+        self.internal
+            .point_indices_within_of_segment(self.points, a, b, radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_point_indices_within_of_ray() {
+        let mut rng = SplitMix64::new(23);
+        let points = (0..3000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        for (origin, direction, radius) in [
+            ([50.0, 50.0, 50.0], [1.0, 0.3, -0.2], 4.0),
+            ([-10.0, 20.0, 30.0], [1.0, 0.0, 0.0], 2.5),
+            ([0.0, 0.0, 0.0], [1.0, 1.0, 1.0], 6.0),
+            ([10.0, 10.0, 10.0], [0.0, 0.0, 0.0], 5.0),
+        ] {
+            let expected = (0..points.len())
+                .filter(|i| {
+                    distance_squared_to_ray(&points[*i], &origin, &direction, f32::INFINITY)
+                        <= radius * radius
+                })
+                .collect::<Vec<_>>();
+
+            let mut result = tree.point_indices_within_of_ray(origin, direction, radius);
+            result.sort_unstable();
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn test_point_indices_within_of_segment() {
+        let mut rng = SplitMix64::new(29);
+        let points = (0..3000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        for (a, b) in [
+            ([10.0, 10.0], [90.0, 60.0]),
+            ([50.0, 50.0], [50.0, 50.0]),
+            ([0.0, 99.0], [99.0, 0.0]),
+        ] {
+            let mut expected = tree.point_indices_hit_by_swept_sphere(a, b, 3.0);
+            expected.sort_unstable();
+
+            let mut result = tree.point_indices_within_of_segment(a, b, 3.0);
+            result.sort_unstable();
+            assert_eq!(result, expected);
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! A Kd-tree that stays correct when its points move a little, by refitting bounds instead of
+//! rebuilding
+use crate::nearest::{pop_nearest_first, Neighbor};
+use crate::utils::axis_values;
+use crate::{KdTreeNoBorrow, Point};
+
+use std::collections::BinaryHeap;
+
+#[derive(Debug, Clone, Copy)]
+/// Axis aligned box around all points in a subtree
+pub struct NodeBounds<const D: usize> {
+    pub min: [f32; D],
+    pub max: [f32; D],
+}
+
+impl<const D: usize> NodeBounds<D> {
+    /// The squared distance from `query_point_axis_values` to the box, `0.0` if it is inside
+    #[inline(always)]
+    pub fn distance_squared(&self, query_point_axis_values: &[f32; D]) -> f32 {
+        let mut distance_squared = 0.0;
+
+        for (axis, value) in query_point_axis_values.iter().enumerate() {
+            let delta = (self.min[axis] - value)
+                .max(value - self.max[axis])
+                .max(0.0);
+            distance_squared += delta * delta;
+        }
+
+        distance_squared
+    }
+}
+
+#[derive(Debug, Clone)]
+/// A KdTree of points that move slightly between queries, like particles jittering from one
+/// frame to the next. Every subtree keeps a box around its points, and queries prune with these
+/// boxes instead of the splitting planes. After the points have moved, `refit` recomputes the
+/// boxes bottom-up in `O(n)` without reordering anything, which keeps queries correct. The
+/// further points move from where they were at build time, the more the boxes overlap and the
+/// slower queries get, so rebuild with `from_points` every now and then.
+///
+/// Like `KdTreeNoBorrow`, the points are passed to every query, and must be the points `refit`
+/// was last called with.
+pub struct KdTreeRefit<const D: usize, P: Point<D>> {
+    pub internal: KdTreeNoBorrow<D, P>,
+    /// Indexed like `internal.tree`
+    pub node_bounds: Vec<NodeBounds<D>>,
+}
+
+impl<const D: usize, P: Point<D>> KdTreeRefit<D, P> {
+    /// Constructs a new tree of `points` using default settings
+    pub fn from_points(points: &[P]) -> Self {
+        Self::from_tree(KdTreeNoBorrow::from_points(points), points)
+    }
+
+    /// Wraps `tree`, which was built from `points`. Use this to build with `KdTreeBuilder`.
+    pub fn from_tree(tree: KdTreeNoBorrow<D, P>, points: &[P]) -> Self {
+        let mut result = Self {
+            internal: tree,
+            node_bounds: vec![],
+        };
+        result.refit(points);
+
+        result
+    }
+
+    /// Recomputes the box of every subtree from the current `points`, which must be the points
+    /// the tree was built from, possibly moved
+    pub fn refit(&mut self, points: &[P]) {
+        let tree = &self.internal.tree;
+
+        self.node_bounds.clear();
+        self.node_bounds.extend(tree.iter().map(|node| {
+            let position = axis_values(&points[node.index()]);
+            NodeBounds {
+                min: position,
+                max: position,
+            }
+        }));
+
+        /*
+            NOTE: Children are always placed after their parent in `tree`, so iterating in
+                  reverse visits every child before its parent.
+        */
+        for tree_index in (0..tree.len()).rev() {
+            for child in tree[tree_index].children().iter().flatten() {
+                let child_bounds = self.node_bounds[*child];
+                let bounds = &mut self.node_bounds[tree_index];
+                for axis in 0..D {
+                    bounds.min[axis] = bounds.min[axis].min(child_bounds.min[axis]);
+                    bounds.max[axis] = bounds.max[axis].max(child_bounds.max[axis]);
+                }
+            }
+        }
+    }
+
+    /// Same as `point_indices_within`, but you provide your own buffers. Indices are appended to
+    /// `result` which is not cleared. `stack` is assumed to be empty and is left empty.
+    pub fn point_indices_within_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<usize>,
+    ) {
+        if self.internal.tree.is_empty() {
+            return;
+        }
+
+        let radius_squared = radius * radius;
+        let query_point_axis_values = axis_values(&query_point);
+
+        stack.push(0);
+        while let Some(tree_index) = stack.pop() {
+            if self.node_bounds[tree_index].distance_squared(&query_point_axis_values)
+                > radius_squared
+            {
+                continue;
+            }
+
+            let node = &self.internal.tree[tree_index];
+            if query_point.distance_squared(points[node.index()]) <= radius_squared {
+                result.push(node.index());
+            }
+
+            stack.extend(node.children().iter().flatten());
+        }
+    }
+
+    /// Returns the indices of the points within `radius` of `query_point`
+    pub fn point_indices_within(&self, points: &[P], query_point: P, radius: f32) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_buffers(points, query_point, radius, &mut result, &mut stack);
+
+        result
+    }
+
+    /// Same as `nearest_n`, but you provide your own buffers. Indices are appended to `result`
+    /// which is not cleared. `heap` and `stack` are assumed to be empty and are left empty.
+    pub fn nearest_n_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        k: usize,
+        result: &mut Vec<usize>,
+        heap: &mut BinaryHeap<Neighbor>,
+        stack: &mut Vec<(usize, f32)>,
+    ) {
+        if k == 0 || self.internal.tree.is_empty() {
+            return;
+        }
+
+        let query_point_axis_values = axis_values(&query_point);
+
+        stack.push((0, 0.0));
+        while let Some((tree_index, lower_bound)) = stack.pop() {
+            let current_bound = if heap.len() == k {
+                heap.peek().map_or(f32::INFINITY, |n| n.distance_squared)
+            } else {
+                f32::INFINITY
+            };
+            if lower_bound > current_bound {
+                continue;
+            }
+
+            let node = &self.internal.tree[tree_index];
+            let distance_squared = query_point.distance_squared(points[node.index()]);
+            if distance_squared <= current_bound {
+                heap.push(Neighbor {
+                    distance_squared,
+                    index: node.index(),
+                });
+                if heap.len() > k {
+                    heap.pop();
+                }
+            }
+
+            let mut children = node.children().map(|child| {
+                child.map(|child| {
+                    let bound = self.node_bounds[child].distance_squared(&query_point_axis_values);
+                    (child, bound)
+                })
+            });
+
+            /*
+                NOTE: The closer child is pushed last so that it is popped first
+            */
+            if let [Some(a), Some(b)] = children {
+                if a.1 < b.1 {
+                    children = [Some(b), Some(a)];
+                }
+            }
+            stack.extend(children.into_iter().flatten());
+        }
+
+        pop_nearest_first(heap, result);
+    }
+
+    /// Returns the indices of the `k` points closest to `query_point`, nearest first. Points at
+    /// the same distance are ordered by index.
+    pub fn nearest_n(&self, points: &[P], query_point: P, k: usize) -> Vec<usize> {
+        let mut result = Vec::with_capacity(k.min(self.internal.tree.len()));
+        let mut heap = BinaryHeap::with_capacity(k.min(self.internal.tree.len()) + 1);
+        let mut stack = vec![];
+
+        self.nearest_n_buffers(points, query_point, k, &mut result, &mut heap, &mut stack);
+
+        result
+    }
+
+    /// Returns the index of and distance to the point closest to `query_point`
+    pub fn nearest_one(&self, points: &[P], query_point: P) -> Option<(usize, f32)> {
+        let index = *self.nearest_n(points, query_point, 1).first()?;
+        Some((index, query_point.distance_squared(points[index]).sqrt()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixture;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_refit() {
+        let mut rng = SplitMix64::new(73);
+        let mut random = |scale: f32| (rng.next_u64() % 2001) as f32 / 1000.0 * scale - scale;
+
+        let mut points = fixture::points(73);
+        let mut tree = KdTreeRefit::from_points(&points);
+
+        for _ in 0..3 {
+            for point in points.iter_mut() {
+                for value in point.iter_mut() {
+                    *value += random(1.5);
+                }
+            }
+            tree.refit(&points);
+
+            fixture::check_queries(
+                &points,
+                |_| true,
+                |query_point, radius| tree.point_indices_within(&points, query_point, radius),
+                |query_point, k| tree.nearest_n(&points, query_point, k),
+                |query_point| tree.nearest_one(&points, query_point),
+            );
+        }
+
+        let mut tree = KdTreeRefit::<3, [f32; 3]>::from_points(&[]);
+        tree.refit(&[]);
+        assert!(tree.point_indices_within(&[], [0.0; 3], 1.0).is_empty());
+        assert!(tree.nearest_n(&[], [0.0; 3], 3).is_empty());
+        assert_eq!(tree.nearest_one(&[], [0.0; 3]), None);
+    }
+}
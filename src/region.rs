@@ -0,0 +1,396 @@
+//! Queries with regions composed out of spheres, boxes and half-spaces
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+/// A region of space. Regions can be combined with `union`, `intersection` and `complement`
+/// and are queried with `point_indices_in_region`.
+///
+/// All primitives are closed, i.e. points on their boundary are inside, which makes their
+/// complements open.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Region<const D: usize> {
+    Sphere {
+        center: [f32; D],
+        radius: f32,
+    },
+    /// Inclusive on both ends
+    Aabb {
+        min: [f32; D],
+        max: [f32; D],
+    },
+    /// All points `p` where `dot(normal, p) <= offset`
+    HalfSpace {
+        normal: [f32; D],
+        offset: f32,
+    },
+    /// Points inside any of the regions. An empty union contains nothing.
+    Union(Vec<Region<D>>),
+    /// Points inside all of the regions. An empty intersection contains everything.
+    Intersection(Vec<Region<D>>),
+    Complement(Box<Region<D>>),
+}
+
+/// How a region relates to a box of space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Overlap {
+    /// No point of the box is inside the region
+    Outside,
+    /// Every point of the box is inside the region
+    Inside,
+    Partial,
+}
+
+impl Overlap {
+    #[inline(always)]
+    fn complement(self) -> Self {
+        match self {
+            Overlap::Outside => Overlap::Inside,
+            Overlap::Inside => Overlap::Outside,
+            Overlap::Partial => Overlap::Partial,
+        }
+    }
+}
+
+impl<const D: usize> Region<D> {
+    pub fn sphere<P: Point<D>>(center: P, radius: f32) -> Self {
+        Region::Sphere {
+            center: axis_values(&center),
+            radius,
+        }
+    }
+
+    pub fn aabb<P: Point<D>>(min: P, max: P) -> Self {
+        Region::Aabb {
+            min: axis_values(&min),
+            max: axis_values(&max),
+        }
+    }
+
+    pub fn half_space(normal: [f32; D], offset: f32) -> Self {
+        Region::HalfSpace { normal, offset }
+    }
+
+    pub fn union(self, other: Self) -> Self {
+        match self {
+            Region::Union(mut regions) => {
+                regions.push(other);
+                Region::Union(regions)
+            }
+            region => Region::Union(vec![region, other]),
+        }
+    }
+
+    pub fn intersection(self, other: Self) -> Self {
+        match self {
+            Region::Intersection(mut regions) => {
+                regions.push(other);
+                Region::Intersection(regions)
+            }
+            region => Region::Intersection(vec![region, other]),
+        }
+    }
+
+    pub fn complement(self) -> Self {
+        match self {
+            Region::Complement(region) => *region,
+            region => Region::Complement(Box::new(region)),
+        }
+    }
+
+    /// Whether `point` lies inside the region
+    pub fn contains<P: Point<D>>(&self, point: &P) -> bool {
+        self.contains_axis_values(&axis_values(point))
+    }
+
+    pub(crate) fn contains_axis_values(&self, point: &[f32; D]) -> bool {
+        match self {
+            Region::Sphere { center, radius } => {
+                let mut distance_squared = 0.0;
+                for axis in 0..D {
+                    let d = point[axis] - center[axis];
+                    distance_squared += d * d;
+                }
+                distance_squared <= radius * radius
+            }
+
+            Region::Aabb { min, max } => {
+                (0..D).all(|axis| min[axis] <= point[axis] && point[axis] <= max[axis])
+            }
+
+            Region::HalfSpace { normal, offset } => {
+                let mut dot = 0.0;
+                for axis in 0..D {
+                    dot += normal[axis] * point[axis];
+                }
+                dot <= *offset
+            }
+
+            Region::Union(regions) => regions.iter().any(|r| r.contains_axis_values(point)),
+            Region::Intersection(regions) => regions.iter().all(|r| r.contains_axis_values(point)),
+            Region::Complement(region) => !region.contains_axis_values(point),
+        }
+    }
+
+    /// Classifies the box `min..=max` against the region. `Partial` is always a valid answer,
+    /// the other two are only returned when they hold for every point of the box. Bounds may
+    /// be infinite.
+    pub(crate) fn overlap(&self, min: &[f32; D], max: &[f32; D]) -> Overlap {
+        match self {
+            Region::Sphere { center, radius } => {
+                let mut nearest = 0.0;
+                let mut farthest = 0.0;
+                for axis in 0..D {
+                    let to_min = min[axis] - center[axis];
+                    let to_max = max[axis] - center[axis];
+
+                    let d = center[axis] - center[axis].max(min[axis]).min(max[axis]);
+                    nearest += d * d;
+                    farthest += (to_min * to_min).max(to_max * to_max);
+                }
+
+                let radius_squared = radius * radius;
+                if nearest > radius_squared {
+                    Overlap::Outside
+                } else if farthest <= radius_squared {
+                    Overlap::Inside
+                } else {
+                    Overlap::Partial
+                }
+            }
+
+            Region::Aabb {
+                min: region_min,
+                max: region_max,
+            } => {
+                if (0..D).any(|axis| max[axis] < region_min[axis] || min[axis] > region_max[axis]) {
+                    Overlap::Outside
+                } else if (0..D)
+                    .all(|axis| region_min[axis] <= min[axis] && max[axis] <= region_max[axis])
+                {
+                    Overlap::Inside
+                } else {
+                    Overlap::Partial
+                }
+            }
+
+            Region::HalfSpace { normal, offset } => {
+                let mut lowest = 0.0;
+                let mut highest = 0.0;
+                for axis in 0..D {
+                    let n = normal[axis];
+                    if n > 0.0 {
+                        lowest += n * min[axis];
+                        highest += n * max[axis];
+                    } else if n < 0.0 {
+                        lowest += n * max[axis];
+                        highest += n * min[axis];
+                    }
+                }
+
+                /*
+                    NOTE: Opposing infinities make these NaN, which compares false and falls
+                          through to `Partial`
+                */
+                if lowest > *offset {
+                    Overlap::Outside
+                } else if highest <= *offset {
+                    Overlap::Inside
+                } else {
+                    Overlap::Partial
+                }
+            }
+
+            Region::Union(regions) => {
+                let mut overlap = Overlap::Outside;
+                for region in regions {
+                    match region.overlap(min, max) {
+                        Overlap::Inside => return Overlap::Inside,
+                        Overlap::Partial => overlap = Overlap::Partial,
+                        Overlap::Outside => {}
+                    }
+                }
+                overlap
+            }
+
+            Region::Intersection(regions) => {
+                let mut overlap = Overlap::Inside;
+                for region in regions {
+                    match region.overlap(min, max) {
+                        Overlap::Outside => return Overlap::Outside,
+                        Overlap::Partial => overlap = Overlap::Partial,
+                        Overlap::Inside => {}
+                    }
+                }
+                overlap
+            }
+
+            Region::Complement(region) => region.overlap(min, max).complement(),
+        }
+    }
+}
+
+/// A pending subtree of a region query, see `point_indices_in_region_buffers`
+#[derive(Debug, Clone, Copy)]
+pub struct RegionCell<const D: usize> {
+    tree_index: usize,
+    min: [f32; D],
+    max: [f32; D],
+    /// The whole subtree is known to be inside the region
+    inside: bool,
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_in_region_buffers(
+        &self,
+        points: &[P],
+        region: &Region<D>,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<RegionCell<D>>,
+    ) {
+        if self.tree.is_empty() {
+            return;
+        }
+
+        stack.push(RegionCell {
+            tree_index: 0,
+            min: [f32::NEG_INFINITY; D],
+            max: [f32::INFINITY; D],
+            inside: false,
+        });
+
+        while let Some(cell) = stack.pop() {
+            let node = &self.tree[cell.tree_index];
+
+            if cell.inside {
+                result.push(node.index());
+                for child in node.children().into_iter().flatten() {
+                    stack.push(RegionCell {
+                        tree_index: child,
+                        ..cell
+                    });
+                }
+                continue;
+            }
+
+            let inside = match region.overlap(&cell.min, &cell.max) {
+                Overlap::Outside => continue,
+                Overlap::Inside => true,
+                Overlap::Partial => false,
+            };
+
+            let point = axis_values(&points[node.index()]);
+            if inside || region.contains_axis_values(&point) {
+                result.push(node.index());
+            }
+
+            /*
+                NOTE: Points equal to the split value can end up on either side, so both
+                      children share the split plane as a boundary
+            */
+            let axis = node.axis();
+            let [left, right] = node.children();
+            if let Some(child) = right {
+                let mut min = cell.min;
+                min[axis] = node.split;
+                stack.push(RegionCell {
+                    tree_index: child,
+                    min,
+                    max: cell.max,
+                    inside,
+                });
+            }
+            if let Some(child) = left {
+                let mut max = cell.max;
+                max[axis] = node.split;
+                stack.push(RegionCell {
+                    tree_index: child,
+                    min: cell.min,
+                    max,
+                    inside,
+                });
+            }
+        }
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_in_region(&self, points: &[P], region: &Region<D>) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_in_region_buffers(points, region, &mut result, &mut stack);
+
+        result
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `point_indices_in_region`, but you provide your own buffers. Indices are
+    /// appended to `result` which is not cleared. `stack` is assumed to be empty and is left
+    /// empty.
+    #[inline(always)]
+    pub fn point_indices_in_region_buffers(
+        &self,
+        region: &Region<D>,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<RegionCell<D>>,
+    ) {
+        self.internal
+            .point_indices_in_region_buffers(self.points, region, result, stack)
+    }
+
+    /// Returns the indices of the points inside `region`. The query keeps track of the box
+    /// every subtree lives in and classifies it against the composed region: subtrees
+    /// entirely outside are skipped and subtrees entirely inside are collected without
+    /// testing their points.
+    #[inline(always)]
+    pub fn point_indices_in_region(&self, region: &Region<D>) -> Vec<usize> {
+        self.internal.point_indices_in_region(self.points, region)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_point_indices_in_region() {
+        let mut rng = SplitMix64::new(11);
+        let points = (0..3000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        let ball = Region::sphere([50.0, 50.0, 50.0], 30.0);
+        let slab = Region::aabb([0.0, 40.0, 0.0], [99.0, 60.0, 99.0]);
+        let below = Region::half_space([1.0, 1.0, 0.0], 90.0);
+
+        for region in [
+            ball.clone(),
+            slab.clone(),
+            below.clone(),
+            ball.clone().complement(),
+            ball.clone().union(slab.clone()),
+            ball.clone().intersection(below.clone()),
+            ball.clone().intersection(slab.clone().complement()),
+            ball.union(slab).intersection(below.complement()),
+            Region::Union(vec![]),
+            Region::Intersection(vec![]),
+        ] {
+            let expected = (0..points.len())
+                .filter(|i| region.contains(&points[*i]))
+                .collect::<Vec<_>>();
+
+            let mut result = tree.point_indices_in_region(&region);
+            result.sort_unstable();
+            assert_eq!(result, expected, "{region:?}");
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Reordering of points for better memory locality during queries
+use crate::utils::compute_bounds;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A reordering of values where `indices[new_index]` is the original index of the value that
+/// ends up at `new_index`, as returned by `reorder_points_morton`
+pub struct Permutation {
+    pub indices: Vec<usize>,
+}
+
+impl Permutation {
+    /// Wraps `indices`, which must contain every index in `0..indices.len()` exactly once
+    pub fn new(indices: Vec<usize>) -> Self {
+        debug_assert!({
+            let mut seen = vec![false; indices.len()];
+            indices
+                .iter()
+                .all(|i| *i < seen.len() && !std::mem::replace(&mut seen[*i], true))
+        });
+
+        Self { indices }
+    }
+
+    /// The permutation that leaves everything in place
+    pub fn identity(len: usize) -> Self {
+        Self {
+            indices: (0..len).collect(),
+        }
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    /// Returns `values` in the new order, i.e. `result[new_index] = values[indices[new_index]]`.
+    /// Use this to reorder data kept alongside the points, such as entity ids.
+    pub fn apply<T: Clone>(&self, values: &[T]) -> Vec<T> {
+        assert_eq!(values.len(), self.len(), "permutation length mismatch");
+        self.indices.iter().map(|i| values[*i].clone()).collect()
+    }
+
+    /// The permutation undoing this one, mapping original indices to new indices
+    pub fn invert(&self) -> Self {
+        let mut inverse = vec![0; self.len()];
+        for (new_index, original_index) in self.indices.iter().enumerate() {
+            inverse[*original_index] = new_index;
+        }
+
+        Self { indices: inverse }
+    }
+
+    /// The permutation equivalent to applying `self` first and `then` afterwards, so that
+    /// `a.compose(&b).apply(values) == b.apply(&a.apply(values))`
+    pub fn compose(&self, then: &Permutation) -> Self {
+        assert_eq!(self.len(), then.len(), "permutation length mismatch");
+
+        Self {
+            indices: then.indices.iter().map(|i| self.indices[*i]).collect(),
+        }
+    }
+
+    /// The original index of the value now at `new_index`
+    #[inline(always)]
+    pub fn original_index(&self, new_index: usize) -> usize {
+        self.indices[new_index]
+    }
+
+    /// Replaces every new index in `indices` with its original index
+    pub fn map_to_original(&self, indices: &mut [usize]) {
+        for index in indices {
+            *index = self.indices[*index];
+        }
+    }
+}
+
+/// The Morton (Z-order) code of `point` within the box `bounds_min..bounds_max`. Every axis is
+/// quantized to `64 / D` bits (at most 32) and the bits of all axes are interleaved with axis 0
+/// in the least significant position, so points that are close in space tend to have close codes.
+pub fn morton_code<const D: usize, P>(
+    point: &P,
+    bounds_min: &[f32; D],
+    bounds_max: &[f32; D],
+) -> u64
+where
+    P: Point<D>,
+{
+    let bits = (64 / D.max(1)).min(32) as u32;
+    if bits == 0 {
+        return 0;
+    }
+
+    let max_cell = (1_u64 << bits) - 1;
+    let mut cells = [0_u64; D];
+    for (axis, cell) in cells.iter_mut().enumerate() {
+        let extent = bounds_max[axis] - bounds_min[axis];
+        let t = if extent > 0.0 {
+            ((point.get_axis(axis) - bounds_min[axis]) / extent).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        *cell = ((t as f64 * max_cell as f64) as u64).min(max_cell);
+    }
+
+    let mut code = 0;
+    for bit in (0..bits).rev() {
+        for cell in cells.iter().rev() {
+            code = (code << 1) | ((cell >> bit) & 1);
+        }
+    }
+    code
+}
+
+/// Returns the points sorted along the Morton curve over their bounding box together with the
+/// permutation used, whose `original_index(new_index)` is the index of the point in `points`.
+///
+/// Building a tree over the reordered points places points that are close in space close in
+/// memory, which makes every later query fetch fewer cache lines.
+pub fn reorder_points_morton<const D: usize, P>(points: &[P]) -> (Vec<P>, Permutation)
+where
+    P: Point<D>,
+{
+    let (bounds_min, bounds_max) = compute_bounds(points);
+
+    let mut keyed = points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| (morton_code(point, &bounds_min, &bounds_max), i))
+        .collect::<Vec<_>>();
+    keyed.sort_unstable();
+
+    let permutation = Permutation::new(keyed.into_iter().map(|(_, i)| i).collect());
+    let reordered = permutation.apply(points);
+
+    (reordered, permutation)
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_within_original_buffers(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        permutation: &Permutation,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        let start = result.len();
+        self.point_indices_within_buffers(points, query_point, radius, result, stack);
+        permutation.map_to_original(&mut result[start..]);
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_original(
+        &self,
+        points: &[P],
+        query_point: P,
+        radius: f32,
+        permutation: &Permutation,
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_within_original_buffers(
+            points,
+            query_point,
+            radius,
+            permutation,
+            &mut result,
+            &mut stack,
+        );
+
+        result
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// For a tree built over reordered points, same as `point_indices_within_buffers` but the
+    /// indices are reported as indices into the original points using `permutation`. The regular
+    /// queries keep reporting indices into the reordered points.
+    #[inline(always)]
+    pub fn point_indices_within_original_buffers(
+        &self,
+        query_point: P,
+        radius: f32,
+        permutation: &Permutation,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_within_original_buffers(
+            self.points,
+            query_point,
+            radius,
+            permutation,
+            result,
+            stack,
+        )
+    }
+
+    /// See `point_indices_within_original_buffers`
+    #[inline(always)]
+    pub fn point_indices_within_original(
+        &self,
+        query_point: P,
+        radius: f32,
+        permutation: &Permutation,
+    ) -> Vec<usize> {
+        self.internal
+            .point_indices_within_original(self.points, query_point, radius, permutation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_permutation() {
+        let a = Permutation::new(vec![2, 0, 1, 3]);
+        let b = Permutation::new(vec![3, 1, 0, 2]);
+        let values = ['a', 'b', 'c', 'd'];
+
+        assert_eq!(a.apply(&values), vec!['c', 'a', 'b', 'd']);
+        assert_eq!(a.invert().apply(&a.apply(&values)), values.to_vec());
+        assert_eq!(a.compose(&a.invert()), Permutation::identity(4));
+        assert_eq!(a.compose(&b).apply(&values), b.apply(&a.apply(&values)));
+    }
+
+    #[test]
+    fn test_original_indices() {
+        let points: Vec<[f32; 2]> = (0..64).map(|i| [(i % 8) as f32, (i / 8) as f32]).collect();
+        let (reordered, permutation) = reorder_points_morton(&points);
+        let tree = KdTree::from_points(&reordered);
+
+        let mut result = tree.point_indices_within_original([2.0, 2.0], 1.0, &permutation);
+        result.sort();
+        assert_eq!(result, vec![10, 17, 18, 19, 26]);
+    }
+
+    #[test]
+    fn test_reorder_points_morton() {
+        #[rustfmt::skip]
+        let points: [[f32; 2]; 4] = [
+            [1.0, 1.0],
+            [0.0, 1.0],
+            [1.0, 0.0],
+            [0.0, 0.0],
+        ];
+
+        let (reordered, permutation) = reorder_points_morton(&points);
+        assert_eq!(permutation, Permutation::new(vec![3, 2, 1, 0]));
+        for (new_index, old_index) in permutation.indices.iter().enumerate() {
+            assert_eq!(reordered[new_index], points[*old_index]);
+        }
+    }
+}
@@ -0,0 +1,304 @@
+//! Abstraction over the growable buffers passed to the `_buffers` queries, so that they can be
+//! allocated from somewhere other than the global heap
+use crate::{KdTreeNode, TreeIndex};
+
+#[cfg(feature = "allocator-api2")]
+use allocator_api2::alloc::Allocator;
+
+/// A growable buffer that queries can push results or traversal state onto. Implemented for
+/// `Vec` and, with the `allocator-api2` feature, for `allocator_api2::vec::Vec` with any
+/// allocator, which lets query scratch live in a frame or arena allocator.
+pub trait ScratchVec<T> {
+    fn push(&mut self, value: T);
+    fn pop(&mut self) -> Option<T>;
+    fn clear(&mut self);
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Storage of the nodes of a `KdTreeNoBorrow`. Implemented for `Vec` and, with the
+/// `allocator-api2` feature, for `allocator_api2::vec::Vec` with any allocator, which lets the
+/// whole tree live in a frame or arena allocator, see `KdTreeNoBorrow::from_points_in`.
+///
+/// `Index` is the type the nodes store their indices as, see `CompactKdTree`.
+pub trait NodeStorage: std::ops::Deref<Target = [KdTreeNode<Self::Index>]> {
+    type Index: TreeIndex;
+}
+
+impl<I: TreeIndex> NodeStorage for Vec<KdTreeNode<I>> {
+    type Index = I;
+}
+
+#[cfg(feature = "allocator-api2")]
+impl<I: TreeIndex, A: Allocator> NodeStorage for allocator_api2::vec::Vec<KdTreeNode<I>, A> {
+    type Index = I;
+}
+
+impl<T> ScratchVec<T> for Vec<T> {
+    #[inline(always)]
+    fn push(&mut self, value: T) {
+        Vec::push(self, value)
+    }
+
+    #[inline(always)]
+    fn pop(&mut self) -> Option<T> {
+        Vec::pop(self)
+    }
+
+    #[inline(always)]
+    fn clear(&mut self) {
+        Vec::clear(self)
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        Vec::len(self)
+    }
+}
+
+#[cfg(feature = "allocator-api2")]
+impl<T, A: Allocator> ScratchVec<T> for allocator_api2::vec::Vec<T, A> {
+    #[inline(always)]
+    fn push(&mut self, value: T) {
+        allocator_api2::vec::Vec::push(self, value)
+    }
+
+    #[inline(always)]
+    fn pop(&mut self) -> Option<T> {
+        allocator_api2::vec::Vec::pop(self)
+    }
+
+    #[inline(always)]
+    fn clear(&mut self) {
+        allocator_api2::vec::Vec::clear(self)
+    }
+
+    #[inline(always)]
+    fn len(&self) -> usize {
+        allocator_api2::vec::Vec::len(self)
+    }
+}
+
+#[cfg(feature = "allocator-api2")]
+impl<const D: usize, P: crate::Point<D>> crate::KdTreeNoBorrow<D, P> {
+    /// Same as `from_points_with_points_sorter`, but the point id scratch used while building is
+    /// allocated with `allocator`. Together with the sorting strategies, none of which use the
+    /// heap apart from `SortingStrategy::StableSort`, the only other allocation is the `Vec` holding
+    /// the nodes of the finished tree.
+    pub fn from_points_with_points_sorter_in<F, A>(
+        points: &[P],
+        mut points_sorter: F,
+        allocator: A,
+    ) -> Self
+    where
+        F: FnMut(&[P], &mut [usize], usize),
+        A: Allocator,
+    {
+        let mut tree = Vec::with_capacity(points.len());
+        let mut point_ids = allocator_api2::vec::Vec::with_capacity_in(points.len(), allocator);
+        point_ids.extend(0..points.len());
+        let (bounds_min, bounds_max) = crate::utils::compute_bounds(points);
+
+        let root_job = crate::kdtree::BuildJob {
+            start: 0,
+            end: points.len(),
+            left_right: 0,
+            depth: 0,
+            parent: 0,
+        };
+
+        crate::kdtree::build_subtree(
+            points,
+            &mut point_ids,
+            root_job,
+            &mut points_sorter,
+            &crate::SplitStrategy::default(),
+            &mut tree,
+            usize::MAX,
+            &mut vec![],
+        );
+
+        Self {
+            tree,
+            bounds_min,
+            bounds_max,
+            bounding_spheres: vec![],
+            subset: false,
+            config: crate::BuildConfig::default(),
+            __marker: std::marker::PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "allocator-api2")]
+impl<const D: usize, P: crate::Point<D>, A: Allocator + Clone>
+    crate::KdTreeNoBorrow<D, P, allocator_api2::vec::Vec<KdTreeNode, A>>
+{
+    /// Builds the same tree as `from_points`, but both the nodes and the point id scratch are
+    /// allocated with `allocator`, for instance an arena that is reset every frame. Pass
+    /// `&bumpalo::Bump` with bumpalo's `allocator-api2` feature enabled to build into a bump
+    /// arena. The ranges are partitioned with `SortingStrategy::MedianSelect`, which works in
+    /// place, so the global heap is not used at all.
+    pub fn from_points_in(points: &[P], allocator: A) -> Self {
+        let mut tree = allocator_api2::vec::Vec::with_capacity_in(points.len(), allocator.clone());
+        let mut point_ids = allocator_api2::vec::Vec::with_capacity_in(points.len(), allocator);
+        point_ids.extend(0..points.len());
+        let (bounds_min, bounds_max) = crate::utils::compute_bounds(points);
+
+        let root_job = crate::kdtree::BuildJob {
+            start: 0,
+            end: points.len(),
+            left_right: 0,
+            depth: 0,
+            parent: 0,
+        };
+
+        crate::kdtree::build_subtree(
+            points,
+            &mut point_ids,
+            root_job,
+            &mut crate::utils::points_sorter(&crate::SortingStrategy::MedianSelect),
+            &crate::SplitStrategy::default(),
+            &mut tree,
+            usize::MAX,
+            &mut vec![],
+        );
+
+        Self {
+            tree,
+            bounds_min,
+            bounds_max,
+            bounding_spheres: vec![],
+            subset: false,
+            config: crate::BuildConfig {
+                sorting_strategy: crate::SortingStrategy::MedianSelect,
+                ..Default::default()
+            },
+            __marker: std::marker::PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "allocator-api2")]
+impl<'a, const D: usize, P: crate::Point<D>> crate::KdTree<'a, D, P> {
+    /// See `KdTreeNoBorrow::from_points_with_points_sorter_in`
+    pub fn from_points_with_points_sorter_in<F, A>(
+        points: &'a [P],
+        points_sorter: F,
+        allocator: A,
+    ) -> Self
+    where
+        F: FnMut(&[P], &mut [usize], usize),
+        A: Allocator,
+    {
+        Self {
+            internal: crate::KdTreeNoBorrow::from_points_with_points_sorter_in(
+                points,
+                points_sorter,
+                allocator,
+            ),
+            points,
+        }
+    }
+}
+
+#[cfg(all(test, feature = "allocator-api2"))]
+mod tests {
+    use super::*;
+    use crate::utils::points_sorter;
+    use crate::{KdTree, KdTreeNoBorrow, SortingStrategy};
+
+    use allocator_api2::alloc::{AllocError, Global, Layout};
+    use std::cell::Cell;
+    use std::ptr::NonNull;
+
+    #[derive(Clone)]
+    struct CountingAllocator<'a> {
+        allocations: &'a Cell<usize>,
+    }
+
+    unsafe impl Allocator for CountingAllocator<'_> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.allocations.set(self.allocations.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn test_allocator_scratch() {
+        let points: Vec<[f32; 2]> = (0..500)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.37).sin() * 10.0, (i * 0.11).cos() * 10.0]
+            })
+            .collect();
+        let reference = KdTree::from_points(&points);
+
+        let allocations = Cell::new(0);
+        let tree = KdTreeNoBorrow::from_points_with_points_sorter_in(
+            &points,
+            points_sorter(&SortingStrategy::QuickSort),
+            CountingAllocator {
+                allocations: &allocations,
+            },
+        );
+        assert_eq!(allocations.get(), 1);
+
+        let mut result = allocator_api2::vec::Vec::new_in(CountingAllocator {
+            allocations: &allocations,
+        });
+        let mut stack = allocator_api2::vec::Vec::new_in(CountingAllocator {
+            allocations: &allocations,
+        });
+        tree.point_indices_within_buffers(&points, [1.0, 2.0], 3.0, &mut result, &mut stack);
+        assert!(allocations.get() > 1);
+
+        let mut result = result.to_vec();
+        let mut expected = reference.point_indices_within([1.0, 2.0], 3.0);
+        result.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn test_allocator_nodes() {
+        let points: Vec<[f32; 2]> = (0..500)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.37).sin() * 10.0, (i * 0.11).cos() * 10.0]
+            })
+            .collect();
+        let reference = KdTreeNoBorrow::from_points(&points);
+
+        let allocations = Cell::new(0);
+        let tree = KdTreeNoBorrow::from_points_in(
+            &points,
+            CountingAllocator {
+                allocations: &allocations,
+            },
+        );
+        assert_eq!(allocations.get(), 2);
+
+        assert_eq!(tree.tree.len(), reference.tree.len());
+        for (node, expected) in tree.tree.iter().zip(reference.tree.iter()) {
+            assert_eq!(node.index, expected.index);
+            assert_eq!(node.children, expected.children);
+        }
+
+        assert_eq!(
+            tree.nearest_n(&points, [1.0, 2.0], 10),
+            reference.nearest_n(&points, [1.0, 2.0], 10)
+        );
+        assert_eq!(
+            tree.point_indices_within(&points, [1.0, 2.0], 3.0),
+            reference.point_indices_within(&points, [1.0, 2.0], 3.0)
+        );
+    }
+}
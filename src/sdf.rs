@@ -0,0 +1,171 @@
+//! Signed distance fields of oriented point clouds
+use crate::nearest::distance_squared_to_axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+use std::collections::BinaryHeap;
+
+/// The distance to `point`, negative when `query_point_axis_values` lies behind it as seen
+/// along `normal`
+#[inline(always)]
+fn signed_distance_to<const D: usize, P: Point<D>>(
+    point: &P,
+    normal: &[f32; D],
+    query_point_axis_values: &[f32; D],
+) -> f32 {
+    let facing = (0..D)
+        .map(|axis| (query_point_axis_values[axis] - point.get_axis(axis)) * normal[axis])
+        .sum::<f32>();
+    let distance = distance_squared_to_axis_values(point, query_point_axis_values).sqrt();
+
+    if facing < 0.0 {
+        -distance
+    } else {
+        distance
+    }
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn signed_distance(
+        &self,
+        points: &[P],
+        normals: &[[f32; D]],
+        query_point_axis_values: [f32; D],
+    ) -> f32 {
+        assert_eq!(
+            points.len(),
+            normals.len(),
+            "one normal per point is required"
+        );
+
+        let mut heap = BinaryHeap::with_capacity(2);
+        let mut stack = vec![];
+        self.nearest_n_axis_values_buffers(
+            points,
+            &query_point_axis_values,
+            1,
+            f32::INFINITY,
+            &mut heap,
+            &mut stack,
+        );
+
+        match heap.pop() {
+            Some(nearest) => signed_distance_to(
+                &points[nearest.index],
+                &normals[nearest.index],
+                &query_point_axis_values,
+            ),
+            None => f32::INFINITY,
+        }
+    }
+
+    /// See `KdTree`
+    pub fn signed_distance_grid(
+        &self,
+        points: &[P],
+        normals: &[[f32; D]],
+        grid_min: [f32; D],
+        grid_max: [f32; D],
+        resolution: [usize; D],
+    ) -> Vec<f32> {
+        assert_eq!(
+            points.len(),
+            normals.len(),
+            "one normal per point is required"
+        );
+
+        let cell_count = resolution.iter().product::<usize>();
+        if self.tree.is_empty() {
+            return vec![f32::INFINITY; cell_count];
+        }
+
+        let mut cell_size = [0.0; D];
+        for axis in 0..D {
+            cell_size[axis] = (grid_max[axis] - grid_min[axis]) / resolution[axis] as f32;
+        }
+
+        let mut result = Vec::with_capacity(cell_count);
+        let mut cell = [0; D];
+        self.sweep_grid(points, grid_min, grid_max, resolution, 1, |neighbors| {
+            let mut center = [0.0; D];
+            for axis in 0..D {
+                center[axis] = grid_min[axis] + (cell[axis] as f32 + 0.5) * cell_size[axis];
+            }
+
+            let nearest = neighbors[0].index;
+            result.push(signed_distance_to(
+                &points[nearest],
+                &normals[nearest],
+                &center,
+            ));
+
+            for axis in 0..D {
+                cell[axis] += 1;
+                if cell[axis] < resolution[axis] {
+                    break;
+                }
+                cell[axis] = 0;
+            }
+        });
+
+        result
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Samples the signed distance field of an oriented point cloud at `query_point_axis_values`,
+    /// where `normals[i]` is the outward normal of the surface at point `i`. The magnitude is the
+    /// distance to the nearest point and the sign is negative when the query lies behind that
+    /// point as seen along its normal, i.e. inside the surface. Returns infinity for an empty tree.
+    ///
+    /// The normals don't need to be normalized. Keyde doesn't estimate normals, they are
+    /// typically available from the scanner or mesh the points were sampled from.
+    pub fn signed_distance(&self, normals: &[[f32; D]], query_point_axis_values: [f32; D]) -> f32 {
+        self.internal
+            .signed_distance(self.points, normals, query_point_axis_values)
+    }
+
+    /// Samples `signed_distance` at the center of every cell of the regular grid spanning
+    /// `grid_min..grid_max`, with the cells in the same order as `classify_grid`. The result can
+    /// be fed directly to a marching cubes implementation.
+    pub fn signed_distance_grid(
+        &self,
+        normals: &[[f32; D]],
+        grid_min: [f32; D],
+        grid_max: [f32; D],
+        resolution: [usize; D],
+    ) -> Vec<f32> {
+        self.internal
+            .signed_distance_grid(self.points, normals, grid_min, grid_max, resolution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::KdTree;
+
+    #[test]
+    fn test_signed_distance_of_circle() {
+        let (points, normals): (Vec<[f32; 2]>, Vec<[f32; 2]>) = (0..360)
+            .map(|i| {
+                let angle = (i as f32).to_radians();
+                let normal = [angle.cos(), angle.sin()];
+                ([normal[0] * 2.0, normal[1] * 2.0], normal)
+            })
+            .unzip();
+        let tree = KdTree::from_points(&points);
+
+        assert!((tree.signed_distance(&normals, [0.0, 0.0]) + 2.0).abs() < 1e-3);
+        assert!((tree.signed_distance(&normals, [3.0, 0.0]) - 1.0).abs() < 1e-3);
+        assert!((tree.signed_distance(&normals, [0.0, -1.5]) + 0.5).abs() < 1e-3);
+
+        let grid = tree.signed_distance_grid(&normals, [-4.0, -4.0], [4.0, 4.0], [8, 8]);
+        assert_eq!(grid.len(), 64);
+        for y in 0..8 {
+            for x in 0..8 {
+                let center = [x as f32 - 3.5, y as f32 - 3.5];
+                assert_eq!(grid[y * 8 + x], tree.signed_distance(&normals, center));
+            }
+        }
+    }
+}
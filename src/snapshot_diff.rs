@@ -0,0 +1,113 @@
+//! Finding what changed between two snapshots of a point set
+use crate::matching::greedy_match_with_tree;
+use crate::{KdTreeNoBorrow, Point};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+/// The changes between an old and a new snapshot of a point set, see `diff`
+pub struct SnapshotDiff {
+    /// Indices into the new points of points without a counterpart in the old ones
+    pub appeared: Vec<usize>,
+    /// Indices into the old points of points without a counterpart in the new ones
+    pub disappeared: Vec<usize>,
+    /// `(old_index, new_index)` of points that moved, but by no more than `epsilon`
+    pub moved: Vec<(usize, usize)>,
+}
+
+impl SnapshotDiff {
+    /// Whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.appeared.is_empty() && self.disappeared.is_empty() && self.moved.is_empty()
+    }
+}
+
+/// Compares two snapshots of a point set, typically taken on successive frames, where the points
+/// carry no identity of their own. Old and new points are paired up one-to-one, closest first,
+/// as long as they are within `epsilon` of each other (see `greedy_match`). Paired points at the
+/// exact same position are unchanged, other pairs have moved and unpaired points have appeared
+/// or disappeared.
+///
+/// The points of the smaller snapshot are matched against the tree of the larger one, so the
+/// cost is about one nearest neighbor query per point of the smaller snapshot. All lists are
+/// sorted.
+pub fn diff<const D: usize, P>(
+    old_tree: &KdTreeNoBorrow<D, P>,
+    new_tree: &KdTreeNoBorrow<D, P>,
+    points_old: &[P],
+    points_new: &[P],
+    epsilon: f32,
+) -> SnapshotDiff
+where
+    P: Point<D>,
+{
+    let pairs = if points_old.len() <= points_new.len() {
+        greedy_match_with_tree(points_old, new_tree, points_new, epsilon)
+    } else {
+        greedy_match_with_tree(points_new, old_tree, points_old, epsilon)
+            .into_iter()
+            .map(|(new, old)| (old, new))
+            .collect()
+    };
+
+    let mut old_paired = vec![false; points_old.len()];
+    let mut new_paired = vec![false; points_new.len()];
+    let mut moved = vec![];
+
+    for (old, new) in pairs {
+        old_paired[old] = true;
+        new_paired[new] = true;
+        if points_old[old].distance_squared(points_new[new]) > 0.0 {
+            moved.push((old, new));
+        }
+    }
+    moved.sort_unstable();
+
+    SnapshotDiff {
+        appeared: (0..points_new.len()).filter(|i| !new_paired[*i]).collect(),
+        disappeared: (0..points_old.len()).filter(|i| !old_paired[*i]).collect(),
+        moved,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let points_old = vec![
+            [0.0_f32, 0.0],
+            [1.0, 0.0],
+            [5.0, 5.0],
+            [9.0, 9.0],
+            [1.0, 0.0],
+        ];
+        let points_new = vec![
+            [1.0_f32, 0.0],
+            [0.05, 0.0],
+            [9.0, 9.0],
+            [20.0, 20.0],
+            [1.0, 0.0],
+            [30.0, 30.0],
+        ];
+
+        let old_tree = KdTreeNoBorrow::from_points(&points_old);
+        let new_tree = KdTreeNoBorrow::from_points(&points_new);
+
+        let expected = SnapshotDiff {
+            appeared: vec![3, 5],
+            disappeared: vec![2],
+            moved: vec![(0, 1)],
+        };
+        assert_eq!(
+            diff(&old_tree, &new_tree, &points_old, &points_new, 0.1),
+            expected
+        );
+
+        let reversed = diff(&new_tree, &old_tree, &points_new, &points_old, 0.1);
+        assert_eq!(reversed.appeared, vec![2]);
+        assert_eq!(reversed.disappeared, vec![3, 5]);
+        assert_eq!(reversed.moved, vec![(1, 0)]);
+
+        assert!(diff(&old_tree, &old_tree, &points_old, &points_old, 0.1).is_empty());
+    }
+}
@@ -0,0 +1,193 @@
+//! A Kd-tree of spheres, where every point has its own radius
+use crate::utils::axis_values;
+use crate::{KdTreeNoBorrow, Point};
+
+#[derive(Debug, Clone)]
+/// A Kd-tree that owns a set of spheres, given by their centers in `points` and their radii
+/// in `radii`, such as proximity triggers or audio emitters with different ranges.
+///
+/// The largest radius within every subtree is kept in `subtree_max_radius`, indexed like the
+/// nodes of `internal`, so that a few large spheres don't slow down queries near the small
+/// ones. Queries on the centers alone can be run on `internal` with `&points`.
+pub struct KdTreeSpheres<const D: usize, P: Point<D>> {
+    pub internal: KdTreeNoBorrow<D, P>,
+    pub points: Vec<P>,
+    pub radii: Vec<f32>,
+    pub subtree_max_radius: Vec<f32>,
+}
+
+impl<const D: usize, P: Point<D>> KdTreeSpheres<D, P> {
+    /// Constructs a new tree of the spheres centered at `points` using default settings, where
+    /// `radii[i]` is the radius of the sphere at `points[i]`
+    pub fn from_spheres(points: Vec<P>, radii: Vec<f32>) -> Self {
+        Self::from_tree(KdTreeNoBorrow::from_points(&points), points, radii)
+    }
+
+    /// Wraps `internal`, which was built from the centers `points`. Use this to build with
+    /// `KdTreeBuilder`.
+    pub fn from_tree(internal: KdTreeNoBorrow<D, P>, points: Vec<P>, radii: Vec<f32>) -> Self {
+        assert_eq!(
+            points.len(),
+            radii.len(),
+            "one radius per point is required"
+        );
+
+        let subtree_max_radius = internal.subtree_max_radius(&radii);
+
+        Self {
+            internal,
+            points,
+            radii,
+            subtree_max_radius,
+        }
+    }
+
+    /// Returns the centers and radii, dropping the tree
+    pub fn into_parts(self) -> (Vec<P>, Vec<f32>) {
+        (self.points, self.radii)
+    }
+
+    #[inline(always)]
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    #[inline(always)]
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Same as `spheres_intersecting_sphere`, but you provide your own buffers. Indices are
+    /// appended to `result` which is not cleared. `stack` is assumed to be empty and is left
+    /// empty.
+    pub fn spheres_intersecting_sphere_buffers(
+        &self,
+        center: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        if self.internal.tree.is_empty() {
+            return;
+        }
+
+        let tree = &self.internal.tree;
+        let center_axis_values = axis_values(&center);
+
+        stack.push((0, 0));
+        while let Some((depth, tree_index)) = stack.pop() {
+            let reach = radius + self.subtree_max_radius[tree_index];
+            if self
+                .internal
+                .sphere_out_of_reach(tree_index, &center_axis_values, reach)
+            {
+                continue;
+            }
+
+            let point_index = tree[tree_index].index();
+            let point = self.points[point_index];
+
+            let touching = radius + self.radii[point_index];
+            if center.distance_squared(point) <= touching * touching {
+                result.push(point_index);
+            }
+
+            let axis = tree[tree_index].axis();
+            let axis_d = tree[tree_index].split - center_axis_values[axis];
+
+            let first = if axis_d >= 0.0 { 0 } else { 1 };
+            let last = (first + 1) % 2;
+
+            if let Some(child) = tree[tree_index].child(last) {
+                let reach = radius + self.subtree_max_radius[child];
+                if axis_d * axis_d <= reach * reach {
+                    stack.push((depth + 1, child));
+                }
+            }
+            if let Some(child) = tree[tree_index].child(first) {
+                stack.push((depth + 1, child));
+            }
+        }
+    }
+
+    /// Returns the indices of the spheres intersecting the sphere of `radius` around `center`,
+    /// touching included, for instance the emitters that can be heard by a listener that has a
+    /// range of its own.
+    pub fn spheres_intersecting_sphere(&self, center: P, radius: f32) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.spheres_intersecting_sphere_buffers(center, radius, &mut result, &mut stack);
+
+        result
+    }
+
+    /// Same as `spheres_containing`, but you provide your own buffers, see
+    /// `spheres_intersecting_sphere_buffers`
+    #[inline(always)]
+    pub fn spheres_containing_buffers(
+        &self,
+        query_point: P,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.spheres_intersecting_sphere_buffers(query_point, 0.0, result, stack)
+    }
+
+    /// Returns the indices of the spheres containing `query_point`, boundary included, for
+    /// instance the proximity triggers an entity is inside of
+    #[inline(always)]
+    pub fn spheres_containing(&self, query_point: P) -> Vec<usize> {
+        self.spheres_intersecting_sphere(query_point, 0.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_sphere_queries() {
+        let mut rng = SplitMix64::new(53);
+        let points = (0..2000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                    (rng.next_u64() % 100) as f32,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let radii = (0..points.len())
+            .map(|i| {
+                if i % 200 == 0 {
+                    30.0
+                } else {
+                    (rng.next_u64() % 100) as f32 / 10.0
+                }
+            })
+            .collect::<Vec<_>>();
+        let spheres = KdTreeSpheres::from_spheres(points.clone(), radii.clone());
+
+        for (center, radius) in [([50.0, 50.0, 50.0], 0.0), ([10.0, 90.0, 3.0], 4.5)] {
+            let expected = (0..points.len())
+                .filter(|i| {
+                    let touching = radius + radii[*i];
+                    points[*i].distance_squared(center) <= touching * touching
+                })
+                .collect::<Vec<_>>();
+            assert!(expected.len() > 1);
+
+            let mut result = spheres.spheres_intersecting_sphere(center, radius);
+            result.sort_unstable();
+            assert_eq!(result, expected);
+
+            if radius == 0.0 {
+                let mut result = spheres.spheres_containing(center);
+                result.sort_unstable();
+                assert_eq!(result, expected);
+            }
+        }
+    }
+}
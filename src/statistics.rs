@@ -0,0 +1,300 @@
+//! Spatial statistics computed over the points of a tree
+use crate::nearest::distance_squared_to_axis_values;
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How to compensate for pairs of points that are missed because their neighbors lie outside
+/// of the observed region, which is taken to be the bounding box of the points
+pub enum EdgeCorrection {
+    /// No correction, points close to the edges have fewer neighbors and bias `K` downwards
+    #[default]
+    None,
+    /// Ohser-Stoyan translation correction: each pair is weighted by the volume of the region
+    /// divided by the volume of its intersection with a copy translated by the pair's offset
+    Translation,
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn ripley_k(
+        &self,
+        points: &[P],
+        radii: &[f32],
+        edge_correction: EdgeCorrection,
+    ) -> Vec<f32> {
+        let n = points.len();
+        let mut sums = vec![0.0_f64; radii.len()];
+
+        let extents = std::array::from_fn::<f32, D, _>(|axis| {
+            (self.bounds_max[axis] - self.bounds_min[axis]).max(0.0)
+        });
+        let volume = extents.iter().map(|extent| *extent as f64).product::<f64>();
+
+        if n < 2 || volume <= 0.0 || radii.is_empty() {
+            return vec![0.0; radii.len()];
+        }
+
+        let max_radius = radii.iter().copied().fold(0.0_f32, f32::max);
+        let mut neighbors = vec![];
+        let mut stack = vec![];
+
+        for (i, point) in points.iter().enumerate() {
+            neighbors.clear();
+            self.point_indices_within_buffers(
+                points,
+                *point,
+                max_radius,
+                &mut neighbors,
+                &mut stack,
+            );
+
+            for &j in &neighbors {
+                if j == i {
+                    continue;
+                }
+
+                let weight = match edge_correction {
+                    EdgeCorrection::None => 1.0,
+                    EdgeCorrection::Translation => {
+                        let overlap = (0..D)
+                            .map(|axis| {
+                                let delta = (point.get_axis(axis) - points[j].get_axis(axis)).abs();
+                                (extents[axis] - delta) as f64
+                            })
+                            .product::<f64>();
+
+                        /*
+                            NOTE: A pair spanning the entire region can't be observed at
+                                  all under translation, so it carries no usable weight
+                        */
+                        if overlap <= 0.0 {
+                            continue;
+                        }
+
+                        volume / overlap
+                    }
+                };
+
+                let distance = point.distance_squared(points[j]).sqrt();
+                for (sum, radius) in sums.iter_mut().zip(radii) {
+                    if distance <= *radius {
+                        *sum += weight;
+                    }
+                }
+            }
+        }
+
+        let scale = volume / (n as f64 * (n - 1) as f64);
+        sums.into_iter().map(|sum| (sum * scale) as f32).collect()
+    }
+
+    /// See `KdTree`
+    pub fn centroid(&self, points: &[P]) -> Option<[f32; D]> {
+        centroid_f64(points).map(|centroid| centroid.map(|value| value as f32))
+    }
+
+    /// See `KdTree`
+    pub fn axis_variance(&self, points: &[P]) -> Option<[f32; D]> {
+        let centroid = centroid_f64(points)?;
+
+        let mut sums = [0.0_f64; D];
+        for point in points {
+            for (axis, sum) in sums.iter_mut().enumerate() {
+                let delta = point.get_axis(axis) as f64 - centroid[axis];
+                *sum += delta * delta;
+            }
+        }
+
+        Some(sums.map(|sum| (sum / points.len() as f64) as f32))
+    }
+
+    /// See `KdTree`
+    pub fn min_enclosing_ball(&self, points: &[P]) -> Option<([f32; D], f32)> {
+        let first = *points.first()?;
+
+        /*
+            NOTE: Ritter's algorithm. The initial ball spans the farthest point from an
+                  arbitrary point and the farthest point from that one, an estimate of the
+                  diameter of the set.
+        */
+        let a = points[self.farthest_n(points, first, 1)[0]];
+        let b = points[self.farthest_n(points, a, 1)[0]];
+
+        let a_values = axis_values(&a).map(|value| value as f64);
+        let b_values = axis_values(&b).map(|value| value as f64);
+        let mut center =
+            std::array::from_fn::<f64, D, _>(|axis| (a_values[axis] + b_values[axis]) * 0.5);
+        let mut radius = distance_f64(&center, &a_values);
+
+        for point in points {
+            let values = axis_values(point).map(|value| value as f64);
+            let distance = distance_f64(&center, &values);
+            if distance <= radius {
+                continue;
+            }
+
+            /*
+                NOTE: Grow the ball just enough to touch `point` while still containing the
+                      previous ball
+            */
+            let new_radius = (radius + distance) * 0.5;
+            let shift = (new_radius - radius) / distance;
+            for axis in 0..D {
+                center[axis] += (values[axis] - center[axis]) * shift;
+            }
+            radius = new_radius;
+        }
+
+        /*
+            NOTE: The radius is measured again in `f32` from the rounded center so that every
+                  point is guaranteed to be inside
+        */
+        let center = center.map(|value| value as f32);
+        let radius_squared = points
+            .iter()
+            .map(|point| distance_squared_to_axis_values(point, &center))
+            .fold(0.0_f32, f32::max);
+
+        Some((center, radius_squared.sqrt()))
+    }
+}
+
+fn centroid_f64<const D: usize, P: Point<D>>(points: &[P]) -> Option<[f64; D]> {
+    if points.is_empty() {
+        return None;
+    }
+
+    let mut sums = [0.0_f64; D];
+    for point in points {
+        for (axis, sum) in sums.iter_mut().enumerate() {
+            *sum += point.get_axis(axis) as f64;
+        }
+    }
+
+    Some(sums.map(|sum| sum / points.len() as f64))
+}
+
+#[inline(always)]
+fn distance_f64<const D: usize>(a: &[f64; D], b: &[f64; D]) -> f64 {
+    (0..D)
+        .map(|axis| (a[axis] - b[axis]) * (a[axis] - b[axis]))
+        .sum::<f64>()
+        .sqrt()
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Estimates Ripley's `K` function for each radius in `radii`, a measure of clustering at
+    /// different scales. For points without any spatial structure (a Poisson process), `K(r)`
+    /// is the volume of a hypersphere of radius `r`, e.g. `π r²` in 2D. Values above that
+    /// indicate clustering and values below regularity.
+    ///
+    /// The observed region is taken to be the bounding box of the points. The neighbor counting
+    /// uses one radius query per point with the largest radius.
+    #[inline(always)]
+    pub fn ripley_k(&self, radii: &[f32]) -> Vec<f32> {
+        self.internal
+            .ripley_k(self.points, radii, EdgeCorrection::None)
+    }
+
+    /// Same as `ripley_k`, but with the given `EdgeCorrection`
+    #[inline(always)]
+    pub fn ripley_k_with_edge_correction(
+        &self,
+        radii: &[f32],
+        edge_correction: EdgeCorrection,
+    ) -> Vec<f32> {
+        self.internal.ripley_k(self.points, radii, edge_correction)
+    }
+
+    /// The mean of the points, or `None` if there are none
+    #[inline(always)]
+    pub fn centroid(&self) -> Option<[f32; D]> {
+        self.internal.centroid(self.points)
+    }
+
+    /// The variance of the points along every axis around the `centroid`, or `None` if there
+    /// are no points. This is the population variance, i.e. divided by the number of points.
+    #[inline(always)]
+    pub fn axis_variance(&self) -> Option<[f32; D]> {
+        self.internal.axis_variance(self.points)
+    }
+
+    /// A ball `(center, radius)` containing every point, or `None` if there are no points.
+    ///
+    /// This is Ritter's approximation, which is typically within a few percent and at worst
+    /// around 20% larger than the true minimal ball. It runs two farthest point queries and one
+    /// linear pass over the points, and the returned ball is guaranteed to contain every point.
+    #[inline(always)]
+    pub fn min_enclosing_ball(&self) -> Option<([f32; D], f32)> {
+        self.internal.min_enclosing_ball(self.points)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ripley_k() {
+        let points: [[f32; 2]; 3] = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]];
+        let tree = KdTree::from_points(&points);
+
+        let k = tree.ripley_k(&[0.5, 1.0, 1.5]);
+        assert_eq!(k, vec![0.0, 4.0 / 6.0, 1.0]);
+    }
+
+    #[test]
+    fn test_ripley_k_edge_correction() {
+        let points: Vec<[f32; 2]> = (0..400)
+            .map(|i| [(i % 20) as f32, (i / 20) as f32])
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        let radii = [1.0, 2.0, 3.0];
+        let plain = tree.ripley_k(&radii);
+        let corrected = tree.ripley_k_with_edge_correction(&radii, EdgeCorrection::Translation);
+
+        for (plain, corrected) in plain.iter().zip(&corrected) {
+            assert!(corrected > plain);
+        }
+
+        /*
+            NOTE: Every point of an infinite unit grid has 4 neighbors at distance 1 and the
+                  region has 19 * 19 area for 400 points
+        */
+        let expected = 4.0 * 19.0 * 19.0 / 399.0;
+        assert!((corrected[0] - expected).abs() < 0.01 * expected);
+    }
+
+    #[test]
+    fn test_summaries() {
+        let points: [[f32; 2]; 4] = [[0.0, 0.0], [2.0, 0.0], [0.0, 2.0], [2.0, 2.0]];
+        let tree = KdTree::from_points(&points);
+
+        assert_eq!(tree.centroid(), Some([1.0, 1.0]));
+        assert_eq!(tree.axis_variance(), Some([1.0, 1.0]));
+
+        let (center, radius) = tree.min_enclosing_ball().unwrap();
+        assert!((center[0] - 1.0).abs() < 1e-5 && (center[1] - 1.0).abs() < 1e-5);
+        assert!((radius - 2.0_f32.sqrt()).abs() < 1e-5);
+
+        let points: Vec<[f32; 3]> = (0..500)
+            .map(|i| {
+                let i = i as f32;
+                [
+                    (i * 0.37).sin() * 4.0,
+                    (i * 0.11).cos() * 7.0,
+                    (i * 0.05).sin(),
+                ]
+            })
+            .collect();
+        let tree = KdTree::from_points(&points);
+
+        let (center, radius) = tree.min_enclosing_ball().unwrap();
+        for point in &points {
+            assert!(point.distance_squared(center) <= radius * radius);
+        }
+        assert!(radius <= 7.0 * 1.2);
+    }
+}
@@ -0,0 +1,249 @@
+//! Queries with spheres moving along a line segment
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+/// The squared distance from `point` to the segment from `start` to `end`
+#[inline(always)]
+pub(crate) fn distance_squared_to_segment<const D: usize>(
+    point: &[f32; D],
+    start: &[f32; D],
+    end: &[f32; D],
+) -> f32 {
+    let mut along = 0.0;
+    let mut length_squared = 0.0;
+    for axis in 0..D {
+        let direction = end[axis] - start[axis];
+        along += (point[axis] - start[axis]) * direction;
+        length_squared += direction * direction;
+    }
+
+    let t = if length_squared > 0.0 {
+        (along / length_squared).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+
+    (0..D)
+        .map(|axis| {
+            let closest = start[axis] + (end[axis] - start[axis]) * t;
+            let delta = point[axis] - closest;
+            delta * delta
+        })
+        .sum()
+}
+
+/// The earliest `t` in `0..=1` where a sphere of `radius` centered at `start + t * (end - start)`
+/// touches `point`, assuming that it does
+#[inline(always)]
+fn entry_time<const D: usize>(
+    point: &[f32; D],
+    start: &[f32; D],
+    end: &[f32; D],
+    radius: f32,
+) -> f32 {
+    let mut a = 0.0;
+    let mut b = 0.0;
+    let mut c = -radius * radius;
+    for axis in 0..D {
+        let direction = end[axis] - start[axis];
+        let offset = start[axis] - point[axis];
+        a += direction * direction;
+        b += 2.0 * offset * direction;
+        c += offset * offset;
+    }
+
+    if c <= 0.0 || a == 0.0 {
+        return 0.0;
+    }
+
+    let discriminant = (b * b - 4.0 * a * c).max(0.0);
+    ((-b - discriminant.sqrt()) / (2.0 * a)).clamp(0.0, 1.0)
+}
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// See `KdTree`
+    pub fn point_indices_hit_by_swept_sphere_buffers(
+        &self,
+        points: &[P],
+        start: P,
+        end: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        if self.tree.is_empty() {
+            return;
+        }
+
+        let start = axis_values(&start);
+        let end = axis_values(&end);
+        let radius_squared = radius * radius;
+
+        stack.push((0, 0));
+        while let Some((depth, tree_index)) = stack.pop() {
+            let point_index = self.tree[tree_index].index();
+            let point = axis_values(&points[point_index]);
+
+            if distance_squared_to_segment(&point, &start, &end) <= radius_squared {
+                result.push(point_index);
+            }
+
+            /*
+                NOTE: The swept sphere covers `low..=high` along the splitting axis, which
+                      decides which sides of the split it can reach.
+            */
+            let axis = self.tree[tree_index].axis();
+            let low = start[axis].min(end[axis]) - radius;
+            let high = start[axis].max(end[axis]) + radius;
+
+            let [left, right] = self.tree[tree_index].children();
+            if high >= self.tree[tree_index].split {
+                if let Some(child) = right {
+                    stack.push((depth + 1, child));
+                }
+            }
+            if low <= self.tree[tree_index].split {
+                if let Some(child) = left {
+                    stack.push((depth + 1, child));
+                }
+            }
+        }
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_hit_by_swept_sphere(
+        &self,
+        points: &[P],
+        start: P,
+        end: P,
+        radius: f32,
+    ) -> Vec<usize> {
+        let mut result = vec![];
+        let mut stack = vec![];
+
+        self.point_indices_hit_by_swept_sphere_buffers(
+            points,
+            start,
+            end,
+            radius,
+            &mut result,
+            &mut stack,
+        );
+
+        result
+    }
+
+    /// See `KdTree`
+    pub fn swept_sphere_hits_by_entry_time(
+        &self,
+        points: &[P],
+        start: P,
+        end: P,
+        radius: f32,
+    ) -> Vec<(usize, f32)> {
+        let start_axis_values = axis_values(&start);
+        let end_axis_values = axis_values(&end);
+
+        let mut hits = self
+            .point_indices_hit_by_swept_sphere(points, start, end, radius)
+            .into_iter()
+            .map(|index| {
+                let point = axis_values(&points[index]);
+                let t = entry_time(&point, &start_axis_values, &end_axis_values, radius);
+                (index, t)
+            })
+            .collect::<Vec<_>>();
+
+        hits.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        hits
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `point_indices_hit_by_swept_sphere`, but you provide your own buffers. Indices are
+    /// appended to `result` which is not cleared. `stack` is assumed to be empty and is left empty.
+    #[inline(always)]
+    pub fn point_indices_hit_by_swept_sphere_buffers(
+        &self,
+        start: P,
+        end: P,
+        radius: f32,
+        result: &mut Vec<usize>,
+        stack: &mut Vec<(usize, usize)>,
+    ) {
+        self.internal.point_indices_hit_by_swept_sphere_buffers(
+            self.points,
+            start,
+            end,
+            radius,
+            result,
+            stack,
+        )
+    }
+
+    /// Returns the indices of the points touched by a sphere of `radius` moving from `start` to
+    /// `end`, i.e. the points whose euclidean distance to the segment between them is at most
+    /// `radius`. Useful for character controller and projectile sweeps.
+    #[inline(always)]
+    pub fn point_indices_hit_by_swept_sphere(&self, start: P, end: P, radius: f32) -> Vec<usize> {
+        self.internal
+            .point_indices_hit_by_swept_sphere(self.points, start, end, radius)
+    }
+
+    /// Same as `point_indices_hit_by_swept_sphere`, but every index comes with the fraction of
+    /// the sweep, from `0.0` at `start` to `1.0` at `end`, where the sphere first touches the
+    /// point. The hits are ordered by that entry time, so the first one is what the sphere runs
+    /// into first. Points the sphere already overlaps at `start` enter at `0.0`.
+    pub fn swept_sphere_hits_by_entry_time(
+        &self,
+        start: P,
+        end: P,
+        radius: f32,
+    ) -> Vec<(usize, f32)> {
+        self.internal
+            .swept_sphere_hits_by_entry_time(self.points, start, end, radius)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_swept_sphere() {
+        let mut rng = SplitMix64::new(31);
+        let points = (0..3000)
+            .map(|_| {
+                [
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                    (rng.next_u64() % 1000) as f32 / 10.0,
+                ]
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        for (start, end) in [
+            ([10.0, 10.0], [90.0, 60.0]),
+            ([50.0, 50.0], [50.0, 50.0]),
+            ([0.0, 99.0], [99.0, 0.0]),
+        ] {
+            let expected = (0..points.len())
+                .filter(|i| distance_squared_to_segment(&points[*i], &start, &end) <= 3.0 * 3.0)
+                .collect::<Vec<_>>();
+
+            let mut result = tree.point_indices_hit_by_swept_sphere(start, end, 3.0);
+            result.sort_unstable();
+            assert_eq!(result, expected);
+
+            let hits = tree.swept_sphere_hits_by_entry_time(start, end, 3.0);
+            assert_eq!(hits.len(), expected.len());
+            assert!(hits.windows(2).all(|w| w[0].1 <= w[1].1));
+        }
+
+        let points = [[5.0_f32, 1.0], [2.0, 0.0], [8.0, -2.0], [0.0, 0.5]];
+        let tree = KdTree::from_points(&points);
+        let hits = tree.swept_sphere_hits_by_entry_time([0.0, 0.0], [10.0, 0.0], 1.0);
+        assert_eq!(hits, vec![(3, 0.0), (1, 0.1), (0, 0.5)]);
+    }
+}
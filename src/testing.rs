@@ -0,0 +1,115 @@
+//! Helpers for property testing code built on keyde: `proptest` strategies generating point
+//! sets, trees and query workloads, plus brute-force references to cross-check results against.
+use crate::{KdTree, KdTreeNoBorrow, Point};
+
+use proptest::prelude::*;
+
+/// Indices of all `points` within `radius` of `query_point`, found by checking every point.
+/// The indices are sorted.
+pub fn brute_force_point_indices_within<const D: usize, P>(
+    points: &[P],
+    query_point: P,
+    radius: f32,
+) -> Vec<usize>
+where
+    P: Point<D>,
+{
+    let radius_squared = radius * radius;
+
+    (0..points.len())
+        .filter(|&i| query_point.distance_squared(points[i]) <= radius_squared)
+        .collect()
+}
+
+/// Runs `point_indices_within` on `tree` and compares the result against
+/// `brute_force_point_indices_within`. On mismatch, the error describes which indices were
+/// missing or unexpected.
+pub fn cross_check_point_indices_within<const D: usize, P>(
+    tree: &KdTree<D, P>,
+    query_point: P,
+    radius: f32,
+) -> Result<(), String>
+where
+    P: Point<D>,
+{
+    let mut result = tree.point_indices_within(query_point, radius);
+    result.sort_unstable();
+    let expected = brute_force_point_indices_within(tree.points, query_point, radius);
+
+    if result == expected {
+        return Ok(());
+    }
+
+    let missing = expected
+        .iter()
+        .filter(|i| result.binary_search(i).is_err())
+        .collect::<Vec<_>>();
+    let unexpected = result
+        .iter()
+        .filter(|i| expected.binary_search(i).is_err())
+        .collect::<Vec<_>>();
+
+    Err(format!(
+        "query {query_point:?} with radius {radius}: missing {missing:?}, unexpected {unexpected:?}"
+    ))
+}
+
+/// Points with every axis in `coordinates`. Coordinates are finite.
+pub fn point_strategy<const D: usize>(
+    coordinates: std::ops::Range<f32>,
+) -> impl Strategy<Value = [f32; D]> {
+    proptest::array::uniform::<_, D>(coordinates)
+}
+
+/// Point sets with a number of points in `len` and every axis in `coordinates`
+pub fn points_strategy<const D: usize>(
+    len: std::ops::Range<usize>,
+    coordinates: std::ops::Range<f32>,
+) -> impl Strategy<Value = Vec<[f32; D]>> {
+    proptest::collection::vec(point_strategy::<D>(coordinates), len)
+}
+
+/// Query workloads of `(query_point, radius)` pairs with the query points in `coordinates` and
+/// radii in `radii`
+pub fn queries_strategy<const D: usize>(
+    len: std::ops::Range<usize>,
+    coordinates: std::ops::Range<f32>,
+    radii: std::ops::Range<f32>,
+) -> impl Strategy<Value = Vec<([f32; D], f32)>> {
+    proptest::collection::vec((point_strategy::<D>(coordinates), radii), len)
+}
+
+/// Non-empty point sets together with a `KdTreeNoBorrow` built from them
+pub fn tree_strategy<const D: usize>(
+    len: std::ops::Range<usize>,
+    coordinates: std::ops::Range<f32>,
+) -> impl Strategy<Value = (Vec<[f32; D]>, KdTreeNoBorrow<D, [f32; D]>)>
+where
+    [f32; D]: Point<D>,
+{
+    let len = len.start.max(1)..len.end.max(2);
+
+    points_strategy::<D>(len, coordinates).prop_map(|points| {
+        let tree = KdTreeNoBorrow::from_points(&points);
+        (points, tree)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    proptest! {
+        #[test]
+        fn test_radius_queries_match_brute_force(
+            (points, tree) in tree_strategy::<3>(1..200, -10.0..10.0),
+            queries in queries_strategy::<3>(1..10, -12.0..12.0, 0.0..6.0),
+        ) {
+            let tree = KdTree { internal: tree, points: &points };
+
+            for (query_point, radius) in queries {
+                prop_assert_eq!(cross_check_point_indices_within(&tree, query_point, radius), Ok(()));
+            }
+        }
+    }
+}
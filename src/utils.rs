@@ -5,22 +5,70 @@ pub use heap_sort::*;
 pub use quicksort::*;
 pub use shell_sort::*;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 /// Depending on the nature of your data, some strategies might work better than others
 pub enum SortingStrategy {
     StableSort,
     UnstableSort,
     ShellSort,
     HeapSort,
+    #[default]
     QuickSort,
+    /// Only partitions each range around its median using `median_select` instead of sorting
+    /// it, which is all construction needs. Builds the same tree as the other strategies.
+    /// Presorted construction needs fully sorted arrays and sorts those with `UnstableSort`.
+    MedianSelect,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// How the axis each node splits its subtree along is chosen
+pub enum SplitStrategy {
+    /// Cycles through the axes by depth, splitting along `depth % D`
+    #[default]
+    RoundRobin,
+    /// Splits along the axis the points of the subtree are spread the most along. This keeps
+    /// the cells of the tree from getting long and thin on elongated data, where cycling
+    /// through the axes prunes poorly, at the cost of a pass over the points per level.
+    WidestAxis,
 }
 
-impl Default for SortingStrategy {
-    fn default() -> Self {
-        Self::QuickSort
+impl SplitStrategy {
+    /// The axis to split the points `ids` at `depth` along
+    pub fn split_axis<P, const D: usize>(&self, points: &[P], ids: &[usize], depth: usize) -> usize
+    where
+        P: Point<D>,
+    {
+        match self {
+            SplitStrategy::RoundRobin => depth % D,
+            SplitStrategy::WidestAxis => {
+                let mut min = [f32::INFINITY; D];
+                let mut max = [f32::NEG_INFINITY; D];
+                for id in ids {
+                    let point = axis_values(&points[*id]);
+                    for axis in 0..D {
+                        min[axis] = min[axis].min(point[axis]);
+                        max[axis] = max[axis].max(point[axis]);
+                    }
+                }
+
+                widest_axis::<D>(|axis| max[axis] - min[axis])
+            }
+        }
     }
 }
 
+/// The first axis with the largest `extent`
+pub(crate) fn widest_axis<const D: usize>(extent: impl Fn(usize) -> f32) -> usize {
+    let mut widest = (0, f32::NEG_INFINITY);
+    for axis in 0..D {
+        let axis_extent = extent(axis);
+        if axis_extent > widest.1 {
+            widest = (axis, axis_extent);
+        }
+    }
+    widest.0
+}
+
 /*
     TODO: Decouple sorting from Point trait.
 
@@ -36,6 +84,22 @@ impl Default for SortingStrategy {
           All sorting methods using `Points` could then utilize `point_axis_compare`
 */
 
+/// Returns the sorting function used by `strategy`
+#[inline]
+pub fn points_sorter<P, const D: usize>(strategy: &SortingStrategy) -> fn(&[P], &mut [usize], usize)
+where
+    P: Point<D>,
+{
+    match strategy {
+        SortingStrategy::StableSort => stable_sort,
+        SortingStrategy::UnstableSort => unstable_sort,
+        SortingStrategy::ShellSort => shell_sort,
+        SortingStrategy::HeapSort => heap_sort,
+        SortingStrategy::QuickSort => quick_sort,
+        SortingStrategy::MedianSelect => median_select,
+    }
+}
+
 #[inline]
 pub fn sort_using_strategy<P, const D: usize>(
     points: &[P],
@@ -51,9 +115,72 @@ pub fn sort_using_strategy<P, const D: usize>(
         SortingStrategy::ShellSort => shell_sort(points, indices, axis),
         SortingStrategy::HeapSort => heap_sort(points, indices, axis),
         SortingStrategy::QuickSort => quick_sort(points, indices, axis),
+        SortingStrategy::MedianSelect => median_select(points, indices, axis),
     };
 }
 
+#[derive(Debug, Clone)]
+/// A small SplitMix64 pseudo random number generator. Used where randomness is needed during
+/// construction, such as pivot selection, so that builds are reproducible from a seed.
+pub struct SplitMix64 {
+    pub state: u64,
+}
+
+impl SplitMix64 {
+    #[inline(always)]
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    #[inline(always)]
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A random index in `0..len`. `len` must not be zero.
+    #[inline(always)]
+    pub fn next_index(&mut self, len: usize) -> usize {
+        (self.next_u64() % len as u64) as usize
+    }
+}
+
+/// Collects the value of every axis of `point` into an array
+#[inline(always)]
+pub fn axis_values<P, const D: usize>(point: &P) -> [f32; D]
+where
+    P: Point<D>,
+{
+    let mut values = [0.0; D];
+    for (axis, value) in values.iter_mut().enumerate() {
+        *value = point.get_axis(axis);
+    }
+    values
+}
+
+/// Returns the per-axis minimum and maximum of `points`. For an empty slice the minimum
+/// is `f32::INFINITY` and the maximum `f32::NEG_INFINITY` on every axis.
+pub fn compute_bounds<P, const D: usize>(points: &[P]) -> ([f32; D], [f32; D])
+where
+    P: Point<D>,
+{
+    let mut min = [f32::INFINITY; D];
+    let mut max = [f32::NEG_INFINITY; D];
+
+    for point in points {
+        for axis in 0..D {
+            let value = point.get_axis(axis);
+            min[axis] = min[axis].min(value);
+            max[axis] = max[axis].max(value);
+        }
+    }
+
+    (min, max)
+}
+
 #[inline(always)]
 pub fn stable_sort<P, const D: usize>(points: &[P], indices: &mut [usize], axis: usize)
 where
@@ -70,6 +197,10 @@ where
     indices.sort_unstable_by(|a, b| point_axis_compare(points, *a, *b, axis));
 }
 
+/// Compares the points with indices `a` and `b` along `axis`. Points with the same value on the
+/// axis are ordered by their index, which makes this a strict total order: every sorting strategy
+/// arrives at exactly the same order and therefore builds exactly the same tree, no matter how
+/// many points share a value on a splitting plane.
 #[inline(always)]
 pub fn point_axis_compare<const D: usize, P>(
     points: &[P],
@@ -82,19 +213,60 @@ where
 {
     points[a]
         .get_axis(axis)
-        .partial_cmp(&points[b].get_axis(axis))
-        .unwrap_or_else(|| std::cmp::Ordering::Equal)
+        .total_cmp(&points[b].get_axis(axis))
+        .then(a.cmp(&b))
+}
+
+/// Whether the point with index `a` comes before the one with index `b` along `axis`, see `point_axis_compare`
+#[inline(always)]
+pub fn point_axis_less<const D: usize, P>(points: &[P], a: usize, b: usize, axis: usize) -> bool
+where
+    P: Point<D>,
+{
+    point_axis_compare(points, a, b, axis).is_lt()
 }
 
 pub mod quicksort {
     use super::*;
 
+    /// Stack of ranges left to sort. The smaller of two ranges is always sorted first, so at
+    /// most one range per halving is pending and the stack never needs to grow.
+    struct RangeStack {
+        ranges: [(usize, usize); usize::BITS as usize + 1],
+        len: usize,
+    }
+
+    impl RangeStack {
+        fn new(len: usize) -> Self {
+            let mut ranges = [(0, 0); usize::BITS as usize + 1];
+            ranges[0] = (0, len);
+            Self { ranges, len: 1 }
+        }
+
+        #[inline(always)]
+        fn pop(&mut self) -> Option<(usize, usize)> {
+            self.len = self.len.checked_sub(1)?;
+            Some(self.ranges[self.len])
+        }
+
+        #[inline(always)]
+        fn push_both(&mut self, a: (usize, usize), b: (usize, usize)) {
+            let (smaller, larger) = if a.1 - a.0 <= b.1 - b.0 {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            self.ranges[self.len] = larger;
+            self.ranges[self.len + 1] = smaller;
+            self.len += 2;
+        }
+    }
+
     pub fn quick_sort<const D: usize, P>(points: &[P], indices: &mut [usize], axis: usize)
     where
         P: Point<D>,
     {
-        let mut stack = Vec::new();
-        stack.push((0, indices.len()));
+        let mut stack = RangeStack::new(indices.len());
 
         while let Some((start, end)) = stack.pop() {
             if start >= end {
@@ -102,9 +274,76 @@ pub mod quicksort {
             }
 
             let pivot = partition(points, indices, start, end, axis);
+            stack.push_both((start, pivot), (pivot + 1, end));
+        }
+    }
+
+    /// Same as `quick_sort` but picks pivots at random using a generator seeded from `seed`,
+    /// the axis and the indices being sorted. The result is reproducible for a given seed while
+    /// avoiding the quadratic worst case of always picking the last element on adversarial input.
+    pub fn quick_sort_seeded<const D: usize, P>(
+        points: &[P],
+        indices: &mut [usize],
+        axis: usize,
+        seed: u64,
+    ) where
+        P: Point<D>,
+    {
+        let first = indices.first().copied().unwrap_or(0) as u64;
+        let mut rng =
+            SplitMix64::new(seed ^ SplitMix64::new(first ^ ((axis as u64) << 48)).next_u64());
+
+        let mut stack = RangeStack::new(indices.len());
+
+        while let Some((start, end)) = stack.pop() {
+            if start >= end {
+                continue;
+            }
 
-            stack.push((start, pivot));
-            stack.push((pivot + 1, end));
+            let random_pivot = start + rng.next_index(end - start);
+            indices.swap(random_pivot, end - 1);
+            let pivot = partition(points, indices, start, end, axis);
+            stack.push_both((start, pivot), (pivot + 1, end));
+        }
+    }
+
+    /// Reorders `indices` so that `indices[nth]` is the point that would be there if they were
+    /// sorted along `axis`, with the smaller points before it and the larger ones after it.
+    /// Only the side holding `nth` is partitioned further, so this takes linear time on average.
+    pub fn quick_select<const D: usize, P>(
+        points: &[P],
+        indices: &mut [usize],
+        nth: usize,
+        axis: usize,
+    ) where
+        P: Point<D>,
+    {
+        let (mut start, mut end) = (0, indices.len());
+
+        while end - start > 1 {
+            /*
+                NOTE: Pivoting around the middle rather than the last element keeps already
+                      sorted ranges, such as those of clustered input, from going quadratic
+            */
+            indices.swap(start + (end - start) / 2, end - 1);
+            let pivot = partition(points, indices, start, end, axis);
+
+            match nth.cmp(&pivot) {
+                std::cmp::Ordering::Less => end = pivot,
+                std::cmp::Ordering::Equal => return,
+                std::cmp::Ordering::Greater => start = pivot + 1,
+            }
+        }
+    }
+
+    /// Places the median along `axis` at `indices.len() / 2` using `quick_select`, which is
+    /// where construction takes its pivot from
+    pub fn median_select<const D: usize, P>(points: &[P], indices: &mut [usize], axis: usize)
+    where
+        P: Point<D>,
+    {
+        if !indices.is_empty() {
+            quick_select(points, indices, indices.len() / 2, axis);
         }
     }
 
@@ -120,10 +359,10 @@ pub mod quicksort {
     {
         let mut i = start;
         let pivot = end - 1;
-        let pivot_val = points[indices[pivot]].get_axis(axis);
+        let pivot_id = indices[pivot];
 
         for j in start..pivot {
-            if points[indices[j]].get_axis(axis) < pivot_val {
+            if point_axis_less(points, indices[j], pivot_id, axis) {
                 indices.swap(i, j);
                 i += 1;
             }
@@ -137,28 +376,94 @@ pub mod quicksort {
     mod tests {
         use super::*;
 
+        #[test]
+        fn test_quick_sort_seeded() {
+            let points = (0..500)
+                .map(|i| (i * 7919 % 503) as f32)
+                .collect::<Vec<_>>();
+            let mut indices = (0..points.len()).collect::<Vec<_>>();
+            let mut indices_2 = indices.clone();
+
+            quick_sort_seeded(&points, &mut indices, 0, 1234);
+            quick_sort_seeded(&points, &mut indices_2, 0, 1234);
+            assert_eq!(indices, indices_2);
+
+            for pair in indices.windows(2) {
+                assert!(points[pair[0]] <= points[pair[1]]);
+            }
+
+            /*
+                NOTE: Another seed takes other pivots, which shows in the number of comparisons,
+                      but sorts into the same order
+            */
+            #[derive(Debug, Clone, Copy)]
+            struct Counted(f32);
+
+            thread_local! {
+                static READS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+            }
+
+            impl Point<1> for Counted {
+                fn get_axis(&self, _d: usize) -> f32 {
+                    READS.with(|reads| reads.set(reads.get() + 1));
+                    self.0
+                }
+            }
+
+            let counted = points.iter().map(|p| Counted(*p)).collect::<Vec<_>>();
+            let reads_and_orders = [1234, 1234, 5678].map(|seed| {
+                let mut indices = (0..counted.len()).collect::<Vec<_>>();
+                READS.with(|reads| reads.set(0));
+                quick_sort_seeded(&counted, &mut indices, 0, seed);
+                (READS.with(|reads| reads.get()), indices)
+            });
+            assert_eq!(reads_and_orders[0], reads_and_orders[1]);
+            assert_ne!(reads_and_orders[0].0, reads_and_orders[2].0);
+            assert_eq!(reads_and_orders[2].1, indices);
+        }
+
+        #[test]
+        fn test_quick_select() {
+            let points = (0..500).map(|i| (i * 7919 % 97) as f32).collect::<Vec<_>>();
+            let mut sorted = (0..points.len()).collect::<Vec<_>>();
+            sorted.sort_by(|a, b| point_axis_compare(&points, *a, *b, 0));
+
+            for nth in [0, 1, 250, 498, 499] {
+                let mut indices = (0..points.len()).collect::<Vec<_>>();
+                quick_select(&points, &mut indices, nth, 0);
+
+                assert_eq!(indices[nth], sorted[nth]);
+                for &before in &indices[..nth] {
+                    assert!(point_axis_less(&points, before, indices[nth], 0));
+                }
+                for &after in &indices[nth + 1..] {
+                    assert!(point_axis_less(&points, indices[nth], after, 0));
+                }
+            }
+        }
+
         #[test]
         fn test_quick_sort() {
             #[rustfmt::skip]
             let points = [1_i32, 7, 56, 34, 576, 2, 4, 5, 6, 7, 9, 10, 9, 1, 2, 3, 100, 23452345, 34, 3, 4545];
-            let mut indices = (0..points.len()).into_iter().collect::<Vec<_>>();
-            let mut indices_2 = (0..points.len()).into_iter().collect::<Vec<_>>();
+            let mut indices = (0..points.len()).collect::<Vec<_>>();
+            let mut indices_2 = (0..points.len()).collect::<Vec<_>>();
 
             quick_sort(&points, &mut indices, 0);
             indices_2.sort_unstable_by(|a, b| {
                 points[*a]
                     .get_axis(0)
                     .partial_cmp(&points[*b].get_axis(0))
-                    .unwrap_or_else(|| std::cmp::Ordering::Equal)
+                    .unwrap_or(std::cmp::Ordering::Equal)
             });
             for i in 0..points.len() {
                 print!("{}, ", points[indices[i]]);
             }
-            println!("");
+            println!();
             for i in 0..points.len() {
                 print!("{}, ", points[indices_2[i]]);
             }
-            println!("");
+            println!();
 
             for i in 0..points.len() {
                 assert!(points[indices[i]] == points[indices_2[i]]);
@@ -180,10 +485,11 @@ pub mod shell_sort {
         while gap > 0 {
             for i in gap..len as i32 {
                 let temp_i = indices[i as usize];
-                let temp = points[temp_i].get_axis(axis);
                 let mut j = i;
 
-                while j >= gap && points[indices[j as usize - gap as usize]].get_axis(axis) > temp {
+                while j >= gap
+                    && point_axis_less(points, temp_i, indices[j as usize - gap as usize], axis)
+                {
                     indices.swap(j as usize, j as usize - gap as usize);
                     j -= gap;
                 }
@@ -203,15 +509,15 @@ pub mod shell_sort {
         fn test_shell_sort() {
             #[rustfmt::skip]
             let points = [1_i32, 7, 56, 34, 576, 2, 4, 5, 6, 7, 9, 10, 9, 1, 2, 3, 100, 23452345, 34, 3, 4545];
-            let mut indices = (0..points.len()).into_iter().collect::<Vec<_>>();
-            let mut indices_2 = (0..points.len()).into_iter().collect::<Vec<_>>();
+            let mut indices = (0..points.len()).collect::<Vec<_>>();
+            let mut indices_2 = (0..points.len()).collect::<Vec<_>>();
 
             shell_sort(&points, &mut indices, 0);
             indices_2.sort_unstable_by(|a, b| {
                 points[*a]
                     .get_axis(0)
                     .partial_cmp(&points[*b].get_axis(0))
-                    .unwrap_or_else(|| std::cmp::Ordering::Equal)
+                    .unwrap_or(std::cmp::Ordering::Equal)
             });
             for i in 0..points.len() {
                 assert!(points[indices[i]] == points[indices_2[i]]);
@@ -256,7 +562,6 @@ pub mod heap_sort {
         P: Point<D>,
     {
         let last = arr.len() - 1;
-        let root_value = points[arr[root]].get_axis(axis);
 
         loop {
             let left = 2 * root + 1;
@@ -266,24 +571,17 @@ pub mod heap_sort {
             }
 
             let right = left + 1;
-            let left_value = points[arr[left]].get_axis(axis);
-
-            let (max, max_value) = if right <= last {
-                let right_value = points[arr[right]].get_axis(axis);
-
-                if right_value > left_value {
-                    (right, right_value)
-                } else {
-                    (left, left_value)
-                }
+            let max = if right <= last && point_axis_less(points, arr[left], arr[right], axis) {
+                right
             } else {
-                (left, left_value)
+                left
             };
 
-            if max_value > root_value {
-                arr.swap(root, max);
+            if !point_axis_less(points, arr[root], arr[max], axis) {
+                break;
             }
 
+            arr.swap(root, max);
             root = max;
         }
     }
@@ -296,25 +594,25 @@ pub mod heap_sort {
         fn test_heap_sort() {
             #[rustfmt::skip]
             let points = [1_i32, 7, 56, 34, 576, 2, 4, 5, 6, 7, 9, 10, 9, 1, 2, 3, 100, 23452345, 34, 3, 4545];
-            let mut indices = (0..points.len()).into_iter().collect::<Vec<_>>();
-            let mut indices_2 = (0..points.len()).into_iter().collect::<Vec<_>>();
+            let mut indices = (0..points.len()).collect::<Vec<_>>();
+            let mut indices_2 = (0..points.len()).collect::<Vec<_>>();
 
             heap_sort(&points, &mut indices, 0);
             indices_2.sort_by(|a, b| {
                 points[*a]
                     .get_axis(0)
                     .partial_cmp(&points[*b].get_axis(0))
-                    .unwrap_or_else(|| std::cmp::Ordering::Equal)
+                    .unwrap_or(std::cmp::Ordering::Equal)
             });
 
             for i in 0..points.len() {
                 print!("{}, ", points[indices[i]]);
             }
-            println!("");
+            println!();
             for i in 0..points.len() {
                 print!("{}, ", points[indices_2[i]]);
             }
-            println!("");
+            println!();
 
             for i in 0..points.len() {
                 assert!(points[indices[i]] == points[indices_2[i]]);
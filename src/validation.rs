@@ -0,0 +1,125 @@
+//! Construction that checks the points for NaN and infinite coordinates first
+use crate::{KdTree, KdTreeError, KdTreeNoBorrow, Point};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// What `from_points_validated` does with points that have a NaN or infinite coordinate
+pub enum NonFinitePolicy {
+    /// Fail with `KdTreeError::NonFinite` naming the first such point
+    #[default]
+    Error,
+    /// Build the tree of the other points only. The excluded points keep their index but are
+    /// never returned by queries.
+    Exclude,
+}
+
+/// The first point with a NaN or infinite coordinate, as `(index, axis)`
+pub(crate) fn first_non_finite<const D: usize, P: Point<D>>(
+    points: &[P],
+) -> Option<(usize, usize)> {
+    points.iter().enumerate().find_map(|(index, point)| {
+        (0..D)
+            .find(|axis| !point.get_axis(*axis).is_finite())
+            .map(|axis| (index, axis))
+    })
+}
+
+/// The indices of the points whose coordinates are all finite
+pub(crate) fn finite_point_ids<const D: usize, P: Point<D>>(points: &[P]) -> Vec<usize> {
+    (0..points.len())
+        .filter(|i| (0..D).all(|axis| points[*i].get_axis(axis).is_finite()))
+        .collect()
+}
+
+impl<const D: usize, P: Point<D>> KdTreeNoBorrow<D, P> {
+    /// See `KdTree`
+    pub fn from_points_validated(
+        points: &[P],
+        policy: NonFinitePolicy,
+    ) -> Result<Self, KdTreeError> {
+        if policy == NonFinitePolicy::Error {
+            if let Some((index, axis)) = first_non_finite(points) {
+                return Err(KdTreeError::NonFinite { index, axis });
+            }
+
+            return Ok(Self::from_points(points));
+        }
+
+        Ok(Self::from_point_ids(points, finite_point_ids(points)))
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Same as `from_points`, but first checks every coordinate. A NaN is ordered after every
+    /// number while building, but any comparison with it is false while querying, so a node
+    /// splitting on a NaN sends queries down the wrong side and valid points go missing.
+    /// Depending on `policy`, points with a NaN or infinite coordinate are reported as an error
+    /// or left out of the tree.
+    pub fn from_points_validated(
+        points: &'a [P],
+        policy: NonFinitePolicy,
+    ) -> Result<Self, KdTreeError> {
+        Ok(Self {
+            internal: KdTreeNoBorrow::from_points_validated(points, policy)?,
+            points,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_finite_points() {
+        let points = (0..100)
+            .map(|i| match i {
+                17 => [1.0, f32::NAN],
+                40 => [f32::INFINITY, 2.0],
+                _ => [(i % 10) as f32, (i / 10) as f32],
+            })
+            .collect::<Vec<[f32; 2]>>();
+
+        assert_eq!(
+            KdTree::from_points_validated(&points, NonFinitePolicy::Error).unwrap_err(),
+            KdTreeError::NonFinite { index: 17, axis: 1 }
+        );
+
+        let tree = KdTree::from_points_validated(&points, NonFinitePolicy::Exclude).unwrap();
+        assert_eq!(tree.internal.tree.len(), 98);
+        assert_eq!(tree.internal.bounds_max, [9.0, 9.0]);
+
+        let mut result = tree.point_indices_within([0.0, 0.0], 100.0);
+        result.sort_unstable();
+        let expected = (0..100)
+            .filter(|i| *i != 17 && *i != 40)
+            .collect::<Vec<_>>();
+        assert_eq!(result, expected);
+        assert_eq!(tree.nearest_n([7.0, 1.0], 1), vec![7]);
+        assert_eq!(tree.nearest_n([0.0, 4.0], 1), vec![30]);
+
+        let mut internal = tree.internal.clone();
+        assert!(!internal.rebalance(&points));
+        internal.rebuild(&points);
+        assert_eq!(internal.tree, tree.internal.tree);
+        assert_eq!(internal.bounds_max, [9.0, 9.0]);
+
+        let points = (0..20)
+            .map(|i| match i {
+                3 => [f32::NAN, 0.0],
+                _ => [i as f32, 0.0],
+            })
+            .collect::<Vec<[f32; 2]>>();
+        let mut internal =
+            KdTreeNoBorrow::from_points_validated(&points, NonFinitePolicy::Exclude).unwrap();
+        assert_eq!(internal.tree.len(), 19);
+        internal.rebuild(&points);
+        assert_eq!(internal.tree.len(), 19);
+        assert!(!internal
+            .point_indices_within(&points, [3.0, 0.0], 100.0)
+            .contains(&3));
+
+        let finite = [[0.0_f32, 1.0], [2.0, 3.0]];
+        let tree = KdTree::from_points_validated(&finite, NonFinitePolicy::Error).unwrap();
+        assert_eq!(tree.nearest_one([2.0, 3.0]), Some((1, 0.0)));
+    }
+}
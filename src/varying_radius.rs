@@ -0,0 +1,206 @@
+//! Queries where every query point, or every stored point, has its own radius
+use crate::utils::axis_values;
+use crate::{KdTree, KdTreeNoBorrow, NodeStorage, Point};
+
+impl<const D: usize, P: Point<D>, N: NodeStorage> KdTreeNoBorrow<D, P, N> {
+    /// The largest of `radii` among the points of every subtree, indexed like `tree`
+    pub(crate) fn subtree_max_radius(&self, radii: &[f32]) -> Vec<f32> {
+        /*
+            NOTE: Children are always placed after their parent in `tree`, so iterating in
+                  reverse visits every child before its parent.
+        */
+        let mut subtree_max_radius = vec![0.0_f32; self.tree.len()];
+        for tree_index in (0..self.tree.len()).rev() {
+            let node = &self.tree[tree_index];
+            subtree_max_radius[tree_index] = node
+                .children()
+                .into_iter()
+                .flatten()
+                .map(|child| subtree_max_radius[child])
+                .fold(radii[node.index()], f32::max);
+        }
+
+        subtree_max_radius
+    }
+
+    /// See `KdTree`
+    pub fn point_indices_within_varying(
+        &self,
+        points: &[P],
+        queries: &[(P, f32)],
+    ) -> Vec<Vec<usize>> {
+        let mut stack = vec![];
+
+        queries
+            .iter()
+            .map(|(query_point, radius)| {
+                let mut result = vec![];
+                self.point_indices_within_buffers(
+                    points,
+                    *query_point,
+                    *radius,
+                    &mut result,
+                    &mut stack,
+                );
+                result
+            })
+            .collect()
+    }
+
+    /// See `KdTree`
+    pub fn pairs_within_varying(&self, points: &[P], radii: &[f32]) -> Vec<(usize, usize)> {
+        assert_eq!(
+            points.len(),
+            radii.len(),
+            "one radius per point is required"
+        );
+
+        let mut pairs = vec![];
+        if self.tree.is_empty() {
+            return pairs;
+        }
+
+        let subtree_max_radius = self.subtree_max_radius(radii);
+        let mut stack = vec![];
+
+        /*
+            NOTE: Only the points in the tree are paired up, which leaves out the points a
+                  `subset` tree was built without.
+        */
+        for i in self.tree.iter().map(|node| node.index()) {
+            let point = &points[i];
+            let query_point_axis_values = axis_values(point);
+            let own_radius = radii[i];
+
+            stack.push((0, 0));
+            while let Some((depth, tree_index)) = stack.pop() {
+                let reach = own_radius.max(subtree_max_radius[tree_index]);
+                if self.sphere_out_of_reach(tree_index, &query_point_axis_values, reach) {
+                    continue;
+                }
+
+                let j = self.tree[tree_index].index();
+
+                let axis = self.tree[tree_index].axis();
+                let axis_d = self.tree[tree_index].split - query_point_axis_values[axis];
+
+                if j > i {
+                    let radius = own_radius.max(radii[j]);
+                    if point.distance_squared(points[j]) <= radius * radius {
+                        pairs.push((i, j));
+                    }
+                }
+
+                let first = if axis_d >= 0.0 { 0 } else { 1 };
+                let last = (first + 1) % 2;
+
+                if let Some(child) = self.tree[tree_index].child(first) {
+                    stack.push((depth + 1, child));
+                }
+                if let Some(child) = self.tree[tree_index].child(last) {
+                    let reach = own_radius.max(subtree_max_radius[child]);
+                    if axis_d * axis_d <= reach * reach {
+                        stack.push((depth + 1, child));
+                    }
+                }
+            }
+        }
+
+        pairs
+    }
+}
+
+impl<'a, const D: usize, P: Point<D>> KdTree<'a, D, P> {
+    /// Runs one radius query per `(query_point, radius)` in `queries`, reusing the traversal
+    /// buffers between them. The result for each query is at the same position as the query.
+    #[inline(always)]
+    pub fn point_indices_within_varying(&self, queries: &[(P, f32)]) -> Vec<Vec<usize>> {
+        self.internal
+            .point_indices_within_varying(self.points, queries)
+    }
+
+    /// Finds every pair `(i, j)` with `i < j` of stored points where each point has its own
+    /// interaction radius given by `radii[i]`, and the two interact when their distance is at
+    /// most the larger of their radii. This is the neighbor search of SPH with variable
+    /// smoothing lengths.
+    ///
+    /// Subtrees are pruned using the largest radius of any point within them, so a few points
+    /// with large radii don't slow down the search for everyone else.
+    #[inline(always)]
+    pub fn pairs_within_varying(&self, radii: &[f32]) -> Vec<(usize, usize)> {
+        self.internal.pairs_within_varying(self.points, radii)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DuplicatePolicy, NonFinitePolicy};
+
+    #[test]
+    fn test_pairs_within_varying() {
+        let points: Vec<[f32; 2]> = (0..300)
+            .map(|i| {
+                let i = i as f32;
+                [(i * 0.61).sin() * 10.0, (i * 0.23).cos() * 10.0]
+            })
+            .collect();
+        let radii = (0..points.len())
+            .map(|i| {
+                if i % 50 == 0 {
+                    5.0
+                } else {
+                    0.5 + (i % 7) as f32 * 0.1
+                }
+            })
+            .collect::<Vec<_>>();
+        let tree = KdTree::from_points(&points);
+
+        let mut pairs = tree.pairs_within_varying(&radii);
+        pairs.sort();
+
+        let mut expected = vec![];
+        for i in 0..points.len() {
+            for j in i + 1..points.len() {
+                let radius = radii[i].max(radii[j]);
+                if points[i].distance_squared(points[j]) <= radius * radius {
+                    expected.push((i, j));
+                }
+            }
+        }
+
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    fn test_point_indices_within_varying() {
+        let points: Vec<[f32; 1]> = (0..10).map(|i| [i as f32]).collect();
+        let tree = KdTree::from_points(&points);
+
+        let mut results = tree.point_indices_within_varying(&[([0.0], 1.0), ([5.0], 0.0)]);
+        results.iter_mut().for_each(|result| result.sort());
+        assert_eq!(results, vec![vec![0, 1], vec![5]]);
+    }
+
+    #[test]
+    fn test_pairs_within_varying_subset() {
+        let points: Vec<[f32; 2]> = vec![[0.0, 0.0], [f32::NAN, 0.0], [0.5, 0.0], [9.0, 9.0]];
+        let radii = [1.0, 100.0, 0.1, 0.1];
+
+        let tree =
+            KdTreeNoBorrow::from_points_validated(&points, NonFinitePolicy::Exclude).unwrap();
+        assert_eq!(tree.pairs_within_varying(&points, &radii), vec![(0, 2)]);
+
+        let points: Vec<[f32; 2]> = vec![[0.0, 0.0], [0.0, 0.0], [0.5, 0.0]];
+        let (tree, _) = KdTreeNoBorrow::from_points_with_duplicate_policy(
+            &points,
+            DuplicatePolicy::Deduplicate,
+        )
+        .unwrap();
+        assert_eq!(tree.pairs_within_varying(&points, &[1.0; 3]), vec![(0, 2)]);
+
+        let empty: [[f32; 2]; 0] = [];
+        let tree = KdTree::from_points(&empty);
+        assert!(tree.pairs_within_varying(&[]).is_empty());
+    }
+}
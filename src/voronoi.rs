@@ -0,0 +1,109 @@
+//! Assigning points to their nearest site
+use crate::nearest::{distance_squared_to_axis_values, Neighbor};
+use crate::reorder::morton_code;
+use crate::utils::{axis_values, compute_bounds};
+use crate::{KdTree, Point};
+
+use std::collections::BinaryHeap;
+
+/// Returns the index of the nearest site of `sites_tree` for every point of `query_points`, i.e.
+/// which cell of the Voronoi diagram of the sites each point falls into. Ties are broken towards
+/// the lower site index.
+///
+/// The queries are processed in Morton order and each one starts out bounded by the distance
+/// to the nearest site of the previous, usually adjacent, query. This prunes most of the tree
+/// up front when the queries are dense compared to the sites, as in clustering assignment or
+/// when labeling the pixels of a territory map.
+///
+/// Panics if `sites_tree` is empty.
+pub fn assign_nearest_site<const D: usize, P>(
+    query_points: &[P],
+    sites_tree: &KdTree<'_, D, P>,
+) -> Vec<usize>
+where
+    P: Point<D>,
+{
+    assert!(
+        !sites_tree.points.is_empty(),
+        "at least one site is required"
+    );
+
+    let (bounds_min, bounds_max) = compute_bounds(query_points);
+    let mut order = query_points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| (morton_code(point, &bounds_min, &bounds_max), i))
+        .collect::<Vec<_>>();
+    order.sort_unstable();
+
+    let mut result = vec![0; query_points.len()];
+    let mut heap = BinaryHeap::with_capacity(2);
+    let mut stack = vec![];
+    let mut previous_site = None;
+
+    for (_, query_index) in order {
+        let query_point_axis_values = axis_values(&query_points[query_index]);
+
+        let bound_squared = previous_site.map_or(f32::INFINITY, |site: usize| {
+            distance_squared_to_axis_values(&sites_tree.points[site], &query_point_axis_values)
+        });
+
+        sites_tree.internal.nearest_n_axis_values_buffers(
+            sites_tree.points,
+            &query_point_axis_values,
+            1,
+            bound_squared,
+            &mut heap,
+            &mut stack,
+        );
+
+        let Neighbor { index, .. } = heap.pop().expect("the bound includes the previous site");
+        result[query_index] = index;
+        previous_site = Some(index);
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::SplitMix64;
+
+    #[test]
+    fn test_assign_nearest_site() {
+        let mut rng = SplitMix64::new(3);
+        let mut random_points = |count: usize| {
+            (0..count)
+                .map(|_| {
+                    [
+                        (rng.next_u64() % 10_000) as f32 / 100.0,
+                        (rng.next_u64() % 10_000) as f32 / 100.0,
+                        (rng.next_u64() % 10_000) as f32 / 100.0,
+                    ]
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let sites = random_points(50);
+        let queries = random_points(2000);
+        let tree = KdTree::from_points(&sites);
+
+        let assignment = assign_nearest_site(&queries, &tree);
+        assert_eq!(assignment.len(), queries.len());
+
+        for (query, site) in queries.iter().zip(&assignment) {
+            let expected = (0..sites.len())
+                .min_by(|a, b| {
+                    query
+                        .distance_squared(sites[*a])
+                        .total_cmp(&query.distance_squared(sites[*b]))
+                        .then(a.cmp(b))
+                })
+                .unwrap();
+            assert_eq!(*site, expected);
+        }
+
+        assert!(assign_nearest_site(&[] as &[[f32; 3]], &tree).is_empty());
+    }
+}